@@ -0,0 +1,645 @@
+//! AY-3-8910 family PSG emulation, also the SSG of the YM2203, YM2608 and YM2610.
+//!
+//! Three square wave channels, noise and the envelope generator, with the
+//! 32-step envelope and finer volume of the Yamaha parts and the AY8930's
+//! expanded mode (per-channel envelopes, 5-bit volumes and duty cycles). The
+//! AY8930 noise AND/OR masks are kept but not applied.
+
+#![allow(clippy::upper_case_acronyms)]
+
+use crate::{Commands, HeaderData, SoundChip, System, CHIP_CLOCK_MASK, VGM_SAMPLE_RATE};
+
+/// Flag bits of `HeaderData::ay8910_flags` and the YM2203/YM2608 SSG flags
+const FLAG_SINGLE_OUTPUT: u8 = 0x02;
+const FLAG_RAW_OUTPUT: u8 = 0x08;
+const FLAG_YM_CLOCK_DIVIDER: u8 = 0x10;
+
+/// Master clock cycles per tone counter tick
+const CLOCKS_PER_TICK: f64 = 8.0;
+/// Gain of one channel at full volume
+const CHANNEL_GAIN: f32 = 0.25;
+/// Tone duty cycles of the AY8930 in 32nds of a period
+const DUTY_CYCLES: [u32; 9] = [1, 2, 4, 8, 16, 24, 28, 30, 31];
+/// Duty cycle selected on reset, 50%
+const DEFAULT_DUTY: u8 = 4;
+
+/// Exact chip of `HeaderData::ay8910_chip_type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Ay8910ChipType {
+    #[default]
+    AY8910,
+    AY8912,
+    AY8913,
+    AY8930,
+    AY8914,
+    YM2149,
+    YM3439,
+    YMZ284,
+    YMZ294,
+}
+
+impl Ay8910ChipType {
+    /// Chip type for a header value, `None` for values the spec doesn't define
+    pub fn from_header_value(value: u8) -> Option<Self> {
+        let chip_type = match value {
+            0x00 => Self::AY8910,
+            0x01 => Self::AY8912,
+            0x02 => Self::AY8913,
+            0x03 => Self::AY8930,
+            0x04 => Self::AY8914,
+            0x10 => Self::YM2149,
+            0x11 => Self::YM3439,
+            0x12 => Self::YMZ284,
+            0x13 => Self::YMZ294,
+            _ => return None,
+        };
+        Some(chip_type)
+    }
+
+    /// Whether this is a Yamaha part, with a 32-step envelope and the pin 26
+    /// clock divider
+    pub fn is_yamaha(&self) -> bool {
+        matches!(
+            self,
+            Self::YM2149 | Self::YM3439 | Self::YMZ284 | Self::YMZ294
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Envelope {
+    period: u16,
+    counter: u32,
+    /// Steps left in the current cycle, counting down
+    step: i32,
+    /// Mask XORed onto the step: all ones while rising
+    attack: i32,
+    hold: bool,
+    alternate: bool,
+    holding: bool,
+}
+
+impl Envelope {
+    /// Restart with `shape` on an envelope of `mask` + 1 steps
+    fn set_shape(&mut self, shape: u8, mask: i32) {
+        self.attack = if shape & 0x04 != 0 { mask } else { 0 };
+        if shape & 0x08 == 0 {
+            // Without continue the envelope drops to 0 after one cycle
+            self.hold = true;
+            self.alternate = self.attack != 0;
+        } else {
+            self.hold = shape & 0x01 != 0;
+            self.alternate = shape & 0x02 != 0;
+        }
+        self.step = mask;
+        self.counter = 0;
+        self.holding = false;
+    }
+
+    /// Advance one tick; each step lasts `ticks_per_step` periods
+    fn tick(&mut self, mask: i32, ticks_per_step: u32) {
+        if self.holding {
+            return;
+        }
+        self.counter += 1;
+        if self.counter < self.period.max(1) as u32 * ticks_per_step {
+            return;
+        }
+        self.counter = 0;
+
+        self.step -= 1;
+        if self.step < 0 {
+            if self.hold {
+                if self.alternate {
+                    self.attack ^= mask;
+                }
+                self.holding = true;
+                self.step = 0;
+            } else {
+                if self.alternate && self.step & (mask + 1) != 0 {
+                    self.attack ^= mask;
+                }
+                self.step &= mask;
+            }
+        }
+    }
+
+    fn level(&self) -> i32 {
+        self.step ^ self.attack
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Channel {
+    period: u16,
+    counter: u32,
+    /// Volume register: level in the low bits, envelope select in bit 4, or
+    /// bit 5 in AY8930 expanded mode
+    volume: u8,
+    duty: u8,
+    /// The shared envelope lives in channel A; B and C use theirs in expanded mode
+    envelope: Envelope,
+}
+
+impl Channel {
+    fn tone_output(&self) -> bool {
+        let period = self.period.max(1) as u32 * 2;
+        self.counter * 32 < period * DUTY_CYCLES[self.duty.min(8) as usize]
+    }
+}
+
+/// One AY8910-compatible PSG
+#[derive(Debug, Clone)]
+pub struct Ay8910 {
+    chip_type: Ay8910ChipType,
+    flags: u8,
+    channels: [Channel; 3],
+    noise_period: u8,
+    noise_counter: u32,
+    /// Noise steps every other period
+    noise_prescale: bool,
+    noise_shift_register: u32,
+    /// Register 7: tone disable in bits 0-2, noise disable in bits 3-5
+    mixer: u8,
+    /// `l1 r1 l2 r2 l3 r3` from bit 0, set by `AY8910StereoMask`
+    stereo_mask: u8,
+    /// AY8930 expanded mode and register bank B selection
+    expanded: bool,
+    bank_b: bool,
+    noise_masks: (u8, u8),
+    /// Chip ticks per output sample
+    ticks_per_sample: f64,
+    tick_fraction: f64,
+}
+
+impl Ay8910 {
+    /// Chip with an AY8910-equivalent `clock` in Hz, of `chip_type`, with the
+    /// header `flags`
+    pub fn new(clock: u32, chip_type: Ay8910ChipType, flags: u8) -> Self {
+        let mut divider = CLOCKS_PER_TICK;
+        if chip_type.is_yamaha() && flags & FLAG_YM_CLOCK_DIVIDER != 0 {
+            divider *= 2.0;
+        }
+
+        let mut channels: [Channel; 3] = Default::default();
+        for channel in &mut channels {
+            channel.duty = DEFAULT_DUTY;
+        }
+
+        Self {
+            chip_type,
+            flags,
+            channels,
+            noise_period: 0,
+            noise_counter: 0,
+            noise_prescale: false,
+            noise_shift_register: 1,
+            mixer: 0xFF,
+            stereo_mask: 0x3F,
+            expanded: false,
+            bank_b: false,
+            noise_masks: (0xFF, 0x00),
+            ticks_per_sample: clock as f64 / divider / VGM_SAMPLE_RATE as f64,
+            tick_fraction: 0.0,
+        }
+    }
+
+    /// Chip `index` of the AY8910 of `header`, or the SSG of its YM2203,
+    /// YM2608 or YM2610.
+    ///
+    /// The SSGs behave as a YM2149 clocked at a quarter of the YM2203 clock
+    /// and an eighth of the YM2608 and YM2610 clocks. The extra header can
+    /// give each chip its own clock. `None` for other chips.
+    pub fn from_header(header: &HeaderData, system: System, index: u8) -> Option<Self> {
+        let clock = header
+            .extra_header
+            .chip_clock_override(&system, index)
+            .unwrap_or_else(|| header.chip_clock(&system))
+            & CHIP_CLOCK_MASK;
+        let chip = match system.base() {
+            System::AY8910 => {
+                let chip_type =
+                    Ay8910ChipType::from_header_value(header.ay8910_chip_type).unwrap_or_default();
+                Self::new(clock, chip_type, header.ay8910_flags)
+            },
+            System::YM2203 => Self::new(
+                clock / 4,
+                Ay8910ChipType::YM2149,
+                header.ym2203_ay8910_flags,
+            ),
+            System::YM2608 => Self::new(
+                clock / 8,
+                Ay8910ChipType::YM2149,
+                header.ym2608_ay8910_flags,
+            ),
+            System::YM2610 => Self::new(clock / 8, Ay8910ChipType::YM2149, 0),
+            _ => return None,
+        };
+        Some(chip)
+    }
+
+    /// Chip type this core emulates
+    pub fn chip_type(&self) -> Ay8910ChipType {
+        self.chip_type
+    }
+
+    /// Set which channels play on each side: bits 0, 2 and 4 enable channels
+    /// 1-3 on the left, bits 1, 3 and 5 on the right. Ignored with the single
+    /// output flag
+    pub fn set_stereo_mask(&mut self, mask: u8) {
+        self.stereo_mask = mask & 0x3F;
+    }
+
+    /// Envelope step mask: 16 steps on AY parts, 32 on Yamaha parts and in
+    /// AY8930 expanded mode
+    fn envelope_mask(&self) -> i32 {
+        if self.chip_type.is_yamaha() || self.expanded {
+            0x1F
+        } else {
+            0x0F
+        }
+    }
+
+    /// Write `value` to `register` 0-15
+    pub fn write(&mut self, register: u8, value: u8) {
+        let register = register & 0x0F;
+        let mask = self.envelope_mask();
+
+        if register == 0x0D {
+            if self.chip_type == Ay8910ChipType::AY8930 {
+                self.expanded = value & 0xE0 == 0xA0;
+                self.bank_b = self.expanded && value & 0x10 != 0;
+            }
+            let mask = self.envelope_mask();
+            self.channels[0].envelope.set_shape(value & 0x0F, mask);
+            return;
+        }
+        if self.bank_b {
+            self.write_bank_b(register, value, mask);
+            return;
+        }
+
+        match register {
+            0x00..=0x05 => {
+                let channel = &mut self.channels[register as usize / 2];
+                channel.period = if register & 1 == 0 {
+                    (channel.period & 0xFF00) | value as u16
+                } else if self.expanded {
+                    (channel.period & 0x00FF) | ((value as u16) << 8)
+                } else {
+                    (channel.period & 0x00FF) | ((value as u16 & 0x0F) << 8)
+                };
+            },
+            0x06 => self.noise_period = if self.expanded { value } else { value & 0x1F },
+            0x07 => self.mixer = value,
+            0x08..=0x0A => {
+                self.channels[register as usize - 8].volume = if self.expanded {
+                    value & 0x3F
+                } else {
+                    value & 0x1F
+                };
+            },
+            0x0B => {
+                self.channels[0].envelope.period =
+                    (self.channels[0].envelope.period & 0xFF00) | value as u16
+            },
+            0x0C => {
+                self.channels[0].envelope.period =
+                    (self.channels[0].envelope.period & 0x00FF) | ((value as u16) << 8);
+            },
+            _ => {},
+        }
+    }
+
+    /// AY8930 expanded mode bank B: envelope periods and shapes of channels B
+    /// and C, duty cycles and noise masks
+    fn write_bank_b(&mut self, register: u8, value: u8, mask: i32) {
+        match register {
+            0x00..=0x03 => {
+                let envelope = &mut self.channels[1 + register as usize / 2].envelope;
+                envelope.period = if register & 1 == 0 {
+                    (envelope.period & 0xFF00) | value as u16
+                } else {
+                    (envelope.period & 0x00FF) | ((value as u16) << 8)
+                };
+            },
+            0x04..=0x06 => self.channels[register as usize - 4].duty = value & 0x0F,
+            0x07 | 0x08 => self.channels[register as usize - 6]
+                .envelope
+                .set_shape(value & 0x0F, mask),
+            0x09 => self.noise_masks.0 = value,
+            0x0A => self.noise_masks.1 = value,
+            _ => {},
+        }
+    }
+
+    /// Advance tone, noise and envelope counters by one tick
+    fn tick(&mut self) {
+        for channel in &mut self.channels {
+            channel.counter += 1;
+            if channel.counter >= channel.period.max(1) as u32 * 2 {
+                channel.counter = 0;
+            }
+        }
+
+        self.noise_counter += 1;
+        if self.noise_counter >= self.noise_period.max(1) as u32 {
+            self.noise_counter = 0;
+            self.noise_prescale = !self.noise_prescale;
+            if !self.noise_prescale {
+                let bit = (self.noise_shift_register ^ (self.noise_shift_register >> 3)) & 1;
+                self.noise_shift_register = (self.noise_shift_register >> 1) | (bit << 16);
+            }
+        }
+
+        let mask = self.envelope_mask();
+        // AY parts take two periods per step of their 16-step envelope
+        let ticks_per_step = if mask == 0x0F { 2 } else { 1 };
+        let envelopes = if self.expanded { 3 } else { 1 };
+        for channel in &mut self.channels[..envelopes] {
+            channel.envelope.tick(mask, ticks_per_step);
+        }
+    }
+
+    /// Amplitude of channel `index`, between 0 and 1
+    fn channel_level(&self, index: usize) -> f32 {
+        let channel = &self.channels[index];
+        let envelope = if self.expanded {
+            &channel.envelope
+        } else {
+            &self.channels[0].envelope
+        };
+
+        // Level on a 32-step scale
+        let level = if self.expanded {
+            if channel.volume & 0x20 != 0 {
+                envelope.level()
+            } else {
+                (channel.volume & 0x1F) as i32
+            }
+        } else if channel.volume & 0x10 != 0 {
+            if self.envelope_mask() == 0x1F {
+                envelope.level()
+            } else {
+                envelope.level() * 2 + 1
+            }
+        } else {
+            match channel.volume & 0x0F {
+                0 => 0,
+                volume => volume as i32 * 2 + 1,
+            }
+        };
+
+        if level == 0 {
+            0.0
+        } else if self.flags & FLAG_RAW_OUTPUT != 0 {
+            level as f32 / 31.0
+        } else {
+            // 1.5 dB per step, so 3 dB per step of the 16 AY levels
+            ((level - 31) as f32 / 4.0).exp2()
+        }
+    }
+
+    /// Mix of the channels at the current tick
+    fn mix(&self) -> (f32, f32) {
+        let noise = self.noise_shift_register & 1 != 0;
+        let single = self.flags & FLAG_SINGLE_OUTPUT != 0;
+        let (mut left, mut right) = (0.0, 0.0);
+
+        for index in 0..3 {
+            let tone_on = self.channels[index].tone_output() || self.mixer & (0x01 << index) != 0;
+            let noise_on = noise || self.mixer & (0x08 << index) != 0;
+            if !(tone_on && noise_on) {
+                continue;
+            }
+
+            let level = self.channel_level(index) * CHANNEL_GAIN;
+            if single || self.stereo_mask & (0x01 << (index * 2)) != 0 {
+                left += level;
+            }
+            if single || self.stereo_mask & (0x02 << (index * 2)) != 0 {
+                right += level;
+            }
+        }
+        (left, right)
+    }
+}
+
+impl SoundChip for Ay8910 {
+    fn write(&mut self, command: &Commands) {
+        match *command {
+            Commands::AY8910Write {
+                register, value, ..
+            } => Ay8910::write(self, register, value),
+            // The SSG takes registers 0x00-0x0F of the YM2203 and port 0 of the YM2608/YM2610
+            Commands::YM2203Write {
+                register, value, ..
+            }
+            | Commands::YM2608Port0Write {
+                register, value, ..
+            }
+            | Commands::YM2610Port0Write {
+                register, value, ..
+            } if register < 0x10 => Ay8910::write(self, register, value),
+            Commands::AY8910StereoMask { value } => self.set_stereo_mask(value),
+            _ => {},
+        }
+    }
+
+    fn next_sample(&mut self) -> (f32, f32) {
+        self.tick_fraction += self.ticks_per_sample;
+        let ticks = self.tick_fraction as u32;
+        self.tick_fraction -= ticks as f64;
+
+        if ticks == 0 {
+            return self.mix();
+        }
+
+        // Average over the ticks of this sample to filter out what's above
+        // the output rate
+        let (mut left, mut right) = (0.0, 0.0);
+        for _ in 0..ticks {
+            self.tick();
+            let (l, r) = self.mix();
+            left += l;
+            right += r;
+        }
+        (left / ticks as f32, right / ticks as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLOCK: u32 = 1_789_773;
+
+    /// Count rising edges of the left output over `samples` samples
+    fn rising_edges(chip: &mut Ay8910, samples: usize) -> usize {
+        let mut previous = chip.next_sample().0;
+        let mut edges = 0;
+        for _ in 0..samples {
+            let sample = chip.next_sample().0;
+            if previous < 0.1 && sample >= 0.1 {
+                edges += 1;
+            }
+            previous = sample;
+        }
+        edges
+    }
+
+    /// Envelope levels over `steps` steps of `shape`
+    fn envelope_levels(shape: u8, mask: i32, steps: usize) -> Vec<i32> {
+        let mut envelope = Envelope {
+            period: 1,
+            ..Default::default()
+        };
+        envelope.set_shape(shape, mask);
+        (0..steps)
+            .map(|_| {
+                let level = envelope.level();
+                envelope.tick(mask, 1);
+                level
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tone_frequency() {
+        let mut chip = Ay8910::new(CLOCK, Ay8910ChipType::AY8910, 0x01);
+        // Channel A tone only, period 254 (~440 Hz), full volume
+        chip.write(0x00, 0xFE);
+        chip.write(0x07, 0x3E);
+        assert!((0..1000).all(|_| chip.next_sample() == (0.0, 0.0)));
+
+        chip.write(0x08, 0x0F);
+        let edges = rising_edges(&mut chip, VGM_SAMPLE_RATE as usize);
+        assert!((435..=445).contains(&edges), "{edges} edges");
+    }
+
+    #[test]
+    fn test_envelope_shapes() {
+        // Sawtooth down repeats
+        let saw = envelope_levels(0x08, 0x0F, 18);
+        assert_eq!(saw[..16], (0..16).rev().collect::<Vec<_>>());
+        assert_eq!(saw[16..], [15, 14]);
+
+        // Attack then hold at the top
+        let hold = envelope_levels(0x0D, 0x0F, 20);
+        assert_eq!(hold[..16], (0..16).collect::<Vec<_>>());
+        assert!(hold[16..].iter().all(|&level| level == 15));
+
+        // Triangle alternates direction, over 32 steps on Yamaha parts
+        let triangle = envelope_levels(0x0E, 0x1F, 64);
+        assert_eq!(triangle[31], 31);
+        assert_eq!(triangle[32], 31);
+        assert_eq!(triangle[63], 0);
+
+        // Shapes without continue decay once and stay silent
+        let single = envelope_levels(0x00, 0x0F, 20);
+        assert!(single[16..].iter().all(|&level| level == 0));
+    }
+
+    #[test]
+    fn test_stereo_mask_and_single_output() {
+        let mut chip = Ay8910::new(CLOCK, Ay8910ChipType::YM2149, 0x01);
+        chip.write(0x07, 0x3F);
+        chip.write(0x08, 0x0F);
+        // Channel 1 on the right only
+        SoundChip::write(&mut chip, &Commands::AY8910StereoMask { value: 0x02 });
+        let (left, right) = chip.next_sample();
+        assert_eq!(left, 0.0);
+        assert_eq!(right, CHANNEL_GAIN);
+
+        let mut mono = Ay8910::new(CLOCK, Ay8910ChipType::YM2149, FLAG_SINGLE_OUTPUT);
+        mono.write(0x07, 0x3F);
+        mono.write(0x08, 0x0F);
+        mono.set_stereo_mask(0x02);
+        assert_eq!(mono.next_sample(), (CHANNEL_GAIN, CHANNEL_GAIN));
+    }
+
+    #[test]
+    fn test_ym2149_volume_and_divider() {
+        let mut chip = Ay8910::new(CLOCK, Ay8910ChipType::YM2149, 0);
+        chip.write(0x07, 0x3F);
+        chip.write(0x08, 0x0E);
+        let (half, _) = chip.next_sample();
+        // One 4-bit volume step is 3 dB
+        assert!((half / CHANNEL_GAIN - 0.5f32.sqrt()).abs() < 1e-6);
+
+        let divided = Ay8910::new(CLOCK, Ay8910ChipType::YM2149, FLAG_YM_CLOCK_DIVIDER);
+        let ay = Ay8910::new(CLOCK, Ay8910ChipType::AY8910, FLAG_YM_CLOCK_DIVIDER);
+        assert_eq!(divided.ticks_per_sample * 2.0, ay.ticks_per_sample);
+    }
+
+    #[test]
+    fn test_ay8930_expanded_mode() {
+        let mut chip = Ay8910::new(CLOCK, Ay8910ChipType::AY8930, 0);
+        chip.write(0x0D, 0xA0);
+        assert!(chip.expanded);
+        chip.write(0x07, 0x3F);
+        // 5-bit volume
+        chip.write(0x09, 0x1E);
+        assert_eq!(chip.channel_level(1), (-0.25f32).exp2());
+        // 16-bit tone period
+        chip.write(0x01, 0xAB);
+        assert_eq!(chip.channels[0].period, 0xAB00);
+
+        // Bank B: duty cycle and channel C envelope
+        chip.write(0x0D, 0xB0);
+        chip.write(0x04, 0x00);
+        chip.write(0x02, 0x34);
+        chip.write(0x08, 0x0D);
+        assert_eq!(chip.channels[0].duty, 0);
+        assert_eq!(chip.channels[2].envelope.period, 0x34);
+        assert_eq!(chip.channels[2].envelope.level(), 0);
+
+        // Other parts ignore the mode bits
+        let mut ay = Ay8910::new(CLOCK, Ay8910ChipType::AY8910, 0);
+        ay.write(0x0D, 0xA0);
+        assert!(!ay.expanded);
+    }
+
+    #[test]
+    fn test_from_header() {
+        let mut header = HeaderData {
+            version: 151,
+            ay8910_chip_type: 0x10,
+            ..Default::default()
+        };
+        header.set_chip(&System::AY8910, CLOCK, false).unwrap();
+        header.set_chip(&System::YM2203, 3_993_600, false).unwrap();
+
+        let ay = Ay8910::from_header(&header, System::AY8910, 0).unwrap();
+        assert_eq!(ay.chip_type(), Ay8910ChipType::YM2149);
+        let ssg = Ay8910::from_header(&header, System::YM2203, 0).unwrap();
+        assert!((ssg.ticks_per_sample - 3_993_600.0 / 32.0 / 44_100.0).abs() < 1e-9);
+        assert!(Ay8910::from_header(&header, System::YM2612, 0).is_none());
+
+        // The second chip takes its clock from the extra header
+        header.set_chip(&System::AY8910, CLOCK, true).unwrap();
+        header.extra_header.set_chip_clock_override(&System::AY8910, 1, CLOCK * 2).unwrap();
+        let first = Ay8910::from_header(&header, System::AY8910, 0).unwrap();
+        let second = Ay8910::from_header(&header, System::AY8910, 1).unwrap();
+        assert!((second.ticks_per_sample - first.ticks_per_sample * 2.0).abs() < 1e-9);
+
+        let mut chip = ssg;
+        SoundChip::write(
+            &mut chip,
+            &Commands::YM2203Write {
+                register: 0x08,
+                value: 0x0F,
+                chip_index: 0,
+            },
+        );
+        SoundChip::write(
+            &mut chip,
+            &Commands::YM2203Write {
+                register: 0x28,
+                value: 0xF0,
+                chip_index: 0,
+            },
+        );
+        assert_eq!(chip.channels[0].volume, 0x0F);
+    }
+}
@@ -0,0 +1,389 @@
+//! Register shadow state of every chip, rebuilt from the command stream.
+//!
+//! [`ChipStateTracker`] replays [`Commands`] and keeps the last value written
+//! to every register of every chip instance, resolving port splits, dual
+//! chips and the SN76489 latch. It doesn't emulate anything: registers a chip
+//! changes by itself, and writes made by DAC streams, aren't reflected.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+use crate::{Commands, DataBlockContent, System, VgmFile};
+
+/// Port holding values that aren't chip registers but shape its output: the
+/// Game Gear stereo byte and AY8910 stereo mask in register 0, and MultiPCM
+/// banks with the channel as register
+pub const CONTROL_PORT: u8 = 0xFF;
+/// Port holding the RF5C68/RF5C164 wave RAM written through `0xC1`/`0xC2`
+pub const MEMORY_PORT: u8 = 0xFE;
+
+/// Register of a chip; `port` tells apart register banks such as the two
+/// halves of the YM2612
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RegisterAddress {
+    pub port: u8,
+    pub register: u16,
+}
+
+/// Last value written to every register of one chip instance
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegisterFile {
+    registers: BTreeMap<RegisterAddress, u16>,
+    /// SN76489 register selected by the last latch byte
+    latched: u8,
+}
+
+impl RegisterFile {
+    /// Value of `register` on `port`, `None` when never written
+    pub fn get(&self, port: u8, register: u16) -> Option<u16> {
+        self.registers.get(&RegisterAddress { port, register }).copied()
+    }
+
+    /// Every written register in address order
+    pub fn iter(&self) -> impl Iterator<Item = (RegisterAddress, u16)> + '_ {
+        self.registers.iter().map(|(&address, &value)| (address, value))
+    }
+
+    /// Number of registers written so far
+    pub fn len(&self) -> usize {
+        self.registers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty()
+    }
+
+    fn write(&mut self, port: u8, register: u16, value: u16) {
+        self.registers.insert(RegisterAddress { port, register }, value);
+    }
+
+    /// SN76489 write: a latch byte (bit 7 set) selects a register and sets its
+    /// low 4 bits, a data byte sets the high 6 bits of a latched tone or the
+    /// whole volume or noise register.
+    ///
+    /// Registers 0-7 are tone 0, volume 0, tone 1, volume 1, tone 2, volume 2,
+    /// noise control and noise volume.
+    fn write_psg(&mut self, value: u8) {
+        let data = value as u16;
+        let register = if value & 0x80 != 0 {
+            self.latched = (value >> 4) & 0x07;
+            self.latched
+        } else {
+            self.latched
+        };
+
+        let is_tone = register & 0x01 == 0 && register != 6;
+        let current = self.get(0, register as u16).unwrap_or(0);
+        let updated = match (is_tone, value & 0x80 != 0) {
+            (true, true) => (current & 0x3F0) | (data & 0x0F),
+            (true, false) => (current & 0x00F) | ((data & 0x3F) << 4),
+            (false, _) => data & 0x0F,
+        };
+        self.write(0, register as u16, updated);
+    }
+}
+
+/// Stream data blocks (types 0x00-0x3F, and 0x40-0x7E once decompressed)
+/// appended per stream type, as players lay them out in memory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DataBanks {
+    banks: HashMap<u8, Vec<u8>>,
+    /// Range of each block within its bank, in the order received
+    blocks: HashMap<u8, Vec<Range<usize>>>,
+    decompression_table: Option<Vec<u8>>,
+}
+
+impl DataBanks {
+    /// Append the content of a data block. Blocks that fail to decompress and
+    /// non-stream blocks are skipped
+    pub fn add(&mut self, block_type: u8, data: &DataBlockContent) {
+        match data {
+            DataBlockContent::DecompressionTable { table_data, .. } => {
+                self.decompression_table = Some(table_data.clone());
+            },
+            DataBlockContent::UncompressedStream { .. } | DataBlockContent::CompressedStream { .. } => {
+                if let Ok(bytes) = data.decompress_data(self.decompression_table.as_deref()) {
+                    let bank = self.banks.entry(block_type & 0x3F).or_default();
+                    let start = bank.len();
+                    bank.extend(bytes);
+                    let range = start..bank.len();
+                    self.blocks.entry(block_type & 0x3F).or_default().push(range);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Data of stream type `stream_type` (0x00 for the YM2612), empty when
+    /// none was sent
+    pub fn bank(&self, stream_type: u8) -> &[u8] {
+        self.banks.get(&stream_type).map_or(&[], Vec::as_slice)
+    }
+
+    /// Range within bank `stream_type` of its `block_id`th block, as used by
+    /// DAC stream fast starts
+    pub fn block(&self, stream_type: u8, block_id: usize) -> Option<Range<usize>> {
+        self.blocks.get(&stream_type)?.get(block_id).cloned()
+    }
+}
+
+/// Register state of every chip instance, updated one command at a time
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChipStateTracker {
+    chips: HashMap<(System, u8), RegisterFile>,
+    data_banks: DataBanks,
+    /// Read position in the YM2612 data bank for `0x8n` writes
+    pcm_position: usize,
+    commands_applied: usize,
+}
+
+impl ChipStateTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replay `commands` from a fresh state
+    pub fn from_commands<'a>(commands: impl IntoIterator<Item = &'a Commands>) -> Self {
+        let mut tracker = Self::new();
+        for command in commands {
+            tracker.apply(command);
+        }
+        tracker
+    }
+
+    /// Update the state with one command
+    pub fn apply(&mut self, command: &Commands) {
+        self.commands_applied += 1;
+        match command {
+            Commands::PSGWrite { value, chip_index } => {
+                self.chip_mut(System::SN76489, *chip_index).write_psg(*value);
+            },
+            Commands::DataBlock { block_type, data } => self.data_banks.add(*block_type, data),
+            Commands::SeekPCM { offset } => self.pcm_position = *offset as usize,
+            Commands::YM2612Port0Address2AWriteWait { .. } => {
+                if let Some(&sample) = self.data_banks.bank(0x00).get(self.pcm_position) {
+                    self.chip_mut(System::YM2612, 0).write(0, 0x2A, sample as u16);
+                }
+                self.pcm_position += 1;
+            },
+            _ => {
+                let (Some(target), Some((port, register, value))) = (command.target(), register_write(command)) else {
+                    return;
+                };
+                let port = port.or(target.port).unwrap_or(0);
+                self.chip_mut(target.system, target.chip_index).write(port, register, value);
+            },
+        }
+    }
+
+    /// Registers of chip `index` of `system`, `None` before its first write.
+    ///
+    /// Variants are tracked under their base chip (YM2610 for YM2610B writes).
+    pub fn chip(&self, system: System, index: u8) -> Option<&RegisterFile> {
+        self.chips.get(&(system.base(), index))
+    }
+
+    /// Value of a register of chip `index` of `system`
+    pub fn register(&self, system: System, index: u8, port: u8, register: u16) -> Option<u16> {
+        self.chip(system, index)?.get(port, register)
+    }
+
+    /// Every chip written so far with its registers
+    pub fn chips(&self) -> impl Iterator<Item = (System, u8, &RegisterFile)> {
+        self.chips.iter().map(|(&(system, index), registers)| (system, index, registers))
+    }
+
+    /// Stream data received so far
+    pub fn data_banks(&self) -> &DataBanks {
+        &self.data_banks
+    }
+
+    /// Number of commands applied, i.e. the index of the next command
+    pub fn commands_applied(&self) -> usize {
+        self.commands_applied
+    }
+
+    fn chip_mut(&mut self, system: System, index: u8) -> &mut RegisterFile {
+        self.chips.entry((system.base(), index)).or_default()
+    }
+}
+
+/// Port override, register and value of a command that writes a register.
+///
+/// The port is `None` when it comes from [`Commands::target`].
+fn register_write(command: &Commands) -> Option<(Option<u8>, u16, u16)> {
+    let write = match *command {
+        Commands::GameGearPSGStereo { value, .. } | Commands::AY8910StereoMask { value } => {
+            (Some(CONTROL_PORT), 0, value as u16)
+        },
+        Commands::MultiPCMSetBank { channel, offset, .. } => (Some(CONTROL_PORT), channel as u16, offset),
+        Commands::RF5C68WriteOffset { offset, value } | Commands::RF5C164WriteOffset { offset, value } => {
+            (Some(MEMORY_PORT), offset, value as u16)
+        },
+        Commands::YM2413Write { register, value, .. }
+        | Commands::YM2612Port0Write { register, value, .. }
+        | Commands::YM2612Port1Write { register, value, .. }
+        | Commands::YM2151Write { register, value, .. }
+        | Commands::YM2203Write { register, value, .. }
+        | Commands::YM2608Port0Write { register, value, .. }
+        | Commands::YM2608Port1Write { register, value, .. }
+        | Commands::YM2610Port0Write { register, value, .. }
+        | Commands::YM2610Port1Write { register, value, .. }
+        | Commands::YM3812Write { register, value, .. }
+        | Commands::YM3526Write { register, value, .. }
+        | Commands::Y8950Write { register, value, .. }
+        | Commands::YMZ280BWrite { register, value, .. }
+        | Commands::YMF262Port0Write { register, value, .. }
+        | Commands::YMF262Port1Write { register, value, .. }
+        | Commands::AY8910Write { register, value, .. }
+        | Commands::RF5C68Write { register, value }
+        | Commands::RF5C164Write { register, value }
+        | Commands::GameBoyDMGWrite { register, value, .. }
+        | Commands::NESAPUWrite { register, value, .. }
+        | Commands::MultiPCMWrite { register, value, .. }
+        | Commands::uPD7759Write { register, value, .. }
+        | Commands::OKIM6258Write { register, value, .. }
+        | Commands::OKIM6295Write { register, value, .. }
+        | Commands::HuC6280Write { register, value, .. }
+        | Commands::K053260Write { register, value, .. }
+        | Commands::PokeyWrite { register, value, .. }
+        | Commands::WonderSwanWrite { register, value, .. }
+        | Commands::SAA1099Write { register, value, .. }
+        | Commands::ES5506Write { register, value, .. }
+        | Commands::GA20Write { register, value, .. }
+        | Commands::MikeyWrite { register, value, .. }
+        | Commands::YMF278BWrite { register, value, .. }
+        | Commands::YMF271Write { register, value, .. }
+        | Commands::SCC1Write { register, value, .. } => (None, register as u16, value as u16),
+        Commands::PWMWrite { register, value }
+        | Commands::QSoundWrite { register, value }
+        | Commands::ES5506Write16 { register, value, .. } => (None, register as u16, value),
+        Commands::SegaPCMWrite { offset, value, .. }
+        | Commands::SCSPWrite { offset, value, .. }
+        | Commands::WonderSwanWrite16 { offset, value, .. }
+        | Commands::VSUWrite { offset, value, .. }
+        | Commands::X1010Write { offset, value, .. } => (None, offset, value as u16),
+        Commands::K054539Write { register, value, .. }
+        | Commands::C140Write { register, value, .. }
+        | Commands::ES5503Write { register, value, .. } => (None, register, value as u16),
+        Commands::C352Write { register, value, .. } => (None, register, value),
+        _ => return None,
+    };
+    Some(write)
+}
+
+impl VgmFile {
+    /// Register state after the first `index` commands
+    pub fn chip_state_at_index(&self, index: usize) -> ChipStateTracker {
+        ChipStateTracker::from_commands(self.commands.iter().take(index))
+    }
+
+    /// Register state a player seeking to `sample` starts from: every command
+    /// before [`crate::Timeline::index_at_sample`] applied
+    pub fn chip_state_at_sample(&self, sample: u64) -> ChipStateTracker {
+        self.chip_state_at_index(self.timeline().index_at_sample(sample))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StreamChipType;
+
+    #[test]
+    fn test_psg_latch_and_data() {
+        let tracker = ChipStateTracker::from_commands(&[
+            // Tone 0 = 0x0FE: latch low nibble, then data byte for the high bits
+            Commands::PSGWrite { value: 0x8E, chip_index: 0 },
+            Commands::PSGWrite { value: 0x0F, chip_index: 0 },
+            // Volume 2 = 0x3, then a data byte rewrites it
+            Commands::PSGWrite { value: 0xD3, chip_index: 0 },
+            Commands::PSGWrite { value: 0x05, chip_index: 0 },
+            // Second chip noise control
+            Commands::PSGWrite { value: 0xE4, chip_index: 1 },
+            Commands::GameGearPSGStereo { value: 0xF0, chip_index: 0 },
+        ]);
+
+        let first = tracker.chip(System::SN76489, 0).unwrap();
+        assert_eq!(first.get(0, 0), Some(0x0FE));
+        assert_eq!(first.get(0, 5), Some(0x5));
+        assert_eq!(first.get(CONTROL_PORT, 0), Some(0xF0));
+        assert_eq!(tracker.register(System::SN76489, 1, 0, 6), Some(0x4));
+        assert_eq!(tracker.register(System::SN76489, 1, 0, 0), None);
+    }
+
+    #[test]
+    fn test_ports_and_dual_chips() {
+        let tracker = ChipStateTracker::from_commands(&[
+            Commands::YM2612Port0Write { register: 0x30, value: 0x71, chip_index: 0 },
+            Commands::YM2612Port1Write { register: 0x30, value: 0x0D, chip_index: 0 },
+            Commands::YM2151Write { register: 0x28, value: 0x4A, chip_index: 1 },
+            Commands::AY8910StereoMask { value: 0xC5 },
+            Commands::C352Write { register: 0x0123, value: 0xBEEF, chip_index: 0 },
+            Commands::Wait735Samples,
+        ]);
+
+        assert_eq!(tracker.register(System::YM2612, 0, 0, 0x30), Some(0x71));
+        assert_eq!(tracker.register(System::YM2612, 0, 1, 0x30), Some(0x0D));
+        assert_eq!(tracker.register(System::YM2151, 1, 0, 0x28), Some(0x4A));
+        assert!(tracker.chip(System::YM2151, 0).is_none());
+        // Bits 6 and 7 of the mask pick the YM2203's second chip
+        assert_eq!(tracker.register(System::YM2203, 1, CONTROL_PORT, 0), Some(0xC5));
+        assert_eq!(tracker.register(System::C352, 0, 0, 0x0123), Some(0xBEEF));
+        assert_eq!(tracker.chips().count(), 4);
+        assert_eq!(tracker.commands_applied(), 6);
+    }
+
+    #[test]
+    fn test_bit7_second_chip_writes() {
+        // Parsed from the bytes so the chip select bit goes through the decoder
+        let mut bytes = bytes::Bytes::from_static(&[0xD2, 0x80, 0x01, 0x05, 0xD0, 0x82, 0x10, 0x3F, 0xD0, 0x02, 0x10, 0x11]);
+        let commands: Vec<Commands> = (0..3).map(|_| Commands::from_bytes(&mut bytes).unwrap()).collect();
+        let tracker = ChipStateTracker::from_commands(&commands);
+
+        assert_eq!(tracker.register(System::K051649, 1, 0, 0x01), Some(0x05));
+        assert!(tracker.chip(System::K051649, 0).is_none());
+        assert_eq!(tracker.register(System::YMF278B, 1, 2, 0x10), Some(0x3F));
+        assert_eq!(tracker.register(System::YMF278B, 0, 2, 0x10), Some(0x11));
+    }
+
+    #[test]
+    fn test_ym2612_pcm_writes() {
+        let block = Commands::DataBlock {
+            block_type: 0x00,
+            data: DataBlockContent::UncompressedStream { chip_type: StreamChipType::YM2612, data: vec![0x10, 0x20, 0x30] },
+        };
+        let tracker = ChipStateTracker::from_commands(&[
+            block,
+            Commands::SeekPCM { offset: 1 },
+            Commands::YM2612Port0Address2AWriteWait { n: 2 },
+            Commands::YM2612Port0Address2AWriteWait { n: 2 },
+        ]);
+
+        assert_eq!(tracker.data_banks().bank(0x00), [0x10, 0x20, 0x30]);
+        assert_eq!(tracker.data_banks().block(0x00, 0), Some(0..3));
+        assert_eq!(tracker.data_banks().block(0x00, 1), None);
+        assert_eq!(tracker.register(System::YM2612, 0, 0, 0x2A), Some(0x30));
+    }
+
+    #[test]
+    fn test_state_at_sample() {
+        let vgm = VgmFile {
+            header: Default::default(),
+            commands: vec![
+                Commands::PSGWrite { value: 0x9F, chip_index: 0 },
+                Commands::Wait735Samples,
+                Commands::PSGWrite { value: 0x90, chip_index: 0 },
+                Commands::Wait735Samples,
+                Commands::EndOfSoundData,
+            ],
+            command_offsets: vec![],
+            metadata: Default::default(),
+            raw_regions: Default::default(),
+        };
+
+        assert_eq!(vgm.chip_state_at_sample(735).register(System::SN76489, 0, 0, 1), Some(0xF));
+        assert_eq!(vgm.chip_state_at_sample(736).register(System::SN76489, 0, 0, 1), Some(0x0));
+        assert_eq!(vgm.chip_state_at_index(0), ChipStateTracker::new());
+    }
+}
@@ -0,0 +1,55 @@
+//! Problems recovered from while parsing in [`crate::ParseMode::Recover`],
+//! and warnings from [`crate::VgmFile::validate_with_diagnostics`].
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::VgmError;
+
+/// How much a recovered problem cost
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Severity {
+    /// The file deviates from the spec but nothing was lost
+    Warning,
+    /// Bytes or metadata had to be dropped to keep parsing
+    Error,
+}
+
+/// A problem the parser recovered from, with where it was found
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// Absolute file offset of the problem
+    pub offset: usize,
+    /// Number of bytes skipped to resynchronize
+    pub skipped: usize,
+    pub severity: Severity,
+    /// The error strict parsing would have returned
+    pub error: VgmError,
+}
+
+impl Diagnostic {
+    pub fn warning(offset: usize, error: VgmError) -> Self {
+        Self { offset, skipped: 0, severity: Severity::Warning, error }
+    }
+
+    pub fn error(offset: usize, skipped: usize, error: VgmError) -> Self {
+        Self { offset, skipped, severity: Severity::Error, error }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{} at 0x{:X}: {}", severity, self.offset, self.error)?;
+        match self.skipped {
+            0 => {},
+            1 => write!(f, " (1 byte skipped)")?,
+            skipped => write!(f, " ({} bytes skipped)", skipped)?,
+        }
+        Ok(())
+    }
+}
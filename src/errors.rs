@@ -31,6 +31,13 @@ pub enum VgmError {
     #[error("File too small to be valid VGM: {path} ({size} bytes, minimum 64 required)")]
     FileTooSmall { path: String, size: usize },
 
+    /// Error writing file contents
+    #[error("Failed to write file {path}: {reason}")]
+    FileWriteError { 
+        path: String, 
+        reason: String,
+    },
+
     // ========== FORMAT VALIDATION ERRORS (2000-2099) ==========
     /// Invalid VGM magic bytes
     #[error("Invalid VGM magic bytes: expected 'Vgm ', found '{found}' at offset {offset}")]
@@ -210,6 +217,7 @@ impl VgmError {
             Self::FileReadError { .. } => 1002,
             Self::PermissionDenied { .. } => 1003,
             Self::FileTooSmall { .. } => 1004,
+            Self::FileWriteError { .. } => 1005,
             
             // Format Validation Errors (2000-2099)
             Self::InvalidMagicBytes { .. } => 2001,
@@ -437,6 +445,7 @@ mod tests {
             VgmError::FileReadError { path: "test".to_string(), reason: "test".to_string() },
             VgmError::PermissionDenied { path: "test".to_string() },
             VgmError::FileTooSmall { path: "test".to_string(), size: 0 },
+            VgmError::FileWriteError { path: "test".to_string(), reason: "test".to_string() },
             VgmError::InvalidMagicBytes { expected: "test".to_string(), found: "test".to_string(), offset: 0 },
             VgmError::CorruptedHeader { reason: "test".to_string(), offset: 0 },
             VgmError::InvalidOffset { field: "test".to_string(), offset: 0, file_size: 0 },
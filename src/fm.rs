@@ -0,0 +1,467 @@
+//! Operators and envelope generator shared by the Yamaha FM cores.
+//!
+//! The OPN and OPM families share their operator layout, detune, envelope
+//! rates and algorithms; chips add their own frequency and LFO handling.
+
+/// Phase accumulators are 20 bits, the top 10 index the sine
+pub(crate) const PHASE_MASK: u32 = 0xFFFFF;
+pub(crate) const MAX_ATTENUATION: i32 = 0x3FF;
+/// Channel outputs are 14 bits signed
+pub(crate) const CHANNEL_LIMIT: i32 = 8191;
+
+/// Detune in phase increment units, per detune amount and key code
+const DETUNE_TABLE: [[u8; 32]; 4] = [
+    [0; 32],
+    [
+        0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 3, 3, 3, 4, 4, 4, 5, 5, 6, 6, 7, 8, 8, 8, 8,
+    ],
+    [
+        1, 1, 1, 1, 2, 2, 2, 2, 2, 3, 3, 3, 4, 4, 4, 5, 5, 6, 6, 7, 8, 8, 9, 10, 11, 12, 13, 14, 16, 16, 16, 16,
+    ],
+    [
+        2, 2, 2, 2, 2, 3, 3, 3, 4, 4, 4, 5, 5, 6, 6, 7, 8, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 20, 22, 22, 22, 22,
+    ],
+];
+
+/// Envelope increments over the 8-step cycle of each rate row
+const EG_INCREMENTS: [[i32; 8]; 17] = [
+    [0, 1, 0, 1, 0, 1, 0, 1],
+    [0, 1, 0, 1, 1, 1, 0, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 1, 2, 1, 1, 1, 2],
+    [1, 2, 1, 2, 1, 2, 1, 2],
+    [1, 2, 2, 2, 1, 2, 2, 2],
+    [2, 2, 2, 2, 2, 2, 2, 2],
+    [2, 2, 2, 4, 2, 2, 2, 4],
+    [2, 4, 2, 4, 2, 4, 2, 4],
+    [2, 4, 4, 4, 2, 4, 4, 4],
+    [4, 4, 4, 4, 4, 4, 4, 4],
+    [4, 4, 4, 8, 4, 4, 4, 8],
+    [4, 8, 4, 8, 4, 8, 4, 8],
+    [4, 8, 8, 8, 4, 8, 8, 8],
+    [8, 8, 8, 8, 8, 8, 8, 8],
+];
+
+/// Operator of each register slot: registers are laid out M1, M2, C1, C2
+/// (S1, S3, S2, S4 in OPN terms) and operators are kept M1, C1, M2, C2
+pub(crate) const SLOT_OPERATOR: [usize; 4] = [0, 2, 1, 3];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Off,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Operator {
+    pub(crate) detune: u8,
+    pub(crate) multiple: u8,
+    pub(crate) total_level: u8,
+    pub(crate) key_scale: u8,
+    pub(crate) attack_rate: u8,
+    pub(crate) decay_rate: u8,
+    pub(crate) sustain_rate: u8,
+    pub(crate) sustain_level: u8,
+    pub(crate) release_rate: u8,
+    pub(crate) am_enabled: bool,
+    pub(crate) ssg_eg: u8,
+    pub(crate) phase: u32,
+    /// Envelope attenuation, 0 is loudest
+    pub(crate) envelope: i32,
+    pub(crate) state: EnvelopeState,
+    pub(crate) key_on: bool,
+    pub(crate) ssg_inverted: bool,
+}
+
+impl Default for Operator {
+    fn default() -> Self {
+        Self {
+            detune: 0,
+            multiple: 0,
+            total_level: 0,
+            key_scale: 0,
+            attack_rate: 0,
+            decay_rate: 0,
+            sustain_rate: 0,
+            sustain_level: 0,
+            release_rate: 0,
+            am_enabled: false,
+            ssg_eg: 0,
+            phase: 0,
+            envelope: MAX_ATTENUATION,
+            state: EnvelopeState::Off,
+            key_on: false,
+            ssg_inverted: false,
+        }
+    }
+}
+
+impl Operator {
+    /// Rate 0-63 for a register rate already doubled, 0 when the register is
+    /// 0 and the envelope doesn't move
+    fn rate(&self, rate: u32, key_code: u8) -> u32 {
+        if rate == 0 {
+            0
+        } else {
+            (rate + (key_code >> (3 - self.key_scale)) as u32).min(63)
+        }
+    }
+
+    fn attack_rate(&self, key_code: u8) -> u32 {
+        self.rate(self.attack_rate as u32 * 2, key_code)
+    }
+
+    fn sustain_attenuation(&self) -> i32 {
+        if self.sustain_level == 15 {
+            31 << 5
+        } else {
+            (self.sustain_level as i32) << 5
+        }
+    }
+
+    fn ssg_enabled(&self) -> bool {
+        self.ssg_eg & 0x08 != 0
+    }
+
+    /// State after the attack, skipping decay when the sustain level is 0
+    fn after_attack(&self) -> EnvelopeState {
+        if self.sustain_attenuation() == 0 {
+            EnvelopeState::Sustain
+        } else {
+            EnvelopeState::Decay
+        }
+    }
+
+    pub(crate) fn key_on(&mut self, key_code: u8) {
+        if self.key_on {
+            return;
+        }
+        self.key_on = true;
+        self.phase = 0;
+        self.ssg_inverted = false;
+        if self.attack_rate(key_code) >= 62 {
+            self.envelope = 0;
+            self.state = self.after_attack();
+        } else {
+            self.state = EnvelopeState::Attack;
+        }
+    }
+
+    pub(crate) fn key_off(&mut self) {
+        if !self.key_on {
+            return;
+        }
+        self.key_on = false;
+        if self.state != EnvelopeState::Off {
+            // Release continues from the level heard, not the internal one
+            if self.ssg_output_inverted() {
+                self.envelope = (0x200 - self.envelope) & MAX_ATTENUATION;
+            }
+            self.state = EnvelopeState::Release;
+        }
+    }
+
+    fn ssg_output_inverted(&self) -> bool {
+        self.ssg_enabled()
+            && matches!(self.state, EnvelopeState::Attack | EnvelopeState::Decay | EnvelopeState::Sustain)
+            && self.ssg_inverted != (self.ssg_eg & 0x04 != 0)
+    }
+
+    /// Repeat, hold or alternate once the SSG-EG envelope reaches the bottom
+    fn update_ssg(&mut self, key_code: u8) {
+        let active = matches!(self.state, EnvelopeState::Attack | EnvelopeState::Decay | EnvelopeState::Sustain);
+        if !active || self.envelope < 0x200 {
+            return;
+        }
+
+        let alternate = self.ssg_eg & 0x02 != 0;
+        if self.ssg_eg & 0x01 != 0 {
+            if alternate {
+                self.ssg_inverted = true;
+            }
+            if self.state != EnvelopeState::Attack && !self.ssg_output_inverted() {
+                self.envelope = MAX_ATTENUATION;
+            }
+        } else {
+            if alternate {
+                self.ssg_inverted = !self.ssg_inverted;
+            } else {
+                self.phase = 0;
+            }
+            if self.state != EnvelopeState::Attack {
+                if self.attack_rate(key_code) >= 62 {
+                    self.envelope = 0;
+                    self.state = self.after_attack();
+                } else {
+                    self.state = EnvelopeState::Attack;
+                }
+            }
+        }
+    }
+
+    /// Step the envelope on an envelope generator tick
+    pub(crate) fn update_envelope(&mut self, counter: u32, key_code: u8) {
+        let ssg = self.ssg_enabled();
+        if ssg {
+            self.update_ssg(key_code);
+        }
+
+        match self.state {
+            EnvelopeState::Attack => {
+                let increment = envelope_increment(self.attack_rate(key_code), counter);
+                if increment > 0 {
+                    self.envelope += (!self.envelope * increment) >> 4;
+                    if self.envelope <= 0 {
+                        self.envelope = 0;
+                        self.state = self.after_attack();
+                    }
+                }
+            },
+            EnvelopeState::Decay => {
+                let increment = envelope_increment(self.rate(self.decay_rate as u32 * 2, key_code), counter);
+                self.advance_envelope(increment, ssg);
+                if self.envelope >= self.sustain_attenuation() {
+                    self.state = EnvelopeState::Sustain;
+                }
+            },
+            EnvelopeState::Sustain => {
+                let increment = envelope_increment(self.rate(self.sustain_rate as u32 * 2, key_code), counter);
+                self.advance_envelope(increment, ssg);
+            },
+            EnvelopeState::Release => {
+                let increment = envelope_increment(self.rate(self.release_rate as u32 * 4 + 2, key_code), counter);
+                self.advance_envelope(increment, ssg);
+                if self.envelope >= MAX_ATTENUATION || (ssg && self.envelope >= 0x200) {
+                    self.envelope = MAX_ATTENUATION;
+                    self.state = EnvelopeState::Off;
+                }
+            },
+            EnvelopeState::Off => {},
+        }
+    }
+
+    /// Raise the attenuation; SSG-EG envelopes move 4 times faster and stop at 0x200
+    fn advance_envelope(&mut self, increment: i32, ssg: bool) {
+        if ssg {
+            if self.envelope < 0x200 {
+                self.envelope = (self.envelope + increment * 4).min(0x200);
+            }
+        } else {
+            self.envelope = (self.envelope + increment).min(MAX_ATTENUATION);
+        }
+    }
+
+    /// Total attenuation with the total level and amplitude modulation
+    pub(crate) fn attenuation(&self, am: i32) -> usize {
+        let envelope = if self.ssg_output_inverted() {
+            (0x200 - self.envelope) & MAX_ATTENUATION
+        } else {
+            self.envelope
+        };
+        let am = if self.am_enabled { am } else { 0 };
+        (envelope + ((self.total_level as i32) << 3) + am).min(MAX_ATTENUATION) as usize
+    }
+
+    /// Phase increment for a block-shifted frequency `base`, with detune and
+    /// the frequency multiple applied
+    pub(crate) fn phase_increment(&self, base: u32, key_code: u8, pm_factor: f32) -> u32 {
+        let detune = DETUNE_TABLE[(self.detune & 0x03) as usize][key_code as usize & 0x1F] as u32;
+        let frequency = if self.detune & 0x04 != 0 {
+            base.wrapping_sub(detune)
+        } else {
+            base + detune
+        } & 0x1FFFF;
+
+        let increment = match self.multiple {
+            0 => frequency >> 1,
+            multiple => frequency * multiple as u32,
+        };
+        if pm_factor == 1.0 {
+            increment
+        } else {
+            (increment as f32 * pm_factor) as u32
+        }
+    }
+}
+
+/// Envelope change at `counter` for `rate`
+fn envelope_increment(rate: u32, counter: u32) -> i32 {
+    if rate == 0 {
+        return 0;
+    }
+    let (shift, row) = match rate {
+        0..=47 => (11 - rate / 4, (rate % 4) as usize),
+        48..=59 => (0, 4 + (rate - 48) as usize),
+        _ => (0, 16),
+    };
+    if counter & ((1 << shift) - 1) != 0 {
+        return 0;
+    }
+    EG_INCREMENTS[row][((counter >> shift) & 7) as usize]
+}
+
+/// Envelope generator clock, stepping every 3 samples
+#[derive(Debug, Clone, Default)]
+pub(crate) struct EnvelopeClock {
+    divider: u8,
+    counter: u32,
+}
+
+impl EnvelopeClock {
+    /// Advance one sample, returning the counter on envelope ticks
+    pub(crate) fn tick(&mut self) -> Option<u32> {
+        self.divider += 1;
+        if self.divider < 3 {
+            return None;
+        }
+        self.divider = 0;
+        self.counter = (self.counter + 1) & 0xFFF;
+        if self.counter == 0 {
+            self.counter = 1;
+        }
+        Some(self.counter)
+    }
+}
+
+/// Sine and attenuation lookup tables
+#[derive(Debug, Clone)]
+pub(crate) struct FmTables {
+    sine: Vec<f32>,
+    /// Amplitude for each attenuation step of 0.09375 dB
+    attenuation: Vec<f32>,
+}
+
+impl FmTables {
+    pub(crate) fn new() -> Self {
+        Self {
+            sine: (0..1024).map(|i| (std::f32::consts::TAU * (i as f32 + 0.5) / 1024.0).sin()).collect(),
+            attenuation: (0..1024).map(|i| (-(i as f32) / 64.0).exp2()).collect(),
+        }
+    }
+
+    /// Output of `operator` with its phase moved by `modulation` sine steps
+    pub(crate) fn output(&self, operator: &Operator, modulation: i32, am: i32) -> i32 {
+        let attenuation = operator.attenuation(am);
+        if attenuation as i32 >= MAX_ATTENUATION {
+            return 0;
+        }
+        let index = ((operator.phase >> 10) as i32 + modulation) as usize & 0x3FF;
+        (self.sine[index] * self.attenuation[attenuation] * 8192.0) as i32
+    }
+
+    /// Amplitude of an attenuation, used for outputs that aren't a sine
+    pub(crate) fn level(&self, attenuation: usize) -> f32 {
+        self.attenuation[attenuation.min(MAX_ATTENUATION as usize)]
+    }
+}
+
+/// Self-feedback input of the first operator from its last two outputs
+pub(crate) fn feedback_input(history: [i32; 2], feedback: u8) -> i32 {
+    match feedback {
+        0 => 0,
+        feedback => (history[0] + history[1]) >> (10 - feedback),
+    }
+}
+
+/// Channel output of `algorithm` given the first operator's output, with
+/// `operator(n, modulation)` producing operator `n` (1-3, in M1, C1, M2, C2
+/// order) modulated by the sum of its inputs
+pub(crate) fn algorithm_output(algorithm: u8, out1: i32, mut operator: impl FnMut(usize, i32) -> i32) -> i32 {
+    let output = match algorithm {
+        0 => {
+            let out2 = operator(1, out1);
+            let out3 = operator(2, out2);
+            operator(3, out3)
+        },
+        1 => {
+            let out2 = operator(1, 0);
+            let out3 = operator(2, out1 + out2);
+            operator(3, out3)
+        },
+        2 => {
+            let out2 = operator(1, 0);
+            let out3 = operator(2, out2);
+            operator(3, out1 + out3)
+        },
+        3 => {
+            let out2 = operator(1, out1);
+            let out3 = operator(2, 0);
+            operator(3, out2 + out3)
+        },
+        4 => {
+            let out3 = operator(2, 0);
+            operator(1, out1) + operator(3, out3)
+        },
+        5 => operator(1, out1) + operator(2, out1) + operator(3, out1),
+        6 => operator(1, out1) + operator(2, 0) + operator(3, 0),
+        _ => out1 + operator(1, 0) + operator(2, 0) + operator(3, 0),
+    };
+    output.clamp(-CHANNEL_LIMIT, CHANNEL_LIMIT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope_stages() {
+        let mut operator = Operator {
+            attack_rate: 0x10,
+            decay_rate: 0x10,
+            sustain_level: 4,
+            ..Default::default()
+        };
+        operator.key_on(0);
+        assert_eq!(operator.state, EnvelopeState::Attack);
+
+        let mut counter = 0;
+        while operator.state == EnvelopeState::Attack {
+            counter = (counter + 1) & 0xFFF;
+            operator.update_envelope(counter, 0);
+        }
+        assert_eq!(operator.envelope, 0);
+        while operator.state == EnvelopeState::Decay {
+            counter = (counter + 1) & 0xFFF;
+            operator.update_envelope(counter, 0);
+        }
+        assert_eq!(operator.state, EnvelopeState::Sustain);
+        assert_eq!(operator.envelope, 4 << 5);
+
+        // Sustain rate 0 holds the level
+        operator.update_envelope(0x800, 0);
+        assert_eq!(operator.envelope, 4 << 5);
+    }
+
+    #[test]
+    fn test_ssg_eg_hold() {
+        // SSG-EG 0x0B: decay then hold at full volume
+        let mut operator = Operator {
+            attack_rate: 0x1F,
+            decay_rate: 0x1F,
+            sustain_level: 15,
+            ssg_eg: 0x0B,
+            ..Default::default()
+        };
+        operator.key_on(0);
+        for counter in 1..2000 {
+            operator.update_envelope(counter, 0);
+        }
+        assert!(operator.ssg_output_inverted());
+        assert_eq!(operator.attenuation(0), 0);
+    }
+
+    #[test]
+    fn test_algorithm_routing() {
+        // Algorithm 0 chains every operator, 7 adds them all
+        let chained = algorithm_output(0, 1, |n, modulation| modulation * 10 + n as i32);
+        assert_eq!(chained, ((10 + 1) * 10 + 2) * 10 + 3);
+        let parallel = algorithm_output(7, 1, |n, modulation| modulation + n as i32);
+        assert_eq!(parallel, 1 + 1 + 2 + 3);
+        assert_eq!(algorithm_output(7, 9000, |_, _| 0), CHANNEL_LIMIT);
+    }
+}
@@ -0,0 +1,685 @@
+//! Fuzzing support: byte-driven value generators and parser entry points.
+//!
+//! [`Unstructured`] and [`Arbitrary`] follow the model of the `arbitrary`
+//! crate: a generator consumes raw bytes and turns them into a value, so the
+//! same generators serve coverage-guided fuzzers and seeded property tests.
+//! Running out of input yields zeros rather than failing, which keeps every
+//! generator total.
+//!
+//! The `fuzz_*` functions are meant to back fuzz targets, e.g. with cargo-fuzz:
+//! `fuzz_target!(|data: &[u8]| vgm_parser::fuzz::fuzz_vgm_file(data));`.
+//! They panic only when the library misbehaves.
+
+use std::ops::RangeInclusive;
+
+use bytes::Bytes;
+
+use crate::{
+    opcodes::opcode_table, Commands, CompressionType, DataBlockContent, Gd3LocaleData, HeaderData, ParseMode,
+    ParserConfig, RAMWriteChipType, RawRegions, ROMDumpChipType, StreamChipType, VgmFile, VgmMetadata,
+};
+
+/// Largest payload generated for a data block
+const MAX_DATA_BLOCK_PAYLOAD: usize = 64;
+
+/// Most commands generated for a [`VgmFile`]
+const MAX_FILE_COMMANDS: usize = 32;
+
+/// Characters metadata strings are drawn from, including a non-BMP one to
+/// exercise UTF-16 surrogate pairs
+const GD3_CHARACTERS: &[char] = &['a', 'Z', '0', ' ', '-', 'é', 'ー', '音', '楽', '🎵'];
+
+/// A source of generator input that never runs dry
+#[derive(Debug, Clone)]
+pub struct Unstructured<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Unstructured<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    /// Number of input bytes left
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Next input byte, or 0 once the input is used up
+    pub fn u8(&mut self) -> u8 {
+        match self.data.split_first() {
+            Some((&byte, rest)) => {
+                self.data = rest;
+                byte
+            },
+            None => 0,
+        }
+    }
+
+    pub fn u16(&mut self) -> u16 {
+        u16::from_le_bytes([self.u8(), self.u8()])
+    }
+
+    pub fn u32(&mut self) -> u32 {
+        u32::from_le_bytes([self.u8(), self.u8(), self.u8(), self.u8()])
+    }
+
+    /// A value within `range`, consuming only as many bytes as the range needs
+    pub fn int_in_range(&mut self, range: RangeInclusive<u32>) -> u32 {
+        let (start, end) = range.into_inner();
+        assert!(start <= end, "empty range {}..={}", start, end);
+
+        let span = end - start;
+        let mut raw = 0u32;
+        let mut covered = 0u64;
+        while covered < span as u64 {
+            raw = raw << 8 | self.u8() as u32;
+            covered = covered << 8 | 0xFF;
+        }
+
+        start + (raw as u64 % (span as u64 + 1)) as u32
+    }
+
+    /// One of `choices`, which must not be empty
+    pub fn choose<T: Copy>(&mut self, choices: &[T]) -> T {
+        choices[self.int_in_range(0..=choices.len() as u32 - 1) as usize]
+    }
+
+    /// True with probability `numerator / denominator`
+    pub fn ratio(&mut self, numerator: u8, denominator: u8) -> bool {
+        (self.int_in_range(1..=denominator as u32)) <= numerator as u32
+    }
+
+    /// Up to `max_len` bytes, taken from the input as-is
+    pub fn bytes(&mut self, max_len: usize) -> Vec<u8> {
+        let len = self.int_in_range(0..=max_len as u32) as usize;
+        (0..len).map(|_| self.u8()).collect()
+    }
+
+    /// Generate any [`Arbitrary`] value
+    pub fn arbitrary<T: Arbitrary>(&mut self) -> T {
+        T::arbitrary(self)
+    }
+
+    fn chip_index(&mut self) -> u8 {
+        self.int_in_range(0..=1) as u8
+    }
+}
+
+/// Types that can be generated from fuzzer input.
+///
+/// Generated values are always ones the writer accepts, so anything
+/// generated must survive a write/parse round trip unchanged.
+pub trait Arbitrary: Sized {
+    fn arbitrary(u: &mut Unstructured<'_>) -> Self;
+}
+
+impl Arbitrary for Commands {
+    fn arbitrary(u: &mut Unstructured<'_>) -> Self {
+        match u.int_in_range(0..=16) {
+            0 => dual_chip_write(u),
+            1 => bit7_chip_write(u),
+            2 => offset_write(u),
+            3 => {
+                let (port, register, value, chip_index) = (u.u8() & 0x7F, u.u8(), u.u8(), u.chip_index());
+                match u.int_in_range(0..=2) {
+                    0 => Commands::YMF278BWrite { port, register, value, chip_index },
+                    1 => Commands::YMF271Write { port, register, value, chip_index },
+                    _ => Commands::SCC1Write { port, register, value, chip_index },
+                }
+            },
+            4 => match u.int_in_range(0..=4) {
+                0 => Commands::WaitNSamples { n: u.u16() },
+                1 => Commands::Wait735Samples,
+                2 => Commands::Wait882Samples,
+                3 => Commands::WaitNSamplesPlus1 { n: u.int_in_range(0..=0x0F) as u8 },
+                _ => Commands::YM2612Port0Address2AWriteWait { n: u.int_in_range(0..=0x0F) as u8 },
+            },
+            5 => Commands::EndOfSoundData,
+            6 => data_block(u),
+            7 => Commands::PCMRAMWrite {
+                chip_type: u.u8(),
+                read_offset: u.int_in_range(0..=0xFF_FFFF),
+                write_offset: u.int_in_range(0..=0xFF_FFFF),
+                size: u.int_in_range(1..=0x100_0000),
+            },
+            8 => dac_stream_control(u),
+            9 => Commands::PWMWrite {
+                register: u.int_in_range(0..=0x0F) as u8,
+                value: u.int_in_range(0..=0x0FFF) as u16,
+            },
+            10 => match u.int_in_range(0..=2) {
+                0 => Commands::AY8910StereoMask { value: u.u8() },
+                1 => Commands::GameGearPSGStereo { value: u.u8(), chip_index: u.chip_index() },
+                _ => Commands::PSGWrite { value: u.u8(), chip_index: u.chip_index() },
+            },
+            11 => match u.int_in_range(0..=2) {
+                0 => Commands::RF5C68Write { register: u.u8(), value: u.u8() },
+                1 => Commands::RF5C164Write { register: u.u8(), value: u.u8() },
+                // Mikey has no second chip, so bit 7 of the register is free
+                _ => Commands::MikeyWrite { register: u.u8(), value: u.u8() },
+            },
+            12 => Commands::MultiPCMSetBank {
+                channel: u.u8() & 0x7F,
+                offset: u.u16(),
+                chip_index: u.chip_index(),
+            },
+            13 => match u.int_in_range(0..=1) {
+                0 => Commands::QSoundWrite { register: u.u8(), value: u.u16() },
+                _ => Commands::ES5506Write16 {
+                    register: u.u8() & 0x7F,
+                    value: u.u16(),
+                    chip_index: u.chip_index(),
+                },
+            },
+            14 => Commands::SeekPCM { offset: u.u32() },
+            15 => Commands::C352Write {
+                register: u.u16() & 0x7FFF,
+                value: u.u16(),
+                chip_index: u.chip_index(),
+            },
+            _ => reserved(u),
+        }
+    }
+}
+
+/// Register writes whose second chip has its own opcode
+fn dual_chip_write(u: &mut Unstructured<'_>) -> Commands {
+    let (register, value, chip_index) = (u.u8(), u.u8(), u.chip_index());
+    match u.int_in_range(0..=14) {
+        0 => Commands::YM2413Write { register, value, chip_index },
+        1 => Commands::YM2612Port0Write { register, value, chip_index },
+        2 => Commands::YM2612Port1Write { register, value, chip_index },
+        3 => Commands::YM2151Write { register, value, chip_index },
+        4 => Commands::YM2203Write { register, value, chip_index },
+        5 => Commands::YM2608Port0Write { register, value, chip_index },
+        6 => Commands::YM2608Port1Write { register, value, chip_index },
+        7 => Commands::YM2610Port0Write { register, value, chip_index },
+        8 => Commands::YM2610Port1Write { register, value, chip_index },
+        9 => Commands::YM3812Write { register, value, chip_index },
+        10 => Commands::YM3526Write { register, value, chip_index },
+        11 => Commands::Y8950Write { register, value, chip_index },
+        12 => Commands::YMZ280BWrite { register, value, chip_index },
+        13 => Commands::YMF262Port0Write { register, value, chip_index },
+        _ => Commands::YMF262Port1Write { register, value, chip_index },
+    }
+}
+
+/// Register writes selecting the second chip with bit 7 of the register
+fn bit7_chip_write(u: &mut Unstructured<'_>) -> Commands {
+    let (register, value, chip_index) = (u.u8() & 0x7F, u.u8(), u.chip_index());
+    match u.int_in_range(0..=13) {
+        0 => Commands::AY8910Write { register, value, chip_index },
+        1 => Commands::GameBoyDMGWrite { register, value, chip_index },
+        2 => Commands::NESAPUWrite { register, value, chip_index },
+        3 => Commands::MultiPCMWrite { register, value, chip_index },
+        4 => Commands::uPD7759Write { register, value, chip_index },
+        5 => Commands::OKIM6258Write { register, value, chip_index },
+        6 => Commands::OKIM6295Write { register, value, chip_index },
+        7 => Commands::HuC6280Write { register, value, chip_index },
+        8 => Commands::K053260Write { register, value, chip_index },
+        9 => Commands::PokeyWrite { register, value, chip_index },
+        10 => Commands::WonderSwanWrite { register, value, chip_index },
+        11 => Commands::SAA1099Write { register, value, chip_index },
+        12 => Commands::ES5506Write { register, value, chip_index },
+        _ => Commands::GA20Write { register, value, chip_index },
+    }
+}
+
+/// Writes of a byte to a 16-bit offset or register, most selecting the second
+/// chip with bit 15
+fn offset_write(u: &mut Unstructured<'_>) -> Commands {
+    let (offset, value) = (u.u16(), u.u8());
+    let (register, chip_index) = (offset & 0x7FFF, u.chip_index());
+    match u.int_in_range(0..=9) {
+        0 => Commands::SegaPCMWrite { offset: register, value, chip_index },
+        1 => Commands::RF5C68WriteOffset { offset, value },
+        2 => Commands::RF5C164WriteOffset { offset, value },
+        3 => Commands::SCSPWrite { offset: register, value, chip_index },
+        4 => Commands::WonderSwanWrite16 { offset: register, value, chip_index },
+        5 => Commands::VSUWrite { offset: register, value, chip_index },
+        6 => Commands::X1010Write { offset: register, value, chip_index },
+        7 => Commands::K054539Write { register, value, chip_index },
+        8 => Commands::C140Write { register, value, chip_index },
+        _ => Commands::ES5503Write { register, value, chip_index },
+    }
+}
+
+fn dac_stream_control(u: &mut Unstructured<'_>) -> Commands {
+    let stream_id = u.u8();
+    match u.int_in_range(0..=5) {
+        0 => Commands::DACStreamSetupControl {
+            stream_id,
+            chip_type: u.u8() & 0x7F,
+            port: u.u8(),
+            command: u.u8(),
+            chip_index: u.chip_index(),
+        },
+        1 => Commands::DACStreamSetData {
+            stream_id,
+            data_bank_id: u.u8(),
+            step_size: u.u8(),
+            step_base: u.u8(),
+        },
+        2 => Commands::DACStreamSetFrequency { stream_id, frequency: u.u32() },
+        3 => Commands::DACStreamStart {
+            stream_id,
+            data_start_offset: u.u32(),
+            length_mode: u.u8(),
+            data_length: u.u32(),
+        },
+        4 => Commands::DACStreamStop { stream_id },
+        _ => Commands::DACStreamStartFast { stream_id, block_id: u.u16(), flags: u.u8() },
+    }
+}
+
+/// A data block whose content matches its block type, as the parser builds it
+fn data_block(u: &mut Unstructured<'_>) -> Commands {
+    let block_type = u.u8();
+    let data = match block_type {
+        0x00..=0x3F => DataBlockContent::UncompressedStream {
+            chip_type: StreamChipType::from_block_type(block_type),
+            data: u.bytes(MAX_DATA_BLOCK_PAYLOAD),
+        },
+        0x40..=0x7E => DataBlockContent::CompressedStream {
+            chip_type: StreamChipType::from_block_type(block_type),
+            compression: u.arbitrary(),
+            uncompressed_size: u.u32(),
+            data: u.bytes(MAX_DATA_BLOCK_PAYLOAD),
+        },
+        0x7F => DataBlockContent::DecompressionTable {
+            compression_type: u.u8(),
+            sub_type: u.u8(),
+            bits_decompressed: u.u8(),
+            bits_compressed: u.u8(),
+            value_count: u.u16(),
+            table_data: u.bytes(MAX_DATA_BLOCK_PAYLOAD),
+        },
+        0x80..=0xBF => DataBlockContent::ROMDump {
+            chip_type: ROMDumpChipType::from_block_type(block_type),
+            total_size: u.u32(),
+            start_address: u.u32(),
+            data: u.bytes(MAX_DATA_BLOCK_PAYLOAD),
+        },
+        0xC0..=0xDF => DataBlockContent::RAMWriteSmall {
+            chip_type: RAMWriteChipType::from_block_type(block_type),
+            start_address: u.u16(),
+            data: u.bytes(MAX_DATA_BLOCK_PAYLOAD),
+        },
+        0xE0..=0xFF => DataBlockContent::RAMWriteLarge {
+            chip_type: RAMWriteChipType::from_block_type(block_type),
+            start_address: u.u32(),
+            data: u.bytes(MAX_DATA_BLOCK_PAYLOAD),
+        },
+    };
+    Commands::DataBlock { block_type, data }
+}
+
+/// A command from one of the spec's reserved opcode ranges
+fn reserved(u: &mut Unstructured<'_>) -> Commands {
+    let ranges: Vec<_> = opcode_table().iter().filter(|info| info.is_reserved()).collect();
+    let info = u.choose(&ranges);
+    Commands::Reserved {
+        opcode: u.int_in_range(info.opcode as u32..=info.last_opcode as u32) as u8,
+        operands: (0..info.operand_len()).map(|_| u.u8()).collect(),
+    }
+}
+
+impl Arbitrary for CompressionType {
+    fn arbitrary(u: &mut Unstructured<'_>) -> Self {
+        if u.ratio(1, 2) {
+            CompressionType::BitPacking {
+                bits_decompressed: u.u8(),
+                bits_compressed: u.u8(),
+                sub_type: u.u8(),
+                add_value: u.u16(),
+            }
+        } else {
+            CompressionType::DPCM {
+                bits_decompressed: u.u8(),
+                bits_compressed: u.u8(),
+                start_value: u.u16(),
+            }
+        }
+    }
+}
+
+impl Arbitrary for HeaderData {
+    /// A header without an extra header, whose fields past the VGM data start
+    /// are zero since they aren't stored
+    fn arbitrary(u: &mut Unstructured<'_>) -> Self {
+        let version = u.choose(&[100, 101, 110, 150, 151, 160, 161, 170, 171, 172]);
+        // Pre-1.50 files always start their data at 0x40
+        let vgm_start = if version < 150 { 0x40 } else { 0x40 + 4 * u.int_in_range(0..=0x30) as usize };
+
+        let mut header = HeaderData {
+            end_of_file_offset: u.u32(),
+            version,
+            sn76489_clock: u.u32(),
+            ym2413_clock: u.u32(),
+            gd3_offset: u.u32(),
+            total_nb_samples: u.u32(),
+            loop_offset: u.u32(),
+            loop_nb_samples: u.u32(),
+            rate: u.u32(),
+            sn76489_feedback: u.u16(),
+            sn76489_shift_register_width: u.u8(),
+            sn76489_flags: u.u8(),
+            ym2612_clock: u.u32(),
+            ym2151_clock: u.u32(),
+            vgm_data_offset: if version < 150 { 0 } else { vgm_start as u32 - 0x34 },
+            sega_pcm_clock: u.u32(),
+            spcm_interface: u.u32(),
+            ..Default::default()
+        };
+
+        // Each field is only present when it lies before the VGM data
+        let mut field = |offset: usize| if offset < vgm_start { u.u32() } else { 0 };
+        header.rf5_c68_clock = field(0x40);
+        header.ym2203_clock = field(0x44);
+        header.ym2608_clock = field(0x48);
+        header.ym2610_b_clock = field(0x4C);
+        header.ym3812_clock = field(0x50);
+        header.ym3526_clock = field(0x54);
+        header.y8950_clock = field(0x58);
+        header.ymf262_clock = field(0x5C);
+        header.ymf278_b_clock = field(0x60);
+        header.ymf271_clock = field(0x64);
+        header.ymz280_b_clock = field(0x68);
+        header.rf5_c164_clock = field(0x6C);
+        header.pwm_clock = field(0x70);
+        header.ay8910_clock = field(0x74);
+        header.ay8910_chip_type = field(0x78) as u8;
+        header.ay8910_flags = field(0x79) as u8;
+        header.ym2203_ay8910_flags = field(0x7A) as u8;
+        header.ym2608_ay8910_flags = field(0x7B) as u8;
+        header.volume_modifier = field(0x7C) as u8;
+        header.loop_base = field(0x7E) as u8;
+        header.loop_modifier = field(0x7F) as u8;
+        header.gb_dmg_clock = field(0x80);
+        header.nes_apu_clock = field(0x84);
+        header.multi_pcm_clock = field(0x88);
+        header.u_pd7759_clock = field(0x8C);
+        header.okim6258_clock = field(0x90);
+        header.okim6258_flags = field(0x94) as u8;
+        header.k054539_flags = field(0x95) as u8;
+        header.c140_chip_type = field(0x96) as u8;
+        header.okim6295_clock = field(0x98);
+        header.k051649_k052539_clock = field(0x9C);
+        header.k054539_clock = field(0xA0);
+        header.hu_c6280_clock = field(0xA4);
+        header.c140_clock = field(0xA8);
+        header.k053260_clock = field(0xAC);
+        header.pokey_clock = field(0xB0);
+        header.qsound_clock = field(0xB4);
+        header.scsp_clock = field(0xB8);
+        header.wonder_swan_clock = field(0xC0);
+        header.vsu_clock = field(0xC4);
+        header.saa1099_clock = field(0xC8);
+        header.es5503_clock = field(0xCC);
+        header.es5506_clock = field(0xD0);
+        header.es5503_nb_channels = field(0xD4) as u8;
+        header.es5505_es5506_nb_channels = field(0xD5) as u8;
+        header.c352_clock_divider = field(0xD6) as u8;
+        header.x1010_clock = field(0xD8);
+        header.c352_clock = field(0xDC);
+        header.ga20_clock = field(0xE0);
+        header.mikey_clock = field(0xE4);
+        header.reserved = [field(0x7D) as u8, field(0x97) as u8, field(0xD7) as u8];
+
+        header
+    }
+}
+
+fn gd3_string(u: &mut Unstructured<'_>) -> String {
+    let len = u.int_in_range(0..=16);
+    (0..len).map(|_| u.choose(GD3_CHARACTERS)).collect()
+}
+
+impl Arbitrary for Gd3LocaleData {
+    fn arbitrary(u: &mut Unstructured<'_>) -> Self {
+        Gd3LocaleData {
+            track: gd3_string(u),
+            game: gd3_string(u),
+            system: gd3_string(u),
+            author: gd3_string(u),
+        }
+    }
+}
+
+impl Arbitrary for VgmMetadata {
+    fn arbitrary(u: &mut Unstructured<'_>) -> Self {
+        VgmMetadata {
+            english_data: u.arbitrary(),
+            japanese_data: u.arbitrary(),
+            date_release: gd3_string(u),
+            name_vgm_creator: gd3_string(u),
+            notes: gd3_string(u),
+        }
+    }
+}
+
+impl Arbitrary for VgmFile {
+    /// A file whose commands end with a single `EndOfSoundData`
+    fn arbitrary(u: &mut Unstructured<'_>) -> Self {
+        let header = u.arbitrary();
+        let count = u.int_in_range(0..=MAX_FILE_COMMANDS as u32);
+        let mut commands: Vec<Commands> = (0..count)
+            .map(|_| u.arbitrary())
+            .filter(|command| *command != Commands::EndOfSoundData)
+            .collect();
+        commands.push(Commands::EndOfSoundData);
+
+        let mut file = VgmFile {
+            header,
+            commands,
+            command_offsets: Vec::new(),
+            metadata: u.arbitrary(),
+            raw_regions: RawRegions::default(),
+        };
+        // Record offsets like the parser does, so the loop offset resolves
+        if file.recompute_command_offsets().is_err() {
+            file.header.loop_offset = 0;
+        }
+        file
+    }
+}
+
+/// Fuzz entry point for [`VgmFile::from_bytes_with_config`].
+///
+/// Parses `data` strictly and in [`ParseMode::Recover`] under
+/// [`ParserConfig::security_focused`] limits. Whatever serializes again must
+/// parse back, to the same commands when they were terminated by
+/// `EndOfSoundData`.
+pub fn fuzz_vgm_file(data: &[u8]) {
+    for parse_mode in [ParseMode::Strict, ParseMode::Recover] {
+        let config = ParserConfig { parse_mode, ..ParserConfig::security_focused() };
+        let Ok(file) = VgmFile::from_bytes_with_config(&mut Bytes::copy_from_slice(data), config.clone()) else {
+            continue;
+        };
+        // Corrupt header layouts may be refused by the writer
+        let Ok(written) = file.to_bytes_normalized() else {
+            continue;
+        };
+
+        let reparsed = VgmFile::from_bytes_with_config(&mut Bytes::from(written), config)
+            .expect("serialized file failed to parse");
+        // Without an end marker the reparse runs on into the GD3 tag
+        if file.commands.last() == Some(&Commands::EndOfSoundData) {
+            assert_eq!(reparsed.commands, file.commands);
+        }
+    }
+}
+
+/// Fuzz entry point for [`DataBlockContent::decompress_data`].
+///
+/// Builds a compressed stream and optional decompression table from `data`.
+/// Decompression may fail but must produce exactly the declared size when it
+/// succeeds.
+pub fn fuzz_decompress_data(data: &[u8]) {
+    let mut u = Unstructured::new(data);
+    let table = u.ratio(1, 2).then(|| u.bytes(1024));
+    let uncompressed_size = u.u32();
+    let stream = DataBlockContent::CompressedStream {
+        chip_type: StreamChipType::from_block_type(u.u8()),
+        compression: u.arbitrary(),
+        uncompressed_size,
+        data: u.bytes(4096),
+    };
+
+    if let Ok(decompressed) = stream.decompress_data(table.as_deref()) {
+        assert_eq!(decompressed.len(), uncompressed_size as usize);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use bytes::BytesMut;
+
+    use super::*;
+    use crate::{opcode_info, VgmParser, VgmWriter};
+
+    /// Deterministic input for the generators, so failures reproduce offline
+    struct SplitMix64(u64);
+
+    impl SplitMix64 {
+        fn next(&mut self) -> u64 {
+            self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        }
+
+        fn bytes(&mut self, len: usize) -> Vec<u8> {
+            (0..len).map(|_| self.next() as u8).collect()
+        }
+    }
+
+    /// Run `check` on a value generated from each of `cases` seeds
+    fn for_each_generated<T: Arbitrary>(cases: u64, input_len: usize, mut check: impl FnMut(u64, T)) {
+        for seed in 0..cases {
+            let input = SplitMix64(seed).bytes(input_len);
+            check(seed, Unstructured::new(&input).arbitrary());
+        }
+    }
+
+    #[test]
+    fn test_unstructured_is_total() {
+        let mut u = Unstructured::new(&[0x34, 0x12]);
+        assert_eq!(u.u16(), 0x1234);
+        assert!(u.is_empty());
+        assert_eq!(u.u32(), 0);
+        assert_eq!(u.int_in_range(5..=9), 5);
+        assert!(u.bytes(16).is_empty());
+
+        let mut u = Unstructured::new(&[0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(u.int_in_range(0..=u32::MAX), u32::MAX);
+        let _: VgmFile = Unstructured::new(&[]).arbitrary();
+    }
+
+    #[test]
+    fn test_commands_round_trip() {
+        let mut covered = BTreeSet::new();
+        for_each_generated(20_000, 32, |seed, command: Commands| {
+            let bytes = command.clone().to_bytes().unwrap_or_else(|e| panic!("seed {}: {:?}: {}", seed, command, e));
+            covered.insert(opcode_info(bytes[0]).unwrap().name);
+
+            let parsed = Commands::from_bytes(&mut Bytes::from(bytes)).unwrap();
+            assert_eq!(parsed, command, "seed {}", seed);
+        });
+
+        // Every entry of the opcode table is generated
+        let all: BTreeSet<_> = opcode_table().iter().map(|info| info.name).collect();
+        assert_eq!(covered, all);
+    }
+
+    #[test]
+    fn test_header_round_trip() {
+        for_each_generated(2_000, 256, |seed, header: HeaderData| {
+            let mut buffer = BytesMut::new();
+            header.to_bytes(&mut buffer).unwrap();
+            let parsed = HeaderData::from_bytes(&mut buffer.freeze()).unwrap();
+
+            assert_eq!(parsed, header, "seed {}", seed);
+        });
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        for_each_generated(2_000, 256, |seed, metadata: VgmMetadata| {
+            let mut buffer = BytesMut::new();
+            metadata.to_bytes(&mut buffer).unwrap();
+            assert_eq!(VgmMetadata::from_bytes(&mut buffer.freeze()).unwrap(), metadata, "seed {}", seed);
+        });
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        for_each_generated(500, 2048, |seed, file: VgmFile| {
+            let written = file.to_bytes_normalized().unwrap();
+            let parsed = VgmFile::from_bytes(&mut Bytes::from(written.clone())).unwrap();
+
+            assert_eq!(parsed.commands, file.commands, "seed {}", seed);
+            assert_eq!(parsed.metadata, file.metadata, "seed {}", seed);
+            assert_eq!(parsed.header.vgm_data_start().unwrap(), file.header.vgm_data_start().unwrap());
+
+            // An unmodified file writes back byte for byte
+            let mut buffer = BytesMut::new();
+            parsed.to_bytes(&mut buffer).unwrap();
+            assert_eq!(buffer, written, "seed {}", seed);
+            assert_eq!(VgmFile::from_bytes(&mut buffer.freeze()).unwrap(), parsed, "seed {}", seed);
+        });
+    }
+
+    #[test]
+    fn test_fuzz_vgm_file() {
+        let mut rng = SplitMix64(0x5647_4D20);
+        for seed in 0..500 {
+            let input = SplitMix64(seed).bytes(1024);
+            let file: VgmFile = Unstructured::new(&input).arbitrary();
+            let mut bytes = file.to_bytes_normalized().unwrap();
+
+            // Corrupt a valid file: flip bytes, then maybe cut it short
+            for _ in 0..rng.next() % 8 {
+                let index = rng.next() as usize % bytes.len();
+                bytes[index] = rng.next() as u8;
+            }
+            if rng.next().is_multiple_of(4) {
+                bytes.truncate(rng.next() as usize % bytes.len());
+            }
+
+            fuzz_vgm_file(&bytes);
+        }
+
+        for seed in 0..200 {
+            fuzz_vgm_file(&SplitMix64(seed).bytes(seed as usize));
+        }
+    }
+
+    #[test]
+    fn test_fuzz_decompress_data() {
+        for seed in 0..2_000 {
+            let mut rng = SplitMix64(seed);
+            let len = rng.next() as usize % 512;
+            fuzz_decompress_data(&rng.bytes(len));
+        }
+
+        // Widths that used to loop forever or overflow
+        for (bits_compressed, bits_decompressed, sub_type) in [(0, 8, 0), (8, 0, 0), (8, 4, 1), (4, 40, 1)] {
+            let stream = DataBlockContent::CompressedStream {
+                chip_type: StreamChipType::YM2612,
+                compression: CompressionType::BitPacking { bits_decompressed, bits_compressed, sub_type, add_value: 0 },
+                uncompressed_size: u32::MAX,
+                data: vec![0xAA; 16],
+            };
+            assert!(stream.decompress_data(None).is_err());
+        }
+    }
+}
@@ -5,31 +5,230 @@ use crate::{
     errors::{VgmError, VgmResult},
     traits::{VgmParser, VgmWriter},
     utils::{bcd_from_bytes, decimal_to_bcd},
+    ChipInstance, System,
 };
 
-#[derive(Default, Serialize, Deserialize, Debug)]
+/// Clock bit marking that two instances of the chip are used
+pub const DUAL_CHIP_FLAG: u32 = 0x4000_0000;
+/// Clock bit selecting a chip variant (YM2610B, K052539, ES5506, T6W28...)
+pub const CHIP_VARIANT_FLAG: u32 = 0x8000_0000;
+/// Bits of a clock field holding the frequency in Hz
+pub const CHIP_CLOCK_MASK: u32 = 0x3FFF_FFFF;
+
+/// End of the last header field the spec defines (1.72)
+const HEADER_END: usize = 0xE8;
+
+/// Positions of the reserved header bytes stored in [`HeaderData::reserved`]
+pub const RESERVED_HEADER_OFFSETS: [usize; 3] = [0x7D, 0x97, 0xD7];
+
+/// Size of the extra header's own fields: header size and both table offsets
+const EXTRA_HEADER_SIZE: u32 = 0x0C;
+
+/// Bit 15 of a chip volume, set when the volume scales the chip's default
+const RELATIVE_VOLUME_FLAG: u16 = 0x8000;
+/// Largest volume a chip volume entry holds, 0x100 being 100%
+pub const MAX_CHIP_VOLUME: u16 = 0x7FFF;
+
+/// Clock override of the extra header
+#[derive(Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
 pub struct ChipClockEntry {
+    /// Chip ID, bit 7 selecting the second chip
     pub chip_id: u8,
     pub clock: u32,
 }
 
-#[derive(Default, Serialize, Deserialize, Debug)]
+impl ChipClockEntry {
+    /// Chip the entry overrides, `None` for unknown chip IDs
+    pub fn system(&self) -> Option<System> {
+        System::from_chip_id(self.chip_id)
+    }
+
+    /// 0 for the first chip, 1 for the second chip
+    pub fn chip_index(&self) -> u8 {
+        self.chip_id >> 7
+    }
+}
+
+/// Volume override of the extra header
+#[derive(Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
 pub struct ChipVolumeEntry {
+    /// Chip ID, bit 7 selecting the paired chip (the SSG of a YM2203, YM2608
+    /// or YM2610)
     pub chip_id: u8,
+    /// Bit 0 selects the second chip
     pub flags: u8,
+    /// Bits 0-14 are the volume, bit 15 is set for a relative volume
+    pub volume: u16,
+}
+
+impl ChipVolumeEntry {
+    /// Chip the entry applies to, `None` for unknown chip IDs
+    pub fn system(&self) -> Option<System> {
+        System::from_chip_id(self.chip_id)
+    }
+
+    /// 0 for the first chip, 1 for the second chip
+    pub fn chip_index(&self) -> u8 {
+        self.flags & 0x01
+    }
+
+    /// Whether the volume applies to the chip's paired SSG part
+    pub fn is_paired(&self) -> bool {
+        self.chip_id & 0x80 != 0
+    }
+
+    /// Volume and how it applies
+    pub fn chip_volume(&self) -> ChipVolume {
+        let mode = if self.volume & RELATIVE_VOLUME_FLAG != 0 { VolumeMode::Relative } else { VolumeMode::Absolute };
+        ChipVolume { volume: self.volume & MAX_CHIP_VOLUME, mode }
+    }
+}
+
+/// How a chip volume override applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum VolumeMode {
+    /// Replaces the player's default volume for the chip
+    Absolute,
+    /// Scales the player's default volume for the chip
+    Relative,
+}
+
+/// Decoded chip volume override, 0x100 meaning 100%
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ChipVolume {
     pub volume: u16,
+    pub mode: VolumeMode,
 }
 
-#[derive(Default, Serialize, Deserialize, Debug)]
+/// Chip clock and volume overrides added in 1.70.
+///
+/// The offsets are kept as read so unmodified files write back unchanged.
+/// After editing the entries, [`ExtraHeaderData::normalize`] recomputes them;
+/// [`crate::VgmFile::to_bytes_normalized`] does so itself.
+#[derive(Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
 pub struct ExtraHeaderData {
     pub header_size: u32,
     pub chip_clock_offset: u32,
     pub chip_vol_offset: u32,
     pub chip_clock_entries: Vec<ChipClockEntry>,
     pub chip_volume_entries: Vec<ChipVolumeEntry>,
+    /// Bytes between the last header field and an extra header placed after
+    /// it, empty when they're all zero
+    pub leading_bytes: Vec<u8>,
 }
 
-#[derive(Default, Serialize, Deserialize, Debug)]
+impl ExtraHeaderData {
+    /// Whether the extra header overrides nothing
+    pub fn is_empty(&self) -> bool {
+        self.chip_clock_entries.is_empty() && self.chip_volume_entries.is_empty()
+    }
+
+    /// Clock override of chip `index` of `system`
+    pub fn chip_clock_override(&self, system: &System, index: u8) -> Option<u32> {
+        self.chip_clock_entries
+            .iter()
+            .find(|entry| entry.chip_id == clock_chip_id(system, index))
+            .map(|entry| entry.clock)
+    }
+
+    /// Override the clock of chip `index` of `system`, replacing any previous override
+    pub fn set_chip_clock_override(&mut self, system: &System, index: u8, clock: u32) -> VgmResult<()> {
+        check_chip_index(system, index)?;
+        let chip_id = clock_chip_id(system, index);
+        match self.chip_clock_entries.iter_mut().find(|entry| entry.chip_id == chip_id) {
+            Some(entry) => entry.clock = clock,
+            None => self.chip_clock_entries.push(ChipClockEntry { chip_id, clock }),
+        }
+        Ok(())
+    }
+
+    /// Drop the clock override of chip `index` of `system`
+    pub fn remove_chip_clock_override(&mut self, system: &System, index: u8) {
+        let chip_id = clock_chip_id(system, index);
+        self.chip_clock_entries.retain(|entry| entry.chip_id != chip_id);
+    }
+
+    /// Volume override of chip `index` of `system`, not counting paired chips
+    pub fn chip_volume(&self, system: &System, index: u8) -> Option<ChipVolume> {
+        self.find_chip_volume(system, index).map(|position| self.chip_volume_entries[position].chip_volume())
+    }
+
+    /// Override the volume of chip `index` of `system`, replacing any previous
+    /// override. `volume` is at most [`MAX_CHIP_VOLUME`], 0x100 being 100%
+    pub fn set_chip_volume(&mut self, system: &System, index: u8, volume: u16, mode: VolumeMode) -> VgmResult<()> {
+        check_chip_index(system, index)?;
+        if volume > MAX_CHIP_VOLUME {
+            return Err(VgmError::InvalidDataFormat {
+                field: "volume".to_string(),
+                details: format!("{:?} volume 0x{:X} exceeds 0x{:X}", system, volume, MAX_CHIP_VOLUME),
+            });
+        }
+
+        let volume = match mode {
+            VolumeMode::Absolute => volume,
+            VolumeMode::Relative => volume | RELATIVE_VOLUME_FLAG,
+        };
+        match self.find_chip_volume(system, index) {
+            Some(position) => self.chip_volume_entries[position].volume = volume,
+            None => self.chip_volume_entries.push(ChipVolumeEntry { chip_id: system.chip_id(), flags: index, volume }),
+        }
+        Ok(())
+    }
+
+    /// Drop the volume override of chip `index` of `system`
+    pub fn remove_chip_volume(&mut self, system: &System, index: u8) {
+        if let Some(position) = self.find_chip_volume(system, index) {
+            self.chip_volume_entries.remove(position);
+        }
+    }
+
+    fn find_chip_volume(&self, system: &System, index: u8) -> Option<usize> {
+        self.chip_volume_entries
+            .iter()
+            .position(|entry| entry.chip_id == system.chip_id() && entry.chip_index() == index)
+    }
+
+    /// Recompute the header size and table offsets for the entries: clock
+    /// table first, volume table right after it, each left at 0 when empty.
+    /// The leading bytes are dropped, as the extra header then directly
+    /// follows the header fields.
+    pub fn normalize(&mut self) {
+        self.leading_bytes.clear();
+        let clock_table = if self.chip_clock_entries.is_empty() { 0 } else { 1 + 5 * self.chip_clock_entries.len() as u32 };
+        self.header_size = EXTRA_HEADER_SIZE;
+        self.chip_clock_offset = if clock_table == 0 { 0 } else { EXTRA_HEADER_SIZE - 4 };
+        self.chip_vol_offset = if self.chip_volume_entries.is_empty() { 0 } else { EXTRA_HEADER_SIZE - 8 + clock_table };
+    }
+
+    /// Number of bytes the extra header takes once normalized
+    pub fn encoded_size(&self) -> usize {
+        let mut size = EXTRA_HEADER_SIZE as usize;
+        if !self.chip_clock_entries.is_empty() {
+            size += 1 + 5 * self.chip_clock_entries.len();
+        }
+        if !self.chip_volume_entries.is_empty() {
+            size += 1 + 4 * self.chip_volume_entries.len();
+        }
+        size
+    }
+}
+
+/// Chip ID of a clock entry, bit 7 selecting the second chip
+fn clock_chip_id(system: &System, index: u8) -> u8 {
+    system.chip_id() | (index << 7)
+}
+
+fn check_chip_index(system: &System, index: u8) -> VgmResult<()> {
+    if index > 1 {
+        return Err(VgmError::InvalidDataFormat {
+            field: "index".to_string(),
+            details: format!("{:?} chip index {} exceeds the two chips VGM supports", system, index),
+        });
+    }
+    Ok(())
+}
+
+#[derive(Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
 pub struct HeaderData {
     pub end_of_file_offset: u32,
     pub version: u32,
@@ -126,6 +325,10 @@ pub struct HeaderData {
 
     // 0xE0
     pub ga20_clock: u32,
+    pub mikey_clock: u32,
+
+    /// Reserved bytes at 0x7D, 0x97 and 0xD7, kept so files write back unchanged
+    pub reserved: [u8; 3],
 
     // TODO: extra headers
     /// With VGM v1.70, there was an extra header added. This one has to be placed between the usual header and the actual VGM data.
@@ -133,12 +336,268 @@ pub struct HeaderData {
 }
 
 impl HeaderData {
+    /// Absolute file position of the first VGM command.
+    ///
+    /// Files older than 1.50 leave `vgm_data_offset` at 0, in which case the
+    /// data always starts at 0x40.
+    pub fn vgm_data_start(&self) -> VgmResult<usize> {
+        if self.vgm_data_offset == 0 {
+            return Ok(0x40);
+        }
+
+        // Security: Prevent integer overflow in VGM data position calculation
+        self.vgm_data_offset
+            .checked_add(0x34)
+            .and_then(|v| usize::try_from(v).ok())
+            .ok_or(VgmError::IntegerOverflow {
+                operation: "VGM data position calculation".to_string(),
+                details: format!("vgm_data_offset {} + 0x34", self.vgm_data_offset),
+            })
+    }
+
+    /// Absolute file position of the loop point, or `None` if the file doesn't loop
+    pub fn loop_start(&self) -> Option<usize> {
+        if self.loop_offset == 0 {
+            return None;
+        }
+        (self.loop_offset as usize).checked_add(0x1C)
+    }
+
+    /// Number of times the looped section plays when a player asks for
+    /// `program_loops`, after applying the loop modifier and loop base.
+    ///
+    /// `NumLoops = ProgramNumLoops * LoopModifier / 0x10 - LoopBase`, where a
+    /// modifier of 0 means 0x10 and the base is signed. Never less than 1.
+    pub fn effective_loop_count(&self, program_loops: u32) -> u32 {
+        let modifier = if self.loop_modifier == 0 { 0x10 } else { self.loop_modifier as u64 };
+        let scaled = (program_loops as u64 * modifier + 0x08) / 0x10;
+        let loops = scaled as i64 - self.loop_base as i8 as i64;
+        loops.clamp(1, u32::MAX as i64) as u32
+    }
+
+    /// Raw clock field of `system`, flag bits included.
+    ///
+    /// Variants sharing a field with their base chip (T6W28, YM2610B, K052539,
+    /// ES5506) return that field.
+    pub fn chip_clock(&self, system: &System) -> u32 {
+        match system {
+            System::SN76489 | System::T6W28 => self.sn76489_clock,
+            System::YM2413 => self.ym2413_clock,
+            System::YM2612 => self.ym2612_clock,
+            System::YM2151 => self.ym2151_clock,
+            System::SegaPcm => self.sega_pcm_clock,
+            System::RF5C68 => self.rf5_c68_clock,
+            System::YM2203 => self.ym2203_clock,
+            System::YM2608 => self.ym2608_clock,
+            System::YM2610 | System::YM2610B => self.ym2610_b_clock,
+            System::YM3812 => self.ym3812_clock,
+            System::YM3526 => self.ym3526_clock,
+            System::Y8950 => self.y8950_clock,
+            System::YMF262 => self.ymf262_clock,
+            System::YMF278B => self.ymf278_b_clock,
+            System::YMF271 => self.ymf271_clock,
+            System::YMZ280B => self.ymz280_b_clock,
+            System::RF5C164 => self.rf5_c164_clock,
+            System::Pwm => self.pwm_clock,
+            System::AY8910 => self.ay8910_clock,
+            System::GameboyDmg => self.gb_dmg_clock,
+            System::NesApu => self.nes_apu_clock,
+            System::MultiPcm => self.multi_pcm_clock,
+            System::UPD7759 => self.u_pd7759_clock,
+            System::OKIM6258 => self.okim6258_clock,
+            System::K054539 => self.k054539_clock,
+            System::C140 => self.c140_clock,
+            System::OKIM6295 => self.okim6295_clock,
+            System::K051649 | System::K052539 => self.k051649_k052539_clock,
+            System::HuC6280 => self.hu_c6280_clock,
+            System::K053260 => self.k053260_clock,
+            System::Pokey => self.pokey_clock,
+            System::QSound => self.qsound_clock,
+            System::SCSP => self.scsp_clock,
+            System::WonderSwan => self.wonder_swan_clock,
+            System::VSU => self.vsu_clock,
+            System::SAA1099 => self.saa1099_clock,
+            System::ES5503 => self.es5503_clock,
+            System::ES5505 | System::ES5506 => self.es5506_clock,
+            System::C352 => self.c352_clock,
+            System::X1_010 => self.x1010_clock,
+            System::GA20 => self.ga20_clock,
+            System::Mikey => self.mikey_clock,
+        }
+    }
+
+    /// Mutable clock field of `system`, see [`HeaderData::chip_clock`]
+    fn chip_clock_mut(&mut self, system: &System) -> &mut u32 {
+        match system {
+            System::SN76489 | System::T6W28 => &mut self.sn76489_clock,
+            System::YM2413 => &mut self.ym2413_clock,
+            System::YM2612 => &mut self.ym2612_clock,
+            System::YM2151 => &mut self.ym2151_clock,
+            System::SegaPcm => &mut self.sega_pcm_clock,
+            System::RF5C68 => &mut self.rf5_c68_clock,
+            System::YM2203 => &mut self.ym2203_clock,
+            System::YM2608 => &mut self.ym2608_clock,
+            System::YM2610 | System::YM2610B => &mut self.ym2610_b_clock,
+            System::YM3812 => &mut self.ym3812_clock,
+            System::YM3526 => &mut self.ym3526_clock,
+            System::Y8950 => &mut self.y8950_clock,
+            System::YMF262 => &mut self.ymf262_clock,
+            System::YMF278B => &mut self.ymf278_b_clock,
+            System::YMF271 => &mut self.ymf271_clock,
+            System::YMZ280B => &mut self.ymz280_b_clock,
+            System::RF5C164 => &mut self.rf5_c164_clock,
+            System::Pwm => &mut self.pwm_clock,
+            System::AY8910 => &mut self.ay8910_clock,
+            System::GameboyDmg => &mut self.gb_dmg_clock,
+            System::NesApu => &mut self.nes_apu_clock,
+            System::MultiPcm => &mut self.multi_pcm_clock,
+            System::UPD7759 => &mut self.u_pd7759_clock,
+            System::OKIM6258 => &mut self.okim6258_clock,
+            System::K054539 => &mut self.k054539_clock,
+            System::C140 => &mut self.c140_clock,
+            System::OKIM6295 => &mut self.okim6295_clock,
+            System::K051649 | System::K052539 => &mut self.k051649_k052539_clock,
+            System::HuC6280 => &mut self.hu_c6280_clock,
+            System::K053260 => &mut self.k053260_clock,
+            System::Pokey => &mut self.pokey_clock,
+            System::QSound => &mut self.qsound_clock,
+            System::SCSP => &mut self.scsp_clock,
+            System::WonderSwan => &mut self.wonder_swan_clock,
+            System::VSU => &mut self.vsu_clock,
+            System::SAA1099 => &mut self.saa1099_clock,
+            System::ES5503 => &mut self.es5503_clock,
+            System::ES5505 | System::ES5506 => &mut self.es5506_clock,
+            System::C352 => &mut self.c352_clock,
+            System::X1_010 => &mut self.x1010_clock,
+            System::GA20 => &mut self.ga20_clock,
+            System::Mikey => &mut self.mikey_clock,
+        }
+    }
+
+    /// Chips the header enables, in chip ID order, with the variant and
+    /// dual-chip bits resolved
+    pub fn chips(&self) -> Vec<ChipInstance> {
+        let mut chips = Vec::new();
+        for system in (0..=u8::MAX).map_while(System::from_chip_id) {
+            let clock = self.chip_clock(&system);
+            let clock_hz = clock & CHIP_CLOCK_MASK;
+            if clock_hz == 0 {
+                continue;
+            }
+
+            let variant_flag = clock & CHIP_VARIANT_FLAG != 0;
+            let dual = clock & DUAL_CHIP_FLAG != 0;
+            let system = match system.variant() {
+                // Bit 31 only means T6W28 together with the dual chip bit
+                Some(System::T6W28) if !dual => system,
+                Some(variant) if variant_flag => variant,
+                _ => system,
+            };
+            chips.push(ChipInstance { system, clock_hz, index: 0, variant_flag });
+            if dual {
+                chips.push(ChipInstance { system, clock_hz, index: 1, variant_flag });
+            }
+        }
+        chips
+    }
+
+    /// Enable `system` at `clock_hz`, setting the variant bit for variants
+    /// and the dual-chip bit when `dual` is set
+    pub fn set_chip(&mut self, system: &System, clock_hz: u32, dual: bool) -> VgmResult<()> {
+        if clock_hz == 0 || clock_hz > CHIP_CLOCK_MASK {
+            return Err(VgmError::InvalidDataFormat {
+                field: "clock_hz".to_string(),
+                details: format!("{:?} clock {} Hz must be between 1 and {}", system, clock_hz, CHIP_CLOCK_MASK),
+            });
+        }
+
+        if *system == System::T6W28 && !dual {
+            return Err(VgmError::InvalidDataFormat {
+                field: "dual".to_string(),
+                details: "A T6W28 is selected by bit 31 together with the dual chip bit".to_string(),
+            });
+        }
+
+        let mut clock = clock_hz;
+        if system.is_variant() {
+            clock |= CHIP_VARIANT_FLAG;
+        }
+        if dual {
+            clock |= DUAL_CHIP_FLAG;
+        }
+        *self.chip_clock_mut(system) = clock;
+        Ok(())
+    }
+
+    /// Disable `system`, along with any variant sharing its clock field
+    pub fn remove_chip(&mut self, system: &System) {
+        *self.chip_clock_mut(system) = 0;
+    }
+
+    /// Replace every chip clock with the given chips, the inverse of
+    /// [`HeaderData::chips`]
+    pub fn set_chips(&mut self, chips: &[ChipInstance]) -> VgmResult<()> {
+        let mut resolved: Vec<(System, u32, bool, bool)> = Vec::new();
+        for chip in chips {
+            if chip.index > 1 {
+                return Err(VgmError::InvalidDataFormat {
+                    field: "index".to_string(),
+                    details: format!("{:?} chip index {} exceeds the two chips VGM supports", chip.system, chip.index),
+                });
+            }
+
+            match resolved.iter_mut().find(|(system, ..)| system.base() == chip.system.base()) {
+                Some((system, clock_hz, dual, variant_flag)) => {
+                    if *system != chip.system || *clock_hz != chip.clock_hz || *variant_flag != chip.variant_flag {
+                        return Err(VgmError::InconsistentData {
+                            context: "Chip configuration".to_string(),
+                            reason: format!(
+                                "{:?} at {} Hz and {:?} at {} Hz share a clock field",
+                                system, clock_hz, chip.system, chip.clock_hz
+                            ),
+                        });
+                    }
+                    *dual |= chip.index == 1;
+                },
+                None => resolved.push((chip.system, chip.clock_hz, chip.index == 1, chip.variant_flag)),
+            }
+        }
+
+        for system in (0..=u8::MAX).map_while(System::from_chip_id) {
+            self.remove_chip(&system);
+        }
+        for (system, clock_hz, dual, variant_flag) in resolved {
+            self.set_chip(&system, clock_hz, dual)?;
+            if variant_flag {
+                *self.chip_clock_mut(&system) |= CHIP_VARIANT_FLAG;
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of `system` chips the header enables: 0 without a clock, 2 when
+    /// the dual chip bit is set, 1 otherwise
+    pub fn chip_count(&self, system: &System) -> u8 {
+        let clock = self.chip_clock(system);
+        if clock & CHIP_CLOCK_MASK == 0 {
+            0
+        } else if clock & DUAL_CHIP_FLAG != 0 {
+            2
+        } else {
+            1
+        }
+    }
+
     /// Parse VGM header with resource limits and allocation tracking
     pub fn from_bytes_with_config(data: &mut Bytes, config: &crate::ParserConfig, tracker: &mut crate::ResourceTracker) -> VgmResult<Self> {
         // Enter parsing context for depth tracking
         tracker.enter_parsing_context(config)?;
         
-        let result = Self::from_bytes_internal_with_config(data, config, tracker);
+        let len_data = data.len();
+        let result = Self::from_bytes_internal_with_config(data, config, tracker).and_then(|mut header| {
+            header.read_detached_extra_header(data, len_data, Some(config))?;
+            Ok(header)
+        });
         
         // Exit parsing context regardless of success/failure
         tracker.exit_parsing_context();
@@ -150,6 +609,9 @@ impl HeaderData {
         let mut header = HeaderData::default();
         // get length of data for position calculation
         let len_data = data.len();
+        if len_data < 0x40 {
+            return Err(VgmError::TruncatedFile { expected: 0x40, actual: len_data });
+        }
 
         // validate magic
         let magic = data.get_u32();
@@ -188,204 +650,199 @@ impl HeaderData {
         header.sega_pcm_clock = data.get_u32_le();
         header.spcm_interface = data.get_u32_le();
 
-        // Security: Prevent integer overflow in VGM data position calculation
-        let pos_start_vgm = header.vgm_data_offset
-            .checked_add(0x34)
-            .ok_or(VgmError::IntegerOverflow {
-                operation: "VGM data position calculation".to_string(),
-                details: format!("vgm_data_offset {} + 0x34", header.vgm_data_offset),
-            })?;
-        
-        // Security: Convert pos_start_vgm to usize safely
-        let pos_start_vgm_usize = usize::try_from(pos_start_vgm)
-            .map_err(|_| VgmError::IntegerOverflow {
-                operation: "VGM position usize conversion".to_string(),
-                details: format!("pos_start_vgm {} cannot fit in usize", pos_start_vgm),
-            })?;
+        // Resolve where the VGM data starts (0x40 for pre-1.50 files)
+        let pos_start_vgm_usize = header.vgm_data_start()?;
+
+        // Fields are read up to the VGM data, finishing a field it cuts into,
+        // or the last defined field
+        let header_end = pos_start_vgm_usize.next_multiple_of(4).min(HEADER_END);
+        if len_data < header_end {
+            return Err(VgmError::TruncatedFile { expected: header_end, actual: len_data });
+        }
 
         // 0x40
         // From here, need to check if is still header, or start of vgm data
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.rf5_c68_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ym2203_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ym2608_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ym2610_b_clock = data.get_u32_le();
 
         // 0x50
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ym3812_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ym3526_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.y8950_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ymf262_clock = data.get_u32_le();
 
         // 0x60
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ymf278_b_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ymf271_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ymz280_b_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.rf5_c164_clock = data.get_u32_le();
 
         // 0x70
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.pwm_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ay8910_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ay8910_chip_type = data.get_u8();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ay8910_flags = data.get_u8();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ym2203_ay8910_flags = data.get_u8();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ym2608_ay8910_flags = data.get_u8();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.volume_modifier = data.get_u8();
 
-        // skip reserved
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        // reserved
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
-        data.get_u8();
+        header.reserved[0] = data.get_u8();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.loop_base = data.get_u8();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.loop_modifier = data.get_u8();
 
         // 0x80
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.gb_dmg_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.nes_apu_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.multi_pcm_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.u_pd7759_clock = data.get_u32_le();
 
         // 0x90
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.okim6258_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.okim6258_flags = data.get_u8();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.k054539_flags = data.get_u8();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.c140_chip_type = data.get_u8();
 
-        // skip reserved
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        // reserved
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
-        data.get_u8();
+        header.reserved[1] = data.get_u8();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.okim6295_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.k051649_k052539_clock = data.get_u32_le();
 
         // 0xA0
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.k054539_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.hu_c6280_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.c140_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.k053260_clock = data.get_u32_le();
 
         // 0xB0
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.pokey_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.qsound_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.scsp_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.extra_header_offset = data.get_u32_le();
@@ -406,7 +863,7 @@ impl HeaderData {
         // 0xC0
         // from here need to also check for extra header data
         // can assume that after extra header is vgm data?
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -416,7 +873,7 @@ impl HeaderData {
         }
         header.wonder_swan_clock = data.get_u32_le();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -426,7 +883,7 @@ impl HeaderData {
         }
         header.vsu_clock = data.get_u32_le();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -436,7 +893,7 @@ impl HeaderData {
         }
         header.saa1099_clock = data.get_u32_le();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -447,7 +904,7 @@ impl HeaderData {
         header.es5503_clock = data.get_u32_le();
 
         // 0xD0
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -457,7 +914,7 @@ impl HeaderData {
         }
         header.es5506_clock = data.get_u32_le();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -467,7 +924,7 @@ impl HeaderData {
         }
         header.es5503_nb_channels = data.get_u8();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -477,7 +934,7 @@ impl HeaderData {
         }
         header.es5505_es5506_nb_channels = data.get_u8();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -487,8 +944,8 @@ impl HeaderData {
         }
         header.c352_clock_divider = data.get_u8();
 
-        // skip reserved
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        // reserved
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -496,9 +953,9 @@ impl HeaderData {
                 return Ok(header);
             }
         }
-        data.get_u8();
+        header.reserved[2] = data.get_u8();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -508,7 +965,7 @@ impl HeaderData {
         }
         header.x1010_clock = data.get_u32_le();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -519,7 +976,7 @@ impl HeaderData {
         header.c352_clock = data.get_u32_le();
 
         // 0xE0
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -529,18 +986,36 @@ impl HeaderData {
         }
         header.ga20_clock = data.get_u32_le();
 
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
+            return Ok(header);
+        } else if let Some(pos_extra_header) = pos_extra_header {
+            if (len_data - data.remaining()) == pos_extra_header {
+                header.parse_extra_header_with_config(data, pos_extra_header, config)?;
+                return Ok(header);
+            }
+        }
+        header.mikey_clock = data.get_u32_le();
+
         Ok(header)
     }
     
     fn parse_extra_header_with_config(&mut self, data: &mut Bytes, extra_header_pos: usize, config: &crate::ParserConfig) -> VgmResult<()> {
         // use this to track pos in the extra header?
         let remaining_bytes = data.remaining();
+        if remaining_bytes < 12 {
+            return Err(VgmError::BufferUnderflow {
+                offset: extra_header_pos,
+                needed: 12,
+                available: remaining_bytes,
+            });
+        }
 
-        let mut extra_header = ExtraHeaderData::default();
-
-        extra_header.header_size = data.get_u32_le();
-        extra_header.chip_clock_offset = data.get_u32_le();
-        extra_header.chip_vol_offset = data.get_u32_le();
+        let mut extra_header = ExtraHeaderData {
+            header_size: data.get_u32_le(),
+            chip_clock_offset: data.get_u32_le(),
+            chip_vol_offset: data.get_u32_le(),
+            ..Default::default()
+        };
 
         // should be options, no guarantee that both are set
         let chip_clock_pos = if extra_header.chip_clock_offset == 0 {
@@ -577,7 +1052,7 @@ impl HeaderData {
             let curr_pos = extra_header_pos + remaining_bytes - data.remaining();
             if let Some(chip_clock_pos) = chip_clock_pos {
                 if chip_clock_pos == curr_pos {
-                    let nb_entries = data.get_u8();
+                    let nb_entries = read_entry_count(data, curr_pos, 5)?;
                     
                     // Security: Check chip clock entry count against config limits
                     config.check_chip_entries(nb_entries, 0)?;
@@ -595,7 +1070,7 @@ impl HeaderData {
 
             if let Some(chip_vol_pos) = chip_vol_pos {
                 if chip_vol_pos == curr_pos {
-                    let nb_entries = data.get_u8();
+                    let nb_entries = read_entry_count(data, curr_pos, 4)?;
                     
                     // Security: Check chip volume entry count against config limits  
                     config.check_chip_entries(0, nb_entries)?;
@@ -623,12 +1098,20 @@ impl HeaderData {
     fn parse_extra_header(&mut self, data: &mut Bytes, extra_header_pos: usize) -> VgmResult<()> {
         // use this to track pos in the extra header?
         let remaining_bytes = data.remaining();
+        if remaining_bytes < 12 {
+            return Err(VgmError::BufferUnderflow {
+                offset: extra_header_pos,
+                needed: 12,
+                available: remaining_bytes,
+            });
+        }
 
-        let mut extra_header = ExtraHeaderData::default();
-
-        extra_header.header_size = data.get_u32_le();
-        extra_header.chip_clock_offset = data.get_u32_le();
-        extra_header.chip_vol_offset = data.get_u32_le();
+        let mut extra_header = ExtraHeaderData {
+            header_size: data.get_u32_le(),
+            chip_clock_offset: data.get_u32_le(),
+            chip_vol_offset: data.get_u32_le(),
+            ..Default::default()
+        };
 
         // should be options, no guarantee that both are set
         let chip_clock_pos = if extra_header.chip_clock_offset == 0 {
@@ -665,7 +1148,7 @@ impl HeaderData {
             let curr_pos = extra_header_pos + remaining_bytes - data.remaining();
             if let Some(chip_clock_pos) = chip_clock_pos {
                 if chip_clock_pos == curr_pos {
-                    let nb_entries = data.get_u8();
+                    let nb_entries = read_entry_count(data, curr_pos, 5)?;
                     for _i in 0..nb_entries {
                         let curr_entry = ChipClockEntry {
                             chip_id: data.get_u8(),
@@ -679,7 +1162,7 @@ impl HeaderData {
 
             if let Some(chip_vol_pos) = chip_vol_pos {
                 if chip_vol_pos == curr_pos {
-                    let nb_entries = data.get_u8();
+                    let nb_entries = read_entry_count(data, curr_pos, 4)?;
                     for _i in 0..nb_entries {
                         let curr_entry = ChipVolumeEntry {
                             chip_id: data.get_u8(),
@@ -700,7 +1183,7 @@ impl HeaderData {
         Ok(())
     }
 
-    fn write_extra_header(&self, buffer: &mut BytesMut, vgm_data_pos: usize) {
+    pub(crate) fn write_extra_header(&self, buffer: &mut BytesMut) {
         // write header
         buffer.put(&self.extra_header.header_size.to_le_bytes()[..]);
         buffer.put(&self.extra_header.chip_clock_offset.to_le_bytes()[..]);
@@ -768,21 +1251,77 @@ impl HeaderData {
                 }
             }
         }
+    }
+}
+
+impl HeaderData {
+    /// Read an extra header placed after the header fields, as 1.70+ files
+    /// with a 0x100 byte header do, leaving `data` right after it.
+    ///
+    /// Extra headers already read among the fields, or pointing outside the
+    /// space before the VGM data, are left alone.
+    fn read_detached_extra_header(&mut self, data: &mut Bytes, len_data: usize, config: Option<&crate::ParserConfig>) -> VgmResult<()> {
+        if self.extra_header_offset == 0 {
+            return Ok(());
+        }
+        let position = len_data - data.remaining();
+        let Some(extra_header_pos) = (self.extra_header_offset as usize).checked_add(0xBC) else {
+            return Ok(());
+        };
+        if extra_header_pos < position || extra_header_pos >= self.vgm_data_start()?.min(len_data) {
+            return Ok(());
+        }
 
-        // pad until start of VGM?
-        while buffer.len() < vgm_data_pos {
-            buffer.put(&[0x00][..]);
+        let leading_bytes = data.split_to(extra_header_pos - position);
+        match config {
+            Some(config) => self.parse_extra_header_with_config(data, extra_header_pos, config)?,
+            None => self.parse_extra_header(data, extra_header_pos)?,
         }
+        if leading_bytes.iter().any(|&byte| byte != 0) {
+            self.extra_header.leading_bytes = leading_bytes.to_vec();
+        }
+        Ok(())
     }
 }
 
+/// Read the entry count at `position` of an extra header table, checking
+/// that all entries of `entry_size` bytes follow it
+fn read_entry_count(data: &mut Bytes, position: usize, entry_size: usize) -> VgmResult<u8> {
+    if !data.has_remaining() {
+        return Err(VgmError::BufferUnderflow { offset: position, needed: 1, available: 0 });
+    }
+    let count = data.get_u8();
+    let needed = count as usize * entry_size;
+    if data.remaining() < needed {
+        return Err(VgmError::BufferUnderflow {
+            offset: position + 1,
+            needed,
+            available: data.remaining(),
+        });
+    }
+    Ok(count)
+}
+
 impl VgmParser for HeaderData {
     /// Read header data
     /// From 1.5 onwards, any length of header is valid as long as it is at least 64 bytes long
     fn from_bytes(data: &mut Bytes) -> VgmResult<Self> {
+        let len_data = data.len();
+        let mut header = Self::read_fields(data)?;
+        header.read_detached_extra_header(data, len_data, None)?;
+        Ok(header)
+    }
+}
+
+impl HeaderData {
+    /// Read the header fields, and an extra header placed among them
+    fn read_fields(data: &mut Bytes) -> VgmResult<Self> {
         let mut header = HeaderData::default();
         // get length of data for position calculation
         let len_data = data.len();
+        if len_data < 0x40 {
+            return Err(VgmError::TruncatedFile { expected: 0x40, actual: len_data });
+        }
 
         // validate magic
         let magic = data.get_u32();
@@ -821,204 +1360,196 @@ impl VgmParser for HeaderData {
         header.sega_pcm_clock = data.get_u32_le();
         header.spcm_interface = data.get_u32_le();
 
-        // Security: Prevent integer overflow in VGM data position calculation
-        let pos_start_vgm = header.vgm_data_offset
-            .checked_add(0x34)
-            .ok_or(VgmError::IntegerOverflow {
-                operation: "VGM data position calculation".to_string(),
-                details: format!("vgm_data_offset {} + 0x34", header.vgm_data_offset),
-            })?;
-        
-        // Security: Convert pos_start_vgm to usize safely
-        let pos_start_vgm_usize = usize::try_from(pos_start_vgm)
-            .map_err(|_| VgmError::IntegerOverflow {
-                operation: "VGM position usize conversion".to_string(),
-                details: format!("pos_start_vgm {} cannot fit in usize", pos_start_vgm),
-            })?;
+        // Resolve where the VGM data starts (0x40 for pre-1.50 files)
+        let pos_start_vgm_usize = header.vgm_data_start()?;
+        let header_end = pos_start_vgm_usize.next_multiple_of(4).min(HEADER_END);
+        if len_data < header_end {
+            return Err(VgmError::TruncatedFile { expected: header_end, actual: len_data });
+        }
 
         // 0x40
         // From here, need to check if is still header, or start of vgm data
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.rf5_c68_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ym2203_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ym2608_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ym2610_b_clock = data.get_u32_le();
 
         // 0x50
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ym3812_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ym3526_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.y8950_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ymf262_clock = data.get_u32_le();
 
         // 0x60
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ymf278_b_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ymf271_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ymz280_b_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.rf5_c164_clock = data.get_u32_le();
 
         // 0x70
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.pwm_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ay8910_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ay8910_chip_type = data.get_u8();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ay8910_flags = data.get_u8();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ym2203_ay8910_flags = data.get_u8();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.ym2608_ay8910_flags = data.get_u8();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.volume_modifier = data.get_u8();
 
-        // skip reserved
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        // reserved
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
-        data.get_u8();
+        header.reserved[0] = data.get_u8();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.loop_base = data.get_u8();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.loop_modifier = data.get_u8();
 
         // 0x80
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.gb_dmg_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.nes_apu_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.multi_pcm_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.u_pd7759_clock = data.get_u32_le();
 
         // 0x90
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.okim6258_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.okim6258_flags = data.get_u8();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.k054539_flags = data.get_u8();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.c140_chip_type = data.get_u8();
 
-        // skip reserved
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        // reserved
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
-        data.get_u8();
+        header.reserved[1] = data.get_u8();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.okim6295_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.k051649_k052539_clock = data.get_u32_le();
 
         // 0xA0
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.k054539_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.hu_c6280_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.c140_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.k053260_clock = data.get_u32_le();
 
         // 0xB0
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.pokey_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.qsound_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.scsp_clock = data.get_u32_le();
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         }
         header.extra_header_offset = data.get_u32_le();
@@ -1039,7 +1570,7 @@ impl VgmParser for HeaderData {
         // 0xC0
         // from here need to also check for extra header data
         // can assume that after extra header is vgm data?
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -1049,7 +1580,7 @@ impl VgmParser for HeaderData {
         }
         header.wonder_swan_clock = data.get_u32_le();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -1059,7 +1590,7 @@ impl VgmParser for HeaderData {
         }
         header.vsu_clock = data.get_u32_le();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -1069,7 +1600,7 @@ impl VgmParser for HeaderData {
         }
         header.saa1099_clock = data.get_u32_le();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -1080,7 +1611,7 @@ impl VgmParser for HeaderData {
         header.es5503_clock = data.get_u32_le();
 
         // 0xD0
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -1090,7 +1621,7 @@ impl VgmParser for HeaderData {
         }
         header.es5506_clock = data.get_u32_le();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -1100,7 +1631,7 @@ impl VgmParser for HeaderData {
         }
         header.es5503_nb_channels = data.get_u8();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -1110,7 +1641,7 @@ impl VgmParser for HeaderData {
         }
         header.es5505_es5506_nb_channels = data.get_u8();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -1120,8 +1651,8 @@ impl VgmParser for HeaderData {
         }
         header.c352_clock_divider = data.get_u8();
 
-        // skip reserved
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        // reserved
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -1129,9 +1660,9 @@ impl VgmParser for HeaderData {
                 return Ok(header);
             }
         }
-        data.get_u8();
+        header.reserved[2] = data.get_u8();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -1141,7 +1672,7 @@ impl VgmParser for HeaderData {
         }
         header.x1010_clock = data.get_u32_le();
 
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -1152,7 +1683,7 @@ impl VgmParser for HeaderData {
         header.c352_clock = data.get_u32_le();
 
         // 0xE0
-        if (len_data - data.remaining()) == pos_start_vgm_usize {
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
             return Ok(header);
         } else if let Some(pos_extra_header) = pos_extra_header {
             if (len_data - data.remaining()) == pos_extra_header {
@@ -1162,13 +1693,23 @@ impl VgmParser for HeaderData {
         }
         header.ga20_clock = data.get_u32_le();
 
+        if (len_data - data.remaining()) >= pos_start_vgm_usize {
+            return Ok(header);
+        } else if let Some(pos_extra_header) = pos_extra_header {
+            if (len_data - data.remaining()) == pos_extra_header {
+                header.parse_extra_header(data, pos_extra_header)?;
+                return Ok(header);
+            }
+        }
+        header.mikey_clock = data.get_u32_le();
+
         Ok(header)
     }
 }
 
 impl VgmWriter for HeaderData {
     fn to_bytes(&self, buffer: &mut BytesMut) -> VgmResult<()> {
-        let vgm_data_pos = (self.vgm_data_offset + 0x34) as usize;
+        let vgm_data_pos = self.vgm_data_start()?;
         let extra_header_pos = if self.extra_header_offset == 0 {
             None
         } else {
@@ -1203,214 +1744,214 @@ impl VgmWriter for HeaderData {
 
         // 0x40
         // From here, need to check if is still header, or start of vgm data
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.rf5_c68_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.ym2203_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.ym2608_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.ym2610_b_clock.to_le_bytes()[..]);
 
         // 0x50
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.ym3812_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.ym3526_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.y8950_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.ymf262_clock.to_le_bytes()[..]);
 
         // 0x60
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.ymf278_b_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.ymf271_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.ymz280_b_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.rf5_c164_clock.to_le_bytes()[..]);
 
         // 0x70
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.pwm_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.ay8910_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.ay8910_chip_type.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.ay8910_flags.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.ym2203_ay8910_flags.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.ym2608_ay8910_flags.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.volume_modifier.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
-        buffer.put(&[0x00][..]); // reserved
+        buffer.put(&self.reserved[0].to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.loop_base.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.loop_modifier.to_le_bytes()[..]);
 
         // 0x80
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.gb_dmg_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.nes_apu_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.multi_pcm_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.u_pd7759_clock.to_le_bytes()[..]);
 
         // 0x90
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.okim6258_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.okim6258_flags.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.k054539_flags.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.c140_chip_type.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
-        buffer.put(&[0x00][..]); // reserved
+        buffer.put(&self.reserved[1].to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.okim6295_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.k051649_k052539_clock.to_le_bytes()[..]);
 
         // 0xA0
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.k054539_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.hu_c6280_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.c140_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.k053260_clock.to_le_bytes()[..]);
 
         // 0xB0
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.pokey_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.qsound_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.scsp_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         }
         buffer.put(&self.extra_header_offset.to_le_bytes()[..]);
@@ -1418,128 +1959,148 @@ impl VgmWriter for HeaderData {
         // 0xC0
         // from here need to also check for extra header data
         // can assume that after extra header is vgm data?
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         } else if let Some(extra_header_pos) = extra_header_pos {
             if buffer.len() == extra_header_pos {
-                self.write_extra_header(buffer, vgm_data_pos);
+                self.write_extra_header(buffer);
                 return Ok(());
             }
         }
         buffer.put(&self.wonder_swan_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         } else if let Some(extra_header_pos) = extra_header_pos {
             if buffer.len() == extra_header_pos {
-                self.write_extra_header(buffer, vgm_data_pos);
+                self.write_extra_header(buffer);
                 return Ok(());
             }
         }
         buffer.put(&self.vsu_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         } else if let Some(extra_header_pos) = extra_header_pos {
             if buffer.len() == extra_header_pos {
-                self.write_extra_header(buffer, vgm_data_pos);
+                self.write_extra_header(buffer);
                 return Ok(());
             }
         }
         buffer.put(&self.saa1099_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         } else if let Some(extra_header_pos) = extra_header_pos {
             if buffer.len() == extra_header_pos {
-                self.write_extra_header(buffer, vgm_data_pos);
+                self.write_extra_header(buffer);
                 return Ok(());
             }
         }
         buffer.put(&self.es5503_clock.to_le_bytes()[..]);
 
         // 0xD0
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         } else if let Some(extra_header_pos) = extra_header_pos {
             if buffer.len() == extra_header_pos {
-                self.write_extra_header(buffer, vgm_data_pos);
+                self.write_extra_header(buffer);
                 return Ok(());
             }
         }
         buffer.put(&self.es5506_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         } else if let Some(extra_header_pos) = extra_header_pos {
             if buffer.len() == extra_header_pos {
-                self.write_extra_header(buffer, vgm_data_pos);
+                self.write_extra_header(buffer);
                 return Ok(());
             }
         }
         buffer.put(&self.es5503_nb_channels.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         } else if let Some(extra_header_pos) = extra_header_pos {
             if buffer.len() == extra_header_pos {
-                self.write_extra_header(buffer, vgm_data_pos);
+                self.write_extra_header(buffer);
                 return Ok(());
             }
         }
         buffer.put(&self.es5505_es5506_nb_channels.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         } else if let Some(extra_header_pos) = extra_header_pos {
             if buffer.len() == extra_header_pos {
-                self.write_extra_header(buffer, vgm_data_pos);
+                self.write_extra_header(buffer);
                 return Ok(());
             }
         }
         buffer.put(&self.c352_clock_divider.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         } else if let Some(extra_header_pos) = extra_header_pos {
             if buffer.len() == extra_header_pos {
-                self.write_extra_header(buffer, vgm_data_pos);
+                self.write_extra_header(buffer);
                 return Ok(());
             }
         }
-        buffer.put(&[0x00][..]); // reserved
+        buffer.put(&self.reserved[2].to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         } else if let Some(extra_header_pos) = extra_header_pos {
             if buffer.len() == extra_header_pos {
-                self.write_extra_header(buffer, vgm_data_pos);
+                self.write_extra_header(buffer);
                 return Ok(());
             }
         }
         buffer.put(&self.x1010_clock.to_le_bytes()[..]);
 
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         } else if let Some(extra_header_pos) = extra_header_pos {
             if buffer.len() == extra_header_pos {
-                self.write_extra_header(buffer, vgm_data_pos);
+                self.write_extra_header(buffer);
                 return Ok(());
             }
         }
         buffer.put(&self.c352_clock.to_le_bytes()[..]);
 
         // 0xE0
-        if buffer.len() == vgm_data_pos {
+        if buffer.len() >= vgm_data_pos {
             return Ok(());
         } else if let Some(extra_header_pos) = extra_header_pos {
             if buffer.len() == extra_header_pos {
-                self.write_extra_header(buffer, vgm_data_pos);
+                self.write_extra_header(buffer);
                 return Ok(());
             }
         }
         buffer.put(&self.ga20_clock.to_le_bytes()[..]);
-        
+
+        if buffer.len() >= vgm_data_pos {
+            return Ok(());
+        } else if let Some(extra_header_pos) = extra_header_pos {
+            if buffer.len() == extra_header_pos {
+                self.write_extra_header(buffer);
+                return Ok(());
+            }
+        }
+        buffer.put(&self.mikey_clock.to_le_bytes()[..]);
+
+        // Extra header after every field, before the VGM data
+        if let Some(extra_header_pos) = extra_header_pos {
+            if extra_header_pos >= buffer.len() && extra_header_pos < vgm_data_pos {
+                let leading_bytes = &self.extra_header.leading_bytes;
+                buffer.put(&leading_bytes[..leading_bytes.len().min(extra_header_pos - buffer.len())]);
+                buffer.resize(extra_header_pos, 0);
+                self.write_extra_header(buffer);
+            }
+        }
+
         Ok(())
     }
 }
@@ -1612,7 +2173,8 @@ mod tests {
 
     use crate::traits::{VgmParser, VgmWriter};
 
-    use super::HeaderData;
+    use super::{ChipVolume, ExtraHeaderData, HeaderData, VolumeMode, CHIP_VARIANT_FLAG, DUAL_CHIP_FLAG};
+    use crate::{ChipInstance, System};
 
     /// Get project root directory for test file paths
     fn get_project_root() -> PathBuf {
@@ -1698,4 +2260,255 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn effective_loop_count() {
+        let mut header = HeaderData::default();
+        assert_eq!(header.effective_loop_count(2), 2);
+
+        // 0x20 doubles the requested loops
+        header.loop_modifier = 0x20;
+        assert_eq!(header.effective_loop_count(2), 4);
+
+        // Loop base is subtracted afterwards and is signed
+        header.loop_base = 1;
+        assert_eq!(header.effective_loop_count(2), 3);
+        header.loop_base = 0xFF;
+        assert_eq!(header.effective_loop_count(2), 5);
+
+        // Never drops below a single pass through the loop
+        header.loop_modifier = 0x10;
+        header.loop_base = 10;
+        assert_eq!(header.effective_loop_count(2), 1);
+    }
+
+    #[test]
+    fn chips_resolve_flags() {
+        let header = HeaderData {
+            sn76489_clock: 3_579_545 | DUAL_CHIP_FLAG,
+            ym2610_b_clock: 8_000_000 | CHIP_VARIANT_FLAG,
+            k051649_k052539_clock: 1_500_000,
+            ..HeaderData::default()
+        };
+
+        assert_eq!(
+            header.chips(),
+            vec![
+                ChipInstance { system: System::SN76489, clock_hz: 3_579_545, index: 0, variant_flag: false },
+                ChipInstance { system: System::SN76489, clock_hz: 3_579_545, index: 1, variant_flag: false },
+                ChipInstance { system: System::YM2610B, clock_hz: 8_000_000, index: 0, variant_flag: true },
+                ChipInstance { system: System::K051649, clock_hz: 1_500_000, index: 0, variant_flag: false },
+            ]
+        );
+        assert_eq!(header.chip_count(&System::SN76489), 2);
+        assert_eq!(header.chip_count(&System::YM2612), 0);
+    }
+
+    #[test]
+    fn set_chips_is_inverse_of_chips() {
+        let mut header = HeaderData::default();
+        header.set_chip(&System::ES5506, 16_000_000, true).unwrap();
+        header.set_chip(&System::YM2612, 7_670_453, false).unwrap();
+        assert_eq!(header.es5506_clock, 16_000_000 | CHIP_VARIANT_FLAG | DUAL_CHIP_FLAG);
+
+        let chips = header.chips();
+        let mut rebuilt = HeaderData { ay8910_clock: 1_789_772, ..HeaderData::default() };
+        rebuilt.set_chips(&chips).unwrap();
+        assert_eq!(rebuilt.chips(), chips);
+        assert_eq!(rebuilt.es5506_clock, header.es5506_clock);
+        assert_eq!(rebuilt.ay8910_clock, 0);
+
+        header.remove_chip(&System::ES5505);
+        assert_eq!(header.es5506_clock, 0);
+
+        // A chip and its variant can't both be present
+        let conflicting = [
+            ChipInstance { system: System::YM2610, clock_hz: 8_000_000, index: 0, variant_flag: false },
+            ChipInstance { system: System::YM2610B, clock_hz: 8_000_000, index: 1, variant_flag: true },
+        ];
+        assert!(header.set_chips(&conflicting).is_err());
+        assert!(header.set_chip(&System::YM2612, 0, false).is_err());
+    }
+
+    #[test]
+    fn set_chips_keeps_variant_flags() {
+        let header = HeaderData {
+            ym2612_clock: 7_670_453 | CHIP_VARIANT_FLAG,
+            ym2151_clock: 3_579_545 | CHIP_VARIANT_FLAG,
+            ym2610_b_clock: 8_000_000 | CHIP_VARIANT_FLAG,
+            nes_apu_clock: 1_789_772 | CHIP_VARIANT_FLAG | DUAL_CHIP_FLAG,
+            k051649_k052539_clock: 1_500_000 | CHIP_VARIANT_FLAG,
+            es5506_clock: 16_000_000 | CHIP_VARIANT_FLAG,
+            okim6295_clock: 1_000_000 | CHIP_VARIANT_FLAG,
+            ..HeaderData::default()
+        };
+
+        let chips = header.chips();
+        assert!(chips.iter().all(|chip| chip.variant_flag));
+        let mut rebuilt = HeaderData::default();
+        rebuilt.set_chips(&chips).unwrap();
+        assert_eq!(rebuilt.ym2612_clock, 0x8075_0AB5);
+        assert_eq!(rebuilt.okim6295_clock, 0x800F_4240);
+        assert_eq!(rebuilt, header);
+
+        // The flags of a chip's two instances must agree
+        let mut mismatched = chips.clone();
+        mismatched.retain(|chip| chip.system == System::NesApu);
+        mismatched[1].variant_flag = false;
+        assert!(rebuilt.set_chips(&mismatched).is_err());
+    }
+
+    #[test]
+    fn t6w28_needs_the_dual_chip_bit() {
+        let single = HeaderData { sn76489_clock: 3_579_545 | CHIP_VARIANT_FLAG, ..HeaderData::default() };
+        let chips = single.chips();
+        assert_eq!(
+            chips,
+            vec![ChipInstance { system: System::SN76489, clock_hz: 3_579_545, index: 0, variant_flag: true }]
+        );
+        let mut rebuilt = HeaderData::default();
+        rebuilt.set_chips(&chips).unwrap();
+        assert_eq!(rebuilt.sn76489_clock, single.sn76489_clock);
+
+        let dual = HeaderData {
+            sn76489_clock: 3_579_545 | CHIP_VARIANT_FLAG | DUAL_CHIP_FLAG,
+            ..HeaderData::default()
+        };
+        assert!(dual.chips().iter().all(|chip| chip.system == System::T6W28));
+        rebuilt.set_chips(&dual.chips()).unwrap();
+        assert_eq!(rebuilt.sn76489_clock, dual.sn76489_clock);
+        assert!(rebuilt.set_chip(&System::T6W28, 3_579_545, false).is_err());
+    }
+
+    #[test]
+    fn unaligned_data_offset_stops_header() {
+        let header = HeaderData {
+            version: 151,
+            // Commands start at 0x42, inside the RF5C68 clock
+            vgm_data_offset: 0x0E,
+            ..HeaderData::default()
+        };
+        let mut buffer = BytesMut::new();
+        header.to_bytes(&mut buffer).unwrap();
+        buffer.truncate(0x40);
+        buffer.extend_from_slice(&[0x00, 0x00, 0x62, 0x62, 0x62, 0x62, 0x62, 0x62]);
+
+        let parsed = HeaderData::from_bytes(&mut Bytes::from(buffer.to_vec())).unwrap();
+        assert_eq!(parsed.vgm_data_start().unwrap(), 0x42);
+        // The field cut by the data is read whole, nothing after it
+        assert_eq!(parsed.rf5_c68_clock, 0x6262_0000);
+        assert_eq!(parsed.ym2203_clock, 0);
+
+        // A file ending inside that field is truncated
+        buffer.truncate(0x43);
+        assert!(HeaderData::from_bytes(&mut Bytes::from(buffer.to_vec())).is_err());
+    }
+
+    #[test]
+    fn mikey_clock_round_trip() {
+        let header = HeaderData {
+            version: 172,
+            vgm_data_offset: 0xCC,
+            mikey_clock: 16_000_000,
+            ..HeaderData::default()
+        };
+
+        let mut buffer = BytesMut::new();
+        header.to_bytes(&mut buffer).unwrap();
+        assert_eq!(buffer.len(), 0xE8);
+        assert_eq!(buffer[0xE4..0xE8], 16_000_000u32.to_le_bytes());
+
+        buffer.resize(0x100, 0);
+        let parsed = HeaderData::from_bytes(&mut Bytes::from(buffer.to_vec())).unwrap();
+        assert_eq!(parsed.mikey_clock, 16_000_000);
+        assert_eq!(
+            parsed.chips(),
+            vec![ChipInstance { system: System::Mikey, clock_hz: 16_000_000, index: 0, variant_flag: false }]
+        );
+    }
+
+    #[test]
+    fn extra_header_overrides() {
+        let mut extra = ExtraHeaderData::default();
+        extra.set_chip_clock_override(&System::SN76489, 1, 4_000_000).unwrap();
+        extra.set_chip_volume(&System::YM2203, 0, 0x80, VolumeMode::Relative).unwrap();
+        extra.set_chip_volume(&System::YM2203, 1, 0x200, VolumeMode::Absolute).unwrap();
+        extra.set_chip_volume(&System::YM2203, 0, 0x180, VolumeMode::Relative).unwrap();
+
+        assert_eq!(extra.chip_clock_entries[0].chip_id, 0x80);
+        assert_eq!(extra.chip_clock_override(&System::SN76489, 1), Some(4_000_000));
+        assert_eq!(extra.chip_clock_override(&System::SN76489, 0), None);
+        assert_eq!(extra.chip_volume_entries.len(), 2);
+        assert_eq!(extra.chip_volume_entries[0].volume, 0x8180);
+        assert_eq!(
+            extra.chip_volume(&System::YM2203, 1),
+            Some(ChipVolume { volume: 0x200, mode: VolumeMode::Absolute })
+        );
+
+        assert!(extra.set_chip_volume(&System::YM2203, 0, 0x8000, VolumeMode::Absolute).is_err());
+        assert!(extra.set_chip_clock_override(&System::YM2203, 2, 1).is_err());
+
+        extra.remove_chip_volume(&System::YM2203, 0);
+        extra.remove_chip_clock_override(&System::SN76489, 1);
+        assert!(extra.chip_clock_entries.is_empty());
+        assert_eq!(extra.chip_volume(&System::YM2203, 0), None);
+
+        extra.normalize();
+        assert_eq!((extra.header_size, extra.chip_clock_offset, extra.chip_vol_offset), (12, 0, 4));
+        assert_eq!(extra.encoded_size(), 12 + 1 + 4);
+    }
+
+    #[test]
+    fn extra_header_after_fields() {
+        let mut header = HeaderData {
+            version: 172,
+            sn76489_clock: 3_579_545,
+            vgm_data_offset: 0x120 - 0x34,
+            extra_header_offset: 0x100 - 0xBC,
+            ..HeaderData::default()
+        };
+        header.extra_header.set_chip_volume(&System::SN76489, 0, 0x100, VolumeMode::Absolute).unwrap();
+        header.extra_header.normalize();
+
+        let mut buffer = BytesMut::new();
+        header.to_bytes(&mut buffer).unwrap();
+        assert_eq!(buffer.len(), 0x100 + 17);
+        assert_eq!(buffer[0xE8..0x100], [0; 0x18]);
+
+        // Read from where the offset points, past the last field
+        buffer.resize(0x120, 0);
+        let mut data = Bytes::from(buffer.to_vec());
+        let parsed = HeaderData::from_bytes(&mut data).unwrap();
+        assert_eq!(parsed, header);
+        assert_eq!(data.len(), 0x120 - 0x111);
+    }
+
+    #[test]
+    fn extra_header_keeps_leading_bytes() {
+        let mut header = HeaderData {
+            version: 172,
+            sn76489_clock: 3_579_545,
+            vgm_data_offset: 0x120 - 0x34,
+            extra_header_offset: 0x100 - 0xBC,
+            ..HeaderData::default()
+        };
+        header.extra_header.set_chip_volume(&System::SN76489, 0, 0x100, VolumeMode::Absolute).unwrap();
+        header.extra_header.normalize();
+
+        let mut buffer = BytesMut::new();
+        header.to_bytes(&mut buffer).unwrap();
+        buffer[0xF0..0xF4].copy_from_slice(b"TAG!");
+        buffer.resize(0x120, 0);
+
+        let parsed = HeaderData::from_bytes(&mut Bytes::from(buffer.to_vec())).unwrap();
+        assert_eq!(parsed.extra_header.leading_bytes.len(), 0x100 - 0xE8);
+        let mut written = BytesMut::new();
+        parsed.to_bytes(&mut written).unwrap();
+        assert_eq!(written[..], buffer[..0x111]);
+
+        // Relocating the extra header drops them
+        let mut normalized = parsed.extra_header.clone();
+        normalized.normalize();
+        assert!(normalized.leading_bytes.is_empty());
+    }
 }
@@ -1,30 +1,73 @@
+pub mod ay8910;
+pub mod chip_state;
+pub mod diagnostics;
 pub mod errors;
+pub(crate) mod fm;
+pub mod fuzz;
 pub mod header;
+pub mod looping;
 pub mod metadata;
+pub mod opcodes;
 pub mod parser_config;
+pub mod raw_regions;
+pub mod reader;
+pub mod render;
+pub mod sn76489;
 pub mod systems;
+pub mod timeline;
 pub mod traits;
 pub mod utils;
 pub mod validation;
+pub mod version;
 pub mod vgm_commands;
+pub mod writer;
+pub mod ym2151;
+pub mod ym2612;
 
+pub use ay8910::*;
+pub use chip_state::*;
+pub use diagnostics::*;
 pub use errors::*;
 pub use header::*;
+pub use looping::*;
 pub use metadata::*;
+pub use opcodes::*;
 pub use parser_config::*;
+pub use raw_regions::*;
+pub use reader::*;
+pub use render::*;
+pub use sn76489::*;
 pub use systems::*;
+pub use timeline::*;
 pub use traits::*;
 pub use validation::*;
+pub use version::*;
 pub use vgm_commands::*;
+pub use writer::*;
+pub use ym2151::*;
+pub use ym2612::*;
 
-use bytes::{Buf, Bytes, BytesMut};
+use bytes::{BufMut, Bytes, BytesMut};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A parsed VGM file.
+///
+/// Parsing a file and writing it back with [`VgmWriter::to_bytes`],
+/// [`VgmFile::to_vgz_bytes`] or [`VgmFile::write_to_path`] without modifying
+/// it reproduces the input byte for byte, reserved header bytes and
+/// [`RawRegions`] included, as long as the commands end before the GD3 tag.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct VgmFile {
     pub header: HeaderData,
     pub commands: Vec<Commands>,
+    /// Absolute file offset of each entry in `commands`, as read by the parser.
+    /// Empty when the file was built by hand; see [`VgmFile::recompute_command_offsets`].
+    #[serde(default)]
+    pub command_offsets: Vec<u32>,
     pub metadata: VgmMetadata,
+    /// Bytes outside the header, commands and GD3 tag
+    #[serde(default)]
+    pub raw_regions: RawRegions,
 }
 
 impl VgmFile {
@@ -74,12 +117,12 @@ impl VgmFile {
             });
         }
 
-        let mut data = Bytes::from(vgm_data.clone());
+        let file_size = vgm_data.len();
+        let mut data = Bytes::from(vgm_data);
         let vgm_file = VgmFile::from_bytes_with_config(&mut data, parser_config)?;
         
         // Perform validation using decompressed data size
-        let validator = VgmValidator::new(validation_config);
-        validator.validate_vgm_file(&vgm_file.header, &vgm_file.commands, &vgm_file.metadata, vgm_data.len())?;
+        vgm_file.validate_with_config(validation_config, file_size)?;
         
         Ok(vgm_file)
     }
@@ -90,33 +133,84 @@ impl VgmFile {
     }
     
     /// Parse VGM file from bytes with parser configuration (no validation)
+    ///
+    /// `data` must hold the complete file; on success it is advanced past the
+    /// end of the command stream.
     pub fn from_bytes_with_config(data: &mut Bytes, parser_config: ParserConfig) -> VgmResult<Self> {
-        let len_data = data.len();
+        Self::from_bytes_with_diagnostics(data, parser_config).map(|(file, _)| file)
+    }
+
+    /// Parse VGM file from bytes, also returning every problem recovered from.
+    ///
+    /// Diagnostics are only produced with [`ParseMode::Recover`]: malformed
+    /// commands are skipped and an unreadable GD3 tag is replaced by empty
+    /// metadata. Header errors and resource limits are still returned as errors.
+    pub fn from_bytes_with_diagnostics(data: &mut Bytes, parser_config: ParserConfig) -> VgmResult<(Self, Vec<Diagnostic>)> {
+        let file = data.clone();
         let mut resource_tracker = ResourceTracker::new();
         
         let header_data = HeaderData::from_bytes_with_config(data, &parser_config, &mut resource_tracker)?;
+        let header_end = file.len() - data.len();
         
-        // Security: Prevent integer overflow in offset calculation
-        let vgm_start_pos = header_data.vgm_data_offset
-            .checked_add(0x34)
-            .and_then(|v| usize::try_from(v).ok())
-            .ok_or(VgmError::IntegerOverflow {
-                operation: "VGM data offset calculation".to_string(),
-                details: format!("offset {} + 0x34", header_data.vgm_data_offset),
-            })?;
-
-        while len_data - data.len() < vgm_start_pos {
-            data.get_u8();
+        let vgm_start_pos = header_data.vgm_data_start()?;
+        if vgm_start_pos > file.len() {
+            return Err(VgmError::InvalidOffset {
+                field: "vgm_data_offset".to_string(),
+                offset: header_data.vgm_data_offset,
+                file_size: file.len(),
+            });
+        }
+
+        let mut diagnostics = Vec::new();
+        let mut gd3 = None;
+        let metadata = match VgmMetadata::from_vgm_bytes(&file, header_data.gd3_offset, &parser_config) {
+            Ok(metadata) => {
+                if header_data.gd3_offset != 0 {
+                    let gd3_start = header_data.gd3_offset as usize + 0x14;
+                    gd3 = Some(gd3_start..gd3_start + VgmMetadata::gd3_block_size(&file[gd3_start..], gd3_start)?);
+                }
+                metadata
+            },
+            Err(error) if parser_config.parse_mode == ParseMode::Recover => {
+                let offset = header_data.gd3_offset as usize + 0x14;
+                diagnostics.push(Diagnostic::error(offset, 0, error));
+                VgmMetadata::default()
+            },
+            Err(error) => return Err(error),
+        };
+
+        let mut command_iter = CommandIter::with_tracker(
+            file.slice(vgm_start_pos..),
+            vgm_start_pos,
+            parser_config,
+            resource_tracker,
+        );
+        let mut commands = Vec::new();
+        let mut command_offsets = Vec::new();
+        while let Some(positioned) = command_iter.next_positioned() {
+            let positioned = positioned?;
+            command_offsets.push(positioned.offset);
+            commands.push(positioned.command);
         }
 
-        let metadata = VgmMetadata::from_bytes_with_config(data, &parser_config)?;
-        let commands = parse_commands_with_config(data, &parser_config, &mut resource_tracker)?;
+        let commands_end = command_iter.position();
+        *data = file.slice(commands_end..);
+        diagnostics.extend(command_iter.take_diagnostics());
 
-        Ok(VgmFile {
+        let raw_regions = RawRegions {
+            header_padding: file[header_end.min(vgm_start_pos)..vgm_start_pos].to_vec(),
+            after_commands: file.get(raw_regions::after_commands_range(commands_end, gd3.as_ref())).unwrap_or_default().to_vec(),
+            trailing: file.get(raw_regions::trailing_start(commands_end, gd3.as_ref())..).unwrap_or_default().to_vec(),
+        };
+
+        let vgm_file = VgmFile {
             header: header_data,
             commands,
+            command_offsets,
             metadata,
-        })
+            raw_regions,
+        };
+        Ok((vgm_file, diagnostics))
     }
     
     /// Parse VGM file from bytes with both parser and validation configuration
@@ -125,16 +219,41 @@ impl VgmFile {
         let vgm_file = Self::from_bytes_with_config(data, parser_config)?;
         
         // Perform validation
-        let validator = VgmValidator::new(validation_config);
-        validator.validate_vgm_file(&vgm_file.header, &vgm_file.commands, &vgm_file.metadata, original_len)?;
+        vgm_file.validate_with_config(validation_config, original_len)?;
         
         Ok(vgm_file)
     }
     
     /// Validate this VGM file with the given configuration
     pub fn validate_with_config(&self, config: ValidationConfig, file_size: usize) -> VgmResult<()> {
+        self.validate_with_diagnostics(config, file_size).map(|_| ())
+    }
+
+    /// Validate this VGM file, returning the warnings that don't fail
+    /// validation.
+    ///
+    /// Warns about non-zero reserved header bytes, unparsed regions, DAC
+    /// streams controlled before their setup and header sample counts that
+    /// don't match the waits. Strict mode rejects the sample counts and keeps
+    /// the rest as warnings.
+    pub fn validate_with_diagnostics(&self, config: ValidationConfig, file_size: usize) -> VgmResult<Vec<Diagnostic>> {
+        let strict = config.strict_mode;
         let validator = VgmValidator::new(config);
-        validator.validate_vgm_file(&self.header, &self.commands, &self.metadata, file_size)
+        validator.validate_vgm_file(&self.header, &self.commands, &self.metadata, file_size)?;
+        ConsistencyValidator::validate_loop_point(&self.header, &self.command_offsets)?;
+        let mut warnings: Vec<Diagnostic> = self.verify_sample_counts(strict)?.warning().into_iter().collect();
+        warnings.extend(ConsistencyValidator::validate_unparsed_bytes(&self.header, &self.raw_regions, file_size));
+        warnings.extend(ConsistencyValidator::validate_dac_stream_setup(&self.commands, &self.command_offsets));
+        Ok(warnings)
+    }
+
+    /// Compare the header's total and loop sample counts with the waits in
+    /// `commands`.
+    ///
+    /// With `strict` a mismatch is returned as an error, otherwise the report
+    /// describes any discrepancy.
+    pub fn verify_sample_counts(&self, strict: bool) -> VgmResult<SampleCountReport> {
+        ConsistencyValidator::validate_sample_counts(&self.header, &self.commands, self.loop_start_index(), strict)
     }
     
     /// Validate this VGM file with default configuration
@@ -142,6 +261,108 @@ impl VgmFile {
         self.validate_with_config(ValidationConfig::default(), file_size)
     }
 
+    /// Absolute file offset of the command at `index`
+    pub fn offset_of(&self, index: usize) -> Option<u32> {
+        self.command_offsets.get(index).copied()
+    }
+
+    /// Index of the command starting exactly at absolute file offset `offset`
+    pub fn index_at_offset(&self, offset: u32) -> Option<usize> {
+        self.command_offsets.binary_search(&offset).ok()
+    }
+
+    /// Index of the command whose encoded bytes contain absolute file offset `offset`
+    pub fn index_containing_offset(&self, offset: u32) -> Option<usize> {
+        match self.command_offsets.binary_search(&offset) {
+            Ok(index) => Some(index),
+            Err(0) => None,
+            Err(index) if index < self.command_offsets.len() => Some(index - 1),
+            Err(index) => {
+                // Past the start of the last command: only inside it if within its length
+                let last = index - 1;
+                let length = self.commands[last].clone().to_bytes().ok()?.len();
+                let end = self.command_offsets[last] as usize + length;
+                ((offset as usize) < end).then_some(last)
+            },
+        }
+    }
+
+    /// Index of the command the header's loop offset points at.
+    ///
+    /// `None` when the file doesn't loop or the loop offset doesn't land on a
+    /// command boundary (invalid loop offsets are treated as "no loop").
+    pub fn loop_start_index(&self) -> Option<usize> {
+        let loop_start = reader::file_offset(self.header.loop_start()?).ok()?;
+        self.index_at_offset(loop_start)
+    }
+
+    /// Iterate over commands in playback order for a player configured to
+    /// play `program_loops` loops, honoring the header's loop base and modifier
+    pub fn iter_looped(&self, program_loops: u32) -> LoopedCommands<'_> {
+        let loops = self.header.effective_loop_count(program_loops);
+        LoopedCommands::new(&self.commands, self.loop_start_index(), loops)
+    }
+
+    /// Iterate over commands along with their absolute file offsets
+    pub fn commands_with_offsets(&self) -> impl Iterator<Item = (u32, &Commands)> {
+        self.command_offsets.iter().copied().zip(self.commands.iter())
+    }
+
+    /// Rebuild `command_offsets` from the encoded size of each command, laid
+    /// out from the header's VGM data start. Use after editing `commands`;
+    /// the header's loop offset isn't moved, see [`VgmFile::insert_command`].
+    pub fn recompute_command_offsets(&mut self) -> VgmResult<()> {
+        let mut position = self.header.vgm_data_start()?;
+        let mut offsets = Vec::with_capacity(self.commands.len());
+
+        for command in &self.commands {
+            offsets.push(reader::file_offset(position)?);
+            position += command.clone().to_bytes()?.len();
+        }
+
+        self.command_offsets = offsets;
+        Ok(())
+    }
+
+    /// Insert `command` before `index`, refreshing `command_offsets` and
+    /// moving the header's loop offset so it stays on the same command
+    pub fn insert_command(&mut self, index: usize, command: Commands) -> VgmResult<()> {
+        if index > self.commands.len() {
+            return Err(command_index_error(index, self.commands.len()));
+        }
+
+        let loop_index = self.loop_start_index().map(|loop_index| loop_index + usize::from(loop_index >= index));
+        self.commands.insert(index, command);
+        self.refresh_offsets(loop_index)
+    }
+
+    /// Remove the command at `index`, refreshing `command_offsets` and moving
+    /// the header's loop offset so it stays on the same command. Removing the
+    /// loop command itself loops to the command that followed it
+    pub fn remove_command(&mut self, index: usize) -> VgmResult<Commands> {
+        if index >= self.commands.len() {
+            return Err(command_index_error(index, self.commands.len()));
+        }
+
+        let loop_index = self.loop_start_index().map(|loop_index| loop_index - usize::from(loop_index > index));
+        let command = self.commands.remove(index);
+        self.refresh_offsets(loop_index)?;
+        Ok(command)
+    }
+
+    /// Recompute the offsets after an edit and point the loop offset at the
+    /// command now at `loop_index`
+    fn refresh_offsets(&mut self, loop_index: Option<usize>) -> VgmResult<()> {
+        self.recompute_command_offsets()?;
+        if let Some(loop_index) = loop_index {
+            self.header.loop_offset = match self.offset_of(loop_index) {
+                Some(offset) => offset - 0x1C,
+                None => 0,
+            };
+        }
+        Ok(())
+    }
+
     pub fn has_data_block(&self) -> bool {
         for cmd in &self.commands {
             if let Commands::DataBlock { .. } = cmd { return true }
@@ -155,44 +376,53 @@ impl VgmFile {
         }
         false
     }
+
+    /// Whether any command uses an opcode reserved by the spec, typically
+    /// from a newer spec version than this crate knows
+    pub fn has_reserved_commands(&self) -> bool {
+        self.commands.iter().any(Commands::is_reserved)
+    }
 }
 
 impl VgmParser for VgmFile {
+    /// Parse with the default [`ParserConfig`]. A malformed command stream is
+    /// an error; use [`ParseMode::Recover`] to keep the commands that parse
     fn from_bytes(data: &mut Bytes) -> VgmResult<Self> {
-        let len_data = data.len();
-        let header_data = HeaderData::from_bytes(data)?;
-        // Security: Prevent integer overflow in offset calculation
-        let vgm_start_pos = header_data.vgm_data_offset
-            .checked_add(0x34)
-            .and_then(|v| usize::try_from(v).ok())
-            .ok_or(VgmError::IntegerOverflow {
-                operation: "VGM data offset calculation".to_string(),
-                details: format!("offset {} + 0x34", header_data.vgm_data_offset),
-            })?;
-
-        while len_data - data.len() < vgm_start_pos {
-            data.get_u8();
-        }
-
-        let metadata = VgmMetadata::from_bytes(data)?;
-
-        Ok(VgmFile {
-            header: header_data,
-            commands: parse_commands(data),
-            metadata,
-        })
+        Self::from_bytes_with_config(data, ParserConfig::default())
     }
 }
 
 impl VgmWriter for VgmFile {
+    /// Write the file as stored, header fields untouched; see
+    /// [`VgmFile::to_bytes_normalized`] to recompute offsets after edits
     fn to_bytes(&self, buffer: &mut BytesMut) -> VgmResult<()> {
+        let vgm_start = self.header.vgm_data_start()?;
         self.header.to_bytes(buffer)?;
+
+        // A data start inside the last header field cuts it short
+        buffer.truncate(vgm_start);
+        let gap = vgm_start - buffer.len();
+        let padding = &self.raw_regions.header_padding;
+        buffer.put(&padding[..gap.min(padding.len())]);
+        buffer.resize(vgm_start, 0);
+
         write_commands(buffer, &self.commands)?;
-        self.metadata.to_bytes(buffer)?;
+        buffer.put(&self.raw_regions.after_commands[..]);
+        if self.header.gd3_offset != 0 || self.metadata != VgmMetadata::default() {
+            self.metadata.to_bytes(buffer)?;
+        }
+        buffer.put(&self.raw_regions.trailing[..]);
         Ok(())
     }
 }
 
+fn command_index_error(index: usize, len: usize) -> VgmError {
+    VgmError::InvalidDataFormat {
+        field: "command_index".to_string(),
+        details: format!("command index {} out of range for {} commands", index, len),
+    }
+}
+
 #[cfg(test)]
 mod validation_integration_test;
 
@@ -221,6 +451,332 @@ mod tests {
         get_project_root().join(relative_path)
     }
 
+    /// Build a minimal v1.51 file with VGM data at 0x100 and no GD3 tag
+    pub(crate) fn build_test_vgm(commands: &[u8]) -> Vec<u8> {
+        build_test_vgm_with_gd3(commands, None)
+    }
+
+    /// Build a minimal v1.51 file with VGM data at 0x100, followed by a GD3
+    /// tag when `metadata` is given. Shared by the test modules of the crate
+    pub(crate) fn build_test_vgm_with_gd3(commands: &[u8], metadata: Option<&VgmMetadata>) -> Vec<u8> {
+        let mut file = vec![0u8; 0x100];
+        file[0..4].copy_from_slice(b"Vgm ");
+        file[0x08..0x0C].copy_from_slice(&[0x51, 0x01, 0x00, 0x00]);
+        file[0x0C..0x10].copy_from_slice(&3_579_545u32.to_le_bytes());
+        file[0x34..0x38].copy_from_slice(&(0x100u32 - 0x34).to_le_bytes());
+        file.extend_from_slice(commands);
+
+        if let Some(metadata) = metadata {
+            let gd3_pos = file.len() as u32;
+            file[0x14..0x18].copy_from_slice(&(gd3_pos - 0x14).to_le_bytes());
+
+            let mut gd3 = BytesMut::new();
+            metadata.to_bytes(&mut gd3).unwrap();
+            file.extend_from_slice(&gd3);
+        }
+
+        let eof = file.len() as u32 - 4;
+        file[0x04..0x08].copy_from_slice(&eof.to_le_bytes());
+        file
+    }
+
+    #[test]
+    fn test_command_offsets() {
+        let file = build_test_vgm(&[0x50, 0x9F, 0x61, 0x44, 0xAC, 0x72, 0x66]);
+        let mut vgm = VgmFile::from_bytes(&mut Bytes::from(file)).unwrap();
+
+        assert_eq!(vgm.command_offsets, vec![0x100, 0x102, 0x105, 0x106]);
+        assert_eq!(vgm.offset_of(1), Some(0x102));
+        assert_eq!(vgm.offset_of(4), None);
+
+        assert_eq!(vgm.index_at_offset(0x105), Some(2));
+        assert_eq!(vgm.index_at_offset(0x103), None);
+
+        assert_eq!(vgm.index_containing_offset(0x103), Some(1));
+        assert_eq!(vgm.index_containing_offset(0x106), Some(3));
+        assert_eq!(vgm.index_containing_offset(0x107), None);
+        assert_eq!(vgm.index_containing_offset(0x40), None);
+
+        let (offset, command) = vgm.commands_with_offsets().nth(2).unwrap();
+        assert_eq!(offset, 0x105);
+        assert_eq!(*command, Commands::WaitNSamplesPlus1 { n: 2 });
+
+        // Inserting a command shifts everything after it
+        vgm.commands.insert(1, Commands::Wait735Samples);
+        vgm.recompute_command_offsets().unwrap();
+        assert_eq!(vgm.command_offsets, vec![0x100, 0x102, 0x103, 0x106, 0x107]);
+    }
+
+    #[test]
+    fn test_loop_resolution() {
+        let mut file = build_test_vgm(&[0x50, 0x9F, 0x62, 0x50, 0x90, 0x63, 0x66]);
+        // Loop back to the second PSG write at 0x103
+        file[0x1C..0x20].copy_from_slice(&(0x103u32 - 0x1C).to_le_bytes());
+        file[0x20..0x24].copy_from_slice(&882u32.to_le_bytes());
+        file[0x7F] = 0x20; // double the loop count
+
+        let vgm = VgmFile::from_bytes(&mut Bytes::from(file)).unwrap();
+        assert_eq!(vgm.loop_start_index(), Some(2));
+        assert!(ConsistencyValidator::validate_loop_point(&vgm.header, &vgm.command_offsets).is_ok());
+
+        // One program loop becomes two passes through the loop section
+        let played: Vec<&Commands> = vgm.iter_looped(1).collect();
+        assert_eq!(played.len(), 7);
+        assert_eq!(played[4], &Commands::PSGWrite { value: 0x90, chip_index: 0 });
+        assert_eq!(played[6], &Commands::EndOfSoundData);
+    }
+
+    #[test]
+    fn test_loop_offset_off_command_boundary() {
+        let mut file = build_test_vgm(&[0x50, 0x9F, 0x62, 0x66]);
+        // 0x101 is the operand of the PSG write
+        file[0x1C..0x20].copy_from_slice(&(0x101u32 - 0x1C).to_le_bytes());
+        file[0x20..0x24].copy_from_slice(&735u32.to_le_bytes());
+
+        let vgm = VgmFile::from_bytes(&mut Bytes::from(file)).unwrap();
+        assert_eq!(vgm.loop_start_index(), None);
+        assert_eq!(vgm.iter_looped(2).count(), 3);
+        assert!(matches!(
+            ConsistencyValidator::validate_loop_point(&vgm.header, &vgm.command_offsets),
+            Err(VgmError::InconsistentData { .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_sample_counts() {
+        let mut file = build_test_vgm(&[0x50, 0x9F, 0x62, 0x50, 0x90, 0x63, 0x66]);
+        file[0x18..0x1C].copy_from_slice(&1617u32.to_le_bytes());
+        file[0x1C..0x20].copy_from_slice(&(0x103u32 - 0x1C).to_le_bytes());
+        file[0x20..0x24].copy_from_slice(&882u32.to_le_bytes());
+
+        let mut vgm = VgmFile::from_bytes(&mut Bytes::from(file)).unwrap();
+        assert!(vgm.verify_sample_counts(true).unwrap().is_consistent());
+
+        // A rip whose header claims a shorter loop
+        vgm.header.loop_nb_samples = 735;
+        let report = vgm.verify_sample_counts(false).unwrap();
+        assert_eq!(report.loop_difference(), -147);
+        assert_eq!(report.total_difference(), 0);
+        assert!(vgm.verify_sample_counts(true).is_err());
+
+        let strict = ValidationConfig { strict_mode: true, ..ValidationConfig::default() };
+        assert!(vgm.validate_with_config(strict, 0x200).is_err());
+    }
+
+    #[test]
+    fn test_lenient_validation_warns_about_sample_counts() {
+        // One 735 sample wait, but the header claims 1470
+        let mut file = build_test_vgm(&[0x62, 0x66]);
+        file[0x18..0x1C].copy_from_slice(&1470u32.to_le_bytes());
+        let vgm = VgmFile::from_bytes(&mut Bytes::from(file.clone())).unwrap();
+
+        let warnings = vgm.validate_with_diagnostics(ValidationConfig::default(), file.len()).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!((warnings[0].offset, warnings[0].severity), (0x18, Severity::Warning));
+        let report = vgm.verify_sample_counts(false).unwrap();
+        assert_eq!((report.total_difference(), report.total_difference_seconds()), (735, 735.0 / 44100.0));
+        assert!(matches!(&warnings[0].error, VgmError::InconsistentData { reason, .. } if *reason == report.to_string()));
+
+        let strict = ValidationConfig { strict_mode: true, ..ValidationConfig::default() };
+        assert!(vgm.validate_with_diagnostics(strict, file.len()).is_err());
+    }
+
+    #[test]
+    fn test_reserved_commands() {
+        let mut file = build_test_vgm(&[0x50, 0x9F, 0xE5, 0x01, 0x02, 0x03, 0x04, 0x62, 0x66]);
+        file[0x18..0x1C].copy_from_slice(&735u32.to_le_bytes());
+        let vgm = VgmFile::from_bytes(&mut Bytes::from(file.clone())).unwrap();
+
+        assert!(vgm.has_reserved_commands());
+        assert_eq!(vgm.commands[1], Commands::Reserved { opcode: 0xE5, operands: vec![1, 2, 3, 4] });
+        assert_eq!(vgm.command_offsets, vec![0x100, 0x102, 0x107, 0x108]);
+        assert_eq!(vgm.to_bytes_normalized().unwrap()[0x100..], file[0x100..]);
+
+        // Only strict validation refuses them
+        assert!(vgm.validate(file.len()).is_ok());
+        let strict = ValidationConfig { strict_mode: true, ..ValidationConfig::default() };
+        assert!(matches!(
+            vgm.validate_with_config(strict, file.len()),
+            Err(VgmError::ValidationFailed { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_errors_carry_file_offsets() {
+        let unknown = build_test_vgm(&[0x50, 0x9F, 0x64, 0x00, 0x66]);
+        assert!(matches!(
+            VgmFile::from_bytes(&mut Bytes::from(unknown)),
+            Err(VgmError::UnknownCommand { opcode: 0x64, position: 0x102 })
+        ));
+
+        let file = build_test_vgm(&[0x50, 0x9F, 0x67, 0x66, 0x00, 0x04, 0x00, 0x00, 0x00, 1, 2, 3, 4, 0xB2, 0x00, 0x01, 0x66]);
+        for len in 0..file.len() {
+            // Truncated uploads are errors, never panics
+            assert!(VgmFile::from_bytes(&mut Bytes::from(file[..len].to_vec())).is_err());
+        }
+        assert!(matches!(
+            VgmFile::from_bytes(&mut Bytes::from(file[..0x10A].to_vec())),
+            Err(VgmError::IncompleteCommand { opcode: 0x67, position: 0x102, expected_bytes: 11, available_bytes: 8 })
+        ));
+    }
+
+    #[test]
+    fn test_from_bytes_rejects_malformed_commands() {
+        let file = build_test_vgm(&[0x50, 0x9F, 0x62, 0x64, 0x66]);
+
+        // Not an empty command list, as the old lenient parse returned
+        assert!(matches!(
+            VgmFile::from_bytes(&mut Bytes::from(file.clone())),
+            Err(VgmError::UnknownCommand { opcode: 0x64, position: 0x103 })
+        ));
+
+        let valid = build_test_vgm(&[0x50, 0x9F, 0x62, 0x66]);
+        assert_eq!(
+            VgmFile::from_bytes(&mut Bytes::from(valid.clone())).unwrap(),
+            VgmFile::from_bytes_with_config(&mut Bytes::from(valid), ParserConfig::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_recover_mode_keeps_what_parses() {
+        let mut file = build_test_vgm(&[0x50, 0x9F, 0x64, 0x62, 0x66]);
+        // GD3 tag pointing at a block without the GD3 magic
+        file.extend_from_slice(b"Gd4 \0\0\0\0");
+        file[0x14..0x18].copy_from_slice(&(0x105u32 - 0x14).to_le_bytes());
+
+        assert!(VgmFile::from_bytes(&mut Bytes::from(file.clone())).is_err());
+
+        let config = ParserConfig { parse_mode: ParseMode::Recover, ..ParserConfig::default() };
+        let (vgm, diagnostics) = VgmFile::from_bytes_with_diagnostics(&mut Bytes::from(file), config).unwrap();
+
+        assert_eq!(vgm.commands.len(), 3);
+        assert_eq!(vgm.command_offsets, vec![0x100, 0x103, 0x104]);
+        assert_eq!(vgm.metadata, VgmMetadata::default());
+
+        let found: Vec<(usize, usize, Severity)> = diagnostics.iter().map(|d| (d.offset, d.skipped, d.severity)).collect();
+        assert_eq!(found, vec![(0x105, 0, Severity::Error), (0x102, 1, Severity::Error)]);
+        assert_eq!(diagnostics[1].to_string(), format!("error at 0x102: {} (1 byte skipped)", diagnostics[1].error));
+    }
+
+    #[test]
+    fn test_unmodified_file_round_trips_byte_for_byte() {
+        let mut file = build_test_vgm(&[0x50, 0x9F, 0x62, 0x66]);
+        // Padding before the data, a GD3 tag, then bytes past the tag
+        file[0xE8..0x100].copy_from_slice(b"ripped by someone\0\0\0\0\0\0\0");
+        let gd3_pos = file.len() as u32;
+        file[0x14..0x18].copy_from_slice(&(gd3_pos - 0x14).to_le_bytes());
+        let metadata = VgmMetadata { notes: "kept".to_string(), ..Default::default() };
+        let mut gd3 = BytesMut::new();
+        metadata.to_bytes(&mut gd3).unwrap();
+        file.extend_from_slice(&gd3);
+        file.extend_from_slice(b"\x1Atrailer");
+
+        let vgm = VgmFile::from_bytes(&mut Bytes::from(file.clone())).unwrap();
+        assert_eq!(vgm.raw_regions.header_padding, file[0xE8..0x100]);
+        assert_eq!(vgm.raw_regions.trailing, b"\x1Atrailer");
+        assert_eq!(vgm.metadata, metadata);
+
+        let mut buffer = BytesMut::new();
+        vgm.to_bytes(&mut buffer).unwrap();
+        assert_eq!(buffer, file);
+
+        let reparsed = VgmFile::from_bytes(&mut buffer.freeze()).unwrap();
+        assert_eq!(reparsed, vgm);
+        let unique: std::collections::HashSet<VgmFile> = [vgm.clone(), reparsed].into_iter().collect();
+        assert_eq!(unique.len(), 1);
+    }
+
+    #[test]
+    fn test_dac_stream_without_setup_is_a_warning() {
+        // 0x95 starts stream 1, which no 0x90 command set up
+        let file = build_test_vgm(&[0x95, 0x01, 0x00, 0x00, 0x00, 0x66]);
+        let vgm = VgmFile::from_bytes(&mut Bytes::from(file.clone())).unwrap();
+
+        assert!(vgm.validate(file.len()).is_ok());
+        let strict = ValidationConfig { strict_mode: true, ..ValidationConfig::default() };
+        for config in [ValidationConfig::default(), strict] {
+            let warnings = vgm.validate_with_diagnostics(config, file.len()).unwrap();
+            assert_eq!(warnings.len(), 1);
+            assert_eq!(warnings[0].offset, 0x100);
+            assert!(matches!(warnings[0].error, VgmError::InconsistentData { .. }));
+        }
+    }
+
+    #[test]
+    fn test_unparsed_bytes_are_kept() {
+        let mut file = build_test_vgm(&[0x62, 0x66]);
+        file[0x18..0x1C].copy_from_slice(&735u32.to_le_bytes());
+        file[0x7D] = 0x12;
+        file[0xD7] = 0x34;
+        // Tracker notes between the end marker and the GD3 tag
+        file.extend_from_slice(b"notes");
+        let gd3_pos = file.len() as u32;
+        file[0x14..0x18].copy_from_slice(&(gd3_pos - 0x14).to_le_bytes());
+        let mut gd3 = BytesMut::new();
+        VgmMetadata::default().to_bytes(&mut gd3).unwrap();
+        file.extend_from_slice(&gd3);
+        file.extend_from_slice(&[0; 4]);
+
+        let vgm = VgmFile::from_bytes(&mut Bytes::from(file.clone())).unwrap();
+        assert_eq!(vgm.header.reserved, [0x12, 0x00, 0x34]);
+        assert_eq!(vgm.raw_regions.after_commands, b"notes");
+        assert_eq!(vgm.raw_regions.trailing, [0; 4]);
+
+        let mut buffer = BytesMut::new();
+        vgm.to_bytes(&mut buffer).unwrap();
+        assert_eq!(buffer, file);
+
+        // Non-zero unparsed bytes are warnings, even in strict mode
+        let strict = ValidationConfig { strict_mode: true, ..ValidationConfig::default() };
+        assert!(vgm.validate_with_config(strict.clone(), file.len()).is_ok());
+        let warnings = vgm.validate_with_diagnostics(strict.clone(), file.len()).unwrap();
+        assert_eq!(warnings, vgm.validate_with_diagnostics(ValidationConfig::default(), file.len()).unwrap());
+        let found: Vec<(usize, &str)> = warnings
+            .iter()
+            .map(|warning| match &warning.error {
+                VgmError::ValidationFailed { field, .. } => (warning.offset, field.as_str()),
+                other => panic!("unexpected warning {:?}", other),
+            })
+            .collect();
+        assert_eq!(found, vec![(0x7D, "reserved"), (0xD7, "reserved"), (gd3_pos as usize - 5, "after_commands")]);
+        assert!(warnings.iter().all(|warning| warning.severity == Severity::Warning));
+
+        let mut cleared = vgm.clone();
+        cleared.header.reserved = [0; 3];
+        cleared.raw_regions.after_commands.fill(0);
+        assert!(cleared.validate_with_diagnostics(strict, file.len()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_round_trip_layouts() {
+        // 1.70 extra header followed by padding
+        let mut extra = build_test_vgm(&[0x62, 0x66]);
+        extra[0x08..0x0C].copy_from_slice(&[0x70, 0x01, 0x00, 0x00]);
+        extra[0xBC..0xC0].copy_from_slice(&(0xC0u32 - 0xBC).to_le_bytes());
+        extra[0xC0..0xD1].copy_from_slice(&[
+            0x0C, 0x00, 0x00, 0x00, // header size
+            0x00, 0x00, 0x00, 0x00, // no chip clocks
+            0x04, 0x00, 0x00, 0x00, // chip volumes right after
+            0x01, 0x00, 0x01, 0x00, 0x81,
+        ]);
+        extra[0xD1..0x100].fill(0xEE);
+
+        // Data starting inside the first 1.51 field, with no GD3 and a trailer
+        let mut early = build_test_vgm(&[]);
+        early.truncate(0x42);
+        early[0x34..0x38].copy_from_slice(&(0x42u32 - 0x34).to_le_bytes());
+        early.extend_from_slice(&[0x61, 0x10, 0x00, 0x66, 0xDE, 0xAD]);
+        let eof = early.len() as u32 - 4;
+        early[0x04..0x08].copy_from_slice(&eof.to_le_bytes());
+
+        for file in [extra, early] {
+            let vgm = VgmFile::from_bytes(&mut Bytes::from(file.clone())).unwrap();
+            let mut buffer = BytesMut::new();
+            vgm.to_bytes(&mut buffer).unwrap();
+            assert_eq!(buffer, file);
+        }
+    }
+
     #[test]
     fn test_vgm_parse_write_cycle() {
         // Use project-relative paths
@@ -266,7 +822,6 @@ mod tests {
         };
 
         // Compare
-        assert_eq!(vgm.header.version, vgm2.header.version);
-        assert_eq!(vgm.commands.len(), vgm2.commands.len());
+        assert_eq!(vgm2, vgm);
     }
 }
@@ -0,0 +1,127 @@
+//! Loop-aware playback order over a parsed command list.
+
+use crate::Commands;
+
+/// Iterates over commands in playback order: the intro and first pass through
+/// the loop, then the looped section again until the requested number of
+/// loops has played, finishing with `EndOfSoundData`.
+///
+/// Created by [`crate::VgmFile::iter_looped`] or [`LoopedCommands::new`].
+#[derive(Debug, Clone)]
+pub struct LoopedCommands<'a> {
+    commands: &'a [Commands],
+    loop_start: Option<usize>,
+    end: usize,
+    index: usize,
+    remaining_loops: u32,
+    end_emitted: bool,
+}
+
+impl<'a> LoopedCommands<'a> {
+    /// Play `commands` with the loop section starting at `loop_start` played
+    /// `loops` times in total. Without a loop point the commands play once.
+    pub fn new(commands: &'a [Commands], loop_start: Option<usize>, loops: u32) -> Self {
+        // Everything up to the end marker takes part in playback
+        let end = commands
+            .iter()
+            .position(|command| matches!(command, Commands::EndOfSoundData))
+            .unwrap_or(commands.len());
+
+        // A loop point must fall inside the played section and the loop must
+        // contain at least one command, otherwise it's ignored
+        let loop_start = loop_start.filter(|&start| start < end);
+
+        Self {
+            commands,
+            loop_start,
+            end,
+            index: 0,
+            remaining_loops: loops.saturating_sub(1),
+            end_emitted: false,
+        }
+    }
+
+    /// Index into the underlying command slice of the next command yielded
+    pub fn next_index(&self) -> Option<usize> {
+        if self.index < self.end {
+            Some(self.index)
+        } else if let (Some(loop_start), true) = (self.loop_start, self.remaining_loops > 0) {
+            Some(loop_start)
+        } else if !self.end_emitted && self.end < self.commands.len() {
+            Some(self.end)
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> Iterator for LoopedCommands<'a> {
+    type Item = &'a Commands;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.end {
+            match self.loop_start {
+                Some(loop_start) if self.remaining_loops > 0 => {
+                    self.remaining_loops -= 1;
+                    self.index = loop_start;
+                },
+                _ => {
+                    if self.end_emitted || self.end >= self.commands.len() {
+                        return None;
+                    }
+                    self.end_emitted = true;
+                    return Some(&self.commands[self.end]);
+                },
+            }
+        }
+
+        let command = &self.commands[self.index];
+        self.index += 1;
+        Some(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_commands() -> Vec<Commands> {
+        vec![
+            Commands::PSGWrite { value: 0x9F, chip_index: 0 },
+            Commands::Wait735Samples,
+            Commands::PSGWrite { value: 0x90, chip_index: 0 },
+            Commands::Wait882Samples,
+            Commands::EndOfSoundData,
+        ]
+    }
+
+    #[test]
+    fn test_looped_commands_repeats_loop_section() {
+        let commands = sample_commands();
+        let played: Vec<&Commands> = LoopedCommands::new(&commands, Some(2), 3).collect();
+
+        let expected: Vec<&Commands> = [0, 1, 2, 3, 2, 3, 2, 3, 4].iter().map(|&i| &commands[i]).collect();
+        assert_eq!(played, expected);
+    }
+
+    #[test]
+    fn test_looped_commands_without_loop_plays_once() {
+        let commands = sample_commands();
+        assert_eq!(LoopedCommands::new(&commands, None, 5).count(), commands.len());
+
+        // Loop point on the end marker leaves nothing to repeat
+        assert_eq!(LoopedCommands::new(&commands, Some(4), 5).count(), commands.len());
+    }
+
+    #[test]
+    fn test_looped_commands_next_index() {
+        let commands = sample_commands();
+        let mut iter = LoopedCommands::new(&commands, Some(3), 2);
+        let mut indices = Vec::new();
+        while let Some(index) = iter.next_index() {
+            indices.push(index);
+            iter.next();
+        }
+        assert_eq!(indices, vec![0, 1, 2, 3, 3, 4]);
+    }
+}
@@ -7,13 +7,13 @@ use crate::{
     utils::write_string_as_u16_bytes,
 };
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum LanguageData {
     English(Gd3LocaleData),
     Japanese(Gd3LocaleData),
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct Gd3LocaleData {
     //pub Language: Language,
     pub track: String,
@@ -22,7 +22,7 @@ pub struct Gd3LocaleData {
     pub author: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct VgmMetadata {
     pub english_data: Gd3LocaleData,
     pub japanese_data: Gd3LocaleData,
@@ -31,8 +31,75 @@ pub struct VgmMetadata {
     pub notes: String,
 }
 
+/// Size of the fixed GD3 prefix: "Gd3 " magic, version and data length
+pub const GD3_HEADER_SIZE: usize = 12;
+
 impl VgmMetadata {
+    /// Total size of a GD3 tag, prefix included, as declared by its first 12 bytes
+    pub fn gd3_block_size(prefix: &[u8], position: usize) -> VgmResult<usize> {
+        if prefix.len() < GD3_HEADER_SIZE {
+            return Err(VgmError::BufferUnderflow {
+                offset: position,
+                needed: GD3_HEADER_SIZE,
+                available: prefix.len(),
+            });
+        }
+
+        if &prefix[0..4] != b"Gd3 " {
+            return Err(VgmError::InvalidMagicBytes {
+                expected: "Gd3 ".to_string(),
+                found: String::from_utf8_lossy(&prefix[0..4]).to_string(),
+                offset: position,
+            });
+        }
+
+        let data_length = u32::from_le_bytes([prefix[8], prefix[9], prefix[10], prefix[11]]);
+        GD3_HEADER_SIZE
+            .checked_add(data_length as usize)
+            .ok_or(VgmError::IntegerOverflow {
+                operation: "GD3 size calculation".to_string(),
+                details: format!("header {} + data length {}", GD3_HEADER_SIZE, data_length),
+            })
+    }
+
+    /// Parse the GD3 tag referenced by `gd3_offset` out of a complete VGM file.
+    ///
+    /// Returns empty metadata when the header declares no GD3 tag.
+    pub fn from_vgm_bytes(file: &Bytes, gd3_offset: u32, config: &crate::ParserConfig) -> VgmResult<Self> {
+        if gd3_offset == 0 {
+            return Ok(Self::default());
+        }
+
+        let start = (gd3_offset as usize)
+            .checked_add(0x14)
+            .ok_or(VgmError::IntegerOverflow {
+                operation: "GD3 position calculation".to_string(),
+                details: format!("gd3_offset {} + 0x14", gd3_offset),
+            })?;
+        if start >= file.len() {
+            return Err(VgmError::InvalidOffset {
+                field: "gd3_offset".to_string(),
+                offset: gd3_offset,
+                file_size: file.len(),
+            });
+        }
+
+        let size = Self::gd3_block_size(&file[start..], start)?;
+        config.check_metadata_size(size)?;
+
+        let end = start + size;
+        if end > file.len() {
+            return Err(VgmError::TruncatedFile {
+                expected: end,
+                actual: file.len(),
+            });
+        }
+
+        Self::from_bytes_with_config(&mut file.slice(start..end), config)
+    }
+
     /// Parse VGM metadata with resource limits and allocation tracking
+    #[allow(clippy::manual_is_multiple_of)]
     pub fn from_bytes_with_config(data: &mut Bytes, config: &crate::ParserConfig) -> VgmResult<Self> {
         // Check metadata size before processing
         config.check_metadata_size(data.len())?;
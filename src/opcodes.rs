@@ -0,0 +1,1011 @@
+//! Opcode descriptor table.
+//!
+//! Every command the spec defines has one [`OpcodeInfo`] entry giving its
+//! operand layout, the chip it drives and how a second chip is addressed.
+//! Decoding, encoding and [`crate::command_length`] are all driven by the
+//! table, so adding a chip only needs a new entry.
+
+use std::fmt;
+
+use bytes::{Buf, Bytes};
+
+use crate::errors::{VgmError, VgmResult};
+use crate::vgm_commands::{decode_data_block, encode_data_block};
+use crate::{Commands, ParserConfig, ResourceTracker, System};
+
+/// Most operand fields any opcode has
+const MAX_OPERANDS: usize = 5;
+
+/// One operand field following an opcode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// Low bits of the opcode itself, for ranges like `0x7n`; takes no bytes
+    OpcodeNibble,
+    /// Fixed byte, checked when reading
+    Const(u8),
+    U8,
+    U16Le,
+    U16Be,
+    U24Le,
+    U32Le,
+}
+
+impl Operand {
+    /// Number of bytes the operand takes
+    pub const fn size(self) -> usize {
+        match self {
+            Operand::OpcodeNibble => 0,
+            Operand::Const(_) | Operand::U8 => 1,
+            Operand::U16Le | Operand::U16Be => 2,
+            Operand::U24Le => 3,
+            Operand::U32Le => 4,
+        }
+    }
+
+    fn max(self) -> u32 {
+        match self {
+            Operand::OpcodeNibble => 0x0F,
+            Operand::Const(byte) => byte as u32,
+            Operand::U8 => 0xFF,
+            Operand::U16Le | Operand::U16Be => 0xFFFF,
+            Operand::U24Le => 0xFF_FFFF,
+            Operand::U32Le => u32::MAX,
+        }
+    }
+
+    /// Bit 7 of the operand's most significant byte, which selects the second
+    /// chip of [`DualChip::Bit7`] opcodes
+    fn chip_select_bit(self) -> u32 {
+        0x80 << (self.size().saturating_sub(1) * 8)
+    }
+
+    fn read(self, bytes: &mut Bytes) -> u32 {
+        match self {
+            Operand::OpcodeNibble => 0,
+            Operand::Const(_) | Operand::U8 => bytes.get_u8() as u32,
+            Operand::U16Le => bytes.get_u16_le() as u32,
+            Operand::U16Be => bytes.get_u16() as u32,
+            Operand::U24Le => bytes.get_uint_le(3) as u32,
+            Operand::U32Le => bytes.get_u32_le(),
+        }
+    }
+
+    fn write(self, value: u32, out: &mut Vec<u8>) {
+        match self {
+            Operand::OpcodeNibble => {},
+            Operand::Const(_) | Operand::U8 => out.push(value as u8),
+            Operand::U16Le => out.extend((value as u16).to_le_bytes()),
+            Operand::U16Be => out.extend((value as u16).to_be_bytes()),
+            Operand::U24Le => out.extend(&value.to_le_bytes()[..3]),
+            Operand::U32Le => out.extend(value.to_le_bytes()),
+        }
+    }
+}
+
+/// How the second chip of a dual-chip setup is addressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DualChip {
+    /// Single chip only
+    None,
+    /// The second chip has its own opcode
+    Opcode(u8),
+    /// Bit 7 of the operand at this index selects the second chip; for 16-bit
+    /// operands it's bit 7 of the high byte, whatever the byte order
+    Bit7(usize),
+}
+
+/// Operand values read from the stream, handed to an entry's constructor
+struct Operands {
+    opcode: u8,
+    chip_index: u8,
+    values: [u32; MAX_OPERANDS],
+    len: usize,
+}
+
+impl Operands {
+    fn u8(&self, index: usize) -> u8 {
+        self.values[index] as u8
+    }
+
+    fn u16(&self, index: usize) -> u16 {
+        self.values[index] as u16
+    }
+
+    fn u32(&self, index: usize) -> u32 {
+        self.values[index]
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        self.values[..self.len].iter().map(|&value| value as u8).collect()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Build {
+    Operands(fn(&Operands) -> Commands),
+    /// Operands are followed by a payload whose size they give
+    DataBlock,
+}
+
+/// Descriptor of one opcode, or of a range of opcodes sharing a layout
+#[derive(Clone, Copy)]
+pub struct OpcodeInfo {
+    /// First opcode of the entry
+    pub opcode: u8,
+    /// Last opcode of the entry, equal to `opcode` unless it covers a range
+    pub last_opcode: u8,
+    /// Name of the [`Commands`] variant
+    pub name: &'static str,
+    /// Chip the command writes to, when fixed by the opcode
+    pub chip: Option<System>,
+    /// Operand notation as written in the VGM spec
+    pub notation: &'static str,
+    pub operands: &'static [Operand],
+    pub dual_chip: DualChip,
+    build: Build,
+}
+
+impl OpcodeInfo {
+    const fn new(
+        opcode: u8,
+        name: &'static str,
+        chip: Option<System>,
+        notation: &'static str,
+        operands: &'static [Operand],
+        build: fn(&Operands) -> Commands,
+    ) -> Self {
+        Self {
+            opcode,
+            last_opcode: opcode,
+            name,
+            chip,
+            notation,
+            operands,
+            dual_chip: DualChip::None,
+            build: Build::Operands(build),
+        }
+    }
+
+    const fn range(mut self, last_opcode: u8) -> Self {
+        self.last_opcode = last_opcode;
+        self
+    }
+
+    const fn dual(mut self, dual_chip: DualChip) -> Self {
+        self.dual_chip = dual_chip;
+        self
+    }
+
+    /// Number of operand bytes, excluding any data block payload
+    pub fn operand_len(&self) -> usize {
+        self.operands.iter().map(|operand| operand.size()).sum()
+    }
+
+    /// Whether a variable-size payload follows the operands
+    pub fn has_payload(&self) -> bool {
+        matches!(self.build, Build::DataBlock)
+    }
+
+    /// Whether the entry covers an opcode range the spec reserves
+    pub fn is_reserved(&self) -> bool {
+        self.name == "Reserved"
+    }
+}
+
+impl fmt::Debug for OpcodeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OpcodeInfo")
+            .field("opcode", &self.opcode)
+            .field("last_opcode", &self.last_opcode)
+            .field("name", &self.name)
+            .field("chip", &self.chip)
+            .field("notation", &self.notation)
+            .field("dual_chip", &self.dual_chip)
+            .finish()
+    }
+}
+
+impl fmt::Display for OpcodeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:02X}", self.opcode)?;
+        if self.last_opcode != self.opcode {
+            write!(f, "-0x{:02X}", self.last_opcode)?;
+        }
+        if !self.notation.is_empty() {
+            write!(f, " {}", self.notation)?;
+        }
+        write!(f, ": {}", self.name)?;
+        if let Some(chip) = self.chip {
+            write!(f, " ({:?})", chip)?;
+        }
+        match self.dual_chip {
+            DualChip::None => Ok(()),
+            DualChip::Opcode(second) => write!(f, ", second chip 0x{:02X}", second),
+            DualChip::Bit7(index) => write!(f, ", second chip bit 7 of operand {}", index),
+        }
+    }
+}
+
+use Operand::*;
+
+const REG_DATA: &[Operand] = &[U8, U8];
+const OFFSET_LE_DATA: &[Operand] = &[U16Le, U8];
+const OFFSET_BE_DATA: &[Operand] = &[U16Be, U8];
+const PORT_REG_DATA: &[Operand] = &[U8, U8, U8];
+
+const YM2612: Option<System> = Some(System::YM2612);
+
+/// Every opcode of the spec, reserved ranges included
+const OPCODES: &[OpcodeInfo] = &[
+    OpcodeInfo::new(0x31, "AY8910StereoMask", Some(System::AY8910), "dd", &[U8], |o| {
+        Commands::AY8910StereoMask { value: o.u8(0) }
+    }),
+    OpcodeInfo::new(0x32, "Reserved", None, "dd", &[U8], reserved).range(0x3E),
+    OpcodeInfo::new(0x40, "MikeyWrite", Some(System::Mikey), "aa dd", REG_DATA, |o| {
+        Commands::MikeyWrite { register: o.u8(0), value: o.u8(1) }
+    }),
+    OpcodeInfo::new(0x41, "Reserved", None, "dd dd", &[U8, U8], reserved).range(0x4E),
+    OpcodeInfo::new(0x4F, "GameGearPSGStereo", Some(System::SN76489), "dd", &[U8], |o| {
+        Commands::GameGearPSGStereo { value: o.u8(0), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Opcode(0x3F)),
+    OpcodeInfo::new(0x50, "PSGWrite", Some(System::SN76489), "dd", &[U8], |o| {
+        Commands::PSGWrite { value: o.u8(0), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Opcode(0x30)),
+    OpcodeInfo::new(0x51, "YM2413Write", Some(System::YM2413), "aa dd", REG_DATA, |o| {
+        Commands::YM2413Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Opcode(0xA1)),
+    OpcodeInfo::new(0x52, "YM2612Port0Write", YM2612, "aa dd", REG_DATA, |o| {
+        Commands::YM2612Port0Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Opcode(0xA2)),
+    OpcodeInfo::new(0x53, "YM2612Port1Write", YM2612, "aa dd", REG_DATA, |o| {
+        Commands::YM2612Port1Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Opcode(0xA3)),
+    OpcodeInfo::new(0x54, "YM2151Write", Some(System::YM2151), "aa dd", REG_DATA, |o| {
+        Commands::YM2151Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Opcode(0xA4)),
+    OpcodeInfo::new(0x55, "YM2203Write", Some(System::YM2203), "aa dd", REG_DATA, |o| {
+        Commands::YM2203Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Opcode(0xA5)),
+    OpcodeInfo::new(0x56, "YM2608Port0Write", Some(System::YM2608), "aa dd", REG_DATA, |o| {
+        Commands::YM2608Port0Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Opcode(0xA6)),
+    OpcodeInfo::new(0x57, "YM2608Port1Write", Some(System::YM2608), "aa dd", REG_DATA, |o| {
+        Commands::YM2608Port1Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Opcode(0xA7)),
+    OpcodeInfo::new(0x58, "YM2610Port0Write", Some(System::YM2610), "aa dd", REG_DATA, |o| {
+        Commands::YM2610Port0Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Opcode(0xA8)),
+    OpcodeInfo::new(0x59, "YM2610Port1Write", Some(System::YM2610), "aa dd", REG_DATA, |o| {
+        Commands::YM2610Port1Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Opcode(0xA9)),
+    OpcodeInfo::new(0x5A, "YM3812Write", Some(System::YM3812), "aa dd", REG_DATA, |o| {
+        Commands::YM3812Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Opcode(0xAA)),
+    OpcodeInfo::new(0x5B, "YM3526Write", Some(System::YM3526), "aa dd", REG_DATA, |o| {
+        Commands::YM3526Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Opcode(0xAB)),
+    OpcodeInfo::new(0x5C, "Y8950Write", Some(System::Y8950), "aa dd", REG_DATA, |o| {
+        Commands::Y8950Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Opcode(0xAC)),
+    OpcodeInfo::new(0x5D, "YMZ280BWrite", Some(System::YMZ280B), "aa dd", REG_DATA, |o| {
+        Commands::YMZ280BWrite { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Opcode(0xAD)),
+    OpcodeInfo::new(0x5E, "YMF262Port0Write", Some(System::YMF262), "aa dd", REG_DATA, |o| {
+        Commands::YMF262Port0Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Opcode(0xAE)),
+    OpcodeInfo::new(0x5F, "YMF262Port1Write", Some(System::YMF262), "aa dd", REG_DATA, |o| {
+        Commands::YMF262Port1Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Opcode(0xAF)),
+    OpcodeInfo::new(0x61, "WaitNSamples", None, "nnnn", &[U16Le], |o| {
+        Commands::WaitNSamples { n: o.u16(0) }
+    }),
+    OpcodeInfo::new(0x62, "Wait735Samples", None, "", &[], |_| Commands::Wait735Samples),
+    OpcodeInfo::new(0x63, "Wait882Samples", None, "", &[], |_| Commands::Wait882Samples),
+    OpcodeInfo::new(0x66, "EndOfSoundData", None, "", &[], |_| Commands::EndOfSoundData),
+    OpcodeInfo {
+        opcode: 0x67,
+        last_opcode: 0x67,
+        name: "DataBlock",
+        chip: None,
+        notation: "66 tt ss ss ss ss (data)",
+        operands: &[Const(0x66), U8, U32Le],
+        dual_chip: DualChip::None,
+        build: Build::DataBlock,
+    },
+    OpcodeInfo::new(
+        0x68,
+        "PCMRAMWrite",
+        None,
+        "66 cc oo oo oo dd dd dd ss ss ss",
+        &[Const(0x66), U8, U24Le, U24Le, U24Le],
+        |o| Commands::PCMRAMWrite {
+            chip_type: o.u8(1),
+            read_offset: o.u32(2),
+            write_offset: o.u32(3),
+            size: if o.u32(4) == 0 { 0x0100_0000 } else { o.u32(4) },
+        },
+    ),
+    OpcodeInfo::new(0x70, "WaitNSamplesPlus1", None, "", &[OpcodeNibble], |o| {
+        Commands::WaitNSamplesPlus1 { n: o.u8(0) }
+    })
+    .range(0x7F),
+    OpcodeInfo::new(0x80, "YM2612Port0Address2AWriteWait", YM2612, "", &[OpcodeNibble], |o| {
+        Commands::YM2612Port0Address2AWriteWait { n: o.u8(0) }
+    })
+    .range(0x8F),
+    OpcodeInfo::new(0x90, "DACStreamSetupControl", None, "ss tt pp cc", &[U8, U8, U8, U8], |o| {
+        Commands::DACStreamSetupControl {
+            stream_id: o.u8(0),
+            chip_type: o.u8(1),
+            port: o.u8(2),
+            command: o.u8(3),
+            chip_index: o.chip_index,
+        }
+    })
+    .dual(DualChip::Bit7(1)),
+    OpcodeInfo::new(0x91, "DACStreamSetData", None, "ss dd ll bb", &[U8, U8, U8, U8], |o| {
+        Commands::DACStreamSetData {
+            stream_id: o.u8(0),
+            data_bank_id: o.u8(1),
+            step_size: o.u8(2),
+            step_base: o.u8(3),
+        }
+    }),
+    OpcodeInfo::new(0x92, "DACStreamSetFrequency", None, "ss ff ff ff ff", &[U8, U32Le], |o| {
+        Commands::DACStreamSetFrequency { stream_id: o.u8(0), frequency: o.u32(1) }
+    }),
+    OpcodeInfo::new(
+        0x93,
+        "DACStreamStart",
+        None,
+        "ss aa aa aa aa mm ll ll ll ll",
+        &[U8, U32Le, U8, U32Le],
+        |o| Commands::DACStreamStart {
+            stream_id: o.u8(0),
+            data_start_offset: o.u32(1),
+            length_mode: o.u8(2),
+            data_length: o.u32(3),
+        },
+    ),
+    OpcodeInfo::new(0x94, "DACStreamStop", None, "ss", &[U8], |o| {
+        Commands::DACStreamStop { stream_id: o.u8(0) }
+    }),
+    OpcodeInfo::new(0x95, "DACStreamStartFast", None, "ss bb bb ff", &[U8, U16Le, U8], |o| {
+        Commands::DACStreamStartFast { stream_id: o.u8(0), block_id: o.u16(1), flags: o.u8(2) }
+    }),
+    OpcodeInfo::new(0xA0, "AY8910Write", Some(System::AY8910), "aa dd", REG_DATA, |o| {
+        Commands::AY8910Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xB0, "RF5C68Write", Some(System::RF5C68), "aa dd", REG_DATA, |o| {
+        Commands::RF5C68Write { register: o.u8(0), value: o.u8(1) }
+    }),
+    OpcodeInfo::new(0xB1, "RF5C164Write", Some(System::RF5C164), "aa dd", REG_DATA, |o| {
+        Commands::RF5C164Write { register: o.u8(0), value: o.u8(1) }
+    }),
+    // Register in the top 4 bits, value in the low 12
+    OpcodeInfo::new(0xB2, "PWMWrite", Some(System::Pwm), "ad dd", &[U16Be], |o| {
+        Commands::PWMWrite { register: (o.u16(0) >> 12) as u8, value: o.u16(0) & 0x0FFF }
+    }),
+    OpcodeInfo::new(0xB3, "GameBoyDMGWrite", Some(System::GameboyDmg), "aa dd", REG_DATA, |o| {
+        Commands::GameBoyDMGWrite { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xB4, "NESAPUWrite", Some(System::NesApu), "aa dd", REG_DATA, |o| {
+        Commands::NESAPUWrite { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xB5, "MultiPCMWrite", Some(System::MultiPcm), "aa dd", REG_DATA, |o| {
+        Commands::MultiPCMWrite { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xB6, "uPD7759Write", Some(System::UPD7759), "aa dd", REG_DATA, |o| {
+        Commands::uPD7759Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xB7, "OKIM6258Write", Some(System::OKIM6258), "aa dd", REG_DATA, |o| {
+        Commands::OKIM6258Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xB8, "OKIM6295Write", Some(System::OKIM6295), "aa dd", REG_DATA, |o| {
+        Commands::OKIM6295Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xB9, "HuC6280Write", Some(System::HuC6280), "aa dd", REG_DATA, |o| {
+        Commands::HuC6280Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xBA, "K053260Write", Some(System::K053260), "aa dd", REG_DATA, |o| {
+        Commands::K053260Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xBB, "PokeyWrite", Some(System::Pokey), "aa dd", REG_DATA, |o| {
+        Commands::PokeyWrite { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xBC, "WonderSwanWrite", Some(System::WonderSwan), "aa dd", REG_DATA, |o| {
+        Commands::WonderSwanWrite { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xBD, "SAA1099Write", Some(System::SAA1099), "aa dd", REG_DATA, |o| {
+        Commands::SAA1099Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xBE, "ES5506Write", Some(System::ES5506), "aa dd", REG_DATA, |o| {
+        Commands::ES5506Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xBF, "GA20Write", Some(System::GA20), "aa dd", REG_DATA, |o| {
+        Commands::GA20Write { register: o.u8(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xC0, "SegaPCMWrite", Some(System::SegaPcm), "bbaa dd", OFFSET_LE_DATA, |o| {
+        Commands::SegaPCMWrite { offset: o.u16(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    // The RF5C68 and RF5C164 are single chips, like their 0xB0/0xB1 writes
+    OpcodeInfo::new(0xC1, "RF5C68WriteOffset", Some(System::RF5C68), "bbaa dd", OFFSET_LE_DATA, |o| {
+        Commands::RF5C68WriteOffset { offset: o.u16(0), value: o.u8(1) }
+    }),
+    OpcodeInfo::new(0xC2, "RF5C164WriteOffset", Some(System::RF5C164), "bbaa dd", OFFSET_LE_DATA, |o| {
+        Commands::RF5C164WriteOffset { offset: o.u16(0), value: o.u8(1) }
+    }),
+    OpcodeInfo::new(0xC3, "MultiPCMSetBank", Some(System::MultiPcm), "cc bbaa", &[U8, U16Le], |o| {
+        Commands::MultiPCMSetBank { channel: o.u8(0), offset: o.u16(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    // The first byte is the data MSB, leaving no bit to select a second chip
+    OpcodeInfo::new(0xC4, "QSoundWrite", Some(System::QSound), "mmll rr", &[U16Be, U8], |o| {
+        Commands::QSoundWrite { register: o.u8(1), value: o.u16(0) }
+    }),
+    OpcodeInfo::new(0xC5, "SCSPWrite", Some(System::SCSP), "mmll dd", OFFSET_BE_DATA, |o| {
+        Commands::SCSPWrite { offset: o.u16(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xC6, "WonderSwanWrite16", Some(System::WonderSwan), "mmll dd", OFFSET_BE_DATA, |o| {
+        Commands::WonderSwanWrite16 { offset: o.u16(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xC7, "VSUWrite", Some(System::VSU), "mmll dd", OFFSET_BE_DATA, |o| {
+        Commands::VSUWrite { offset: o.u16(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xC8, "X1010Write", Some(System::X1_010), "mmll dd", OFFSET_BE_DATA, |o| {
+        Commands::X1010Write { offset: o.u16(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xC9, "Reserved", None, "dd dd dd", &[U8, U8, U8], reserved).range(0xCF),
+    OpcodeInfo::new(0xD0, "YMF278BWrite", Some(System::YMF278B), "pp aa dd", PORT_REG_DATA, |o| {
+        Commands::YMF278BWrite { port: o.u8(0), register: o.u8(1), value: o.u8(2), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xD1, "YMF271Write", Some(System::YMF271), "pp aa dd", PORT_REG_DATA, |o| {
+        Commands::YMF271Write { port: o.u8(0), register: o.u8(1), value: o.u8(2), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xD2, "SCC1Write", Some(System::K051649), "pp aa dd", PORT_REG_DATA, |o| {
+        Commands::SCC1Write { port: o.u8(0), register: o.u8(1), value: o.u8(2), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xD3, "K054539Write", Some(System::K054539), "pp aa dd", OFFSET_BE_DATA, |o| {
+        Commands::K054539Write { register: o.u16(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xD4, "C140Write", Some(System::C140), "pp aa dd", OFFSET_BE_DATA, |o| {
+        Commands::C140Write { register: o.u16(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xD5, "ES5503Write", Some(System::ES5503), "pp aa dd", OFFSET_BE_DATA, |o| {
+        Commands::ES5503Write { register: o.u16(0), value: o.u8(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xD6, "ES5506Write16", Some(System::ES5506), "aa ddee", &[U8, U16Be], |o| {
+        Commands::ES5506Write16 { register: o.u8(0), value: o.u16(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xD7, "Reserved", None, "dd dd dd", &[U8, U8, U8], reserved).range(0xDF),
+    OpcodeInfo::new(0xE0, "SeekPCM", YM2612, "dddddddd", &[U32Le], |o| {
+        Commands::SeekPCM { offset: o.u32(0) }
+    }),
+    OpcodeInfo::new(0xE1, "C352Write", Some(System::C352), "mmll aadd", &[U16Be, U16Be], |o| {
+        Commands::C352Write { register: o.u16(0), value: o.u16(1), chip_index: o.chip_index }
+    })
+    .dual(DualChip::Bit7(0)),
+    OpcodeInfo::new(0xE2, "Reserved", None, "dd dd dd dd", &[U8, U8, U8, U8], reserved).range(0xFF),
+];
+
+fn reserved(operands: &Operands) -> Commands {
+    Commands::Reserved { opcode: operands.opcode, operands: operands.bytes() }
+}
+
+/// Table entry and chip index for every opcode byte
+const INDEX: [Option<(u8, u8)>; 256] = build_index();
+
+const fn build_index() -> [Option<(u8, u8)>; 256] {
+    let mut index = [None; 256];
+    let mut entry = 0;
+    while entry < OPCODES.len() {
+        let info = &OPCODES[entry];
+        assert!(info.operands.len() <= MAX_OPERANDS);
+
+        let mut opcode = info.opcode as usize;
+        while opcode <= info.last_opcode as usize {
+            assert!(index[opcode].is_none(), "two opcode table entries claim the same opcode");
+            index[opcode] = Some((entry as u8, 0));
+            opcode += 1;
+        }
+        if let DualChip::Opcode(second) = info.dual_chip {
+            assert!(index[second as usize].is_none(), "two opcode table entries claim the same opcode");
+            index[second as usize] = Some((entry as u8, 1));
+        }
+        entry += 1;
+    }
+    index
+}
+
+/// Descriptor for `opcode`, including opcodes that address a second chip.
+/// `None` for bytes the spec doesn't define as an opcode
+pub fn opcode_info(opcode: u8) -> Option<&'static OpcodeInfo> {
+    INDEX[opcode as usize].map(|(entry, _)| &OPCODES[entry as usize])
+}
+
+/// The whole descriptor table, ordered by opcode
+pub fn opcode_table() -> &'static [OpcodeInfo] {
+    OPCODES
+}
+
+/// Decode one command, opcode included, that starts at absolute file
+/// position `position`.
+///
+/// The whole command is checked to be present before anything is consumed.
+pub(crate) fn decode_command(
+    bytes: &mut Bytes,
+    position: usize,
+    config: &ParserConfig,
+    tracker: &mut ResourceTracker,
+) -> VgmResult<Commands> {
+    let Some(&opcode) = bytes.first() else {
+        return Err(VgmError::BufferUnderflow {
+            offset: position,
+            needed: 1,
+            available: 0,
+        });
+    };
+    let Some((entry, chip_index)) = INDEX[opcode as usize] else {
+        return Err(VgmError::UnknownCommand { opcode, position });
+    };
+    let info = &OPCODES[entry as usize];
+
+    let expected_bytes = 1 + info.operand_len();
+    if bytes.remaining() < expected_bytes {
+        return Err(VgmError::IncompleteCommand {
+            opcode,
+            position,
+            expected_bytes,
+            available_bytes: bytes.remaining(),
+        });
+    }
+    let build = match info.build {
+        Build::Operands(build) => build,
+        Build::DataBlock => return decode_data_block(bytes, position, config, tracker),
+    };
+    bytes.advance(1);
+
+    let mut operands = Operands {
+        opcode,
+        chip_index,
+        values: [0; MAX_OPERANDS],
+        len: info.operands.len(),
+    };
+    for (value, operand) in operands.values.iter_mut().zip(info.operands) {
+        *value = match *operand {
+            OpcodeNibble => (opcode - info.opcode) as u32,
+            Const(expected) => {
+                let found = bytes.get_u8();
+                if found != expected {
+                    return Err(VgmError::InvalidCommandParameters {
+                        opcode,
+                        position,
+                        reason: format!(
+                            "Expected compatibility byte 0x{:02X}, found 0x{:02X}",
+                            expected, found
+                        ),
+                    });
+                }
+                found as u32
+            },
+            operand => operand.read(bytes),
+        };
+    }
+
+    if let DualChip::Bit7(index) = info.dual_chip {
+        let select = info.operands[index].chip_select_bit();
+        operands.chip_index = u8::from(operands.values[index] & select != 0);
+        operands.values[index] &= !select;
+    }
+
+    Ok(build(&operands))
+}
+
+/// Encode one command, opcode included
+pub(crate) fn encode_command(command: Commands) -> VgmResult<Vec<u8>> {
+    let (opcode, chip_index, mut values) = match command {
+        Commands::DataBlock { block_type, data } => return Ok(encode_data_block(block_type, data)),
+        command => operand_values(command)?,
+    };
+    let info = opcode_info(opcode).ok_or(VgmError::UnknownCommand { opcode, position: 0 })?;
+
+    if values.len() != info.operands.len() {
+        return Err(invalid_operand(
+            "operands",
+            format!(
+                "{} takes {} operand bytes, got {}",
+                info.name,
+                info.operand_len(),
+                values.len()
+            ),
+        ));
+    }
+
+    let mut opcode = opcode;
+    match (chip_index, info.dual_chip) {
+        (0, _) => {},
+        (1, DualChip::Opcode(second)) => opcode = second,
+        (1, DualChip::Bit7(_)) => {},
+        _ => {
+            return Err(invalid_operand(
+                "chip_index",
+                format!("Invalid chip_index {} for {}, must be 0 or 1", chip_index, info.name),
+            ));
+        },
+    }
+    if let DualChip::Bit7(index) = info.dual_chip {
+        let select = info.operands[index].chip_select_bit();
+        if values[index] >= select {
+            return Err(invalid_operand(
+                info.name,
+                format!("0x{:X} doesn't fit the bits below the chip select bit", values[index]),
+            ));
+        }
+        if chip_index == 1 {
+            values[index] |= select;
+        }
+    }
+
+    let mut out = Vec::with_capacity(1 + info.operand_len());
+    out.push(opcode);
+    for (&value, &operand) in values.iter().zip(info.operands) {
+        let in_range = match operand {
+            Const(expected) => value == expected as u32,
+            operand => value <= operand.max(),
+        };
+        if !in_range {
+            return Err(invalid_operand(
+                info.name,
+                format!("operand 0x{:X} out of range for {:?}", value, operand),
+            ));
+        }
+        if operand == OpcodeNibble {
+            if value > (info.last_opcode - info.opcode) as u32 {
+                return Err(invalid_operand(info.name, format!("{} exceeds the opcode range", value)));
+            }
+            out[0] = info.opcode + value as u8;
+        }
+        operand.write(value, &mut out);
+    }
+
+    Ok(out)
+}
+
+fn invalid_operand(field: &str, details: String) -> VgmError {
+    VgmError::InvalidDataFormat {
+        field: field.to_string(),
+        details,
+    }
+}
+
+/// First-chip opcode, chip index and operand values of a command, in the
+/// order of its table entry's operands
+fn operand_values(command: Commands) -> VgmResult<(u8, u8, Vec<u32>)> {
+    let encoding = match command {
+        Commands::AY8910StereoMask { value } => (0x31, 0, vec![value.into()]),
+        Commands::MikeyWrite { register, value } => (0x40, 0, vec![register.into(), value.into()]),
+        Commands::GameGearPSGStereo { value, chip_index } => (0x4F, chip_index, vec![value.into()]),
+        Commands::PSGWrite { value, chip_index } => (0x50, chip_index, vec![value.into()]),
+        Commands::YM2413Write { register, value, chip_index } => (0x51, chip_index, vec![register.into(), value.into()]),
+        Commands::YM2612Port0Write { register, value, chip_index } => (0x52, chip_index, vec![register.into(), value.into()]),
+        Commands::YM2612Port1Write { register, value, chip_index } => (0x53, chip_index, vec![register.into(), value.into()]),
+        Commands::YM2151Write { register, value, chip_index } => (0x54, chip_index, vec![register.into(), value.into()]),
+        Commands::YM2203Write { register, value, chip_index } => (0x55, chip_index, vec![register.into(), value.into()]),
+        Commands::YM2608Port0Write { register, value, chip_index } => (0x56, chip_index, vec![register.into(), value.into()]),
+        Commands::YM2608Port1Write { register, value, chip_index } => (0x57, chip_index, vec![register.into(), value.into()]),
+        Commands::YM2610Port0Write { register, value, chip_index } => (0x58, chip_index, vec![register.into(), value.into()]),
+        Commands::YM2610Port1Write { register, value, chip_index } => (0x59, chip_index, vec![register.into(), value.into()]),
+        Commands::YM3812Write { register, value, chip_index } => (0x5A, chip_index, vec![register.into(), value.into()]),
+        Commands::YM3526Write { register, value, chip_index } => (0x5B, chip_index, vec![register.into(), value.into()]),
+        Commands::Y8950Write { register, value, chip_index } => (0x5C, chip_index, vec![register.into(), value.into()]),
+        Commands::YMZ280BWrite { register, value, chip_index } => (0x5D, chip_index, vec![register.into(), value.into()]),
+        Commands::YMF262Port0Write { register, value, chip_index } => (0x5E, chip_index, vec![register.into(), value.into()]),
+        Commands::YMF262Port1Write { register, value, chip_index } => (0x5F, chip_index, vec![register.into(), value.into()]),
+        Commands::WaitNSamples { n } => (0x61, 0, vec![n.into()]),
+        Commands::Wait735Samples => (0x62, 0, vec![]),
+        Commands::Wait882Samples => (0x63, 0, vec![]),
+        Commands::EndOfSoundData => (0x66, 0, vec![]),
+        Commands::PCMRAMWrite { chip_type, read_offset, write_offset, size } => {
+            // A size of 0 stands for 0x1000000 bytes
+            let size = if size == 0x0100_0000 { 0 } else { size };
+            (0x68, 0, vec![0x66, chip_type.into(), read_offset, write_offset, size])
+        },
+        Commands::WaitNSamplesPlus1 { n } => (0x70, 0, vec![n.into()]),
+        Commands::YM2612Port0Address2AWriteWait { n } => (0x80, 0, vec![n.into()]),
+        Commands::DACStreamSetupControl { stream_id, chip_type, port, command, chip_index } => {
+            (0x90, chip_index, vec![stream_id.into(), chip_type.into(), port.into(), command.into()])
+        },
+        Commands::DACStreamSetData { stream_id, data_bank_id, step_size, step_base } => {
+            (0x91, 0, vec![stream_id.into(), data_bank_id.into(), step_size.into(), step_base.into()])
+        },
+        Commands::DACStreamSetFrequency { stream_id, frequency } => (0x92, 0, vec![stream_id.into(), frequency]),
+        Commands::DACStreamStart { stream_id, data_start_offset, length_mode, data_length } => {
+            (0x93, 0, vec![stream_id.into(), data_start_offset, length_mode.into(), data_length])
+        },
+        Commands::DACStreamStop { stream_id } => (0x94, 0, vec![stream_id.into()]),
+        Commands::DACStreamStartFast { stream_id, block_id, flags } => {
+            (0x95, 0, vec![stream_id.into(), block_id.into(), flags.into()])
+        },
+        Commands::AY8910Write { register, value, chip_index } => (0xA0, chip_index, vec![register.into(), value.into()]),
+        Commands::RF5C68Write { register, value } => (0xB0, 0, vec![register.into(), value.into()]),
+        Commands::RF5C164Write { register, value } => (0xB1, 0, vec![register.into(), value.into()]),
+        Commands::PWMWrite { register, value } => {
+            if register > 0x0F || value > 0x0FFF {
+                return Err(invalid_operand(
+                    "PWMWrite",
+                    format!("register 0x{:X} must fit 4 bits and value 0x{:X} 12 bits", register, value),
+                ));
+            }
+            (0xB2, 0, vec![(register as u32) << 12 | value as u32])
+        },
+        Commands::GameBoyDMGWrite { register, value, chip_index } => (0xB3, chip_index, vec![register.into(), value.into()]),
+        Commands::NESAPUWrite { register, value, chip_index } => (0xB4, chip_index, vec![register.into(), value.into()]),
+        Commands::MultiPCMWrite { register, value, chip_index } => (0xB5, chip_index, vec![register.into(), value.into()]),
+        Commands::uPD7759Write { register, value, chip_index } => (0xB6, chip_index, vec![register.into(), value.into()]),
+        Commands::OKIM6258Write { register, value, chip_index } => (0xB7, chip_index, vec![register.into(), value.into()]),
+        Commands::OKIM6295Write { register, value, chip_index } => (0xB8, chip_index, vec![register.into(), value.into()]),
+        Commands::HuC6280Write { register, value, chip_index } => (0xB9, chip_index, vec![register.into(), value.into()]),
+        Commands::K053260Write { register, value, chip_index } => (0xBA, chip_index, vec![register.into(), value.into()]),
+        Commands::PokeyWrite { register, value, chip_index } => (0xBB, chip_index, vec![register.into(), value.into()]),
+        Commands::WonderSwanWrite { register, value, chip_index } => (0xBC, chip_index, vec![register.into(), value.into()]),
+        Commands::SAA1099Write { register, value, chip_index } => (0xBD, chip_index, vec![register.into(), value.into()]),
+        Commands::ES5506Write { register, value, chip_index } => (0xBE, chip_index, vec![register.into(), value.into()]),
+        Commands::GA20Write { register, value, chip_index } => (0xBF, chip_index, vec![register.into(), value.into()]),
+        Commands::SegaPCMWrite { offset, value, chip_index } => (0xC0, chip_index, vec![offset.into(), value.into()]),
+        Commands::RF5C68WriteOffset { offset, value } => (0xC1, 0, vec![offset.into(), value.into()]),
+        Commands::RF5C164WriteOffset { offset, value } => (0xC2, 0, vec![offset.into(), value.into()]),
+        Commands::MultiPCMSetBank { channel, offset, chip_index } => (0xC3, chip_index, vec![channel.into(), offset.into()]),
+        Commands::QSoundWrite { register, value } => (0xC4, 0, vec![value.into(), register.into()]),
+        Commands::SCSPWrite { offset, value, chip_index } => (0xC5, chip_index, vec![offset.into(), value.into()]),
+        Commands::WonderSwanWrite16 { offset, value, chip_index } => (0xC6, chip_index, vec![offset.into(), value.into()]),
+        Commands::VSUWrite { offset, value, chip_index } => (0xC7, chip_index, vec![offset.into(), value.into()]),
+        Commands::X1010Write { offset, value, chip_index } => (0xC8, chip_index, vec![offset.into(), value.into()]),
+        Commands::YMF278BWrite { port, register, value, chip_index } => (0xD0, chip_index, vec![port.into(), register.into(), value.into()]),
+        Commands::YMF271Write { port, register, value, chip_index } => (0xD1, chip_index, vec![port.into(), register.into(), value.into()]),
+        Commands::SCC1Write { port, register, value, chip_index } => (0xD2, chip_index, vec![port.into(), register.into(), value.into()]),
+        Commands::K054539Write { register, value, chip_index } => (0xD3, chip_index, vec![register.into(), value.into()]),
+        Commands::C140Write { register, value, chip_index } => (0xD4, chip_index, vec![register.into(), value.into()]),
+        Commands::ES5503Write { register, value, chip_index } => (0xD5, chip_index, vec![register.into(), value.into()]),
+        Commands::ES5506Write16 { register, value, chip_index } => (0xD6, chip_index, vec![register.into(), value.into()]),
+        Commands::SeekPCM { offset } => (0xE0, 0, vec![offset]),
+        Commands::C352Write { register, value, chip_index } => (0xE1, chip_index, vec![register.into(), value.into()]),
+        Commands::Reserved { opcode, operands } => {
+            if opcode_info(opcode).is_none_or(|info| !info.is_reserved()) {
+                return Err(invalid_operand(
+                    "opcode",
+                    format!("0x{:02X} is not a reserved opcode", opcode),
+                ));
+            }
+            (opcode, 0, operands.into_iter().map(u32::from).collect())
+        },
+        Commands::DataBlock { .. } => unreachable!("data blocks are encoded separately"),
+    };
+    Ok(encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Operand bytes for every opcode of `info`, chip select bits clear
+    fn sample_operands(info: &OpcodeInfo) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for (index, operand) in info.operands.iter().enumerate() {
+            match operand {
+                Const(byte) => bytes.push(*byte),
+                operand => bytes.extend((0..operand.size()).map(|i| 0x12 + (index * 4 + i) as u8)),
+            }
+        }
+        bytes
+    }
+
+    /// Position in the command of the byte holding the chip select bit of
+    /// operand `index`: its most significant byte
+    fn select_byte(info: &OpcodeInfo, index: usize) -> usize {
+        let start = 1 + info.operands[..index].iter().map(|operand| operand.size()).sum::<usize>();
+        match info.operands[index] {
+            U16Le => start + 1,
+            _ => start,
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> VgmResult<Commands> {
+        decode_command(
+            &mut Bytes::from(bytes.to_vec()),
+            0,
+            &ParserConfig::default(),
+            &mut ResourceTracker::new(),
+        )
+    }
+
+    #[test]
+    fn test_every_opcode_round_trips() {
+        for info in opcode_table().iter().filter(|info| !info.has_payload()) {
+            let mut opcodes: Vec<u8> = (info.opcode..=info.last_opcode).collect();
+            if let DualChip::Opcode(second) = info.dual_chip {
+                opcodes.push(second);
+            }
+            for opcode in opcodes {
+                let mut bytes = vec![opcode];
+                bytes.extend(sample_operands(info));
+                let command = decode(&bytes).unwrap();
+                assert_eq!(command.clone().to_bytes().unwrap(), bytes, "{}", info);
+                assert_eq!(crate::command_length(&bytes, 0).unwrap(), Some(bytes.len()));
+
+                if let DualChip::Bit7(index) = info.dual_chip {
+                    bytes[select_byte(info, index)] |= 0x80;
+                    let second = decode(&bytes).unwrap();
+                    assert_eq!(second.target().unwrap().chip_index, 1, "{}", info);
+                    assert_eq!(second.to_bytes().unwrap(), bytes);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_table_chips_match_targets() {
+        for info in opcode_table().iter().filter(|info| !info.has_payload()) {
+            let mut bytes = vec![info.opcode];
+            bytes.extend(sample_operands(info));
+            let target = decode(&bytes).unwrap().target();
+            if let Some(chip) = info.chip {
+                assert_eq!(target.map(|target| target.system), Some(chip), "{}", info);
+            }
+        }
+    }
+
+    #[test]
+    fn test_spec_byte_orders() {
+        let cases: Vec<(Vec<u8>, Commands)> = vec![
+            (vec![0xB2, 0x51, 0x23], Commands::PWMWrite { register: 0x5, value: 0x123 }),
+            (vec![0xB7, 0x81, 0x02], Commands::OKIM6258Write { register: 0x01, value: 0x02, chip_index: 1 }),
+            (vec![0xC0, 0x34, 0x12, 0x56], Commands::SegaPCMWrite { offset: 0x1234, value: 0x56, chip_index: 0 }),
+            // The chip select bit is bit 7 of the offset's high byte, which comes second
+            (vec![0xC0, 0x34, 0x92, 0x56], Commands::SegaPCMWrite { offset: 0x1234, value: 0x56, chip_index: 1 }),
+            (vec![0xC2, 0x34, 0x12, 0x56], Commands::RF5C164WriteOffset { offset: 0x1234, value: 0x56 }),
+            (vec![0xC3, 0x05, 0x34, 0x12], Commands::MultiPCMSetBank { channel: 0x05, offset: 0x1234, chip_index: 0 }),
+            (vec![0xC4, 0x12, 0x34, 0x56], Commands::QSoundWrite { register: 0x56, value: 0x1234 }),
+            (vec![0xC5, 0x12, 0x34, 0x56], Commands::SCSPWrite { offset: 0x1234, value: 0x56, chip_index: 0 }),
+            (vec![0xD3, 0x01, 0x23, 0x45], Commands::K054539Write { register: 0x0123, value: 0x45, chip_index: 0 }),
+            (vec![0xD3, 0x81, 0x23, 0x45], Commands::K054539Write { register: 0x0123, value: 0x45, chip_index: 1 }),
+            (vec![0xD6, 0x01, 0x23, 0x45], Commands::ES5506Write16 { register: 0x01, value: 0x2345, chip_index: 0 }),
+            (vec![0xE1, 0x12, 0x34, 0x56, 0x78], Commands::C352Write { register: 0x1234, value: 0x5678, chip_index: 0 }),
+            (
+                vec![0x68, 0x66, 0x01, 0x10, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00],
+                Commands::PCMRAMWrite { chip_type: 0x01, read_offset: 0x10, write_offset: 0x20, size: 0x0100_0000 },
+            ),
+        ];
+
+        for (bytes, command) in cases {
+            assert_eq!(decode(&bytes).unwrap(), command);
+            assert_eq!(command.to_bytes().unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_encode_rejects_out_of_range_operands() {
+        let invalid = [
+            Commands::WaitNSamplesPlus1 { n: 16 },
+            Commands::AY8910Write { register: 0x80, value: 0, chip_index: 0 },
+            Commands::YM2612Port0Write { register: 0, value: 0, chip_index: 2 },
+            Commands::PWMWrite { register: 0x10, value: 0 },
+            Commands::SCC1Write { port: 0x80, register: 0, value: 0, chip_index: 0 },
+            Commands::C140Write { register: 0x8000, value: 0, chip_index: 1 },
+            Commands::Reserved { opcode: 0x50, operands: vec![0] },
+            Commands::Reserved { opcode: 0xE5, operands: vec![0] },
+        ];
+        for command in invalid {
+            assert!(command.clone().to_bytes().is_err(), "{:?}", command);
+        }
+        assert!(matches!(decode(&[0x67, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]), Err(VgmError::InvalidCommandParameters { .. })));
+        assert!(matches!(decode(&[0x60]), Err(VgmError::UnknownCommand { opcode: 0x60, .. })));
+    }
+
+    #[test]
+    fn test_truncated_commands_report_position() {
+        let config = ParserConfig::default();
+        for info in opcode_table() {
+            let mut full = vec![info.opcode];
+            full.extend(sample_operands(info));
+            for len in 1..full.len() {
+                let mut bytes = Bytes::from(full[..len].to_vec());
+                let result = decode_command(&mut bytes, 0x1234, &config, &mut ResourceTracker::new());
+                match result {
+                    Err(VgmError::IncompleteCommand { opcode, position, expected_bytes, available_bytes }) => {
+                        assert_eq!((opcode, position), (info.opcode, 0x1234));
+                        assert_eq!((expected_bytes, available_bytes), (full.len(), len));
+                    },
+                    other => panic!("{}: expected IncompleteCommand, got {:?}", info, other),
+                }
+                // Nothing is consumed, so a caller can resynchronize
+                assert_eq!(bytes.len(), len);
+            }
+        }
+
+        let mut empty = Bytes::new();
+        assert!(matches!(
+            decode_command(&mut empty, 0x40, &config, &mut ResourceTracker::new()),
+            Err(VgmError::BufferUnderflow { offset: 0x40, .. })
+        ));
+        let mut unknown = Bytes::from_static(&[0x64, 0x00]);
+        assert!(matches!(
+            decode_command(&mut unknown, 0x40, &config, &mut ResourceTracker::new()),
+            Err(VgmError::UnknownCommand { opcode: 0x64, position: 0x40 })
+        ));
+    }
+
+    #[test]
+    fn test_truncated_data_blocks() {
+        let config = ParserConfig::default();
+        let short_payload: &[u8] = &[0x67, 0x66, 0x00, 0x10, 0x00, 0x00, 0x00, 0x01, 0x02];
+        let mut bytes = Bytes::from_static(short_payload);
+        assert!(matches!(
+            decode_command(&mut bytes, 0x200, &config, &mut ResourceTracker::new()),
+            Err(VgmError::IncompleteCommand { opcode: 0x67, position: 0x200, expected_bytes: 0x17, available_bytes: 9 })
+        ));
+        // Nothing is consumed, as for other truncated commands
+        assert_eq!(bytes, short_payload);
+
+        // A ROM dump needs 8 bytes of sizes and addresses before its data
+        let short_rom_dump: &[u8] = &[0x67, 0x66, 0x80, 0x02, 0x00, 0x00, 0x00, 0x01, 0x02, 0x62];
+        let mut bytes = Bytes::from_static(short_rom_dump);
+        assert!(matches!(
+            decode_command(&mut bytes, 0x200, &config, &mut ResourceTracker::new()),
+            Err(VgmError::InvalidCommandParameters { opcode: 0x67, position: 0x200, .. })
+        ));
+    }
+
+    #[test]
+    fn test_opcode_docs() {
+        assert_eq!(
+            opcode_info(0xA2).unwrap().to_string(),
+            "0x52 aa dd: YM2612Port0Write (YM2612), second chip 0xA2"
+        );
+        assert_eq!(opcode_info(0x75).unwrap().to_string(), "0x70-0x7F: WaitNSamplesPlus1");
+        assert!(opcode_info(0x64).is_none());
+        assert!(opcode_table().windows(2).all(|pair| pair[0].last_opcode < pair[1].opcode));
+    }
+}
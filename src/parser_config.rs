@@ -1,5 +1,16 @@
 use crate::errors::{VgmError, VgmResult};
 
+/// What the parser does when it meets malformed command data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Stop at the first error
+    #[default]
+    Strict,
+    /// Skip past bad opcodes and data blocks, keeping everything that could be
+    /// decoded and reporting each problem as a [`crate::Diagnostic`]
+    Recover,
+}
+
 /// Configuration for resource management and security limits during VGM parsing
 /// 
 /// This struct controls memory allocation limits and parsing constraints to prevent
@@ -33,6 +44,9 @@ pub struct ParserConfig {
     
     /// Maximum depth for nested parsing operations
     pub max_parsing_depth: u32,
+
+    /// Whether parsing stops at or recovers from malformed commands
+    pub parse_mode: ParseMode,
 }
 
 impl Default for ParserConfig {
@@ -47,6 +61,7 @@ impl Default for ParserConfig {
             strict_resource_limits: false,             // Conservative default
             max_command_memory: 64 * 1024 * 1024,      // 64MB for command vector
             max_parsing_depth: 16,                     // Prevent deep recursion
+            parse_mode: ParseMode::Strict,
         }
     }
 }
@@ -64,6 +79,7 @@ impl ParserConfig {
             strict_resource_limits: true,             // Enable all limits
             max_command_memory: 16 * 1024 * 1024,     // 16MB for commands
             max_parsing_depth: 8,                     // Shallow recursion only
+            parse_mode: ParseMode::Strict,
         }
     }
     
@@ -79,6 +95,7 @@ impl ParserConfig {
             strict_resource_limits: false,            // Relaxed limits
             max_command_memory: 256 * 1024 * 1024,    // 256MB for commands
             max_parsing_depth: 32,                    // Deeper recursion allowed
+            parse_mode: ParseMode::Strict,
         }
     }
     
@@ -283,6 +300,11 @@ impl<'a> AllocationGuard<'a> {
         Self { tracker, config }
     }
     
+    /// Resource tracker this guard allocates against
+    pub fn tracker(&self) -> &ResourceTracker {
+        self.tracker
+    }
+    
     /// Safely allocate a vector with size checking
     pub fn allocate_vec<T>(&mut self, size: usize, purpose: &str) -> VgmResult<Vec<T>> {
         let byte_size = size * std::mem::size_of::<T>();
@@ -390,6 +412,7 @@ mod tests {
     }
     
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_total_data_block_memory_limit() {
         let mut config = ParserConfig::default();
         config.max_total_data_block_memory = 1024; // 1KB limit
@@ -408,6 +431,7 @@ mod tests {
     }
     
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_command_count_limit() {
         let mut config = ParserConfig::default();
         config.max_commands = 5; // Very low limit for testing
@@ -424,6 +448,7 @@ mod tests {
     }
     
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_parsing_depth_tracking() {
         let mut config = ParserConfig::default();
         config.max_parsing_depth = 3;
@@ -0,0 +1,53 @@
+//! Bytes of a file that belong to no parsed structure.
+//!
+//! Keeping them lets [`crate::VgmWriter::to_bytes`] reproduce an unmodified
+//! file byte for byte.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+/// Unparsed regions of a VGM file, re-emitted as-is by the plain and
+/// normalized writers
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RawRegions {
+    /// Bytes between the end of the header (or extra header) and the VGM data
+    pub header_padding: Vec<u8>,
+    /// Bytes between the end of the commands and the GD3 tag
+    pub after_commands: Vec<u8>,
+    /// Bytes after the GD3 tag, or after the commands when there is no tag
+    pub trailing: Vec<u8>,
+}
+
+impl RawRegions {
+    /// Whether the file had nothing outside its parsed structures
+    pub fn is_empty(&self) -> bool {
+        self.header_padding.is_empty() && self.after_commands.is_empty() && self.trailing.is_empty()
+    }
+
+    /// Name and contents of each region holding a non-zero byte
+    pub fn non_zero(&self) -> impl Iterator<Item = (&'static str, &[u8])> {
+        [
+            ("header_padding", &self.header_padding),
+            ("after_commands", &self.after_commands),
+            ("trailing", &self.trailing),
+        ]
+        .into_iter()
+        .filter(|(_, bytes)| bytes.iter().any(|&byte| byte != 0))
+        .map(|(name, bytes)| (name, bytes.as_slice()))
+    }
+}
+
+/// Span of the bytes between the command stream and a GD3 tag placed after it
+pub(crate) fn after_commands_range(commands_end: usize, gd3: Option<&Range<usize>>) -> Range<usize> {
+    match gd3 {
+        Some(gd3) if gd3.start >= commands_end => commands_end..gd3.start,
+        _ => commands_end..commands_end,
+    }
+}
+
+/// Absolute position where trailing bytes start: after whichever of the
+/// command stream and the GD3 tag ends last
+pub(crate) fn trailing_start(commands_end: usize, gd3: Option<&Range<usize>>) -> usize {
+    gd3.map_or(commands_end, |gd3| gd3.end.max(commands_end))
+}
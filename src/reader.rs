@@ -0,0 +1,888 @@
+//! Streaming access to VGM command data.
+//!
+//! [`CommandIter`] decodes commands lazily out of an in-memory [`Bytes`] buffer,
+//! and [`VgmReader`] does the same on top of any `Read + Seek` source so large
+//! files never have to be fully resident in memory.
+
+use std::{
+    io::{ErrorKind, Read, Seek, SeekFrom},
+    ops::Range,
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    diagnostics::Diagnostic,
+    errors::{VgmError, VgmResult},
+    metadata::GD3_HEADER_SIZE,
+    parser_config::{ParseMode, ParserConfig, ResourceTracker},
+    raw_regions::{after_commands_range, trailing_start, RawRegions},
+    vgm_commands::{command_length, Commands},
+    HeaderData, VgmFile, VgmMetadata,
+};
+
+/// Smallest chunk requested from the underlying reader when refilling
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Minimum number of bytes read for the header (all fields up to 1.72)
+const HEADER_READ_SIZE: usize = 0x100;
+
+/// A decoded command together with the absolute file position it was read from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PositionedCommand {
+    pub offset: u32,
+    pub command: Commands,
+}
+
+/// Lazily decodes commands from an in-memory command stream.
+///
+/// Yields `VgmResult<Commands>` items and stops after `EndOfSoundData` or the
+/// first error. Resource limits from the [`ParserConfig`] are enforced as each
+/// command is decoded rather than once the whole stream has been collected.
+///
+/// With [`ParseMode::Recover`] malformed commands are skipped instead and
+/// recorded in [`CommandIter::diagnostics`]; only resource limit errors are
+/// still yielded.
+#[derive(Debug)]
+pub struct CommandIter {
+    data: Bytes,
+    config: ParserConfig,
+    tracker: ResourceTracker,
+    position: usize,
+    /// Position of the command most recently decoded
+    command_start: usize,
+    finished: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl CommandIter {
+    /// Iterate over `data` with the default parser configuration.
+    ///
+    /// `position` is the absolute file position of `data[0]`, used in errors.
+    pub fn new(data: Bytes, position: usize) -> Self {
+        Self::with_config(data, position, ParserConfig::default())
+    }
+
+    /// Iterate over `data` with a custom parser configuration
+    pub fn with_config(data: Bytes, position: usize, config: ParserConfig) -> Self {
+        Self::with_tracker(data, position, config, ResourceTracker::new())
+    }
+
+    /// Iterate over `data`, continuing the accounting of an existing tracker
+    pub fn with_tracker(data: Bytes, position: usize, config: ParserConfig, tracker: ResourceTracker) -> Self {
+        Self {
+            data,
+            config,
+            tracker,
+            position,
+            command_start: position,
+            finished: false,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Absolute file position of the next command
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Resources consumed so far
+    pub fn tracker(&self) -> &ResourceTracker {
+        &self.tracker
+    }
+
+    /// Problems skipped over so far in [`ParseMode::Recover`]
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Take the diagnostics collected so far, leaving none behind
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    /// Whether the iterator has reached `EndOfSoundData` or an error
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Release the unread data and the resource tracker
+    pub fn into_parts(self) -> (Bytes, ResourceTracker) {
+        (self.data, self.tracker)
+    }
+
+    /// Decode the next command along with the file position it starts at
+    pub fn next_positioned(&mut self) -> Option<VgmResult<PositionedCommand>> {
+        self.next().map(|result| {
+            let command = result?;
+            Ok(PositionedCommand { offset: file_offset(self.command_start)?, command })
+        })
+    }
+
+    /// Adapt into an iterator of [`PositionedCommand`]s
+    pub fn positioned(mut self) -> impl Iterator<Item = VgmResult<PositionedCommand>> {
+        std::iter::from_fn(move || self.next_positioned())
+    }
+
+    fn next_command(&mut self) -> VgmResult<Commands> {
+        self.command_start = self.position;
+        self.tracker.track_command(&self.config)?;
+
+        // Decode from a copy so a failed command leaves the stream where it was
+        let mut data = self.data.clone();
+        let command = Commands::from_bytes_at(&mut data, self.position, &self.config, &mut self.tracker)?;
+        self.position += self.data.remaining() - data.remaining();
+        self.data = data;
+
+        Ok(command)
+    }
+
+    /// Skip past a malformed command, returning whether to keep reading
+    fn recover(&mut self, error: VgmError) -> VgmResult<bool> {
+        match Recovery::for_error(&error, &self.data)? {
+            Recovery::Skip(len) => {
+                let len = len.min(self.data.len());
+                record(&mut self.diagnostics, self.position, len, error);
+                self.data.advance(len);
+                self.position += len;
+                Ok(true)
+            },
+            Recovery::Stop => {
+                record(&mut self.diagnostics, self.position, self.data.len(), error);
+                Ok(false)
+            },
+        }
+    }
+}
+
+impl Iterator for CommandIter {
+    type Item = VgmResult<Commands>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let mut result = self.next_command();
+        if self.config.parse_mode == ParseMode::Recover {
+            while let Err(error) = result {
+                match self.recover(error) {
+                    Ok(true) => result = self.next_command(),
+                    Ok(false) => {
+                        self.finished = true;
+                        return None;
+                    },
+                    Err(fatal) => {
+                        result = Err(fatal);
+                        break;
+                    },
+                }
+            }
+        }
+
+        if matches!(result, Ok(Commands::EndOfSoundData) | Err(_)) {
+            self.finished = true;
+        }
+
+        Some(result)
+    }
+}
+
+/// Streaming VGM reader over any seekable byte source.
+///
+/// The header is parsed up front; commands are then read through a small
+/// buffer and yielded one at a time, so only the command being decoded (plus
+/// a read-ahead chunk) is held in memory. Data blocks are checked against the
+/// configured limits before their payload is buffered.
+///
+/// Malformed commands are handled per [`ParseMode`] as in [`CommandIter`].
+#[derive(Debug)]
+pub struct VgmReader<R> {
+    inner: R,
+    header: HeaderData,
+    config: ParserConfig,
+    tracker: ResourceTracker,
+    buffer: BytesMut,
+    position: usize,
+    /// Position of the command most recently decoded
+    command_start: usize,
+    source_exhausted: bool,
+    finished: bool,
+    diagnostics: Vec<Diagnostic>,
+    /// Bytes between the end of the header and the VGM data
+    header_padding: Vec<u8>,
+}
+
+impl<R: Read + Seek> VgmReader<R> {
+    /// Open a reader with the default parser configuration
+    pub fn new(inner: R) -> VgmResult<Self> {
+        Self::with_config(inner, ParserConfig::default())
+    }
+
+    /// Open a reader with a custom parser configuration
+    pub fn with_config(mut inner: R, config: ParserConfig) -> VgmResult<Self> {
+        let mut tracker = ResourceTracker::new();
+
+        inner.seek(SeekFrom::Start(0))?;
+        let mut header_bytes = read_up_to(&mut inner, 0x40)?;
+        if header_bytes.len() < 0x40 {
+            return Err(VgmError::BufferUnderflow {
+                offset: 0,
+                needed: 0x40,
+                available: header_bytes.len(),
+            });
+        }
+
+        // Work out where the VGM data starts before reading the rest of the header
+        let probe = HeaderData {
+            vgm_data_offset: u32::from_le_bytes([header_bytes[0x34], header_bytes[0x35], header_bytes[0x36], header_bytes[0x37]]),
+            ..Default::default()
+        };
+        let vgm_start = probe.vgm_data_start()?;
+
+        let header_size = vgm_start.max(HEADER_READ_SIZE);
+        header_bytes.extend(read_up_to(&mut inner, header_size - 0x40)?);
+        if header_bytes.len() < vgm_start {
+            return Err(VgmError::TruncatedFile {
+                expected: vgm_start,
+                actual: header_bytes.len(),
+            });
+        }
+
+        let header_bytes = Bytes::from(header_bytes);
+        let mut remaining = header_bytes.clone();
+        let header = HeaderData::from_bytes_with_config(&mut remaining, &config, &mut tracker)?;
+        let header_end = header_bytes.len() - remaining.len();
+        let header_padding = header_bytes[header_end.min(vgm_start)..vgm_start].to_vec();
+
+        inner.seek(SeekFrom::Start(vgm_start as u64))?;
+
+        Ok(Self {
+            inner,
+            header,
+            config,
+            tracker,
+            buffer: BytesMut::new(),
+            position: vgm_start,
+            command_start: vgm_start,
+            source_exhausted: false,
+            finished: false,
+            diagnostics: Vec::new(),
+            header_padding,
+        })
+    }
+
+    /// Parsed header of the file
+    pub fn header(&self) -> &HeaderData {
+        &self.header
+    }
+
+    /// Absolute file position of the next command
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Resources consumed so far
+    pub fn tracker(&self) -> &ResourceTracker {
+        &self.tracker
+    }
+
+    /// Problems skipped over so far in [`ParseMode::Recover`]
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Read the GD3 tag without disturbing command iteration
+    pub fn read_metadata(&mut self) -> VgmResult<VgmMetadata> {
+        Ok(self.read_gd3()?.map(|(metadata, _)| metadata).unwrap_or_default())
+    }
+
+    /// Read the GD3 tag, if any, along with the file span it occupies
+    fn read_gd3(&mut self) -> VgmResult<Option<(VgmMetadata, Range<usize>)>> {
+        let gd3_offset = self.header.gd3_offset;
+        if gd3_offset == 0 {
+            return Ok(None);
+        }
+
+        let gd3_pos = gd3_offset as u64 + 0x14;
+        let resume_at = self.inner.stream_position()?;
+        let result = self.read_metadata_at(gd3_pos);
+        self.inner.seek(SeekFrom::Start(resume_at))?;
+
+        result.map(|(metadata, size)| Some((metadata, gd3_pos as usize..gd3_pos as usize + size)))
+    }
+
+    /// Read every remaining command and the GD3 tag into a [`VgmFile`]
+    pub fn into_vgm_file(self) -> VgmResult<VgmFile> {
+        self.into_vgm_file_with_diagnostics().map(|(file, _)| file)
+    }
+
+    /// Like [`VgmReader::into_vgm_file`], also returning every problem
+    /// recovered from in [`ParseMode::Recover`]
+    pub fn into_vgm_file_with_diagnostics(mut self) -> VgmResult<(VgmFile, Vec<Diagnostic>)> {
+        let mut gd3 = None;
+        let metadata = match self.read_gd3() {
+            Ok(Some((metadata, span))) => {
+                gd3 = Some(span);
+                metadata
+            },
+            Ok(None) => VgmMetadata::default(),
+            Err(error) if self.config.parse_mode == ParseMode::Recover => {
+                let offset = self.header.gd3_offset as usize + 0x14;
+                self.diagnostics.push(Diagnostic::error(offset, 0, error));
+                VgmMetadata::default()
+            },
+            Err(error) => return Err(error),
+        };
+
+        let mut commands = Vec::new();
+        let mut command_offsets = Vec::new();
+        while let Some(positioned) = self.next_positioned() {
+            let positioned = positioned?;
+            command_offsets.push(positioned.offset);
+            commands.push(positioned.command);
+        }
+
+        let after_commands = after_commands_range(self.position, gd3.as_ref());
+        self.inner.seek(SeekFrom::Start(after_commands.start as u64))?;
+        let after_commands = read_up_to(&mut self.inner, after_commands.len())?;
+
+        let mut trailing = Vec::new();
+        self.inner.seek(SeekFrom::Start(trailing_start(self.position, gd3.as_ref()) as u64))?;
+        self.inner.read_to_end(&mut trailing)?;
+
+        let file = VgmFile {
+            header: self.header,
+            commands,
+            command_offsets,
+            metadata,
+            raw_regions: RawRegions { header_padding: self.header_padding, after_commands, trailing },
+        };
+        Ok((file, self.diagnostics))
+    }
+
+    /// Release the underlying reader
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Decode the next command along with the file position it starts at
+    pub fn next_positioned(&mut self) -> Option<VgmResult<PositionedCommand>> {
+        self.next().map(|result| {
+            let command = result?;
+            Ok(PositionedCommand { offset: file_offset(self.command_start)?, command })
+        })
+    }
+
+    /// Adapt into an iterator of [`PositionedCommand`]s
+    pub fn positioned(mut self) -> impl Iterator<Item = VgmResult<PositionedCommand>> {
+        std::iter::from_fn(move || self.next_positioned())
+    }
+
+    fn read_metadata_at(&mut self, gd3_pos: u64) -> VgmResult<(VgmMetadata, usize)> {
+        self.inner.seek(SeekFrom::Start(gd3_pos))?;
+
+        let mut gd3 = read_up_to(&mut self.inner, GD3_HEADER_SIZE)?;
+        let size = VgmMetadata::gd3_block_size(&gd3, gd3_pos as usize)?;
+        self.config.check_metadata_size(size)?;
+
+        gd3.extend(read_up_to(&mut self.inner, size - GD3_HEADER_SIZE)?);
+        if gd3.len() < size {
+            return Err(VgmError::TruncatedFile {
+                expected: gd3_pos as usize + size,
+                actual: gd3_pos as usize + gd3.len(),
+            });
+        }
+
+        let metadata = VgmMetadata::from_bytes_with_config(&mut Bytes::from(gd3), &self.config)?;
+        Ok((metadata, size))
+    }
+
+    /// Buffer at least `wanted` bytes, unless the source runs out first
+    fn fill_buffer(&mut self, wanted: usize) -> VgmResult<()> {
+        while self.buffer.len() < wanted && !self.source_exhausted {
+            let start = self.buffer.len();
+            let chunk = READ_CHUNK_SIZE.max(wanted - start);
+            self.buffer.resize(start + chunk, 0);
+
+            let read = loop {
+                match self.inner.read(&mut self.buffer[start..]) {
+                    Ok(read) => break read,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        self.buffer.truncate(start);
+                        return Err(e.into());
+                    },
+                }
+            };
+
+            self.buffer.truncate(start + read);
+            if read == 0 {
+                self.source_exhausted = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn next_command(&mut self) -> VgmResult<Commands> {
+        self.command_start = self.position;
+        let length = loop {
+            if let Some(length) = command_length(&self.buffer, self.position)? {
+                break length;
+            }
+
+            let buffered = self.buffer.len();
+            self.fill_buffer(buffered + 1)?;
+            if self.buffer.len() == buffered {
+                return match self.buffer.first() {
+                    Some(&opcode) => Err(VgmError::IncompleteCommand {
+                        opcode,
+                        position: self.position,
+                        expected_bytes: buffered + 1,
+                        available_bytes: buffered,
+                    }),
+                    None => Err(VgmError::BufferUnderflow {
+                        offset: self.position,
+                        needed: 1,
+                        available: 0,
+                    }),
+                };
+            }
+        };
+
+        // Refuse oversized data blocks before buffering their payload
+        if self.buffer[0] == 0x67 {
+            self.config.check_data_block_size((length - 7) as u32)?;
+        }
+
+        self.fill_buffer(length)?;
+        if self.buffer.len() < length {
+            return Err(VgmError::IncompleteCommand {
+                opcode: self.buffer[0],
+                position: self.position,
+                expected_bytes: length,
+                available_bytes: self.buffer.len(),
+            });
+        }
+
+        self.tracker.track_command(&self.config)?;
+
+        let command_bytes = self.buffer.split_to(length).freeze();
+        let command = match Commands::from_bytes_at(&mut command_bytes.clone(), self.position, &self.config, &mut self.tracker) {
+            Ok(command) => command,
+            Err(error) => {
+                // Put the bytes back so recovery sees the failed command
+                let mut restored = BytesMut::from(&command_bytes[..]);
+                restored.unsplit(std::mem::take(&mut self.buffer));
+                self.buffer = restored;
+                return Err(error);
+            },
+        };
+        self.position += length;
+
+        Ok(command)
+    }
+
+    /// Skip past a malformed command, returning whether to keep reading
+    fn recover(&mut self, error: VgmError) -> VgmResult<bool> {
+        match Recovery::for_error(&error, &self.buffer)? {
+            Recovery::Skip(len) => {
+                let skipped = self.skip_bytes(len)?;
+                record(&mut self.diagnostics, self.position, skipped, error);
+                self.position += skipped;
+                Ok(true)
+            },
+            Recovery::Stop => {
+                record(&mut self.diagnostics, self.position, self.buffer.len(), error);
+                Ok(false)
+            },
+        }
+    }
+
+    /// Drop `len` bytes from the buffer and beyond it, without buffering them
+    fn skip_bytes(&mut self, len: usize) -> VgmResult<usize> {
+        if len <= self.buffer.len() {
+            self.buffer.advance(len);
+            return Ok(len);
+        }
+
+        let buffered = self.buffer.len();
+        self.buffer.clear();
+        let wanted = (len - buffered) as u64;
+        let skipped = std::io::copy(&mut (&mut self.inner).take(wanted), &mut std::io::sink())?;
+        if skipped < wanted {
+            self.source_exhausted = true;
+        }
+
+        Ok(buffered + skipped as usize)
+    }
+}
+
+impl<R: Read + Seek> Iterator for VgmReader<R> {
+    type Item = VgmResult<Commands>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let mut result = self.next_command();
+        if self.config.parse_mode == ParseMode::Recover {
+            while let Err(error) = result {
+                match self.recover(error) {
+                    Ok(true) => result = self.next_command(),
+                    Ok(false) => {
+                        self.finished = true;
+                        return None;
+                    },
+                    Err(fatal) => {
+                        result = Err(fatal);
+                        break;
+                    },
+                }
+            }
+        }
+
+        if matches!(result, Ok(Commands::EndOfSoundData) | Err(_)) {
+            self.finished = true;
+        }
+
+        Some(result)
+    }
+}
+
+/// How [`ParseMode::Recover`] gets past a decode error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Recovery {
+    /// Drop this many bytes and resume decoding after them
+    Skip(usize),
+    /// Give up on the rest of the command stream
+    Stop,
+}
+
+impl Recovery {
+    /// Decide how to resynchronize after `error`, given the bytes starting at
+    /// the failed command. Resource limit and other fatal errors are returned.
+    fn for_error(error: &VgmError, pending: &[u8]) -> VgmResult<Self> {
+        // A data block whose framing is intact can be skipped as a whole
+        let whole_block = || match command_length(pending, 0) {
+            Ok(Some(length)) if pending.get(1) == Some(&0x66) => Some(length),
+            _ => None,
+        };
+
+        match error {
+            VgmError::UnknownCommand { .. } => Ok(Self::Skip(1)),
+            VgmError::InvalidCommandParameters { opcode: 0x67, .. } => Ok(Self::Skip(whole_block().unwrap_or(1))),
+            VgmError::InvalidCommandParameters { .. } => Ok(Self::Skip(1)),
+            VgmError::DataSizeExceedsLimit { field, .. }
+                if field == "data_block_size" || field == "total_data_block_memory" =>
+            {
+                Ok(Self::Skip(whole_block().unwrap_or(1)))
+            },
+            // The size may be corrupt, so look for commands right after the
+            // block header rather than giving up on the rest of the file
+            VgmError::IncompleteCommand { opcode: 0x67, .. } if pending.len() > 7 => Ok(Self::Skip(7)),
+            VgmError::IncompleteCommand { .. } | VgmError::BufferUnderflow { .. } => Ok(Self::Stop),
+            _ => Err(error.clone()),
+        }
+    }
+}
+
+/// Record a recovered error, merging runs of unknown opcodes into one entry
+fn record(diagnostics: &mut Vec<Diagnostic>, offset: usize, skipped: usize, error: VgmError) {
+    if let Some(last) = diagnostics.last_mut() {
+        let contiguous = last.offset + last.skipped == offset;
+        if contiguous
+            && matches!(last.error, VgmError::UnknownCommand { .. })
+            && matches!(error, VgmError::UnknownCommand { .. })
+        {
+            last.skipped += skipped;
+            return;
+        }
+    }
+
+    let diagnostic = match error {
+        // Running out of data only loses the end marker
+        VgmError::BufferUnderflow { .. } => Diagnostic::warning(offset, error),
+        _ => Diagnostic::error(offset, skipped, error),
+    };
+    diagnostics.push(diagnostic);
+}
+
+/// Narrow a file position to the 32-bit offsets used throughout the format
+pub(crate) fn file_offset(position: usize) -> VgmResult<u32> {
+    u32::try_from(position).map_err(|_| VgmError::IntegerOverflow {
+        operation: "command offset conversion".to_string(),
+        details: format!("position {} does not fit in 32 bits", position),
+    })
+}
+
+/// Read up to `len` bytes, stopping early only at end of input
+fn read_up_to<R: Read>(reader: &mut R, len: usize) -> VgmResult<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(len as u64).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use bytes::BufMut;
+
+    use super::*;
+    use crate::tests::build_test_vgm_with_gd3;
+    use crate::{Gd3LocaleData, Severity};
+
+    fn sample_metadata() -> VgmMetadata {
+        VgmMetadata {
+            english_data: Gd3LocaleData {
+                track: "Stage 1".to_string(),
+                game: "Test Game".to_string(),
+                system: "Mega Drive".to_string(),
+                author: "Composer".to_string(),
+            },
+            notes: "streamed".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Build a v1.51 file with data at 0x100 followed by the sample GD3 tag
+    fn build_vgm(commands: &[u8]) -> Vec<u8> {
+        build_test_vgm_with_gd3(commands, Some(&sample_metadata()))
+    }
+
+    fn sample_commands() -> Vec<u8> {
+        let mut commands = BytesMut::new();
+        commands.put(&[0x50, 0x9F][..]);
+        commands.put(&[0x67, 0x66, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01, 0x02, 0x03, 0x04][..]);
+        commands.put(&[0x61, 0x44, 0xAC][..]);
+        commands.put(&[0x62, 0x66][..]);
+        commands.to_vec()
+    }
+
+    #[test]
+    fn test_command_iter_yields_until_end_of_data() {
+        let data = Bytes::from(vec![0x50, 0x9F, 0x62, 0x66, 0xFF]);
+        let mut iter = CommandIter::new(data, 0x40);
+
+        assert_eq!(iter.next().unwrap().unwrap(), Commands::PSGWrite { value: 0x9F, chip_index: 0 });
+        assert_eq!(iter.position(), 0x42);
+        assert_eq!(iter.next().unwrap().unwrap(), Commands::Wait735Samples);
+        assert_eq!(iter.next().unwrap().unwrap(), Commands::EndOfSoundData);
+        assert!(iter.next().is_none());
+        assert!(iter.is_finished());
+
+        // Trailing bytes after the end marker are left untouched
+        let (remaining, tracker) = iter.into_parts();
+        assert_eq!(&remaining[..], &[0xFF]);
+        assert_eq!(tracker.command_count, 3);
+    }
+
+    #[test]
+    fn test_command_iter_positioned() {
+        let data = Bytes::from(vec![0x52, 0x2A, 0x80, 0x61, 0x10, 0x00, 0x66]);
+        let positioned: Vec<PositionedCommand> = CommandIter::new(data, 0x100)
+            .positioned()
+            .collect::<VgmResult<_>>()
+            .unwrap();
+
+        let offsets: Vec<u32> = positioned.iter().map(|p| p.offset).collect();
+        assert_eq!(offsets, vec![0x100, 0x103, 0x106]);
+        assert_eq!(positioned[1].command, Commands::WaitNSamples { n: 0x10 });
+    }
+
+    #[test]
+    fn test_command_iter_reports_truncation() {
+        let data = Bytes::from(vec![0x52, 0x2A]);
+        let mut iter = CommandIter::new(data, 0x100);
+
+        match iter.next() {
+            Some(Err(VgmError::IncompleteCommand { opcode, position, expected_bytes, available_bytes })) => {
+                assert_eq!(opcode, 0x52);
+                assert_eq!(position, 0x100);
+                assert_eq!(expected_bytes, 3);
+                assert_eq!(available_bytes, 2);
+            },
+            other => panic!("Expected IncompleteCommand, got {:?}", other),
+        }
+        assert!(iter.next().is_none());
+
+        // Running out of data without an end marker is an error, not a panic
+        let mut iter = CommandIter::new(Bytes::from(vec![0x62]), 0);
+        assert!(iter.next().unwrap().is_ok());
+        assert!(matches!(iter.next(), Some(Err(VgmError::BufferUnderflow { offset: 1, .. }))));
+    }
+
+    #[test]
+    fn test_command_iter_enforces_limits_incrementally() {
+        let config = ParserConfig {
+            max_commands: 2,
+            ..ParserConfig::default()
+        };
+        let data = Bytes::from(vec![0x62, 0x62, 0x62, 0x66]);
+        let results: Vec<_> = CommandIter::with_config(data, 0, config).collect();
+
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_ok());
+        assert!(matches!(results[2], Err(VgmError::DataSizeExceedsLimit { .. })));
+    }
+
+    fn recover_config() -> ParserConfig {
+        ParserConfig {
+            parse_mode: ParseMode::Recover,
+            ..ParserConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_command_iter_recovers_from_bad_opcodes() {
+        let data = Bytes::from(vec![0x50, 0x9F, 0x64, 0x64, 0x64, 0x62, 0x66]);
+        assert!(matches!(
+            CommandIter::new(data.clone(), 0x100).collect::<VgmResult<Vec<_>>>(),
+            Err(VgmError::UnknownCommand { opcode: 0x64, position: 0x102 })
+        ));
+
+        let mut iter = CommandIter::with_config(data, 0x100, recover_config());
+        let offsets: Vec<u32> = std::iter::from_fn(|| iter.next_positioned()).map(|p| p.unwrap().offset).collect();
+        assert_eq!(offsets, vec![0x100, 0x105, 0x106]);
+
+        // The run of bad bytes is reported once
+        assert_eq!(
+            iter.diagnostics(),
+            &[Diagnostic::error(0x102, 3, VgmError::UnknownCommand { opcode: 0x64, position: 0x102 })]
+        );
+    }
+
+    #[test]
+    fn test_command_iter_recovers_from_bad_data_blocks() {
+        // Over the size limit but intact: skipped whole
+        let config = ParserConfig { max_data_block_size: 2, ..recover_config() };
+        let data = Bytes::from(vec![0x67, 0x66, 0x00, 0x04, 0x00, 0x00, 0x00, 1, 2, 3, 4, 0x62, 0x66]);
+        let mut iter = CommandIter::with_config(data, 0x100, config);
+        assert_eq!(iter.by_ref().collect::<VgmResult<Vec<_>>>().unwrap(), vec![Commands::Wait735Samples, Commands::EndOfSoundData]);
+        assert_eq!(iter.diagnostics()[0].skipped, 11);
+
+        // Claims more data than the file holds: resume after the block header
+        let data = Bytes::from(vec![0x67, 0x66, 0x00, 0xFF, 0x00, 0x00, 0x00, 0x62, 0x66]);
+        let mut iter = CommandIter::with_config(data, 0x100, recover_config());
+        assert_eq!(iter.by_ref().collect::<VgmResult<Vec<_>>>().unwrap(), vec![Commands::Wait735Samples, Commands::EndOfSoundData]);
+        let diagnostic = &iter.diagnostics()[0];
+        assert_eq!((diagnostic.offset, diagnostic.skipped, diagnostic.severity), (0x100, 7, Severity::Error));
+        assert!(matches!(diagnostic.error, VgmError::IncompleteCommand { opcode: 0x67, .. }));
+    }
+
+    #[test]
+    fn test_command_iter_recovers_at_end_of_data() {
+        let mut iter = CommandIter::with_config(Bytes::from(vec![0x62, 0x52, 0x2A]), 0, recover_config());
+        assert_eq!(iter.by_ref().collect::<VgmResult<Vec<_>>>().unwrap(), vec![Commands::Wait735Samples]);
+        assert!(iter.is_finished());
+        assert_eq!((iter.diagnostics()[0].offset, iter.diagnostics()[0].skipped), (1, 2));
+
+        // A missing end marker loses nothing
+        let mut iter = CommandIter::with_config(Bytes::from(vec![0x62]), 0, recover_config());
+        assert_eq!(iter.by_ref().count(), 1);
+        assert_eq!(iter.diagnostics()[0].severity, Severity::Warning);
+
+        // Resource limits are never skipped over
+        let config = ParserConfig { max_commands: 1, ..recover_config() };
+        let results: Vec<_> = CommandIter::with_config(Bytes::from(vec![0x62, 0x62, 0x66]), 0, config).collect();
+        assert!(matches!(results[1], Err(VgmError::DataSizeExceedsLimit { .. })));
+        assert_eq!(results.len(), 2);
+    }
+
+    #[test]
+    fn test_vgm_reader_recovers_like_in_memory_parse() {
+        let mut commands = vec![0x50, 0x9F, 0x64, 0x67, 0x66, 0x00];
+        commands.extend_from_slice(&100_000u32.to_le_bytes());
+        commands.resize(commands.len() + 100_000, 0x64);
+        commands.extend_from_slice(&[0x62, 0x66]);
+        let file = build_vgm(&commands);
+        let config = ParserConfig { max_data_block_size: 1000, ..recover_config() };
+
+        let (streamed, streamed_diagnostics) = VgmReader::with_config(Cursor::new(file.clone()), config.clone())
+            .unwrap()
+            .into_vgm_file_with_diagnostics()
+            .unwrap();
+        let (in_memory, diagnostics) = VgmFile::from_bytes_with_diagnostics(&mut Bytes::from(file), config).unwrap();
+
+        assert_eq!(streamed.commands, in_memory.commands);
+        assert_eq!(streamed.command_offsets, vec![0x100, 0x103 + 100_007, 0x104 + 100_007]);
+        assert_eq!(streamed.command_offsets, in_memory.command_offsets);
+        assert_eq!(streamed.metadata, sample_metadata());
+        assert_eq!(streamed_diagnostics, diagnostics);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!((diagnostics[1].offset, diagnostics[1].skipped), (0x103, 100_007));
+    }
+
+    #[test]
+    fn test_vgm_reader_streams_commands() {
+        let file = build_vgm(&sample_commands());
+        let mut reader = VgmReader::new(Cursor::new(file)).unwrap();
+
+        assert_eq!(reader.header().version, 151);
+        assert_eq!(reader.header().sn76489_clock, 3_579_545);
+        assert_eq!(reader.position(), 0x100);
+
+        let commands: Vec<Commands> = reader.by_ref().collect::<VgmResult<_>>().unwrap();
+        assert_eq!(commands.len(), 5);
+        assert!(matches!(commands[1], Commands::DataBlock { block_type: 0x00, .. }));
+        assert_eq!(commands[2], Commands::WaitNSamples { n: 0xAC44 });
+        assert_eq!(commands[4], Commands::EndOfSoundData);
+        assert_eq!(reader.position(), 0x100 + sample_commands().len());
+        assert_eq!(reader.tracker().data_block_count, 1);
+
+        assert_eq!(reader.read_metadata().unwrap(), sample_metadata());
+    }
+
+    #[test]
+    fn test_vgm_reader_positioned() {
+        let file = build_vgm(&sample_commands());
+        let reader = VgmReader::new(Cursor::new(file)).unwrap();
+
+        let offsets: Vec<u32> = reader.positioned().map(|p| p.unwrap().offset).collect();
+        assert_eq!(offsets, vec![0x100, 0x102, 0x10D, 0x110, 0x111]);
+    }
+
+    #[test]
+    fn test_vgm_reader_matches_in_memory_parse() {
+        let mut file = build_vgm(&[sample_commands(), b"notes".to_vec()].concat());
+        file[0xF0..0x100].fill(0xA5);
+        file.extend_from_slice(b"trailer");
+
+        let streamed = VgmReader::new(Cursor::new(file.clone())).unwrap().into_vgm_file().unwrap();
+        let in_memory = VgmFile::from_bytes_with_config(&mut Bytes::from(file), ParserConfig::default()).unwrap();
+
+        assert_eq!(streamed, in_memory);
+        assert_eq!(streamed.metadata, sample_metadata());
+        assert_eq!(streamed.raw_regions.header_padding[0x08..], [0xA5; 0x10]);
+        assert_eq!(streamed.raw_regions.after_commands, b"notes");
+        assert_eq!(streamed.raw_regions.trailing, b"trailer");
+    }
+
+    #[test]
+    fn test_vgm_reader_rejects_oversized_data_block_before_buffering() {
+        let mut commands = vec![0x67, 0x66, 0x00];
+        commands.extend_from_slice(&(64 * 1024 * 1024u32).to_le_bytes());
+        let file = build_vgm(&commands);
+
+        let mut reader = VgmReader::new(Cursor::new(file)).unwrap();
+        assert!(matches!(reader.next(), Some(Err(VgmError::DataSizeExceedsLimit { .. }))));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_vgm_reader_truncated_stream() {
+        let mut file = build_vgm(&[0x52, 0x2A, 0x00, 0x66]);
+        file.truncate(0x102);
+
+        let mut reader = VgmReader::new(Cursor::new(file)).unwrap();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(VgmError::IncompleteCommand { opcode: 0x52, position: 0x100, .. }))
+        ));
+    }
+}
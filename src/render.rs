@@ -0,0 +1,420 @@
+//! Software rendering of parsed files to PCM audio.
+//!
+//! [`Renderer`] replays commands against the emulated chips, producing one
+//! stereo frame per VGM sample. Chips without an emulation core are silent,
+//! and the YM2203, YM2608 and YM2610 play their SSG part only.
+//! DAC streams (commands `0x90`-`0x95`) are played for chips with a core,
+//! forwards only.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::{Ay8910, Commands, DataBanks, HeaderData, Sn76489, System, VgmError, VgmFile, VgmResult, Ym2151, Ym2612, VGM_SAMPLE_RATE};
+
+/// Number of channels in rendered audio, interleaved left then right
+pub const RENDER_CHANNELS: u16 = 2;
+
+/// An emulated sound chip
+pub trait SoundChip {
+    /// Apply a command addressed to this chip
+    fn write(&mut self, command: &Commands);
+
+    /// Produce the next left and right output sample at [`VGM_SAMPLE_RATE`],
+    /// roughly within -1.0..=1.0
+    fn next_sample(&mut self) -> (f32, f32);
+}
+
+/// Stream of chip writes from a data bank, set up by commands `0x90`-`0x95`
+#[derive(Debug, Clone, Default)]
+struct DacStream {
+    chip_type: u8,
+    chip_index: u8,
+    port: u8,
+    register: u8,
+    bank: u8,
+    step_size: u8,
+    step_base: u8,
+    frequency: u32,
+    /// Bank offset playback starts from, before the step base
+    start: usize,
+    /// Bank offset of the next write
+    position: usize,
+    /// Writes left before the stream ends or loops, `None` when stopped
+    remaining: Option<usize>,
+    length: usize,
+    looping: bool,
+    /// Fraction of a write accumulated over the output samples
+    fraction: f64,
+}
+
+impl DacStream {
+    fn restart(&mut self) {
+        self.position = self.start + self.step_base as usize;
+        self.remaining = Some(self.length);
+    }
+
+    /// Number of writes playing the data from `start` to `end`
+    fn writes_until(&self, start: usize, end: usize) -> usize {
+        end.saturating_sub(start + self.step_base as usize)
+            .div_ceil(self.step_size.max(1) as usize)
+    }
+
+    /// Command the stream sends for `value`, `None` for chips it can't drive
+    fn command(&self, value: u8) -> Option<Commands> {
+        let (register, chip_index) = (self.register, self.chip_index);
+        let command = match System::from_chip_id(self.chip_type)? {
+            System::YM2612 if self.port == 0 => Commands::YM2612Port0Write { register, value, chip_index },
+            System::YM2612 => Commands::YM2612Port1Write { register, value, chip_index },
+            _ => return None,
+        };
+        Some(command)
+    }
+}
+
+/// Plays commands through the emulated chips of a file
+pub struct Renderer {
+    chips: Vec<(System, u8, Box<dyn SoundChip>)>,
+    data_banks: DataBanks,
+    /// Read position in the YM2612 data bank for `0x8n` writes
+    pcm_position: usize,
+    streams: BTreeMap<u8, DacStream>,
+}
+
+impl Renderer {
+    /// Renderer with every chip of `header` that has an emulation core
+    pub fn new(header: &HeaderData) -> Self {
+        let mut chips: Vec<(System, u8, Box<dyn SoundChip>)> = Vec::new();
+        for chip in header.chips() {
+            let core: Box<dyn SoundChip> = match chip.system {
+                System::SN76489 | System::T6W28 => Box::new(Sn76489::from_header(header, chip.index)),
+                System::YM2612 => Box::new(Ym2612::new(chip.clock_hz)),
+                System::YM2151 => Box::new(Ym2151::new(chip.clock_hz)),
+                System::AY8910 | System::YM2203 | System::YM2608 | System::YM2610 | System::YM2610B => {
+                    match Ay8910::from_header(header, chip.system, chip.index) {
+                        Some(core) => Box::new(core),
+                        None => continue,
+                    }
+                },
+                _ => continue,
+            };
+            chips.push((chip.system.base(), chip.index, core));
+        }
+
+        Self {
+            chips,
+            data_banks: DataBanks::default(),
+            pcm_position: 0,
+            streams: BTreeMap::new(),
+        }
+    }
+
+    /// Whether a core exists for chip `index` of `system`
+    pub fn has_chip(&self, system: System, index: u8) -> bool {
+        self.chips.iter().any(|(s, i, _)| *s == system.base() && *i == index)
+    }
+
+    /// Apply `command`, appending the samples of any wait to `output` as
+    /// interleaved 16-bit stereo
+    pub fn apply(&mut self, command: &Commands, output: &mut Vec<i16>) {
+        match command {
+            Commands::DataBlock { block_type, data } => self.data_banks.add(*block_type, data),
+            Commands::SeekPCM { offset } => self.pcm_position = *offset as usize,
+            Commands::YM2612Port0Address2AWriteWait { .. } => {
+                if let Some(&value) = self.data_banks.bank(0x00).get(self.pcm_position) {
+                    self.dispatch(&Commands::YM2612Port0Write { register: 0x2A, value, chip_index: 0 });
+                }
+                self.pcm_position += 1;
+            },
+            Commands::DACStreamSetupControl { stream_id, chip_type, port, command, chip_index } => {
+                let stream = self.streams.entry(*stream_id).or_default();
+                stream.chip_type = *chip_type & 0x7F;
+                stream.chip_index = *chip_index;
+                stream.port = *port;
+                stream.register = *command;
+            },
+            Commands::DACStreamSetData { stream_id, data_bank_id, step_size, step_base } => {
+                let stream = self.streams.entry(*stream_id).or_default();
+                stream.bank = *data_bank_id;
+                stream.step_size = *step_size;
+                stream.step_base = *step_base;
+            },
+            Commands::DACStreamSetFrequency { stream_id, frequency } => {
+                self.streams.entry(*stream_id).or_default().frequency = *frequency;
+            },
+            Commands::DACStreamStart { stream_id, data_start_offset, length_mode, data_length } => {
+                if let Some(stream) = self.streams.get_mut(stream_id) {
+                    if *data_start_offset != u32::MAX {
+                        stream.start = *data_start_offset as usize;
+                    }
+                    let bank_len = self.data_banks.bank(stream.bank).len();
+                    stream.length = match length_mode & 0x03 {
+                        0 => stream.length,
+                        1 => *data_length as usize,
+                        2 => (*data_length as u64 * stream.frequency as u64 / 1000) as usize,
+                        _ => stream.writes_until(stream.start, bank_len),
+                    };
+                    stream.looping = length_mode & 0x80 != 0;
+                    // Mode 0 only moves the data position
+                    if length_mode & 0x03 == 0 {
+                        stream.position = stream.start + stream.step_base as usize;
+                    } else {
+                        stream.restart();
+                    }
+                }
+            },
+            Commands::DACStreamStartFast { stream_id, block_id, flags } => {
+                if let Some(stream) = self.streams.get_mut(stream_id) {
+                    if let Some(block) = self.data_banks.block(stream.bank, *block_id as usize) {
+                        stream.start = block.start;
+                        stream.length = stream.writes_until(block.start, block.end);
+                        stream.looping = flags & 0x01 != 0;
+                        stream.restart();
+                    }
+                }
+            },
+            Commands::DACStreamStop { stream_id } => {
+                for (id, stream) in &mut self.streams {
+                    if *stream_id == 0xFF || id == stream_id {
+                        stream.remaining = None;
+                    }
+                }
+            },
+            _ => self.dispatch(command),
+        }
+        self.render_samples(command.wait_samples() as usize, output);
+    }
+
+    /// Send the writes of every playing stream due before the next sample
+    fn step_streams(&mut self) {
+        let mut writes = Vec::new();
+        for stream in self.streams.values_mut() {
+            if stream.remaining.is_none() {
+                continue;
+            }
+            stream.fraction += stream.frequency as f64 / VGM_SAMPLE_RATE as f64;
+            while stream.fraction >= 1.0 {
+                stream.fraction -= 1.0;
+                let Some(remaining) = stream.remaining.filter(|&remaining| remaining > 0) else {
+                    stream.remaining = None;
+                    break;
+                };
+                if let Some(command) = self
+                    .data_banks
+                    .bank(stream.bank)
+                    .get(stream.position)
+                    .and_then(|&value| stream.command(value))
+                {
+                    writes.push(command);
+                }
+                stream.position += stream.step_size as usize;
+                stream.remaining = Some(remaining - 1);
+                if remaining == 1 {
+                    if stream.looping {
+                        stream.restart();
+                    } else {
+                        stream.remaining = None;
+                    }
+                }
+            }
+        }
+        for command in &writes {
+            self.dispatch(command);
+        }
+    }
+
+    /// Append `count` stereo frames to `output`
+    pub fn render_samples(&mut self, count: usize, output: &mut Vec<i16>) {
+        output.reserve(count * RENDER_CHANNELS as usize);
+        for _ in 0..count {
+            if !self.streams.is_empty() {
+                self.step_streams();
+            }
+            let (mut left, mut right) = (0.0, 0.0);
+            for (_, _, chip) in &mut self.chips {
+                let (l, r) = chip.next_sample();
+                left += l;
+                right += r;
+            }
+            output.push(to_i16(left));
+            output.push(to_i16(right));
+        }
+    }
+
+    fn dispatch(&mut self, command: &Commands) {
+        let Some(target) = command.target() else {
+            return;
+        };
+        let system = target.system.base();
+        for (s, index, chip) in &mut self.chips {
+            if *s == system && *index == target.chip_index {
+                chip.write(command);
+            }
+        }
+    }
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+/// Write interleaved 16-bit PCM `samples` as a WAV file
+pub fn write_wav<W: Write>(writer: &mut W, samples: &[i16], sample_rate: u32, channels: u16) -> VgmResult<()> {
+    let data_size = (samples.len() * 2) as u32;
+    let block_align = channels * 2;
+
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(36 + data_size).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    // PCM format
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&channels.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&16u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_size.to_le_bytes());
+
+    let data: Vec<u8> = samples.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+    writer
+        .write_all(&header)
+        .and_then(|_| writer.write_all(&data))
+        .map_err(|e| VgmError::FileWriteError {
+            path: "wav output".to_string(),
+            reason: e.to_string(),
+        })
+}
+
+impl VgmFile {
+    /// Render the file as interleaved 16-bit stereo at 44.1 kHz, playing the
+    /// loop `program_loops` times as adjusted by the header's loop modifiers
+    pub fn render(&self, program_loops: u32) -> Vec<i16> {
+        let mut renderer = Renderer::new(&self.header);
+        let mut output = Vec::new();
+        for command in self.iter_looped(program_loops) {
+            renderer.apply(command, &mut output);
+        }
+        output
+    }
+
+    /// Render the file and encode it as a WAV file
+    pub fn to_wav_bytes(&self, program_loops: u32) -> VgmResult<Vec<u8>> {
+        let samples = self.render(program_loops);
+        let mut wav = Vec::with_capacity(44 + samples.len() * 2);
+        write_wav(&mut wav, &samples, VGM_SAMPLE_RATE, RENDER_CHANNELS)?;
+        Ok(wav)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DataBlockContent, StreamChipType};
+
+    fn psg_file(commands: Vec<Commands>) -> VgmFile {
+        let mut header = HeaderData {
+            version: 151,
+            ..Default::default()
+        };
+        header.set_chip(&System::SN76489, 3_579_545, false).unwrap();
+        VgmFile {
+            header,
+            commands,
+            command_offsets: vec![],
+            metadata: Default::default(),
+            raw_regions: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_render_length_and_routing() {
+        let vgm = psg_file(vec![
+            Commands::PSGWrite { value: 0x8E, chip_index: 0 },
+            Commands::PSGWrite { value: 0x0F, chip_index: 0 },
+            Commands::PSGWrite { value: 0x90, chip_index: 0 },
+            Commands::Wait735Samples,
+            // Writes to a chip the header doesn't enable are dropped
+            Commands::PSGWrite { value: 0x9F, chip_index: 1 },
+            Commands::YM2612Port0Write { register: 0x28, value: 0xF0, chip_index: 0 },
+            Commands::Wait882Samples,
+            Commands::EndOfSoundData,
+        ]);
+
+        let samples = vgm.render(1);
+        assert_eq!(samples.len(), (735 + 882) * 2);
+        assert!(samples[735 * 2..].iter().any(|&sample| sample != 0));
+
+        let renderer = Renderer::new(&vgm.header);
+        assert!(renderer.has_chip(System::SN76489, 0));
+        assert!(!renderer.has_chip(System::SN76489, 1));
+        assert!(!renderer.has_chip(System::YM2612, 0));
+    }
+
+    #[test]
+    fn test_render_applies_loop_modifiers_once() {
+        let mut vgm = psg_file(vec![
+            Commands::WaitNSamples { n: 100 },
+            Commands::WaitNSamples { n: 10 },
+            Commands::EndOfSoundData,
+        ]);
+        vgm.command_offsets = vec![0x100, 0x103, 0x106];
+        vgm.header.loop_offset = 0x103 - 0x1C;
+        // Twice the requested loops, minus one
+        vgm.header.loop_modifier = 0x20;
+        vgm.header.loop_base = 1;
+
+        let (total, looped) = crate::timeline::sample_counts(&vgm.commands, vgm.loop_start_index());
+        let loops = vgm.header.effective_loop_count(2);
+        assert_eq!(loops, 3);
+        assert_eq!(vgm.render(2).len(), (total + looped * (loops - 1)) as usize * 2);
+    }
+
+    #[test]
+    fn test_ym2612_dac_stream() {
+        let mut data = vec![0xFF; 99];
+        data.push(0x80);
+        let mut vgm = psg_file(vec![
+            Commands::DataBlock {
+                block_type: 0x00,
+                data: DataBlockContent::UncompressedStream { chip_type: StreamChipType::YM2612, data },
+            },
+            Commands::YM2612Port0Write { register: 0x2B, value: 0x80, chip_index: 0 },
+            Commands::DACStreamSetupControl { stream_id: 0, chip_type: 0x02, port: 0, command: 0x2A, chip_index: 0 },
+            Commands::DACStreamSetData { stream_id: 0, data_bank_id: 0x00, step_size: 1, step_base: 0 },
+            Commands::DACStreamSetFrequency { stream_id: 0, frequency: 22_050 },
+            Commands::DACStreamStartFast { stream_id: 0, block_id: 0, flags: 0 },
+            Commands::WaitNSamples { n: 300 },
+            // 0x8n writes read the same bank
+            Commands::SeekPCM { offset: 0 },
+            Commands::YM2612Port0Address2AWriteWait { n: 0 },
+            Commands::WaitNSamples { n: 10 },
+            Commands::EndOfSoundData,
+        ]);
+        vgm.header.set_chip(&System::YM2612, 7_670_453, false).unwrap();
+        vgm.header.remove_chip(&System::SN76489);
+
+        let samples = vgm.render(1);
+        let left = |frame: usize| samples[frame * 2];
+        // 100 writes at half the output rate last 200 samples, the last one is silence
+        assert!(left(100) > 8000);
+        assert_eq!(left(250), 0);
+        assert!(left(305) > 8000);
+    }
+
+    #[test]
+    fn test_wav_header() {
+        let vgm = psg_file(vec![Commands::WaitNSamples { n: 100 }, Commands::EndOfSoundData]);
+        let wav = vgm.to_wav_bytes(1).unwrap();
+
+        assert_eq!(wav.len(), 44 + 400);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()), 36 + 400);
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u16::from_le_bytes(wav[22..24].try_into().unwrap()), 2);
+        assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), 44_100);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()), 400);
+    }
+}
@@ -0,0 +1,321 @@
+//! SN76489 / Sega PSG emulation.
+//!
+//! Three square wave channels and a noise channel with 4-bit attenuation,
+//! configured from the header's feedback pattern, shift register width and
+//! flags, with optional Game Gear stereo.
+
+use crate::{Commands, HeaderData, SoundChip, System, CHIP_CLOCK_MASK, VGM_SAMPLE_RATE};
+
+/// Noise feedback pattern assumed when the header leaves it at zero, as
+/// version 1.01 and earlier files do
+pub const DEFAULT_SN76489_FEEDBACK: u16 = 0x0009;
+/// Noise shift register width assumed when the header leaves it at zero
+pub const DEFAULT_SN76489_SHIFT_REGISTER_WIDTH: u8 = 16;
+
+/// Flag bits of `HeaderData::sn76489_flags`
+const FLAG_FREQUENCY_0_IS_0X400: u8 = 0x01;
+const FLAG_OUTPUT_NEGATE: u8 = 0x02;
+const FLAG_STEREO_OFF: u8 = 0x04;
+const FLAG_CLOCK_DIVIDER_OFF: u8 = 0x08;
+const FLAG_XNOR_NOISE: u8 = 0x10;
+
+/// Amplitude of one channel at each attenuation step: 2 dB per step, silent at 15
+const VOLUME_TABLE: [f32; 16] = [
+    1.0, 0.794_328, 0.630_957, 0.501_187, 0.398_107, 0.316_228, 0.251_189, 0.199_526, 0.158_489, 0.125_893,
+    0.1, 0.079_433, 0.063_096, 0.050_119, 0.039_811, 0.0,
+];
+
+/// One SN76489 chip
+#[derive(Debug, Clone)]
+pub struct Sn76489 {
+    /// Tone periods of channels 0-2, 10 bits each
+    tone_periods: [u16; 3],
+    /// Attenuation of channels 0-2 and noise, 15 is silent
+    attenuations: [u8; 4],
+    noise_control: u8,
+    /// Register selected by the last latch byte: 0-7 for tone 0, volume 0, ... noise volume
+    latched: u8,
+    counters: [u16; 4],
+    /// Output of the tone flip-flops and the noise period flip-flop
+    flip_flops: [bool; 4],
+    shift_register: u32,
+    feedback: u32,
+    width: u8,
+    flags: u8,
+    stereo: u8,
+    /// Left and right output enable, used to split a T6W28 between two chips
+    outputs: (bool, bool),
+    /// Chip ticks per output sample
+    ticks_per_sample: f64,
+    tick_fraction: f64,
+}
+
+impl Sn76489 {
+    /// Chip running at `clock` Hz, with zero feedback or width replaced by the
+    /// 1.01 defaults
+    pub fn new(clock: u32, feedback: u16, width: u8, flags: u8) -> Self {
+        let feedback = if feedback == 0 { DEFAULT_SN76489_FEEDBACK } else { feedback };
+        let width = if width == 0 { DEFAULT_SN76489_SHIFT_REGISTER_WIDTH } else { width.min(32) };
+        let divider = if flags & FLAG_CLOCK_DIVIDER_OFF != 0 { 2.0 } else { 16.0 };
+
+        Self {
+            tone_periods: [0; 3],
+            attenuations: [0x0F; 4],
+            noise_control: 0,
+            latched: 0,
+            counters: [0; 4],
+            flip_flops: [true; 4],
+            shift_register: 1 << (width - 1),
+            feedback: feedback as u32,
+            width,
+            flags,
+            stereo: 0xFF,
+            outputs: (true, true),
+            ticks_per_sample: clock as f64 / divider / VGM_SAMPLE_RATE as f64,
+            tick_fraction: 0.0,
+        }
+    }
+
+    /// Chip `index` as configured by `header`.
+    ///
+    /// A T6W28 is approximated by putting the first chip on the left output and
+    /// the second on the right.
+    pub fn from_header(header: &HeaderData, index: u8) -> Self {
+        let mut chip = Self::new(
+            header.chip_clock(&System::SN76489) & CHIP_CLOCK_MASK,
+            header.sn76489_feedback,
+            header.sn76489_shift_register_width,
+            header.sn76489_flags,
+        );
+        if header.chips().iter().any(|chip| chip.system == System::T6W28) {
+            chip.outputs = (index == 0, index == 1);
+        }
+        chip
+    }
+
+    /// Write a byte to the chip: a latch byte (bit 7 set) selects a register
+    /// and sets its low bits, a data byte sets the high bits of a latched tone
+    /// or the whole volume or noise register
+    pub fn write(&mut self, value: u8) {
+        if value & 0x80 != 0 {
+            self.latched = (value >> 4) & 0x07;
+        }
+        let is_latch = value & 0x80 != 0;
+        let register = self.latched as usize;
+
+        match register {
+            0 | 2 | 4 => {
+                let period = &mut self.tone_periods[register / 2];
+                *period = if is_latch {
+                    (*period & 0x3F0) | (value as u16 & 0x0F)
+                } else {
+                    (*period & 0x00F) | ((value as u16 & 0x3F) << 4)
+                };
+            },
+            6 => {
+                self.noise_control = value & 0x07;
+                self.shift_register = 1 << (self.width - 1);
+            },
+            _ => self.attenuations[register / 2] = value & 0x0F,
+        }
+    }
+
+    /// Set the Game Gear stereo byte: bits 4-7 enable channels 0-3 on the
+    /// left, bits 0-3 on the right. Ignored when the header turns stereo off
+    pub fn set_stereo(&mut self, value: u8) {
+        self.stereo = value;
+    }
+
+    fn tone_period(&self, channel: usize) -> u16 {
+        match self.tone_periods[channel] {
+            0 if self.flags & FLAG_FREQUENCY_0_IS_0X400 != 0 => 0x400,
+            period => period,
+        }
+    }
+
+    fn noise_period(&self) -> u16 {
+        match self.noise_control & 0x03 {
+            3 => self.tone_period(2),
+            rate => 0x10 << rate,
+        }
+    }
+
+    /// Advance the counters by one chip tick
+    fn tick(&mut self) {
+        for channel in 0..3 {
+            let period = self.tone_period(channel);
+            if period <= 1 {
+                // Periods 0 and 1 hold the output high, used for sample playback
+                self.flip_flops[channel] = true;
+                continue;
+            }
+            self.counters[channel] = self.counters[channel].saturating_sub(1);
+            if self.counters[channel] == 0 {
+                self.counters[channel] = period;
+                self.flip_flops[channel] = !self.flip_flops[channel];
+            }
+        }
+
+        self.counters[3] = self.counters[3].saturating_sub(1);
+        if self.counters[3] == 0 {
+            self.counters[3] = self.noise_period().max(1);
+            self.flip_flops[3] = !self.flip_flops[3];
+            // The shift register steps once per noise period
+            if self.flip_flops[3] {
+                self.shift_noise();
+            }
+        }
+    }
+
+    fn shift_noise(&mut self) {
+        let mut bit = if self.noise_control & 0x04 != 0 {
+            (self.shift_register & self.feedback).count_ones() & 1
+        } else {
+            self.shift_register & 1
+        };
+        if self.flags & FLAG_XNOR_NOISE != 0 {
+            bit ^= 1;
+        }
+        self.shift_register = (self.shift_register >> 1) | (bit << (self.width - 1));
+    }
+
+    /// Mix of the channels at the current tick
+    fn mix(&self) -> (f32, f32) {
+        let stereo = if self.flags & FLAG_STEREO_OFF != 0 { 0xFF } else { self.stereo };
+        let (mut left, mut right) = (0.0, 0.0);
+
+        for channel in 0..4 {
+            let high = if channel == 3 { self.shift_register & 1 != 0 } else { self.flip_flops[channel] };
+            let level = VOLUME_TABLE[self.attenuations[channel] as usize] * 0.25;
+            let level = if high { level } else { -level };
+            if stereo & (0x10 << channel) != 0 {
+                left += level;
+            }
+            if stereo & (0x01 << channel) != 0 {
+                right += level;
+            }
+        }
+
+        let sign = if self.flags & FLAG_OUTPUT_NEGATE != 0 { -1.0 } else { 1.0 };
+        (
+            if self.outputs.0 { left * sign } else { 0.0 },
+            if self.outputs.1 { right * sign } else { 0.0 },
+        )
+    }
+}
+
+impl SoundChip for Sn76489 {
+    fn write(&mut self, command: &Commands) {
+        match *command {
+            Commands::PSGWrite { value, .. } => Sn76489::write(self, value),
+            Commands::GameGearPSGStereo { value, .. } => self.set_stereo(value),
+            _ => {},
+        }
+    }
+
+    fn next_sample(&mut self) -> (f32, f32) {
+        self.tick_fraction += self.ticks_per_sample;
+        let ticks = self.tick_fraction as u32;
+        self.tick_fraction -= ticks as f64;
+
+        if ticks == 0 {
+            return self.mix();
+        }
+
+        // Average over the ticks of this sample to filter out what's above
+        // the output rate
+        let (mut left, mut right) = (0.0, 0.0);
+        for _ in 0..ticks {
+            self.tick();
+            let (l, r) = self.mix();
+            left += l;
+            right += r;
+        }
+        (left / ticks as f32, right / ticks as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NTSC_CLOCK: u32 = 3_579_545;
+
+    /// Count sign changes of the left output over `samples` samples
+    fn zero_crossings(chip: &mut Sn76489, samples: usize) -> usize {
+        let mut previous = chip.next_sample().0;
+        let mut crossings = 0;
+        for _ in 0..samples {
+            let sample = chip.next_sample().0;
+            if (sample > 0.0) != (previous > 0.0) {
+                crossings += 1;
+            }
+            previous = sample;
+        }
+        crossings
+    }
+
+    #[test]
+    fn test_tone_frequency() {
+        let mut chip = Sn76489::new(NTSC_CLOCK, 0x0009, 16, 0);
+        // Tone 0 period 254 (~440 Hz) at full volume
+        chip.write(0x8E);
+        chip.write(0x0F);
+        chip.write(0x90);
+
+        // One second of a 440 Hz square crosses zero about 880 times
+        let crossings = zero_crossings(&mut chip, VGM_SAMPLE_RATE as usize);
+        assert!((870..=890).contains(&crossings), "{crossings} crossings");
+    }
+
+    #[test]
+    fn test_silent_until_volume_set() {
+        let mut chip = Sn76489::new(NTSC_CLOCK, 0, 0, 0);
+        chip.write(0x8E);
+        chip.write(0x0F);
+        assert!((0..1000).all(|_| chip.next_sample() == (0.0, 0.0)));
+
+        chip.write(0x90);
+        assert!((0..1000).any(|_| chip.next_sample().0 != 0.0));
+    }
+
+    #[test]
+    fn test_noise_shift_register() {
+        let mut chip = Sn76489::new(NTSC_CLOCK, 0x0009, 16, 0);
+        // White noise at the fastest rate
+        chip.write(0xE4);
+        assert_eq!(chip.shift_register, 0x8000);
+        for _ in 0..32 {
+            chip.shift_noise();
+        }
+        assert_ne!(chip.shift_register, 0x8000);
+        assert_ne!(chip.shift_register, 0);
+
+        // Periodic noise just rotates the single set bit
+        chip.write(0xE0);
+        for _ in 0..16 {
+            chip.shift_noise();
+        }
+        assert_eq!(chip.shift_register, 0x8000);
+    }
+
+    #[test]
+    fn test_game_gear_stereo() {
+        let mut chip = Sn76489::new(NTSC_CLOCK, 0x0009, 16, 0);
+        chip.write(0x81);
+        chip.write(0x00);
+        chip.write(0x90);
+        // Channel 0 on the left only
+        SoundChip::write(&mut chip, &Commands::GameGearPSGStereo { value: 0x10, chip_index: 0 });
+
+        let samples: Vec<_> = (0..1000).map(|_| chip.next_sample()).collect();
+        assert!(samples.iter().all(|&(_, right)| right == 0.0));
+        assert!(samples.iter().any(|&(left, _)| left != 0.0));
+
+        // With stereo turned off in the flags both sides play
+        let mut mono = Sn76489::new(NTSC_CLOCK, 0x0009, 16, FLAG_STEREO_OFF);
+        mono.write(0x90);
+        mono.set_stereo(0x10);
+        assert!((0..1000).any(|_| mono.next_sample().1 != 0.0));
+    }
+}
@@ -1,8 +1,10 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum System {
     SN76489,
+    T6W28, // SN76489 with bits 30 and 31 set
     YM2413,
     YM2612,
     YM2151,
@@ -11,6 +13,7 @@ pub enum System {
     YM2203,
     YM2608,
     YM2610, // Bit 31 is used to set whether it is an YM2610 or an YM2610B chip
+    YM2610B,
     YM3812,
     YM3526,
     Y8950,
@@ -45,4 +48,276 @@ pub enum System {
     C352,
     X1_010,
     GA20,
+    Mikey,
+}
+
+/// A chip enabled in the header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ChipInstance {
+    /// Chip, with the variant selected by bit 31 already resolved
+    pub system: System,
+    /// Clock in Hz without the flag bits
+    pub clock_hz: u32,
+    /// 0 for the first chip, 1 for the second chip of a dual-chip setup
+    pub index: u8,
+    /// Bit 31 of the clock field. Besides selecting the variant systems it
+    /// marks a YM3438 (YM2612), YM2164 (YM2151), the FDS add-on (NES APU) and
+    /// pin 7 high (OKIM6295), which have no [`System`] of their own
+    pub variant_flag: bool,
+}
+
+impl System {
+    /// Chip for a VGM chip ID, as used by DAC stream setup and the extra header.
+    ///
+    /// IDs shared by a chip and its variant map to the base chip. Bit 7, which
+    /// selects the second chip, is ignored.
+    pub fn from_chip_id(chip_id: u8) -> Option<System> {
+        let system = match chip_id & 0x7F {
+            0x00 => System::SN76489,
+            0x01 => System::YM2413,
+            0x02 => System::YM2612,
+            0x03 => System::YM2151,
+            0x04 => System::SegaPcm,
+            0x05 => System::RF5C68,
+            0x06 => System::YM2203,
+            0x07 => System::YM2608,
+            0x08 => System::YM2610,
+            0x09 => System::YM3812,
+            0x0A => System::YM3526,
+            0x0B => System::Y8950,
+            0x0C => System::YMF262,
+            0x0D => System::YMF278B,
+            0x0E => System::YMF271,
+            0x0F => System::YMZ280B,
+            0x10 => System::RF5C164,
+            0x11 => System::Pwm,
+            0x12 => System::AY8910,
+            0x13 => System::GameboyDmg,
+            0x14 => System::NesApu,
+            0x15 => System::MultiPcm,
+            0x16 => System::UPD7759,
+            0x17 => System::OKIM6258,
+            0x18 => System::OKIM6295,
+            0x19 => System::K051649,
+            0x1A => System::K054539,
+            0x1B => System::HuC6280,
+            0x1C => System::C140,
+            0x1D => System::K053260,
+            0x1E => System::Pokey,
+            0x1F => System::QSound,
+            0x20 => System::SCSP,
+            0x21 => System::WonderSwan,
+            0x22 => System::VSU,
+            0x23 => System::SAA1099,
+            0x24 => System::ES5503,
+            0x25 => System::ES5505,
+            0x26 => System::X1_010,
+            0x27 => System::C352,
+            0x28 => System::GA20,
+            0x29 => System::Mikey,
+            _ => return None,
+        };
+        Some(system)
+    }
+
+    /// Chip whose memory a data block of `block_type` is loaded into, also
+    /// used for the chip type of PCM RAM writes
+    pub fn from_data_block_type(block_type: u8) -> Option<System> {
+        let system = match block_type {
+            // Uncompressed (0x00-0x3F) and compressed (0x40-0x7E) streams
+            0x00..=0x7E => match block_type & 0x3F {
+                0x00 => System::YM2612,
+                0x01 => System::RF5C68,
+                0x02 => System::RF5C164,
+                0x03 => System::Pwm,
+                0x04 => System::OKIM6258,
+                0x05 => System::HuC6280,
+                0x06 => System::SCSP,
+                0x07 => System::NesApu,
+                0x08 => System::Mikey,
+                _ => return None,
+            },
+            // ROM and RAM dumps
+            0x80 => System::SegaPcm,
+            0x81 => System::YM2608,
+            0x82 | 0x83 => System::YM2610,
+            0x84 | 0x87 => System::YMF278B,
+            0x85 => System::YMF271,
+            0x86 => System::YMZ280B,
+            0x88 => System::Y8950,
+            0x89 => System::MultiPcm,
+            0x8A => System::UPD7759,
+            0x8B => System::OKIM6295,
+            0x8C => System::K054539,
+            0x8D => System::C140,
+            0x8E => System::K053260,
+            0x8F => System::QSound,
+            0x90 => System::ES5506,
+            0x91 => System::X1_010,
+            0x92 => System::C352,
+            0x93 => System::GA20,
+            // RAM writes
+            0xC0 => System::RF5C68,
+            0xC1 => System::RF5C164,
+            0xC2 => System::NesApu,
+            0xE0 => System::SCSP,
+            0xE1 => System::ES5503,
+            _ => return None,
+        };
+        Some(system)
+    }
+
+    /// Variant selected when bit 31 of this chip's clock is set
+    pub fn variant(&self) -> Option<System> {
+        match self {
+            System::SN76489 => Some(System::T6W28),
+            System::YM2610 => Some(System::YM2610B),
+            System::K051649 => Some(System::K052539),
+            System::ES5505 => Some(System::ES5506),
+            _ => None,
+        }
+    }
+
+    /// Chip whose clock field this chip shares, itself for non-variants
+    pub fn base(&self) -> System {
+        System::from_chip_id(self.chip_id()).unwrap_or(*self)
+    }
+
+    /// Whether this chip is selected through bit 31 of its base chip's clock
+    pub fn is_variant(&self) -> bool {
+        self.base() != *self
+    }
+
+    /// First VGM version that supports this chip
+    pub fn min_version(&self) -> u32 {
+        match self {
+            System::SN76489 | System::YM2413 => 100,
+            System::YM2612 | System::YM2151 => 110,
+            System::T6W28
+            | System::SegaPcm
+            | System::RF5C68
+            | System::YM2203
+            | System::YM2608
+            | System::YM2610
+            | System::YM2610B
+            | System::YM3812
+            | System::YM3526
+            | System::Y8950
+            | System::YMF262
+            | System::YMF278B
+            | System::YMF271
+            | System::YMZ280B
+            | System::RF5C164
+            | System::Pwm
+            | System::AY8910 => 151,
+            System::GameboyDmg
+            | System::NesApu
+            | System::MultiPcm
+            | System::UPD7759
+            | System::OKIM6258
+            | System::OKIM6295
+            | System::K051649
+            | System::K052539
+            | System::K054539
+            | System::HuC6280
+            | System::C140
+            | System::K053260
+            | System::Pokey
+            | System::QSound => 161,
+            System::SCSP
+            | System::WonderSwan
+            | System::VSU
+            | System::SAA1099
+            | System::ES5503
+            | System::ES5505
+            | System::ES5506
+            | System::X1_010
+            | System::C352
+            | System::GA20 => 171,
+            System::Mikey => 172,
+        }
+    }
+
+    /// VGM chip ID of this chip
+    pub fn chip_id(&self) -> u8 {
+        match self {
+            System::SN76489 | System::T6W28 => 0x00,
+            System::YM2413 => 0x01,
+            System::YM2612 => 0x02,
+            System::YM2151 => 0x03,
+            System::SegaPcm => 0x04,
+            System::RF5C68 => 0x05,
+            System::YM2203 => 0x06,
+            System::YM2608 => 0x07,
+            System::YM2610 | System::YM2610B => 0x08,
+            System::YM3812 => 0x09,
+            System::YM3526 => 0x0A,
+            System::Y8950 => 0x0B,
+            System::YMF262 => 0x0C,
+            System::YMF278B => 0x0D,
+            System::YMF271 => 0x0E,
+            System::YMZ280B => 0x0F,
+            System::RF5C164 => 0x10,
+            System::Pwm => 0x11,
+            System::AY8910 => 0x12,
+            System::GameboyDmg => 0x13,
+            System::NesApu => 0x14,
+            System::MultiPcm => 0x15,
+            System::UPD7759 => 0x16,
+            System::OKIM6258 => 0x17,
+            System::OKIM6295 => 0x18,
+            System::K051649 | System::K052539 => 0x19,
+            System::K054539 => 0x1A,
+            System::HuC6280 => 0x1B,
+            System::C140 => 0x1C,
+            System::K053260 => 0x1D,
+            System::Pokey => 0x1E,
+            System::QSound => 0x1F,
+            System::SCSP => 0x20,
+            System::WonderSwan => 0x21,
+            System::VSU => 0x22,
+            System::SAA1099 => 0x23,
+            System::ES5503 => 0x24,
+            System::ES5505 | System::ES5506 => 0x25,
+            System::X1_010 => 0x26,
+            System::C352 => 0x27,
+            System::GA20 => 0x28,
+            System::Mikey => 0x29,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chip_id_round_trip() {
+        for chip_id in 0x00..=0x29 {
+            let system = System::from_chip_id(chip_id).unwrap();
+            assert_eq!(system.chip_id(), chip_id);
+        }
+        assert_eq!(System::from_chip_id(0x82), Some(System::YM2612));
+        assert_eq!(System::from_chip_id(0x2A), None);
+        assert_eq!(System::from_chip_id(0x7F), None);
+        assert_eq!(System::ES5506.chip_id(), 0x25);
+    }
+
+    #[test]
+    fn test_data_block_types() {
+        assert_eq!(System::from_data_block_type(0x00), Some(System::YM2612));
+        assert_eq!(System::from_data_block_type(0x40), Some(System::YM2612));
+        assert_eq!(System::from_data_block_type(0x7F), None);
+        assert_eq!(System::from_data_block_type(0x8F), Some(System::QSound));
+        assert_eq!(System::from_data_block_type(0xE1), Some(System::ES5503));
+    }
+
+    #[test]
+    fn test_variants() {
+        assert_eq!(System::YM2610.variant(), Some(System::YM2610B));
+        assert_eq!(System::YM2610B.base(), System::YM2610);
+        assert!(System::ES5506.is_variant());
+        assert!(!System::ES5505.is_variant());
+        assert!(!System::YM2612.is_variant());
+    }
 }
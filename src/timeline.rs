@@ -0,0 +1,166 @@
+//! Sample-accurate timing of parsed commands.
+
+use std::time::Duration;
+
+use crate::{Commands, VgmFile};
+
+/// Playback rate every VGM sample count refers to
+pub const VGM_SAMPLE_RATE: u32 = 44_100;
+
+/// Absolute sample position of every command in a command list.
+///
+/// A command's position is the sum of all waits before it, so writes that
+/// follow each other without a wait share a position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timeline {
+    positions: Vec<u64>,
+    total_samples: u64,
+}
+
+impl Timeline {
+    /// Build the timeline for `commands`
+    pub fn new(commands: &[Commands]) -> Self {
+        let mut positions = Vec::with_capacity(commands.len());
+        let mut current: u64 = 0;
+
+        for command in commands {
+            positions.push(current);
+            current += command.wait_samples() as u64;
+        }
+
+        Self {
+            positions,
+            total_samples: current,
+        }
+    }
+
+    /// Sample position of each command, indexed like the command list
+    pub fn positions(&self) -> &[u64] {
+        &self.positions
+    }
+
+    /// Sample position of the command at `index`
+    pub fn sample_at(&self, index: usize) -> Option<u64> {
+        self.positions.get(index).copied()
+    }
+
+    /// Time of the command at `index`
+    pub fn time_at(&self, index: usize) -> Option<Duration> {
+        self.sample_at(index).map(samples_to_duration)
+    }
+
+    /// Sum of all waits
+    pub fn total_samples(&self) -> u64 {
+        self.total_samples
+    }
+
+    /// Playback length of the whole command list
+    pub fn duration(&self) -> Duration {
+        samples_to_duration(self.total_samples)
+    }
+
+    /// Index of the first command at or after `sample`.
+    ///
+    /// Every command before the returned index has executed by `sample`; equal
+    /// to the number of commands when `sample` is past the last one.
+    pub fn index_at_sample(&self, sample: u64) -> usize {
+        self.positions.partition_point(|&position| position < sample)
+    }
+
+    /// Index of the first command at or after `time`, see [`Timeline::index_at_sample`]
+    pub fn index_at_time(&self, time: Duration) -> usize {
+        self.index_at_sample(duration_to_samples(time))
+    }
+}
+
+/// Convert a sample count at 44.1 kHz to a duration
+pub fn samples_to_duration(samples: u64) -> Duration {
+    let nanos = samples as u128 * 1_000_000_000 / VGM_SAMPLE_RATE as u128;
+    Duration::new((nanos / 1_000_000_000) as u64, (nanos % 1_000_000_000) as u32)
+}
+
+/// Convert a duration to a sample count at 44.1 kHz, rounding down
+pub fn duration_to_samples(duration: Duration) -> u64 {
+    (duration.as_nanos() * VGM_SAMPLE_RATE as u128 / 1_000_000_000) as u64
+}
+
+/// Total samples up to the end of sound data, and samples from the loop point on
+pub(crate) fn sample_counts(commands: &[Commands], loop_index: Option<usize>) -> (u32, u32) {
+    let mut total: u32 = 0;
+    let mut looped: u32 = 0;
+
+    for (index, command) in commands.iter().enumerate() {
+        if matches!(command, Commands::EndOfSoundData) {
+            break;
+        }
+        let samples = command.wait_samples();
+        total = total.saturating_add(samples);
+        if loop_index.is_some_and(|start| index >= start) {
+            looped = looped.saturating_add(samples);
+        }
+    }
+
+    (total, looped)
+}
+
+impl VgmFile {
+    /// Sample-accurate timeline of the commands
+    pub fn timeline(&self) -> Timeline {
+        Timeline::new(&self.commands)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_commands() -> Vec<Commands> {
+        vec![
+            Commands::PSGWrite { value: 0x9F, chip_index: 0 },
+            Commands::Wait735Samples,
+            Commands::PSGWrite { value: 0x90, chip_index: 0 },
+            Commands::PSGWrite { value: 0x80, chip_index: 0 },
+            Commands::WaitNSamples { n: 44100 },
+            Commands::YM2612Port0Address2AWriteWait { n: 3 },
+            Commands::WaitNSamplesPlus1 { n: 0 },
+            Commands::Wait882Samples,
+            Commands::EndOfSoundData,
+        ]
+    }
+
+    #[test]
+    fn test_timeline_positions() {
+        let timeline = Timeline::new(&sample_commands());
+
+        assert_eq!(
+            timeline.positions(),
+            &[0, 0, 735, 735, 735, 44835, 44838, 44839, 45721]
+        );
+        assert_eq!(timeline.total_samples(), 45721);
+        assert_eq!(timeline.sample_at(4), Some(735));
+        assert_eq!(timeline.sample_at(9), None);
+    }
+
+    #[test]
+    fn test_timeline_durations() {
+        let timeline = Timeline::new(&sample_commands());
+
+        assert_eq!(timeline.time_at(2), Some(Duration::from_nanos(16_666_666)));
+        assert_eq!(samples_to_duration(44100), Duration::from_secs(1));
+        assert_eq!(samples_to_duration(882), Duration::from_millis(20));
+        assert_eq!(duration_to_samples(Duration::from_millis(20)), 882);
+        assert_eq!(timeline.duration(), samples_to_duration(45721));
+    }
+
+    #[test]
+    fn test_timeline_seek() {
+        let timeline = Timeline::new(&sample_commands());
+
+        assert_eq!(timeline.index_at_sample(0), 0);
+        // Both writes at 735 are still ahead when seeking to 735
+        assert_eq!(timeline.index_at_sample(735), 2);
+        assert_eq!(timeline.index_at_sample(736), 5);
+        assert_eq!(timeline.index_at_time(Duration::from_secs(1)), 5);
+        assert_eq!(timeline.index_at_sample(u64::MAX), 9);
+    }
+}
@@ -1,5 +1,5 @@
 use bytes::{Bytes, BytesMut};
-use crate::errors::{VgmError, VgmResult};
+use crate::errors::VgmResult;
 
 pub trait VgmParser {
     fn from_bytes(data: &mut Bytes) -> VgmResult<Self> where Self: Sized;
@@ -1,6 +1,6 @@
 use bytes::{BufMut, BytesMut};
-use flate2::read::GzDecoder;
-use std::io::Read;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::io::{Read, Write};
 use crate::errors::{VgmError, VgmResult};
 
 /// Gzip magic bytes (RFC 1952)
@@ -37,24 +37,16 @@ pub fn bcd_from_bytes(bytes_list: &[u8]) -> u32 {
 
 /// Read decimal and return bcd version as bytes
 /// For example 151 will return [0x51, 0x01, 0x00, 0x00]
+///
+/// Always returns the 4 bytes of the header field; digits past the eighth
+/// are dropped.
 pub fn decimal_to_bcd(decimal: u32) -> Vec<u8> {
-    //let decimal = decimal as u128;
-    let mut bcd_bytes = Vec::new();
-    let mut remaining = decimal;
-
-    while remaining > 0 {
-        let digit = remaining % 100;
-        let bcd_byte = ((digit / 10) << 4) | (digit % 10);
-        bcd_bytes.push(bcd_byte as u8);
-        remaining /= 100;
-    }
-
-    //bcd_bytes.reverse();
-    // pad bcd_bytes to get target length
-    bcd_bytes.push(0x00);
-    bcd_bytes.push(0x00);
-
-    bcd_bytes
+    (0..4)
+        .map(|i| {
+            let digit = decimal / 100u32.pow(i) % 100;
+            (((digit / 10) << 4) | (digit % 10)) as u8
+        })
+        .collect()
 }
 
 /// Detect if data is gzipped by checking magic bytes
@@ -89,6 +81,30 @@ pub fn decompress_gzip(compressed_data: &[u8]) -> VgmResult<Vec<u8>> {
     Ok(decompressed)
 }
 
+/// Compression level used for VGZ output when none is given (best compression)
+pub const DEFAULT_VGZ_COMPRESSION_LEVEL: u32 = 9;
+
+/// Compress data as gzip, as used by .vgz files.
+///
+/// `level` ranges from 0 (store) to 9 (best compression).
+pub fn compress_gzip(data: &[u8], level: u32) -> VgmResult<Vec<u8>> {
+    if level > 9 {
+        return Err(VgmError::ValidationFailed {
+            field: "compression_level".to_string(),
+            reason: format!("Compression level {} outside valid range 0-9", level),
+        });
+    }
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::new(level));
+    encoder
+        .write_all(data)
+        .and_then(|_| encoder.finish())
+        .map_err(|e| VgmError::InvalidDataFormat {
+            field: "gzip_compression".to_string(),
+            details: format!("Failed to compress gzip data: {}", e),
+        })
+}
+
 /// Detect file format and decompress if necessary
 /// Returns the raw VGM data regardless of whether input was .vgm or .vgz
 pub fn detect_and_decompress(data: &[u8]) -> VgmResult<Vec<u8>> {
@@ -145,6 +161,11 @@ mod test_utils {
         assert_eq!(version, 124);
         let out_bytes = decimal_to_bcd(version);
         assert_eq!(version_bytes.to_vec(), out_bytes);
+
+        // The field keeps its width whatever the value
+        assert_eq!(decimal_to_bcd(0), vec![0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(decimal_to_bcd(12_345), vec![0x45, 0x23, 0x01, 0x00]);
+        assert_eq!(decimal_to_bcd(u32::MAX).len(), 4);
     }
 
     #[test]
@@ -276,4 +297,17 @@ mod test_utils {
         assert!(!is_vgm(b"Vgx ")); // Wrong 3rd byte
         assert!(!is_gzipped(&[0x1f, 0x8c])); // Wrong 2nd byte
     }
+
+    #[test]
+    fn test_compress_gzip_round_trip() {
+        let vgm_data = b"Vgm \x00\x00\x00\x00 some test data".repeat(16);
+
+        for level in [0, 6, 9] {
+            let compressed = compress_gzip(&vgm_data, level).unwrap();
+            assert!(is_gzipped(&compressed));
+            assert_eq!(detect_and_decompress(&compressed).unwrap(), vgm_data);
+        }
+
+        assert!(compress_gzip(&vgm_data, 10).is_err());
+    }
 }
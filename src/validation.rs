@@ -1,5 +1,10 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::diagnostics::Diagnostic;
 use crate::errors::{VgmError, VgmResult};
-use crate::{HeaderData, VgmMetadata, Commands};
+use crate::utils::decimal_to_bcd;
+use crate::{HeaderData, VgmMetadata, Commands, RawRegions, System, CHIP_CLOCK_MASK, RESERVED_HEADER_OFFSETS, VGM_SAMPLE_RATE};
 
 /// Configuration for validation limits and rules
 #[derive(Debug, Clone)]
@@ -22,7 +27,7 @@ impl Default for ValidationConfig {
     fn default() -> Self {
         Self {
             min_vgm_version: 0x00000100, // Version 1.00
-            max_vgm_version: 0x00000171, // Version 1.71 (latest known)
+            max_vgm_version: 0x00000172, // Version 1.72 (latest known)
             max_file_size: 64 * 1024 * 1024, // 64MB limit
             max_commands: 1_000_000, // 1M commands limit
             max_data_block_size: 16 * 1024 * 1024, // 16MB data block limit
@@ -79,6 +84,19 @@ impl VersionValidator {
         Ok(())
     }
     
+    /// Check the version of a parsed header.
+    ///
+    /// `HeaderData::version` holds the decoded decimal version (151 for 1.51)
+    /// while the config limits are BCD, so the header value is converted first.
+    pub fn validate_header_version(header: &HeaderData, config: &ValidationConfig) -> VgmResult<()> {
+        let bcd = decimal_to_bcd(header.version);
+        let mut bcd_bytes = [0u8; 4];
+        for (dst, src) in bcd_bytes.iter_mut().zip(bcd.iter()) {
+            *dst = *src;
+        }
+        Self::validate_version(u32::from_le_bytes(bcd_bytes), config)
+    }
+    
     /// Convert BCD version to human-readable string
     fn version_to_string(version: u32) -> String {
         let major = (version >> 8) & 0xFF;
@@ -145,6 +163,8 @@ impl ChipValidator {
     
     /// Validate that a chip clock is within reasonable bounds
     fn validate_clock_range(clock: u32, chip_name: &str, min_hz: u32, max_hz: u32) -> VgmResult<()> {
+        // Dual chip and variant flags aren't part of the frequency
+        let clock = clock & CHIP_CLOCK_MASK;
         if clock > 0 && (clock < min_hz || clock > max_hz) {
             return Err(VgmError::ValidationFailed {
                 field: format!("{} clock", chip_name),
@@ -197,6 +217,134 @@ impl ConsistencyValidator {
         Ok(())
     }
     
+    /// Validate that the loop offset lands exactly on the start of a command.
+    ///
+    /// `command_offsets` are the absolute offsets of the parsed commands; the
+    /// check is skipped when they aren't available.
+    pub fn validate_loop_point(header: &HeaderData, command_offsets: &[u32]) -> VgmResult<()> {
+        let Some(loop_start) = header.loop_start() else {
+            return Ok(());
+        };
+        if command_offsets.is_empty() {
+            return Ok(());
+        }
+
+        let on_boundary = u32::try_from(loop_start)
+            .map(|start| command_offsets.binary_search(&start).is_ok())
+            .unwrap_or(false);
+        if !on_boundary {
+            return Err(VgmError::InconsistentData {
+                context: "Loop point validation".to_string(),
+                reason: format!(
+                    "Loop offset 0x{:X} (file position 0x{:X}) does not start a command",
+                    header.loop_offset, loop_start
+                ),
+            });
+        }
+
+        Ok(())
+    }
+    
+    /// Reject commands using reserved opcodes, which strict validation treats
+    /// as a warning turned error
+    pub fn validate_no_reserved_commands(commands: &[Commands]) -> VgmResult<()> {
+        if let Some(Commands::Reserved { opcode, .. }) = commands.iter().find(|command| command.is_reserved()) {
+            return Err(VgmError::ValidationFailed {
+                field: "commands".to_string(),
+                reason: format!("Reserved opcode 0x{:02X} found", opcode),
+            });
+        }
+        Ok(())
+    }
+
+    /// One warning for each non-zero reserved header byte and unparsed
+    /// region. They're kept and written back, so validation still passes;
+    /// rips sometimes hide tool metadata there.
+    pub fn validate_unparsed_bytes(header: &HeaderData, raw_regions: &RawRegions, file_size: usize) -> Vec<Diagnostic> {
+        let mut warnings = Vec::new();
+        for (index, &byte) in header.reserved.iter().enumerate().filter(|(_, &byte)| byte != 0) {
+            let offset = RESERVED_HEADER_OFFSETS[index];
+            warnings.push(Diagnostic::warning(offset, VgmError::ValidationFailed {
+                field: "reserved".to_string(),
+                reason: format!("Reserved header byte at 0x{:02X} is 0x{:02X}", offset, byte),
+            }));
+        }
+        for (region, bytes) in raw_regions.non_zero() {
+            // Each region ends where the next parsed structure starts
+            let end = match region {
+                "header_padding" => header.vgm_data_start().unwrap_or(0),
+                "after_commands" => header.gd3_offset as usize + 0x14,
+                _ => file_size,
+            };
+            warnings.push(Diagnostic::warning(end.saturating_sub(bytes.len()), VgmError::ValidationFailed {
+                field: region.to_string(),
+                reason: format!("{} unparsed bytes are not all zero", bytes.len()),
+            }));
+        }
+        warnings
+    }
+
+    /// One warning for each DAC stream driven by a `0x91`-`0x95` command
+    /// before a `0x90` command sets it up. Players ignore those commands, so
+    /// validation only warns about them, even in strict mode.
+    ///
+    /// `command_offsets` locate the warnings; offset 0 is used without them.
+    pub fn validate_dac_stream_setup(commands: &[Commands], command_offsets: &[u32]) -> Vec<Diagnostic> {
+        let mut set_up = HashSet::new();
+        let mut reported = HashSet::new();
+        let mut warnings = Vec::new();
+        for (index, command) in commands.iter().enumerate() {
+            let stream_id = match *command {
+                Commands::DACStreamSetupControl { stream_id, .. } => {
+                    set_up.insert(stream_id);
+                    continue;
+                },
+                Commands::DACStreamStop { stream_id: ALL_DAC_STREAMS } => continue,
+                Commands::DACStreamSetData { stream_id, .. }
+                | Commands::DACStreamSetFrequency { stream_id, .. }
+                | Commands::DACStreamStart { stream_id, .. }
+                | Commands::DACStreamStop { stream_id }
+                | Commands::DACStreamStartFast { stream_id, .. } => stream_id,
+                _ => continue,
+            };
+            if set_up.contains(&stream_id) || !reported.insert(stream_id) {
+                continue;
+            }
+            let offset = command_offsets.get(index).map_or(0, |&offset| offset as usize);
+            warnings.push(Diagnostic::warning(offset, VgmError::InconsistentData {
+                context: "DAC stream validation".to_string(),
+                reason: format!("DAC stream {} is controlled before a 0x90 command sets it up", stream_id),
+            }));
+        }
+        warnings
+    }
+
+    /// Compare the header's `total_nb_samples` and `loop_nb_samples` with the
+    /// waits actually present in `commands`.
+    ///
+    /// `loop_index` is the command the loop starts at. In strict mode a
+    /// mismatch is an error; otherwise it's only described by the report.
+    pub fn validate_sample_counts(
+        header: &HeaderData,
+        commands: &[Commands],
+        loop_index: Option<usize>,
+        strict: bool,
+    ) -> VgmResult<SampleCountReport> {
+        let (actual_total, actual_loop) = crate::timeline::sample_counts(commands, loop_index);
+        let report = SampleCountReport {
+            header_total: header.total_nb_samples,
+            actual_total,
+            header_loop: header.loop_nb_samples,
+            actual_loop,
+        };
+
+        if strict && !report.is_consistent() {
+            return Err(report.mismatch_error());
+        }
+
+        Ok(report)
+    }
+
     /// Validate that commands are consistent with header configuration
     pub fn validate_commands_consistency(header: &HeaderData, commands: &[Commands]) -> VgmResult<()> {
         let mut chip_usage = ChipUsageTracker::new();
@@ -213,19 +361,98 @@ impl ConsistencyValidator {
     }
 }
 
+/// Sample counts stored in the header next to the ones summed from the wait
+/// commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleCountReport {
+    /// `total_nb_samples` from the header
+    pub header_total: u32,
+    /// Sum of all waits before the end of sound data
+    pub actual_total: u32,
+    /// `loop_nb_samples` from the header
+    pub header_loop: u32,
+    /// Sum of all waits from the loop point on, 0 without a loop
+    pub actual_loop: u32,
+}
+
+impl SampleCountReport {
+    /// Whether both header counts match the commands
+    pub fn is_consistent(&self) -> bool {
+        self.header_total == self.actual_total && self.header_loop == self.actual_loop
+    }
+
+    /// Header total minus actual total, in samples
+    pub fn total_difference(&self) -> i64 {
+        self.header_total as i64 - self.actual_total as i64
+    }
+
+    /// Header loop length minus actual loop length, in samples
+    pub fn loop_difference(&self) -> i64 {
+        self.header_loop as i64 - self.actual_loop as i64
+    }
+
+    /// [`SampleCountReport::total_difference`] in seconds at 44.1 kHz
+    pub fn total_difference_seconds(&self) -> f64 {
+        self.total_difference() as f64 / VGM_SAMPLE_RATE as f64
+    }
+
+    /// [`SampleCountReport::loop_difference`] in seconds at 44.1 kHz
+    pub fn loop_difference_seconds(&self) -> f64 {
+        self.loop_difference() as f64 / VGM_SAMPLE_RATE as f64
+    }
+
+    /// The mismatch as a warning at the first header count that's off, or
+    /// `None` when both counts match
+    pub fn warning(&self) -> Option<Diagnostic> {
+        let offset = if self.total_difference() != 0 {
+            TOTAL_SAMPLES_OFFSET
+        } else if self.loop_difference() != 0 {
+            LOOP_SAMPLES_OFFSET
+        } else {
+            return None;
+        };
+        Some(Diagnostic::warning(offset, self.mismatch_error()))
+    }
+
+    fn mismatch_error(&self) -> VgmError {
+        VgmError::InconsistentData {
+            context: "Sample count validation".to_string(),
+            reason: self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for SampleCountReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "header total {} vs {} counted ({:+} samples, {:+.3}s), header loop {} vs {} counted ({:+} samples, {:+.3}s)",
+            self.header_total,
+            self.actual_total,
+            self.total_difference(),
+            self.total_difference_seconds(),
+            self.header_loop,
+            self.actual_loop,
+            self.loop_difference(),
+            self.loop_difference_seconds(),
+        )
+    }
+}
+
+/// Header offsets of `total_nb_samples` and `loop_nb_samples`
+const TOTAL_SAMPLES_OFFSET: usize = 0x18;
+const LOOP_SAMPLES_OFFSET: usize = 0x20;
+
+/// Stream ID of `0x94` commands that stops every stream
+const ALL_DAC_STREAMS: u8 = 0xFF;
+
 /// Helper struct to track chip usage in commands
 #[derive(Debug, Default)]
 struct ChipUsageTracker {
-    sn76489_used: bool,
-    ym2612_used: bool,
-    ym2151_used: bool,
-    ym2413_used: bool,
-    ym2203_used: bool,
-    ym2608_used: bool,
-    ym2610_used: bool,
-    ym3812_used: bool,
-    ym3526_used: bool,
-    y8950_used: bool,
+    /// Chip and chip index of every chip written to, in order of first use
+    used: Vec<(System, u8)>,
+    /// Chip each DAC stream was attached to by its `0x90` setup
+    streams: HashMap<u8, (System, u8)>,
 }
 
 impl ChipUsageTracker {
@@ -234,91 +461,48 @@ impl ChipUsageTracker {
     }
     
     fn track_command(&mut self, command: &Commands) {
-        match command {
-            Commands::PSGWrite { .. } => self.sn76489_used = true,
-            Commands::YM2612Port0Write { .. } | Commands::YM2612Port1Write { .. } => self.ym2612_used = true,
-            Commands::YM2151Write { .. } => self.ym2151_used = true,
-            Commands::YM2413Write { .. } => self.ym2413_used = true,
-            Commands::YM2203Write { .. } => self.ym2203_used = true,
-            Commands::YM2608Port0Write { .. } | Commands::YM2608Port1Write { .. } => self.ym2608_used = true,
-            Commands::YM2610Port0Write { .. } | Commands::YM2610Port1Write { .. } => self.ym2610_used = true,
-            Commands::YM3812Write { .. } => self.ym3812_used = true,
-            Commands::YM3526Write { .. } => self.ym3526_used = true,
-            Commands::Y8950Write { .. } => self.y8950_used = true,
-            _ => {} // Other commands don't indicate specific chip usage
+        let chip = match *command {
+            Commands::DACStreamStop { stream_id: ALL_DAC_STREAMS } => return,
+            // Commands 0x91-0x95 drive the chip of the stream's setup
+            Commands::DACStreamSetData { stream_id, .. }
+            | Commands::DACStreamSetFrequency { stream_id, .. }
+            | Commands::DACStreamStart { stream_id, .. }
+            | Commands::DACStreamStop { stream_id }
+            | Commands::DACStreamStartFast { stream_id, .. } => match self.streams.get(&stream_id) {
+                Some(chip) => *chip,
+                // Players ignore streams that were never set up
+                None => return,
+            },
+            _ => match command.target() {
+                Some(target) => (target.system, target.chip_index),
+                None => return,
+            },
+        };
+        if let Commands::DACStreamSetupControl { stream_id, .. } = *command {
+            self.streams.insert(stream_id, chip);
+        }
+        if !self.used.contains(&chip) {
+            self.used.push(chip);
         }
     }
-    
+
     fn validate_against_header(&self, header: &HeaderData) -> VgmResult<()> {
-        // Check that used chips have clock configurations
-        if self.sn76489_used && header.sn76489_clock == 0 {
-            return Err(VgmError::InconsistentData {
-                context: "Chip usage validation".to_string(),
-                reason: "SN76489 commands found but no clock configured".to_string(),
-            });
-        }
-        
-        if self.ym2612_used && header.ym2612_clock == 0 {
-            return Err(VgmError::InconsistentData {
-                context: "Chip usage validation".to_string(),
-                reason: "YM2612 commands found but no clock configured".to_string(),
-            });
-        }
-        
-        if self.ym2151_used && header.ym2151_clock == 0 {
-            return Err(VgmError::InconsistentData {
-                context: "Chip usage validation".to_string(),
-                reason: "YM2151 commands found but no clock configured".to_string(),
-            });
-        }
-        
-        if self.ym2413_used && header.ym2413_clock == 0 {
-            return Err(VgmError::InconsistentData {
-                context: "Chip usage validation".to_string(),
-                reason: "YM2413 commands found but no clock configured".to_string(),
-            });
-        }
-        
-        if self.ym2203_used && header.ym2203_clock == 0 {
-            return Err(VgmError::InconsistentData {
-                context: "Chip usage validation".to_string(),
-                reason: "YM2203 commands found but no clock configured".to_string(),
-            });
-        }
-        
-        if self.ym2608_used && header.ym2608_clock == 0 {
-            return Err(VgmError::InconsistentData {
-                context: "Chip usage validation".to_string(),
-                reason: "YM2608 commands found but no clock configured".to_string(),
-            });
-        }
-        
-        if self.ym2610_used && header.ym2610_b_clock == 0 {
-            return Err(VgmError::InconsistentData {
-                context: "Chip usage validation".to_string(),
-                reason: "YM2610 commands found but no clock configured".to_string(),
-            });
-        }
-        
-        if self.ym3812_used && header.ym3812_clock == 0 {
-            return Err(VgmError::InconsistentData {
-                context: "Chip usage validation".to_string(),
-                reason: "YM3812 commands found but no clock configured".to_string(),
-            });
-        }
-        
-        if self.ym3526_used && header.ym3526_clock == 0 {
-            return Err(VgmError::InconsistentData {
-                context: "Chip usage validation".to_string(),
-                reason: "YM3526 commands found but no clock configured".to_string(),
-            });
-        }
-        
-        if self.y8950_used && header.y8950_clock == 0 {
-            return Err(VgmError::InconsistentData {
-                context: "Chip usage validation".to_string(),
-                reason: "Y8950 commands found but no clock configured".to_string(),
-            });
+        for (system, chip_index) in &self.used {
+            let available = header.chip_count(system);
+            if *chip_index >= available {
+                let reason = if available == 0 {
+                    format!("{:?} commands found but no clock configured", system)
+                } else {
+                    format!(
+                        "{:?} commands for chip {} found but the header doesn't enable a second chip",
+                        system, chip_index
+                    )
+                };
+                return Err(VgmError::InconsistentData {
+                    context: "Chip usage validation".to_string(),
+                    reason,
+                });
+            }
         }
         
         Ok(())
@@ -337,6 +521,7 @@ impl VgmValidator {
     }
     
     /// Create a validator with default configuration
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> Self {
         Self::new(ValidationConfig::default())
     }
@@ -355,7 +540,7 @@ impl VgmValidator {
         };
         
         // Version compatibility validation
-        VersionValidator::validate_version(header.version, &self.config)?;
+        VersionValidator::validate_header_version(header, &self.config)?;
         
         // Header validation
         header.validate(&context)?;
@@ -375,6 +560,9 @@ impl VgmValidator {
         // Cross-component consistency validation
         ConsistencyValidator::validate_header_consistency(header, file_size)?;
         ConsistencyValidator::validate_commands_consistency(header, commands)?;
+        if self.config.strict_mode {
+            ConsistencyValidator::validate_no_reserved_commands(commands)?;
+        }
         
         Ok(())
     }
@@ -382,7 +570,7 @@ impl VgmValidator {
     /// Perform quick validation suitable for streaming scenarios
     pub fn quick_validate_header(&self, header: &HeaderData) -> VgmResult<()> {
         // Fast version check
-        VersionValidator::validate_version(header.version, &self.config)?;
+        VersionValidator::validate_header_version(header, &self.config)?;
         
         // Basic chip validation
         ChipValidator::validate_chip_clocks(header)?;
@@ -411,6 +599,20 @@ mod tests {
         assert!(VersionValidator::validate_version(0x00000200, &config).is_err());
     }
     
+    #[test]
+    fn test_header_version_is_decimal() {
+        let config = ValidationConfig::default();
+        let header = HeaderData { version: 151, ..HeaderData::default() };
+        assert!(VersionValidator::validate_header_version(&header, &config).is_ok());
+
+        // 1.72 adds the Mikey
+        let header = HeaderData { version: 172, ..HeaderData::default() };
+        assert!(VersionValidator::validate_header_version(&header, &config).is_ok());
+
+        let header = HeaderData { version: 200, ..HeaderData::default() };
+        assert!(VersionValidator::validate_header_version(&header, &config).is_err());
+    }
+    
     #[test]
     fn test_offset_validator() {
         // Valid offset
@@ -427,6 +629,7 @@ mod tests {
     }
     
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_chip_validator() {
         let mut header = HeaderData::default();
         
@@ -440,6 +643,130 @@ mod tests {
         assert!(ChipValidator::validate_chip_clocks(&header).is_err());
     }
     
+    #[test]
+    fn test_chip_usage_covers_all_chips() {
+        let header = HeaderData {
+            sn76489_clock: 3579545,
+            ..HeaderData::default()
+        };
+
+        let commands = vec![Commands::QSoundWrite { register: 0x00, value: 0x1234 }];
+        assert!(ConsistencyValidator::validate_commands_consistency(&header, &commands).is_err());
+
+        let with_clock = HeaderData { qsound_clock: 4_000_000, ..header.clone() };
+        assert!(ConsistencyValidator::validate_commands_consistency(&with_clock, &commands).is_ok());
+
+        // DAC streams are checked against the chip they're attached to
+        let commands = vec![Commands::DACStreamSetupControl {
+            stream_id: 0,
+            chip_type: 0x02,
+            port: 0,
+            command: 0x2A,
+            chip_index: 0,
+        }];
+        assert!(ConsistencyValidator::validate_commands_consistency(&header, &commands).is_err());
+    }
+
+    #[test]
+    fn test_chip_usage_dac_streams() {
+        let mut header = HeaderData {
+            ym2612_clock: 7_670_453,
+            ..HeaderData::default()
+        };
+        let setup = |stream_id, chip_index| Commands::DACStreamSetupControl {
+            stream_id,
+            chip_type: 0x02,
+            port: 0,
+            command: 0x2A,
+            chip_index,
+        };
+        let start = |stream_id| Commands::DACStreamStartFast { stream_id, block_id: 0, flags: 0 };
+
+        let commands = vec![setup(0, 0), start(0), Commands::DACStreamStop { stream_id: ALL_DAC_STREAMS }];
+        assert!(ConsistencyValidator::validate_commands_consistency(&header, &commands).is_ok());
+
+        // Players ignore a stream started before its setup, validation warns
+        let commands = vec![start(1), setup(1, 0), start(1), start(2), start(2)];
+        assert!(ConsistencyValidator::validate_commands_consistency(&header, &commands).is_ok());
+        let warnings = ConsistencyValidator::validate_dac_stream_setup(&commands, &[0x100, 0x105, 0x10F, 0x114, 0x119]);
+        let offsets: Vec<usize> = warnings.iter().map(|warning| warning.offset).collect();
+        assert_eq!(offsets, vec![0x100, 0x114]);
+        assert!(warnings.iter().all(|warning| warning.severity == crate::Severity::Warning));
+
+        // Streams drive the chip their setup attached them to
+        let commands = vec![setup(0, 1), start(0)];
+        let mut tracker = ChipUsageTracker::new();
+        commands.iter().for_each(|command| tracker.track_command(command));
+        assert_eq!(tracker.used, vec![(System::YM2612, 1)]);
+        assert!(ConsistencyValidator::validate_commands_consistency(&header, &commands).is_err());
+        header.ym2612_clock |= crate::DUAL_CHIP_FLAG;
+        assert!(ConsistencyValidator::validate_commands_consistency(&header, &commands).is_ok());
+    }
+
+    #[test]
+    fn test_chip_usage_second_chip() {
+        let mut header = HeaderData {
+            ay8910_clock: 1_789_772,
+            ..HeaderData::default()
+        };
+        let commands = vec![
+            Commands::AY8910Write { register: 0x07, value: 0x38, chip_index: 0 },
+            Commands::AY8910Write { register: 0x07, value: 0x38, chip_index: 1 },
+        ];
+        assert!(ConsistencyValidator::validate_commands_consistency(&header, &commands).is_err());
+
+        header.ay8910_clock |= crate::DUAL_CHIP_FLAG;
+        assert!(ConsistencyValidator::validate_commands_consistency(&header, &commands).is_ok());
+
+        // Second-chip writes selected by bit 7 of a port or offset count too
+        for command in [
+            Commands::SCC1Write { port: 0x00, register: 0x01, value: 0x05, chip_index: 1 },
+            Commands::SegaPCMWrite { offset: 0x1234, value: 0x56, chip_index: 1 },
+        ] {
+            let system = command.target().unwrap().system;
+            let mut header = HeaderData::default();
+            header.set_chip(&system, 3_579_545, false).unwrap();
+            assert!(ConsistencyValidator::validate_commands_consistency(&header, std::slice::from_ref(&command)).is_err());
+            header.set_chip(&system, 3_579_545, true).unwrap();
+            assert!(ConsistencyValidator::validate_commands_consistency(&header, &[command]).is_ok());
+        }
+        // The dual flag doesn't count towards the clock range
+        header.sn76489_clock = 3579545 | crate::DUAL_CHIP_FLAG;
+        assert!(ChipValidator::validate_chip_clocks(&header).is_ok());
+    }
+
+    #[test]
+    fn test_sample_count_validation() {
+        let commands = vec![
+            Commands::Wait735Samples,
+            Commands::PSGWrite { value: 0x9F, chip_index: 0 },
+            Commands::WaitNSamples { n: 44100 },
+            Commands::EndOfSoundData,
+        ];
+        let mut header = HeaderData {
+            total_nb_samples: 735 + 44100,
+            loop_nb_samples: 44100,
+            ..HeaderData::default()
+        };
+
+        let report = ConsistencyValidator::validate_sample_counts(&header, &commands, Some(1), true).unwrap();
+        assert!(report.is_consistent());
+
+        // One second too long in the header
+        header.total_nb_samples += 44100;
+        assert!(ConsistencyValidator::validate_sample_counts(&header, &commands, Some(1), true).is_err());
+        let report = ConsistencyValidator::validate_sample_counts(&header, &commands, Some(1), false).unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(report.total_difference(), 44100);
+        assert_eq!(report.total_difference_seconds(), 1.0);
+        assert_eq!(report.loop_difference(), 0);
+
+        // Without a loop point no samples count as looped
+        let report = ConsistencyValidator::validate_sample_counts(&header, &commands, None, false).unwrap();
+        assert_eq!(report.actual_loop, 0);
+        assert_eq!(report.loop_difference(), 44100);
+    }
+
     #[test]
     fn test_validation_config() {
         let config = ValidationConfig::default();
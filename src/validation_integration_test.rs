@@ -5,6 +5,7 @@ mod integration_tests {
     use bytes::Bytes;
     
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_validation_framework_integration() {
         // Test 1: Version validation (test directly)
         let config = ValidationConfig::default();
@@ -105,6 +106,7 @@ mod integration_tests {
     }
     
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_chip_validator_edge_cases() {
         let mut header = HeaderData::default();
         
@@ -145,7 +147,7 @@ mod integration_tests {
     fn test_comprehensive_vgm_validation() {
         // Create a complete VGM file structure for testing
         let header = HeaderData {
-            version: 0x151,
+            version: 151,
             sn76489_clock: 3579545,
             ym2612_clock: 7670453,
             rate: 44100,
@@ -0,0 +1,547 @@
+//! Header layout of each VGM version.
+//!
+//! Every header field after the identifier has one [`HeaderField`] entry
+//! giving its position, size and the version that introduced it. Writing a
+//! header for a given version and converting files between versions are both
+//! driven by the table.
+
+use std::fmt;
+
+use bytes::BytesMut;
+
+use crate::{
+    errors::{VgmError, VgmResult},
+    utils::decimal_to_bcd,
+    Commands, HeaderData, System, VgmFile, DUAL_CHIP_FLAG, RESERVED_HEADER_OFFSETS,
+};
+
+/// Oldest version the spec describes (1.00)
+pub const MIN_VGM_VERSION: u32 = 100;
+/// Newest version the spec describes (1.72)
+pub const MAX_VGM_VERSION: u32 = 172;
+
+/// First version with a `vgm_data_offset` field
+const DATA_OFFSET_VERSION: u32 = 150;
+/// First version with an extra header
+const EXTRA_HEADER_VERSION: u32 = 170;
+
+/// One header field
+#[derive(Clone, Copy)]
+pub struct HeaderField {
+    /// Name of the [`HeaderData`] field
+    pub name: &'static str,
+    /// Position in the file
+    pub offset: usize,
+    /// Size in bytes
+    pub size: usize,
+    /// Version that introduced the field
+    pub min_version: u32,
+    get: fn(&HeaderData) -> u32,
+    set: fn(&mut HeaderData, u32),
+}
+
+impl HeaderField {
+    /// Position right after the field
+    pub fn end(&self) -> usize {
+        self.offset + self.size
+    }
+
+    /// Value of the field in `header`, widened to 32 bits
+    pub fn get(&self, header: &HeaderData) -> u32 {
+        (self.get)(header)
+    }
+
+    /// Store `value` into the field of `header`, truncated to the field size
+    pub fn set(&self, header: &mut HeaderData, value: u32) {
+        (self.set)(header, value)
+    }
+}
+
+impl fmt::Debug for HeaderField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeaderField")
+            .field("name", &self.name)
+            .field("offset", &self.offset)
+            .field("size", &self.size)
+            .field("min_version", &self.min_version)
+            .finish()
+    }
+}
+
+macro_rules! header_fields {
+    ($(($offset:expr, $min_version:expr, $field:ident: $ty:ty)),* $(,)?) => {
+        &[$(HeaderField {
+            name: stringify!($field),
+            offset: $offset,
+            size: std::mem::size_of::<$ty>(),
+            min_version: $min_version,
+            get: |header| u32::from(header.$field),
+            set: |header, value| header.$field = value as $ty,
+        }),*]
+    };
+}
+
+/// Every header field except the identifier and the version, in file order
+const HEADER_FIELDS: &[HeaderField] = header_fields![
+    (0x04, 100, end_of_file_offset: u32),
+    (0x0C, 100, sn76489_clock: u32),
+    (0x10, 100, ym2413_clock: u32),
+    (0x14, 100, gd3_offset: u32),
+    (0x18, 100, total_nb_samples: u32),
+    (0x1C, 100, loop_offset: u32),
+    (0x20, 100, loop_nb_samples: u32),
+    (0x24, 101, rate: u32),
+    (0x28, 110, sn76489_feedback: u16),
+    (0x2A, 110, sn76489_shift_register_width: u8),
+    (0x2B, 151, sn76489_flags: u8),
+    (0x2C, 110, ym2612_clock: u32),
+    (0x30, 110, ym2151_clock: u32),
+    (0x34, 150, vgm_data_offset: u32),
+    (0x38, 151, sega_pcm_clock: u32),
+    (0x3C, 151, spcm_interface: u32),
+    (0x40, 151, rf5_c68_clock: u32),
+    (0x44, 151, ym2203_clock: u32),
+    (0x48, 151, ym2608_clock: u32),
+    (0x4C, 151, ym2610_b_clock: u32),
+    (0x50, 151, ym3812_clock: u32),
+    (0x54, 151, ym3526_clock: u32),
+    (0x58, 151, y8950_clock: u32),
+    (0x5C, 151, ymf262_clock: u32),
+    (0x60, 151, ymf278_b_clock: u32),
+    (0x64, 151, ymf271_clock: u32),
+    (0x68, 151, ymz280_b_clock: u32),
+    (0x6C, 151, rf5_c164_clock: u32),
+    (0x70, 151, pwm_clock: u32),
+    (0x74, 151, ay8910_clock: u32),
+    (0x78, 151, ay8910_chip_type: u8),
+    (0x79, 151, ay8910_flags: u8),
+    (0x7A, 151, ym2203_ay8910_flags: u8),
+    (0x7B, 151, ym2608_ay8910_flags: u8),
+    (0x7C, 160, volume_modifier: u8),
+    (0x7E, 160, loop_base: u8),
+    (0x7F, 151, loop_modifier: u8),
+    (0x80, 161, gb_dmg_clock: u32),
+    (0x84, 161, nes_apu_clock: u32),
+    (0x88, 161, multi_pcm_clock: u32),
+    (0x8C, 161, u_pd7759_clock: u32),
+    (0x90, 161, okim6258_clock: u32),
+    (0x94, 161, okim6258_flags: u8),
+    (0x95, 161, k054539_flags: u8),
+    (0x96, 161, c140_chip_type: u8),
+    (0x98, 161, okim6295_clock: u32),
+    (0x9C, 161, k051649_k052539_clock: u32),
+    (0xA0, 161, k054539_clock: u32),
+    (0xA4, 161, hu_c6280_clock: u32),
+    (0xA8, 161, c140_clock: u32),
+    (0xAC, 161, k053260_clock: u32),
+    (0xB0, 161, pokey_clock: u32),
+    (0xB4, 161, qsound_clock: u32),
+    (0xB8, 171, scsp_clock: u32),
+    (0xBC, 170, extra_header_offset: u32),
+    (0xC0, 171, wonder_swan_clock: u32),
+    (0xC4, 171, vsu_clock: u32),
+    (0xC8, 171, saa1099_clock: u32),
+    (0xCC, 171, es5503_clock: u32),
+    (0xD0, 171, es5506_clock: u32),
+    (0xD4, 171, es5503_nb_channels: u8),
+    (0xD5, 171, es5505_es5506_nb_channels: u8),
+    (0xD6, 171, c352_clock_divider: u8),
+    (0xD8, 171, x1010_clock: u32),
+    (0xDC, 171, c352_clock: u32),
+    (0xE0, 171, ga20_clock: u32),
+    (0xE4, 172, mikey_clock: u32),
+];
+
+/// The whole field table, ordered by position
+pub fn header_fields() -> &'static [HeaderField] {
+    HEADER_FIELDS
+}
+
+/// Fields a file of `version` defines
+pub fn fields_for_version(version: u32) -> impl Iterator<Item = &'static HeaderField> {
+    HEADER_FIELDS.iter().filter(move |field| field.min_version <= version)
+}
+
+/// Size of the smallest header holding every field of `version`.
+///
+/// Never less than 0x40, where the data of files older than 1.50 starts.
+pub fn header_size(version: u32) -> usize {
+    fields_for_version(version).map(HeaderField::end).fold(0x40, usize::max)
+}
+
+/// Where an extra header goes: after every field `header.version` defines
+/// or that holds a value, on a 4-byte boundary
+pub(crate) fn extra_header_position(header: &HeaderData) -> usize {
+    HEADER_FIELDS
+        .iter()
+        .filter(|field| field.min_version <= header.version || field.get(header) != 0)
+        .map(HeaderField::end)
+        .fold(0xC0, usize::max)
+        .next_multiple_of(4)
+}
+
+/// Check that `version` is one the field table describes
+pub fn check_version(version: u32) -> VgmResult<()> {
+    if !(MIN_VGM_VERSION..=MAX_VGM_VERSION).contains(&version) {
+        return Err(VgmError::UnsupportedVgmVersion {
+            version,
+            supported_range: "1.00-1.72".to_string(),
+        });
+    }
+    Ok(())
+}
+
+impl HeaderData {
+    /// Copy of the header laid out for `version`.
+    ///
+    /// Fields `version` doesn't define are cleared, and `vgm_data_offset` and
+    /// `extra_header_offset` describe the smallest layout: the fields, then the
+    /// extra header for 1.70 and later, then the VGM data on the next 4-byte
+    /// boundary.
+    pub fn for_version(&self, version: u32) -> VgmResult<HeaderData> {
+        check_version(version)?;
+
+        let mut header = HeaderData::default();
+        for field in fields_for_version(version) {
+            field.set(&mut header, field.get(self));
+        }
+        header.version = version;
+
+        let size = header_size(version);
+        for (byte, (&offset, &value)) in header.reserved.iter_mut().zip(RESERVED_HEADER_OFFSETS.iter().zip(&self.reserved)) {
+            if offset < size {
+                *byte = value;
+            }
+        }
+
+        let mut data_start = size;
+        header.extra_header_offset = 0;
+        if version >= EXTRA_HEADER_VERSION && !self.extra_header.is_empty() {
+            header.extra_header = self.extra_header.clone();
+            header.extra_header.normalize();
+            let position = extra_header_position(&header);
+            header.extra_header_offset = (position - 0xBC) as u32;
+            data_start = (position + header.extra_header.encoded_size()).next_multiple_of(4);
+        }
+
+        header.vgm_data_offset = if version >= DATA_OFFSET_VERSION { (data_start - 0x34) as u32 } else { 0 };
+        Ok(header)
+    }
+
+    /// Serialize the header as a file of `version` lays it out, see
+    /// [`HeaderData::for_version`]. The result ends where the VGM data starts.
+    pub fn to_bytes_for_version(&self, version: u32) -> VgmResult<Vec<u8>> {
+        let header = self.for_version(version)?;
+
+        let mut out = vec![0u8; header_size(version)];
+        out[0x00..0x04].copy_from_slice(b"Vgm ");
+        out[0x08..0x0C].copy_from_slice(&decimal_to_bcd(version));
+        for field in fields_for_version(version) {
+            out[field.offset..field.end()].copy_from_slice(&field.get(&header).to_le_bytes()[..field.size]);
+        }
+        for (&offset, &value) in RESERVED_HEADER_OFFSETS.iter().zip(&header.reserved) {
+            if offset < out.len() {
+                out[offset] = value;
+            }
+        }
+
+        if header.extra_header_offset != 0 {
+            let mut extra = BytesMut::new();
+            header.write_extra_header(&mut extra);
+            out.extend_from_slice(&extra);
+        }
+        out.resize(header.vgm_data_start()?, 0);
+        Ok(out)
+    }
+}
+
+impl VgmFile {
+    /// Convert the file to `version`, laying the header out as
+    /// [`HeaderData::for_version`] does and moving every offset with it.
+    ///
+    /// Fails without changing anything when a chip the header enables, its
+    /// dual-chip setup, or a command such as a data block or DAC stream
+    /// control didn't exist yet in `version`. Other fields the
+    /// target doesn't define, such as the loop base or the extra header, are
+    /// dropped. Upgrading a 1.01 or older file fills in the SN76489 feedback
+    /// and shift register width and the YM2612/YM2151 clocks those files
+    /// implied.
+    pub fn convert_version(&mut self, version: u32) -> VgmResult<()> {
+        check_version(version)?;
+        for chip in self.header.chips() {
+            let dual = self.header.chip_clock(&chip.system) & DUAL_CHIP_FLAG != 0;
+            let (feature, min_version) = if dual && chip.index == 1 && version < DUAL_CHIP_VERSION {
+                (format!("dual {:?}", chip.system), DUAL_CHIP_VERSION)
+            } else {
+                (format!("{:?}", chip.system), chip.system.min_version())
+            };
+            if min_version > version {
+                return Err(VgmError::FeatureNotSupported { feature, version, min_version });
+            }
+        }
+        let newer_command = self.commands.iter().filter_map(command_min_version).find(|(_, min_version)| *min_version > version);
+        if let Some((feature, min_version)) = newer_command {
+            return Err(VgmError::FeatureNotSupported { feature: feature.to_string(), version, min_version });
+        }
+
+        let mut source = self.header.clone();
+        if source.version <= 101 && version >= 110 {
+            if source.sn76489_clock != 0 && source.sn76489_feedback == 0 {
+                source.sn76489_feedback = 0x0009;
+                source.sn76489_shift_register_width = 16;
+            }
+            for system in [System::YM2612, System::YM2151] {
+                let used = self.commands.iter().any(|command| command.target().is_some_and(|target| target.system == system));
+                if used && source.chip_clock(&system) == 0 {
+                    source.set_chip(&system, source.ym2413_clock, false)?;
+                }
+            }
+        }
+
+        let mut header = source.for_version(version)?;
+        let delta = header.vgm_data_start()? as i64 - self.header.vgm_data_start()? as i64;
+        header.end_of_file_offset = shift_offset(header.end_of_file_offset, delta)?;
+        header.gd3_offset = shift_offset(header.gd3_offset, delta)?;
+        header.loop_offset = shift_offset(header.loop_offset, delta)?;
+        let command_offsets = self
+            .command_offsets
+            .iter()
+            .map(|&offset| shift_offset(offset, delta))
+            .collect::<VgmResult<Vec<u32>>>()?;
+
+        self.header = header;
+        self.command_offsets = command_offsets;
+        self.raw_regions.header_padding.clear();
+        Ok(())
+    }
+}
+
+/// First version with the dual chip clock bit
+const DUAL_CHIP_VERSION: u32 = 151;
+
+/// Commands added after 1.00 that the chip checks don't cover, with the
+/// version that added them
+fn command_min_version(command: &Commands) -> Option<(&'static str, u32)> {
+    match command {
+        Commands::DataBlock { .. } => Some(("data blocks", 150)),
+        Commands::PCMRAMWrite { .. } => Some(("PCM RAM writes", 160)),
+        Commands::DACStreamSetupControl { .. }
+        | Commands::DACStreamSetData { .. }
+        | Commands::DACStreamSetFrequency { .. }
+        | Commands::DACStreamStart { .. }
+        | Commands::DACStreamStop { .. }
+        | Commands::DACStreamStartFast { .. } => Some(("DAC stream control", 160)),
+        _ => None,
+    }
+}
+
+/// Move a non-zero file offset by `delta` bytes, leaving unset offsets at 0
+fn shift_offset(offset: u32, delta: i64) -> VgmResult<u32> {
+    if offset == 0 {
+        return Ok(0);
+    }
+    u32::try_from(offset as i64 + delta).map_err(|_| VgmError::IntegerOverflow {
+        operation: "version conversion offset".to_string(),
+        details: format!("offset {} moved by {}", offset, delta),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::{ChipClockEntry, ExtraHeaderData, VgmParser, VgmWriter};
+
+    #[test]
+    fn test_field_table_is_contiguous() {
+        let mut position = 0x0C;
+        for field in header_fields().iter().skip(1) {
+            // Reserved bytes are the only gaps
+            while RESERVED_HEADER_OFFSETS.contains(&position) {
+                position += 1;
+            }
+            assert_eq!(field.offset, position, "{}", field.name);
+            position = field.end();
+        }
+        assert_eq!(position, 0xE8);
+    }
+
+    #[test]
+    fn test_header_sizes() {
+        assert_eq!(header_size(100), 0x40);
+        assert_eq!(header_size(150), 0x40);
+        assert_eq!(header_size(151), 0x80);
+        assert_eq!(header_size(161), 0xB8);
+        assert_eq!(header_size(170), 0xC0);
+        assert_eq!(header_size(171), 0xE4);
+        assert_eq!(header_size(172), 0xE8);
+        assert!(HeaderData::default().to_bytes_for_version(173).is_err());
+        assert!(HeaderData::default().to_bytes_for_version(99).is_err());
+    }
+
+    #[test]
+    fn test_to_bytes_for_version() {
+        let header = HeaderData {
+            version: 171,
+            sn76489_clock: 3_579_545,
+            ym2612_clock: 7_670_453,
+            loop_base: 2,
+            saa1099_clock: 8_000_000,
+            ..HeaderData::default()
+        };
+
+        let bytes = header.to_bytes_for_version(150).unwrap();
+        assert_eq!(bytes.len(), 0x40);
+        assert_eq!(bytes[0x08..0x0C], [0x50, 0x01, 0x00, 0x00]);
+        assert_eq!(bytes[0x2C..0x30], 7_670_453u32.to_le_bytes());
+        assert_eq!(bytes[0x34..0x38], 0x0Cu32.to_le_bytes());
+
+        let parsed = HeaderData::from_bytes(&mut Bytes::from(bytes)).unwrap();
+        assert_eq!(parsed, header.for_version(150).unwrap());
+        assert_eq!(parsed.loop_base, 0);
+        assert_eq!(parsed.saa1099_clock, 0);
+
+        // Version 1.00 files have no data offset and start their data at 0x40
+        let bytes = header.to_bytes_for_version(100).unwrap();
+        assert_eq!(bytes.len(), 0x40);
+        assert_eq!(bytes[0x2C..0x38], [0; 12]);
+    }
+
+    #[test]
+    fn test_extra_header_follows_fields() {
+        let header = HeaderData {
+            version: 170,
+            extra_header: ExtraHeaderData {
+                header_size: 12,
+                chip_clock_offset: 8,
+                chip_vol_offset: 0,
+                chip_clock_entries: vec![ChipClockEntry { chip_id: 0x80, clock: 3_000_000 }],
+                chip_volume_entries: vec![],
+                leading_bytes: vec![],
+            },
+            ..HeaderData::default()
+        };
+
+        let bytes = header.to_bytes_for_version(170).unwrap();
+        assert_eq!(bytes.len(), (0xC0 + 12 + 1 + 5usize).next_multiple_of(4));
+        assert_eq!(bytes[0xBC..0xC0], 4u32.to_le_bytes());
+
+        let parsed = HeaderData::from_bytes(&mut Bytes::from(bytes.clone())).unwrap();
+        assert_eq!(parsed.extra_header, header.extra_header);
+        assert_eq!(parsed.vgm_data_start().unwrap(), bytes.len());
+
+        // Dropped below 1.70
+        assert_eq!(header.to_bytes_for_version(161).unwrap().len(), 0xB8);
+    }
+
+    fn sample_file(version: u32) -> VgmFile {
+        let mut vgm = VgmFile {
+            header: HeaderData {
+                version,
+                sn76489_clock: 3_579_545,
+                ym2413_clock: 3_579_545,
+                vgm_data_offset: if version >= 150 { 0xCC } else { 0 },
+                ..HeaderData::default()
+            },
+            commands: vec![
+                Commands::PSGWrite { value: 0x9F, chip_index: 0 },
+                Commands::YM2612Port0Write { register: 0x28, value: 0xF0, chip_index: 0 },
+                Commands::Wait735Samples,
+                Commands::EndOfSoundData,
+            ],
+            command_offsets: vec![],
+            metadata: Default::default(),
+            raw_regions: Default::default(),
+        };
+        vgm.metadata.notes = "converted".to_string();
+        vgm
+    }
+
+    #[test]
+    fn test_convert_version_moves_offsets() {
+        let original = sample_file(151);
+        let mut vgm = VgmFile::from_bytes(&mut Bytes::from(
+            original
+                .to_bytes_with_options(&crate::WriteOptions { loop_point: crate::LoopPoint::Index(2), ..Default::default() })
+                .unwrap(),
+        ))
+        .unwrap();
+
+        // An offset that can't move back fails the whole conversion
+        let mut broken = vgm.clone();
+        broken.command_offsets[1] = 0x10;
+        let before = broken.clone();
+        assert!(matches!(broken.convert_version(150), Err(VgmError::IntegerOverflow { .. })));
+        assert_eq!(broken, before);
+
+        vgm.convert_version(150).unwrap();
+        assert_eq!(vgm.header.vgm_data_start().unwrap(), 0x40);
+        assert_eq!(vgm.offset_of(0), Some(0x40));
+        assert_eq!(vgm.loop_start_index(), Some(2));
+
+        // Written as stored, the moved offsets still describe the file
+        let mut buffer = BytesMut::new();
+        vgm.to_bytes(&mut buffer).unwrap();
+        let reparsed = VgmFile::from_bytes(&mut buffer.freeze()).unwrap();
+        assert_eq!(reparsed.header.version, 150);
+        assert_eq!(reparsed.commands, original.commands);
+        assert_eq!(reparsed.metadata, original.metadata);
+        assert_eq!(reparsed.loop_start_index(), Some(2));
+    }
+
+    #[test]
+    fn test_convert_version_checks_chips() {
+        let mut vgm = sample_file(171);
+        vgm.header.set_chip(&System::SAA1099, 8_000_000, false).unwrap();
+        assert!(matches!(
+            vgm.convert_version(150),
+            Err(VgmError::FeatureNotSupported { version: 150, min_version: 171, .. })
+        ));
+        assert_eq!(vgm.header.version, 171);
+
+        let mut dual = sample_file(151);
+        dual.header.set_chip(&System::SN76489, 3_579_545, true).unwrap();
+        assert!(matches!(
+            dual.convert_version(150),
+            Err(VgmError::FeatureNotSupported { min_version: 151, .. })
+        ));
+    }
+
+    #[test]
+    fn test_convert_version_checks_commands() {
+        let mut vgm = sample_file(171);
+        vgm.commands.insert(0, Commands::DataBlock {
+            block_type: 0x00,
+            data: crate::DataBlockContent::UncompressedStream {
+                chip_type: crate::StreamChipType::YM2612,
+                data: vec![0x80; 4],
+            },
+        });
+        assert!(matches!(
+            vgm.convert_version(110),
+            Err(VgmError::FeatureNotSupported { version: 110, min_version: 150, .. })
+        ));
+        vgm.convert_version(150).unwrap();
+
+        vgm.commands.insert(1, Commands::DACStreamStartFast { stream_id: 0, block_id: 0, flags: 0 });
+        assert!(matches!(
+            vgm.convert_version(151),
+            Err(VgmError::FeatureNotSupported { version: 151, min_version: 160, .. })
+        ));
+        assert_eq!(vgm.header.version, 150);
+        vgm.convert_version(160).unwrap();
+    }
+
+    #[test]
+    fn test_convert_version_upgrades_old_files() {
+        let mut vgm = sample_file(101);
+        vgm.convert_version(110).unwrap();
+
+        assert_eq!(vgm.header.sn76489_feedback, 0x0009);
+        assert_eq!(vgm.header.sn76489_shift_register_width, 16);
+        // The YM2612 writes ran at the YM2413 clock
+        assert_eq!(vgm.header.ym2612_clock, 3_579_545);
+        assert_eq!(vgm.header.ym2151_clock, 0);
+    }
+}
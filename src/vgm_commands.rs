@@ -3,10 +3,11 @@
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use serde::{Deserialize, Serialize};
 use crate::errors::{VgmError, VgmResult};
+use crate::System;
 
-const MAX_DATA_BLOCK_SIZE: u32 = 16 * 1024 * 1024; // 16MB limit
 
 /// Compression types for compressed data blocks
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum CompressionType {
     BitPacking {
@@ -78,6 +79,7 @@ pub enum DataBlockContent {
 }
 
 /// Chip types for streaming data blocks (uncompressed/compressed PCM streams)
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum StreamChipType {
     YM2612,          // 0x00/0x40 - Yamaha YM2612 (SegaPCM streaming)
@@ -93,6 +95,7 @@ pub enum StreamChipType {
 }
 
 /// Chip types for ROM/RAM dump blocks
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ROMDumpChipType {
     SegaPCM,         // 0x80 - Sega PCM ROM data
@@ -119,6 +122,7 @@ pub enum ROMDumpChipType {
 }
 
 /// Chip types for RAM write blocks
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum RAMWriteChipType {
     RF5C68,          // 0xC0/0xE0 - Ricoh RF5C68 RAM
@@ -189,11 +193,37 @@ impl RAMWriteChipType {
 
 impl DataBlockContent {
     pub fn parse_from_bytes(block_type: u8, data_size: u32, bytes: &mut Bytes) -> VgmResult<Self> {
+        let data_size = data_size as usize;
+        if bytes.remaining() < data_size {
+            return Err(VgmError::BufferUnderflow {
+                offset: 0,
+                needed: data_size,
+                available: bytes.remaining(),
+            });
+        }
+
+        // Fixed fields each block type starts with
+        let header_size = match block_type {
+            0x00..=0x3F => 0,
+            0x40..=0x7E => 10,
+            0x7F => 6,
+            0x80..=0xBF => 8,
+            0xC0..=0xDF => 2,
+            0xE0..=0xFF => 4,
+        };
+        if data_size < header_size {
+            return Err(VgmError::InvalidDataLength {
+                field: format!("data block type 0x{:02X}", block_type),
+                expected: header_size,
+                actual: data_size,
+            });
+        }
+
         match block_type {
             // Uncompressed streaming data (0x00-0x3F)
             0x00..=0x3F => {
                 let chip_type = StreamChipType::from_block_type(block_type);
-                let data: Vec<u8> = (0..data_size as usize).map(|_| bytes.get_u8()).collect();
+                let data: Vec<u8> = (0..data_size).map(|_| bytes.get_u8()).collect();
                 Ok(DataBlockContent::UncompressedStream { chip_type, data })
             },
             
@@ -237,8 +267,8 @@ impl DataBlockContent {
                     }
                 };
                 
-                let remaining_size = data_size.saturating_sub(9); // 1 + 4 + 4 bytes consumed
-                let data: Vec<u8> = (0..remaining_size as usize).map(|_| bytes.get_u8()).collect();
+                let remaining_size = data_size - 10; // 1 + 4 + 5 bytes consumed
+                let data: Vec<u8> = (0..remaining_size).map(|_| bytes.get_u8()).collect();
                 
                 Ok(DataBlockContent::CompressedStream {
                     chip_type,
@@ -256,7 +286,7 @@ impl DataBlockContent {
                 let bits_compressed = bytes.get_u8();
                 let value_count = bytes.get_u16_le();
                 let table_size = data_size - 6; // 6 bytes consumed
-                let table_data: Vec<u8> = (0..table_size as usize).map(|_| bytes.get_u8()).collect();
+                let table_data: Vec<u8> = (0..table_size).map(|_| bytes.get_u8()).collect();
                 
                 Ok(DataBlockContent::DecompressionTable {
                     compression_type,
@@ -274,7 +304,7 @@ impl DataBlockContent {
                 let total_size = bytes.get_u32_le();
                 let start_address = bytes.get_u32_le();
                 let data_size_remaining = data_size - 8; // 8 bytes consumed
-                let data: Vec<u8> = (0..data_size_remaining as usize).map(|_| bytes.get_u8()).collect();
+                let data: Vec<u8> = (0..data_size_remaining).map(|_| bytes.get_u8()).collect();
                 
                 Ok(DataBlockContent::ROMDump {
                     chip_type,
@@ -289,7 +319,7 @@ impl DataBlockContent {
                 let chip_type = RAMWriteChipType::from_block_type(block_type);
                 let start_address = bytes.get_u16_le();
                 let data_size_remaining = data_size - 2; // 2 bytes consumed
-                let data: Vec<u8> = (0..data_size_remaining as usize).map(|_| bytes.get_u8()).collect();
+                let data: Vec<u8> = (0..data_size_remaining).map(|_| bytes.get_u8()).collect();
                 
                 Ok(DataBlockContent::RAMWriteSmall {
                     chip_type,
@@ -303,7 +333,7 @@ impl DataBlockContent {
                 let chip_type = RAMWriteChipType::from_block_type(block_type);
                 let start_address = bytes.get_u32_le();
                 let data_size_remaining = data_size - 4; // 4 bytes consumed
-                let data: Vec<u8> = (0..data_size_remaining as usize).map(|_| bytes.get_u8()).collect();
+                let data: Vec<u8> = (0..data_size_remaining).map(|_| bytes.get_u8()).collect();
                 
                 Ok(DataBlockContent::RAMWriteLarge {
                     chip_type,
@@ -365,7 +395,15 @@ fn decompress_bit_packing(
     uncompressed_size: u32,
     decompression_table: Option<&[u8]>,
 ) -> VgmResult<Vec<u8>> {
-    let mut result = Vec::with_capacity(uncompressed_size as usize);
+    check_bit_widths(bits_compressed, bits_decompressed)?;
+    if sub_type == 0x01 && bits_compressed > bits_decompressed {
+        return Err(VgmError::InvalidDataFormat {
+            field: "bits_compressed".to_string(),
+            details: format!("Cannot shift {} bits up to {} bits", bits_compressed, bits_decompressed),
+        });
+    }
+
+    let mut result = Vec::with_capacity(decompressed_capacity(compressed_data, bits_compressed, bits_decompressed, uncompressed_size));
     let mut bit_reader = BitReader::new(compressed_data);
     
     // Calculate bytes per decompressed value
@@ -444,7 +482,9 @@ fn decompress_dpcm(
     uncompressed_size: u32,
     decompression_table: &[u8],
 ) -> VgmResult<Vec<u8>> {
-    let mut result = Vec::with_capacity(uncompressed_size as usize);
+    check_bit_widths(bits_compressed, bits_decompressed)?;
+
+    let mut result = Vec::with_capacity(decompressed_capacity(compressed_data, bits_compressed, bits_decompressed, uncompressed_size));
     let mut bit_reader = BitReader::new(compressed_data);
     let mut state = start_value as i32;
     
@@ -491,6 +531,28 @@ fn decompress_dpcm(
     Ok(result)
 }
 
+/// Reject bit widths that would read nothing or overflow a 32-bit sample
+fn check_bit_widths(bits_compressed: u8, bits_decompressed: u8) -> VgmResult<()> {
+    if !(1..=16).contains(&bits_compressed) || !(1..=32).contains(&bits_decompressed) {
+        return Err(VgmError::InvalidDataFormat {
+            field: "bit_widths".to_string(),
+            details: format!(
+                "Unsupported widths: {} bits compressed, {} bits decompressed",
+                bits_compressed, bits_decompressed
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Output size to reserve: the declared size, capped by what the input can
+/// actually produce so a corrupt header can't force a huge allocation
+fn decompressed_capacity(compressed_data: &[u8], bits_compressed: u8, bits_decompressed: u8, uncompressed_size: u32) -> usize {
+    let values = compressed_data.len().saturating_mul(8) / bits_compressed as usize;
+    let producible = values.saturating_mul((bits_decompressed as usize).div_ceil(8));
+    producible.min(uncompressed_size as usize)
+}
+
 /// Helper struct for reading bits from a byte stream
 struct BitReader<'a> {
     data: &'a [u8],
@@ -650,12 +712,13 @@ pub enum Commands {
         block_type: u8,
         data: DataBlockContent,
     },
+    /// Copy `size` bytes from a data block already sent to the chip's RAM.
+    /// Carries no data itself
     PCMRAMWrite {
         chip_type: u8,
         read_offset: u32,      // 24-bit in VGM spec
         write_offset: u32,     // 24-bit in VGM spec
-        size: u32,             // 24-bit in VGM spec
-        data: Vec<u8>,
+        size: u32,             // 24-bit in VGM spec, 1 to 0x1000000
     },
     WaitNSamplesPlus1 {
         n: u8,
@@ -777,13 +840,20 @@ pub enum Commands {
         value: u8,
         chip_index: u8,
     },
+    /// Mikey isn't a dual chip, so there is no chip index
+    MikeyWrite {
+        register: u8,
+        value: u8,
+    },
     SegaPCMWrite {
         offset: u16,
         value: u8,
+        chip_index: u8,
     },
     MultiPCMSetBank {
         channel: u8,
         offset: u16,
+        chip_index: u8,
     },
     QSoundWrite {
         register: u8,
@@ -792,49 +862,60 @@ pub enum Commands {
     SCSPWrite {
         offset: u16,
         value: u8,
+        chip_index: u8,
     },
     WonderSwanWrite16 {
         offset: u16,
         value: u8,
+        chip_index: u8,
     },
     VSUWrite {
         offset: u16,
         value: u8,
+        chip_index: u8,
     },
     X1010Write {
         offset: u16,
         value: u8,
+        chip_index: u8,
     },
     YMF278BWrite {
         port: u8,
         register: u8,
         value: u8,
+        chip_index: u8,
     },
     YMF271Write {
         port: u8,
         register: u8,
         value: u8,
+        chip_index: u8,
     },
     SCC1Write {
         port: u8,
         register: u8,
         value: u8,
+        chip_index: u8,
     },
     K054539Write {
         register: u16,
         value: u8,
+        chip_index: u8,
     },
     C140Write {
         register: u16,
         value: u8,
+        chip_index: u8,
     },
     ES5503Write {
         register: u16,
         value: u8,
+        chip_index: u8,
     },
     ES5506Write16 {
         register: u8,
         value: u16,
+        chip_index: u8,
     },
     SeekPCM {
         offset: u32,
@@ -842,6 +923,7 @@ pub enum Commands {
     C352Write {
         register: u16,
         value: u16,
+        chip_index: u8,
     },
 
     // offset write
@@ -853,73 +935,239 @@ pub enum Commands {
         offset: u16,
         value: u8,
     },
+
+    /// Opcode from a range the spec reserves for future chips, kept with its
+    /// operands so it can be written back unchanged
+    Reserved {
+        opcode: u8,
+        operands: Vec<u8>,
+    },
+}
+
+/// Number of operand bytes following a reserved opcode, `None` for opcodes
+/// outside the reserved ranges
+pub fn reserved_operand_count(opcode: u8) -> Option<usize> {
+    crate::opcode_info(opcode)
+        .filter(|info| info.is_reserved())
+        .map(|info| info.operand_len())
+}
+
+/// Number of bytes the command starting at `data[0]` occupies, opcode included.
+///
+/// Returns `Ok(None)` when `data` is too short to tell: data blocks carry their
+/// payload size inside the command. `position` is only used to report unknown
+/// opcodes.
+pub fn command_length(data: &[u8], position: usize) -> VgmResult<Option<usize>> {
+    let Some(&opcode) = data.first() else {
+        return Ok(None);
+    };
+    let info = crate::opcode_info(opcode).ok_or(VgmError::UnknownCommand {
+        opcode,
+        position,
+    })?;
+
+    let length = 1 + info.operand_len();
+    if !info.has_payload() {
+        return Ok(Some(length));
+    }
+
+    // 0x67 0x66 tt ss ss ss ss (data)
+    if data.len() < length {
+        return Ok(None);
+    }
+    let data_size = u32::from_le_bytes([data[3], data[4], data[5], data[6]]);
+    Ok(Some(length + data_size as usize))
+}
+
+/// Decode a data block command, opcode included. The caller has checked
+/// that the opcode and its 6 operand bytes are present.
+///
+/// The operands are read from a copy, so `bytes` is only consumed once the
+/// whole payload is known to be there.
+pub(crate) fn decode_data_block(
+    bytes: &mut Bytes,
+    position: usize,
+    config: &crate::ParserConfig,
+    tracker: &mut crate::ResourceTracker
+) -> VgmResult<Commands> {
+    // 0x67 0x66 tt ss ss ss ss (data)
+    let mut operands = bytes.slice(1..7);
+    let compatibility_byte = operands.get_u8();
+    if compatibility_byte != 0x66 {
+        return Err(VgmError::InvalidCommandParameters {
+            opcode: 0x67,
+            position,
+            reason: format!("Expected compatibility byte 0x66, found 0x{:02X}", compatibility_byte),
+        });
+    }
+    
+    let block_type = operands.get_u8();
+    let data_size = operands.get_u32_le();
+    
+    // Check DataBlock size against config limits
+    config.check_data_block_size(data_size)?;
+    
+    // Security: Ensure sufficient data is available before allocation
+    if bytes.remaining() - 7 < data_size as usize {
+        return Err(VgmError::IncompleteCommand {
+            opcode: 0x67,
+            position,
+            expected_bytes: 7 + data_size as usize,
+            available_bytes: bytes.remaining(),
+        });
+    }
+    
+    // Track DataBlock allocation
+    tracker.track_data_block(config, data_size)?;
+    bytes.advance(7);
+    
+    // Parse the data block content based on its type, never past its declared size
+    let mut payload = bytes.split_to(data_size as usize);
+    let data = DataBlockContent::parse_from_bytes(block_type, data_size, &mut payload).map_err(|e| {
+        VgmError::InvalidCommandParameters {
+            opcode: 0x67,
+            position,
+            reason: e.to_string(),
+        }
+    })?;
+    
+    Ok(Commands::DataBlock {
+        block_type,
+        data,
+    })
+}
+
+/// Encode a data block command, opcode included
+pub(crate) fn encode_data_block(block_type: u8, data: DataBlockContent) -> Vec<u8> {
+    // The DataBlock command format: 0x67 0x66 tt ss ss ss ss (data)
+    let mut out_data: Vec<u8> = vec![0x67, 0x66, block_type];
+    
+    // Calculate the size based on the data content
+    let data_size = match &data {
+        DataBlockContent::UncompressedStream { data, .. } => data.len() as u32,
+        DataBlockContent::CompressedStream { data, .. } => data.len() as u32 + 10, // +10 for compression header
+        DataBlockContent::DecompressionTable { table_data, .. } => table_data.len() as u32 + 6, // +6 for header
+        DataBlockContent::ROMDump { data, .. } => data.len() as u32 + 8, // +8 for total_size and start_address
+        DataBlockContent::RAMWriteSmall { data, .. } => data.len() as u32 + 2, // +2 for start_address
+        DataBlockContent::RAMWriteLarge { data, .. } => data.len() as u32 + 4, // +4 for start_address
+        DataBlockContent::Unknown { data } => data.len() as u32,
+    };
+    
+    out_data.extend(data_size.to_le_bytes());
+    
+    // Serialize the data content
+    match data {
+        DataBlockContent::UncompressedStream { data, .. } => {
+            out_data.extend(data);
+        },
+        DataBlockContent::CompressedStream { compression, uncompressed_size, data, .. } => {
+            // Write compression header
+            match compression {
+                CompressionType::BitPacking { bits_decompressed, bits_compressed, sub_type, add_value } => {
+                    out_data.push(0x00); // Bit packing compression type
+                    out_data.extend(uncompressed_size.to_le_bytes());
+                    out_data.push(bits_decompressed);
+                    out_data.push(bits_compressed);
+                    out_data.push(sub_type);
+                    out_data.extend(add_value.to_le_bytes());
+                },
+                CompressionType::DPCM { bits_decompressed, bits_compressed, start_value } => {
+                    out_data.push(0x01); // DPCM compression type
+                    out_data.extend(uncompressed_size.to_le_bytes());
+                    out_data.push(bits_decompressed);
+                    out_data.push(bits_compressed);
+                    out_data.push(0x00); // Reserved byte
+                    out_data.extend(start_value.to_le_bytes());
+                },
+            }
+            out_data.extend(data);
+        },
+        DataBlockContent::DecompressionTable { compression_type, sub_type, bits_decompressed, bits_compressed, value_count, table_data } => {
+            out_data.push(compression_type);
+            out_data.push(sub_type);
+            out_data.push(bits_decompressed);
+            out_data.push(bits_compressed);
+            out_data.extend(value_count.to_le_bytes());
+            out_data.extend(table_data);
+        },
+        DataBlockContent::ROMDump { total_size, start_address, data, .. } => {
+            out_data.extend(total_size.to_le_bytes());
+            out_data.extend(start_address.to_le_bytes());
+            out_data.extend(data);
+        },
+        DataBlockContent::RAMWriteSmall { start_address, data, .. } => {
+            out_data.extend(start_address.to_le_bytes());
+            out_data.extend(data);
+        },
+        DataBlockContent::RAMWriteLarge { start_address, data, .. } => {
+            out_data.extend(start_address.to_le_bytes());
+            out_data.extend(data);
+        },
+        DataBlockContent::Unknown { data } => {
+            out_data.extend(data);
+        },
+    }
+    
+    out_data
 }
 
+/// Parse commands with the default configuration, returning none on error
+#[deprecated(note = "drops errors silently; use `parse_commands_with_diagnostics` or `VgmFile::from_bytes_with_diagnostics`")]
 pub fn parse_commands(data: &mut Bytes) -> Vec<Commands> {
     // Use default parser config for backward compatibility
     let config = crate::ParserConfig::default();
     let mut tracker = crate::ResourceTracker::new();
     
-    match parse_commands_with_config(data, &config, &mut tracker) {
-        Ok(commands) => commands,
-        Err(e) => {
-            println!("Warning: Command parsing failed with error: {}", e);
-            vec![] // Return empty commands on error for backward compatibility
-        }
-    }
+    parse_commands_with_config(data, &config, &mut tracker).unwrap_or_default()
 }
 
 /// Parse commands with resource tracking and limits
+///
+/// Collects a [`crate::CommandIter`] over `data`; prefer the iterator directly
+/// when the commands don't all need to be held in memory.
 pub fn parse_commands_with_config(
     data: &mut Bytes, 
     config: &crate::ParserConfig, 
     tracker: &mut crate::ResourceTracker
 ) -> crate::VgmResult<Vec<Commands>> {
-    let mut commands = Vec::new();
-    let _remaining_at_start = data.len();
-    
-    loop {
-        // Check command count limit before parsing each command
-        tracker.track_command(config)?;
-        
-        match Commands::from_bytes_with_config(data, config, tracker) {
-            Ok(curr_command) => {
-                match curr_command {
-                    Commands::EndOfSoundData => {
-                        commands.push(curr_command);
-                        break;
-                    },
-                    _ => commands.push(curr_command),
-                }
-            },
-            Err(e) => {
-                return Err(e);
-            }
-        }
-    }
+    parse_commands_with_diagnostics(data, config, tracker).map(|(commands, _)| commands)
+}
 
-    Ok(commands)
+/// Parse commands like [`parse_commands_with_config`], also returning the
+/// problems recovered from when `config` uses [`crate::ParseMode::Recover`]
+pub fn parse_commands_with_diagnostics(
+    data: &mut Bytes,
+    config: &crate::ParserConfig,
+    tracker: &mut crate::ResourceTracker,
+) -> crate::VgmResult<(Vec<Commands>, Vec<crate::Diagnostic>)> {
+    let mut iter = crate::CommandIter::with_tracker(
+        data.clone(),
+        0,
+        config.clone(),
+        std::mem::take(tracker),
+    );
+    let commands = iter.by_ref().collect::<crate::VgmResult<Vec<Commands>>>();
+    let diagnostics = iter.take_diagnostics();
+
+    let (remaining, used_tracker) = iter.into_parts();
+    *data = remaining;
+    *tracker = used_tracker;
+
+    Ok((commands?, diagnostics))
 }
 
+/// Parse every command that can be recovered, skipping malformed ones
+#[deprecated(
+    note = "drops what it skipped silently; use `parse_commands_with_diagnostics` with `ParseMode::Recover` or `VgmFile::from_bytes_with_diagnostics`"
+)]
 pub fn parse_commands_safe(data: &mut Bytes) -> Vec<Commands> {
-    let mut commands = vec![];
-
-    loop {
-        let curr_command = Commands::from_bytes_safe(data);
-        match curr_command {
-            Ok(cmd) => match cmd {
-                Commands::EndOfSoundData => {
-                    commands.push(cmd);
-                    break;
-                },
-                _ => commands.push(cmd),
-            },
-            Err(e) => {
-                println!("Command parsing error: {}", e);
-                break;
-            },
-        }
-    }
+    let config = crate::ParserConfig {
+        parse_mode: crate::ParseMode::Recover,
+        ..crate::ParserConfig::permissive()
+    };
+    let mut iter = crate::CommandIter::with_config(data.clone(), 0, config);
+    let commands = iter.by_ref().map_while(Result::ok).collect();
+    *data = iter.into_parts().0;
 
     commands
 }
@@ -930,2513 +1178,183 @@ pub fn write_commands(buffer: &mut BytesMut, commands: &Vec<Commands>) -> VgmRes
         buffer.put(&cmd_bytes[..]);
     }
     Ok(())
-}
-
-impl Commands {
-    pub fn to_bytes(self) -> VgmResult<Vec<u8>> {
-        let bytes = match self {
-            Commands::AY8910StereoMask { value } => {
-                vec![0x31, value]
-            },
-            Commands::GameGearPSGStereo { value, chip_index } => {
-                match chip_index {
-                    0 => vec![0x4f, value],  // First chip
-                    1 => vec![0x3f, value],  // Second chip  
-                    _ => return Err(VgmError::InvalidDataFormat {
-                        field: "chip_index".to_string(),
-                        details: format!("Invalid chip_index {} for GameGearPSGStereo, must be 0 or 1", chip_index),
-                    }),
-                }
-            },
-            Commands::PSGWrite { value, chip_index } => {
-                match chip_index {
-                    0 => vec![0x50, value],  // First chip
-                    1 => vec![0x30, value],  // Second chip
-                    _ => return Err(VgmError::InvalidDataFormat {
-                        field: "chip_index".to_string(),
-                        details: format!("Invalid chip_index {} for PSGWrite, must be 0 or 1", chip_index),
-                    }),
-                }
-            },
-            Commands::YM2413Write { register, value, chip_index } => {
-                let opcode = if chip_index == 0 { 0x51 } else { 0xA1 };
-                vec![opcode, register, value]
-            },
-            Commands::YM2612Port0Write { register, value, chip_index } => {
-                let opcode = if chip_index == 0 { 0x52 } else { 0xA2 };
-                vec![opcode, register, value]
-            },
-            Commands::YM2612Port1Write { register, value, chip_index } => {
-                let opcode = if chip_index == 0 { 0x53 } else { 0xA3 };
-                vec![opcode, register, value]
-            },
-            Commands::YM2151Write { register, value, chip_index } => {
-                let opcode = if chip_index == 0 { 0x54 } else { 0xA4 };
-                vec![opcode, register, value]
-            },
-            Commands::YM2203Write { register, value, chip_index } => {
-                let opcode = if chip_index == 0 { 0x55 } else { 0xA5 };
-                vec![opcode, register, value]
-            },
-            Commands::YM2608Port0Write { register, value, chip_index } => {
-                let opcode = if chip_index == 0 { 0x56 } else { 0xA6 };
-                vec![opcode, register, value]
-            },
-            Commands::YM2608Port1Write { register, value, chip_index } => {
-                let opcode = if chip_index == 0 { 0x57 } else { 0xA7 };
-                vec![opcode, register, value]
-            },
-            Commands::YM2610Port0Write { register, value, chip_index } => {
-                let opcode = if chip_index == 0 { 0x58 } else { 0xA8 };
-                vec![opcode, register, value]
-            },
-            Commands::YM2610Port1Write { register, value, chip_index } => {
-                let opcode = if chip_index == 0 { 0x59 } else { 0xA9 };
-                vec![opcode, register, value]
-            },
-            Commands::YM3812Write { register, value, chip_index } => {
-                let opcode = if chip_index == 0 { 0x5A } else { 0xAA };
-                vec![opcode, register, value]
-            },
-            Commands::YM3526Write { register, value, chip_index } => {
-                let opcode = if chip_index == 0 { 0x5B } else { 0xAB };
-                vec![opcode, register, value]
-            },
-            Commands::Y8950Write { register, value, chip_index } => {
-                let opcode = if chip_index == 0 { 0x5C } else { 0xAC };
-                vec![opcode, register, value]
-            },
-            Commands::YMZ280BWrite { register, value, chip_index } => {
-                let opcode = if chip_index == 0 { 0x5D } else { 0xAD };
-                vec![opcode, register, value]
-            },
-            Commands::YMF262Port0Write { register, value, chip_index } => {
-                let opcode = if chip_index == 0 { 0x5E } else { 0xAE };
-                vec![opcode, register, value]
-            },
-            Commands::YMF262Port1Write { register, value, chip_index } => {
-                let opcode = if chip_index == 0 { 0x5F } else { 0xAF };
-                vec![opcode, register, value]
-            },
-            Commands::WaitNSamples { n } => {
-                let temp = n.to_le_bytes();
-                vec![0x61, temp[0], temp[1]]
-            },
-            Commands::Wait735Samples => {
-                vec![0x62]
-            },
-            Commands::Wait882Samples => {
-                vec![0x63]
-            },
-            Commands::EndOfSoundData => {
-                vec![0x66]
-            },
-
-            Commands::DataBlock {
-                block_type,
-                data,
-            } => {
-                // The DataBlock command format: 0x67 0x66 tt ss ss ss ss (data)
-                let mut out_data: Vec<u8> = vec![0x67, 0x66, block_type];
-                
-                // Calculate the size based on the data content
-                let data_size = match &data {
-                    DataBlockContent::UncompressedStream { data, .. } => data.len() as u32,
-                    DataBlockContent::CompressedStream { data, .. } => data.len() as u32 + 9, // +9 for compression header
-                    DataBlockContent::DecompressionTable { table_data, .. } => table_data.len() as u32 + 6, // +6 for header
-                    DataBlockContent::ROMDump { data, .. } => data.len() as u32 + 8, // +8 for total_size and start_address
-                    DataBlockContent::RAMWriteSmall { data, .. } => data.len() as u32 + 2, // +2 for start_address
-                    DataBlockContent::RAMWriteLarge { data, .. } => data.len() as u32 + 4, // +4 for start_address
-                    DataBlockContent::Unknown { data } => data.len() as u32,
-                };
-                
-                out_data.extend(data_size.to_le_bytes());
-                
-                // Serialize the data content
-                match data {
-                    DataBlockContent::UncompressedStream { data, .. } => {
-                        out_data.extend(data);
-                    },
-                    DataBlockContent::CompressedStream { compression, uncompressed_size, data, .. } => {
-                        // Write compression header
-                        match compression {
-                            CompressionType::BitPacking { bits_decompressed, bits_compressed, sub_type, add_value } => {
-                                out_data.push(0x00); // Bit packing compression type
-                                out_data.extend(uncompressed_size.to_le_bytes());
-                                out_data.push(bits_decompressed);
-                                out_data.push(bits_compressed);
-                                out_data.push(sub_type);
-                                out_data.extend(add_value.to_le_bytes());
-                            },
-                            CompressionType::DPCM { bits_decompressed, bits_compressed, start_value } => {
-                                out_data.push(0x01); // DPCM compression type
-                                out_data.extend(uncompressed_size.to_le_bytes());
-                                out_data.push(bits_decompressed);
-                                out_data.push(bits_compressed);
-                                out_data.push(0x00); // Reserved byte
-                                out_data.extend(start_value.to_le_bytes());
-                            },
-                        }
-                        out_data.extend(data);
-                    },
-                    DataBlockContent::DecompressionTable { compression_type, sub_type, bits_decompressed, bits_compressed, value_count, table_data } => {
-                        out_data.push(compression_type);
-                        out_data.push(sub_type);
-                        out_data.push(bits_decompressed);
-                        out_data.push(bits_compressed);
-                        out_data.extend(value_count.to_le_bytes());
-                        out_data.extend(table_data);
-                    },
-                    DataBlockContent::ROMDump { total_size, start_address, data, .. } => {
-                        out_data.extend(total_size.to_le_bytes());
-                        out_data.extend(start_address.to_le_bytes());
-                        out_data.extend(data);
-                    },
-                    DataBlockContent::RAMWriteSmall { start_address, data, .. } => {
-                        out_data.extend(start_address.to_le_bytes());
-                        out_data.extend(data);
-                    },
-                    DataBlockContent::RAMWriteLarge { start_address, data, .. } => {
-                        out_data.extend(start_address.to_le_bytes());
-                        out_data.extend(data);
-                    },
-                    DataBlockContent::Unknown { data } => {
-                        out_data.extend(data);
-                    },
-                }
-                
-                out_data
-            },
-            Commands::PCMRAMWrite { chip_type: _, read_offset: _, write_offset: _, size: _, data: _ } => {
-                return Err(VgmError::FeatureNotSupported {
-                    feature: "PCM RAM Write command serialization".to_string(),
-                    version: 0, // Unknown version requirement
-                    min_version: 0, // Would need to research the actual VGM version requirement
-                });
-            },
-
-            Commands::WaitNSamplesPlus1 { n } => vec![0x70 + n],
-
-            Commands::YM2612Port0Address2AWriteWait { n } => vec![0x80 + n],
-
-            // DAC Stream Control Commands (0x90-0x95)
-            Commands::DACStreamSetupControl { stream_id, chip_type, port, command, chip_index } => {
-                // Dual chip support: Set bit 7 of chip_type when chip_index == 1
-                let adjusted_chip_type = if chip_index == 0 { chip_type & 0x7F } else { chip_type | 0x80 };
-                vec![0x90, stream_id, adjusted_chip_type, port, command]
-            },
-            Commands::DACStreamSetData { stream_id, data_bank_id, step_size, step_base } => {
-                vec![0x91, stream_id, data_bank_id, step_size, step_base]
-            },
-            Commands::DACStreamSetFrequency { stream_id, frequency } => {
-                let freq_bytes = frequency.to_le_bytes();
-                vec![0x92, stream_id, freq_bytes[0], freq_bytes[1], freq_bytes[2], freq_bytes[3]]
-            },
-            Commands::DACStreamStart { stream_id, data_start_offset, length_mode, data_length } => {
-                let offset_bytes = data_start_offset.to_le_bytes();
-                let length_bytes = data_length.to_le_bytes();
-                vec![0x93, stream_id, 
-                     offset_bytes[0], offset_bytes[1], offset_bytes[2], offset_bytes[3],
-                     length_mode,
-                     length_bytes[0], length_bytes[1], length_bytes[2], length_bytes[3]]
-            },
-            Commands::DACStreamStop { stream_id } => {
-                vec![0x94, stream_id]
-            },
-            Commands::DACStreamStartFast { stream_id, block_id, flags } => {
-                let block_bytes = block_id.to_le_bytes();
-                vec![0x95, stream_id, block_bytes[0], block_bytes[1], flags]
-            },
-
-            Commands::AY8910Write { register, value, chip_index } => {
-                // Method #2: Use bit 7 of register for chip selection (0x00-7F = chip 1, 0x80-FF = chip 2)
-                let adjusted_register = if chip_index == 0 { register & 0x7F } else { register | 0x80 };
-                vec![0xA0, adjusted_register, value]
-            },
-            Commands::RF5C68Write { register, value } => {
-                vec![0xB0, register, value]
-            },
-            Commands::RF5C164Write { register, value } => {
-                vec![0xB1, register, value]
-            },
-            Commands::PWMWrite { register, value } => {
-                let temp = value.to_le_bytes();
-                vec![0xB2, register, temp[0], temp[1]]
-            },
-            Commands::GameBoyDMGWrite { register, value, chip_index } => {
-                // Method #2: Use bit 7 of register for chip selection (0x00-7F = chip 1, 0x80-FF = chip 2)
-                let adjusted_register = if chip_index == 0 { register & 0x7F } else { register | 0x80 };
-                vec![0xB3, adjusted_register, value]
-            },
-            Commands::NESAPUWrite { register, value, chip_index } => {
-                // Method #2: Use bit 7 of register for chip selection (0x00-7F = chip 1, 0x80-FF = chip 2)
-                let adjusted_register = if chip_index == 0 { register & 0x7F } else { register | 0x80 };
-                vec![0xB4, adjusted_register, value]
-            },
-            Commands::MultiPCMWrite { register, value, chip_index } => {
-                // Method #2: Use bit 7 of register for chip selection (0x00-7F = chip 1, 0x80-FF = chip 2)
-                let adjusted_register = if chip_index == 0 { register & 0x7F } else { register | 0x80 };
-                vec![0xB5, adjusted_register, value]
-            },
-            Commands::uPD7759Write { register, value, chip_index } => {
-                // Method #2: Use bit 7 of register for chip selection (0x00-7F = chip 1, 0x80-FF = chip 2)
-                let adjusted_register = if chip_index == 0 { register & 0x7F } else { register | 0x80 };
-                vec![0xB6, adjusted_register, value]
-            },
-            Commands::OKIM6258Write { register, value, chip_index } => {
-                // Method #2: Use bit 7 of register for chip selection (0x00-7F = chip 1, 0x80-FF = chip 2)
-                let adjusted_register = if chip_index == 0 { register & 0x7F } else { register | 0x80 };
-                vec![0xB7, adjusted_register, value]
-            },
-            Commands::OKIM6295Write { register, value, chip_index } => {
-                // Method #2: Use bit 7 of register for chip selection (0x00-7F = chip 1, 0x80-FF = chip 2)
-                let adjusted_register = if chip_index == 0 { register & 0x7F } else { register | 0x80 };
-                vec![0xB8, adjusted_register, value]
-            },
-            Commands::HuC6280Write { register, value, chip_index } => {
-                // Method #2: Use bit 7 of register for chip selection (0x00-7F = chip 1, 0x80-FF = chip 2)
-                let adjusted_register = if chip_index == 0 { register & 0x7F } else { register | 0x80 };
-                vec![0xB9, adjusted_register, value]
-            },
-            Commands::K053260Write { register, value, chip_index } => {
-                // Method #2: Use bit 7 of register for chip selection (0x00-7F = chip 1, 0x80-FF = chip 2)
-                let adjusted_register = if chip_index == 0 { register & 0x7F } else { register | 0x80 };
-                vec![0xBA, adjusted_register, value]
-            },
-            Commands::PokeyWrite { register, value, chip_index } => {
-                // Method #2: Use bit 7 of register for chip selection (0x00-7F = chip 1, 0x80-FF = chip 2)
-                let adjusted_register = if chip_index == 0 { register & 0x7F } else { register | 0x80 };
-                vec![0xBB, adjusted_register, value]
-            },
-            Commands::WonderSwanWrite { register, value, chip_index } => {
-                // Method #2: Use bit 7 of register for chip selection (0x00-7F = chip 1, 0x80-FF = chip 2)
-                let adjusted_register = if chip_index == 0 { register & 0x7F } else { register | 0x80 };
-                vec![0xBC, adjusted_register, value]
-            },
-            Commands::SAA1099Write { register, value, chip_index } => {
-                // Method #2: Use bit 7 of register for chip selection (0x00-7F = chip 1, 0x80-FF = chip 2)
-                let adjusted_register = if chip_index == 0 { register & 0x7F } else { register | 0x80 };
-                vec![0xBD, adjusted_register, value]
-            },
-            Commands::ES5506Write { register, value, chip_index } => {
-                // Method #2: Use bit 7 of register for chip selection (0x00-7F = chip 1, 0x80-FF = chip 2)
-                let adjusted_register = if chip_index == 0 { register & 0x7F } else { register | 0x80 };
-                vec![0xBE, adjusted_register, value]
-            },
-            Commands::GA20Write { register, value, chip_index } => {
-                // Method #2: Use bit 7 of register for chip selection (0x00-7F = chip 1, 0x80-FF = chip 2)
-                let adjusted_register = if chip_index == 0 { register & 0x7F } else { register | 0x80 };
-                vec![0xBF, adjusted_register, value]
-            },
-            Commands::SegaPCMWrite { offset, value } => {
-                let temp = offset.to_le_bytes();
-                vec![0xC0, temp[0], temp[1], value]
-            },
-            Commands::MultiPCMSetBank { channel, offset } => {
-                let temp = offset.to_le_bytes();
-                vec![0xC3, temp[0], temp[1], channel]
-            },
-
-            Commands::QSoundWrite { register, value } => {
-                let temp = value.to_le_bytes();
-                vec![0xC4, temp[1], temp[0], register]
-            },
-            Commands::SCSPWrite { offset, value } => {
-                let temp = offset.to_le_bytes();
-                vec![0xC5, temp[1], temp[0], value]
-            },
-            Commands::WonderSwanWrite16 { offset, value } => {
-                let temp = offset.to_le_bytes();
-                vec![0xC6, temp[1], temp[0], value]
-            },
-            Commands::VSUWrite { offset, value } => {
-                let temp = offset.to_le_bytes();
-                vec![0xC7, temp[1], temp[0], value]
-            },
-            Commands::X1010Write { offset, value } => {
-                let temp = offset.to_le_bytes();
-                vec![0xC8, temp[1], temp[0], value]
-            },
-
-            Commands::YMF278BWrite {
-                port,
-                register,
-                value,
-            } => {
-                vec![0xD0, port, register, value]
-            },
-
-            Commands::YMF271Write {
-                port,
-                register,
-                value,
-            } => {
-                vec![0xD1, port, register, value]
-            },
-            Commands::SCC1Write {
-                port,
-                register,
-                value,
-            } => {
-                vec![0xD2, port, register, value]
-            },
-            Commands::K054539Write { register, value } => {
-                let temp = register.to_le_bytes();
-                vec![0xD3, temp[0], temp[1], value]
-            },
-            Commands::C140Write { register, value } => {
-                let temp = register.to_le_bytes();
-                vec![0xD4, temp[0], temp[1], value]
-            },
-
-            Commands::ES5503Write { register, value } => {
-                let temp = register.to_le_bytes();
-                vec![0xD5, temp[0], temp[1], value]
-            },
-            Commands::ES5506Write16 { register, value } => {
-                let temp = value.to_le_bytes();
-                vec![0xD6, register, temp[0], temp[1]]
-            },
-            Commands::SeekPCM { offset } => {
-                let mut rslt = vec![0xE0];
-                rslt.extend(offset.to_le_bytes());
-                rslt
-            },
-            Commands::C352Write { register, value } => {
-                let mut rslt = vec![0xE1];
-                rslt.extend(register.to_le_bytes());
-                rslt.extend(value.to_le_bytes());
-                rslt
-            },
-
-            // offset write
-            Commands::RF5C68WriteOffset { offset, value } => {
-                let mut rslt = vec![0xC1];
-                rslt.extend(offset.to_le_bytes());
-                rslt.extend(value.to_le_bytes());
-                rslt
-            },
-            Commands::RF5C164WriteOffset { offset, value } => {
-                let mut rslt = vec![0xC1];
-                rslt.extend(offset.to_le_bytes());
-                rslt.extend(value.to_le_bytes());
-                rslt
-            }, // _ => panic!("Not implemented"),
-        };
-        
-        Ok(bytes)
-    }
-
-    pub fn from_bytes(bytes: &mut Bytes) -> VgmResult<Commands> {
-        let command_val = bytes.get_u8();
-        
-
-        let command = match command_val {
-            0x30 => {
-                // handle PSG write command - second chip (dual chip support)
-                Commands::PSGWrite {
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0x31 => {
-                // handle AY8910 stereo mask command
-                // `bytes.get(1)` gives you the `dd` value
-                // create and return a `Command` variant
-                Commands::AY8910StereoMask {
-                    value: bytes.get_u8(),
-                }
-            },
-            0x3F => {
-                // handle Game Gear PSG stereo command - second chip (dual chip support)
-                Commands::GameGearPSGStereo {
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0x4F => {
-                // handle Game Gear PSG stereo command - first chip
-                Commands::GameGearPSGStereo {
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x50 => {
-                // handle PSG write command - first chip
-                Commands::PSGWrite {
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x51 => {
-                // handle YM2413 write command - first chip
-                Commands::YM2413Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x52 => {
-                // handle YM2612 port 0 write command - first chip
-                Commands::YM2612Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x53 => {
-                // handle YM2612 port 1 write command - first chip
-                Commands::YM2612Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x54 => {
-                // handle YM2151 write command - first chip
-                Commands::YM2151Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x55 => {
-                // handle YM2203 write command - first chip
-                Commands::YM2203Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x56 => {
-                // handle YM2608 port 0 write command - first chip
-                Commands::YM2608Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x57 => {
-                // handle YM2608 port 1 write command - first chip
-                Commands::YM2608Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x58 => {
-                // handle YM2610 port 0 write command - first chip
-                Commands::YM2610Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x59 => {
-                // handle YM2610 port 1 write command - first chip
-                Commands::YM2610Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x5A => {
-                // handle YM3812 write command - first chip
-                Commands::YM3812Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x5B => {
-                // handle YM3526 write command - first chip
-                Commands::YM3526Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x5C => {
-                // handle Y8950 write command - first chip
-                Commands::Y8950Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x5D => {
-                // handle YMZ280B write command - first chip
-                Commands::YMZ280BWrite {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x5E => {
-                // handle YMF262 port 0 write command - first chip
-                Commands::YMF262Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x5F => {
-                // handle YMF262 port 1 write command - first chip
-                Commands::YMF262Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            // YM family second chip commands (0xA1-0xAF) - Dual Chip Support Method #1
-            0xA1 => {
-                // handle YM2413 write command - second chip
-                Commands::YM2413Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA2 => {
-                // handle YM2612 port 0 write command - second chip
-                Commands::YM2612Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA3 => {
-                // handle YM2612 port 1 write command - second chip
-                Commands::YM2612Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA4 => {
-                // handle YM2151 write command - second chip
-                Commands::YM2151Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA5 => {
-                // handle YM2203 write command - second chip
-                Commands::YM2203Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA6 => {
-                // handle YM2608 port 0 write command - second chip
-                Commands::YM2608Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA7 => {
-                // handle YM2608 port 1 write command - second chip
-                Commands::YM2608Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA8 => {
-                // handle YM2610 port 0 write command - second chip
-                Commands::YM2610Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA9 => {
-                // handle YM2610 port 1 write command - second chip
-                Commands::YM2610Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xAA => {
-                // handle YM3812 write command - second chip
-                Commands::YM3812Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xAB => {
-                // handle YM3526 write command - second chip
-                Commands::YM3526Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xAC => {
-                // handle Y8950 write command - second chip
-                Commands::Y8950Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xAD => {
-                // handle YMZ280B write command - second chip
-                Commands::YMZ280BWrite {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xAE => {
-                // handle YMF262 port 0 write command - second chip
-                Commands::YMF262Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xAF => {
-                // handle YMF262 port 1 write command - second chip
-                Commands::YMF262Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0x61 => {
-                // handle wait command
-                Commands::WaitNSamples {
-                    n: bytes.get_u16_le(),
-                }
-            },
-            0x62 => {
-                // handle wait 735 samples command
-                Commands::Wait735Samples
-            },
-            0x63 => {
-                // handle wait 882 samples command
-                Commands::Wait882Samples
-            },
-            0x66 => {
-                // handle end of sound data command
-                Commands::EndOfSoundData
-            },
-            0x67 => {
-                // handle data block command: 0x67 0x66 tt ss ss ss ss (data)
-                let compatibility_byte = bytes.get_u8();
-                if compatibility_byte != 0x66 {
-                    return Err(VgmError::InvalidCommandParameters {
-                        opcode: 0x67,
-                        position: 0, // TODO: track position
-                        reason: format!("Expected compatibility byte 0x66, found 0x{:02X}", compatibility_byte),
-                    });
-                }
-                
-                let block_type = bytes.get_u8();
-                let data_size = bytes.get_u32_le();
-                
-                // Security: Check data block size limit
-                if data_size > MAX_DATA_BLOCK_SIZE {
-                    return Err(VgmError::DataSizeExceedsLimit {
-                        field: "DataBlock".to_string(),
-                        size: data_size as usize,
-                        limit: MAX_DATA_BLOCK_SIZE as usize,
-                    });
-                }
-                
-                // Security: Ensure sufficient data is available before allocation
-                if bytes.remaining() < data_size as usize {
-                    return Err(VgmError::BufferUnderflow {
-                        offset: 0, // TODO: Track actual position
-                        needed: data_size as usize,
-                        available: bytes.remaining(),
-                    });
-                }
-                
-                // Parse the data block content based on its type
-                let data = DataBlockContent::parse_from_bytes(block_type, data_size, bytes)?;
-                
-                Commands::DataBlock {
-                    block_type,
-                    data,
-                }
-            },
-            0x68 => {
-                // PCM RAM write command: 0x68 0x66 cc oo oo oo dd dd dd ss ss ss
-                let compatibility_byte = bytes.get_u8();
-                if compatibility_byte != 0x66 {
-                    return Err(VgmError::InvalidCommandParameters {
-                        opcode: 0x68,
-                        position: 0, // TODO: track position
-                        reason: format!("Expected compatibility byte 0x66, found 0x{:02X}", compatibility_byte),
-                    });
-                }
-                
-                let chip_type = bytes.get_u8();
-                
-                // Read 24-bit values (little-endian)
-                let read_offset = bytes.get_u8() as u32 | 
-                                ((bytes.get_u8() as u32) << 8) | 
-                                ((bytes.get_u8() as u32) << 16);
-                                
-                let write_offset = bytes.get_u8() as u32 | 
-                                 ((bytes.get_u8() as u32) << 8) | 
-                                 ((bytes.get_u8() as u32) << 16);
-                                 
-                let mut size = bytes.get_u8() as u32 | 
-                             ((bytes.get_u8() as u32) << 8) | 
-                             ((bytes.get_u8() as u32) << 16);
-                
-                // Special case: size of 0 means 0x01000000 bytes
-                if size == 0 {
-                    size = 0x01000000;
-                }
-                
-                // Read the data
-                let data: Vec<u8> = (0..size as usize)
-                    .map(|_| bytes.get_u8())
-                    .collect();
-                
-                Commands::PCMRAMWrite {
-                    chip_type,
-                    read_offset,
-                    write_offset,
-                    size,
-                    data,
-                }
-            },
-            0x70..=0x7F => {
-                // handle wait n+1 samples command
-                Commands::WaitNSamplesPlus1 {
-                    n: command_val - 0x70,
-                }
-            },
-            0x80..=0x8F => {
-                // handle YM2612 port 0 address 2A write command
-                Commands::YM2612Port0Address2AWriteWait {
-                    n: command_val - 0x80,
-                }
-            },
-            0x90..=0x95 => {
-                // DAC Stream Control Write commands - proper parsing implementation
-                match command_val {
-                    0x90 => {
-                        // Setup Stream Control: ss tt pp cc (4 bytes) - DAC Stream dual chip support
-                        let stream_id = bytes.get_u8();
-                        let chip_type_raw = bytes.get_u8();
-                        let chip_type = chip_type_raw & 0x7F; // Mask off bit 7 for actual chip type
-                        let chip_index = if chip_type_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                        let port = bytes.get_u8();
-                        let command = bytes.get_u8();
-                        Commands::DACStreamSetupControl { stream_id, chip_type, port, command, chip_index }
-                    },
-                    0x91 => {
-                        // Set Stream Data: ss dd ll bb (4 bytes)
-                        let stream_id = bytes.get_u8();
-                        let data_bank_id = bytes.get_u8();
-                        let step_size = bytes.get_u8();
-                        let step_base = bytes.get_u8();
-                        Commands::DACStreamSetData { stream_id, data_bank_id, step_size, step_base }
-                    },
-                    0x92 => {
-                        // Set Stream Frequency: ss ff ff ff ff (5 bytes)
-                        let stream_id = bytes.get_u8();
-                        let frequency = bytes.get_u32_le();
-                        Commands::DACStreamSetFrequency { stream_id, frequency }
-                    },
-                    0x93 => {
-                        // Start Stream: ss aa aa aa aa mm ll ll ll ll (10 bytes)
-                        let stream_id = bytes.get_u8();
-                        let data_start_offset = bytes.get_u32_le();
-                        let length_mode = bytes.get_u8();
-                        let data_length = bytes.get_u32_le();
-                        Commands::DACStreamStart { stream_id, data_start_offset, length_mode, data_length }
-                    },
-                    0x94 => {
-                        // Stop Stream: ss (1 byte)
-                        let stream_id = bytes.get_u8();
-                        Commands::DACStreamStop { stream_id }
-                    },
-                    0x95 => {
-                        // Start Stream (fast call): ss bb bb ff (4 bytes)
-                        let stream_id = bytes.get_u8();
-                        let block_id = bytes.get_u16_le();
-                        let flags = bytes.get_u8();
-                        Commands::DACStreamStartFast { stream_id, block_id, flags }
-                    },
-                    _ => unreachable!(), // Range 0x90..=0x95 guarantees this won't happen
-                }
-            },
-            0xA0 => {
-                // handle AY8910 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::AY8910Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB0 => {
-                // handle RF5C68 write command
-                Commands::RF5C68Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xB1 => {
-                // handle RF5C164 write command
-                Commands::RF5C164Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xB2 => {
-                // handle PWM write command
-                // TODO: is not aadd but addd
-                Commands::PWMWrite {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u16_le(),
-                }
-            },
-            0xB3 => {
-                // handle GameBoy DMG write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::GameBoyDMGWrite {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB4 => {
-                // handle NES APU write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::NESAPUWrite {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB5 => {
-                // handle MultiPCM write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::MultiPCMWrite {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB6 => {
-                // handle uPD7759 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::uPD7759Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB7 => {
-                // handle HuC6280 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::HuC6280Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB8 => {
-                // handle OKIM6295 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::OKIM6295Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB9 => {
-                // handle HuC6280 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::HuC6280Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xBA => {
-                // handle K053260 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::K053260Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xBB => {
-                // handle Pokey write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::PokeyWrite {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xBC => {
-                // handle WonderSwan write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::WonderSwanWrite {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xBD => {
-                // handle SAA1099 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::SAA1099Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xBE => {
-                // handle ES5506 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::ES5506Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xBF => {
-                // handle GA20 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::GA20Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xC0 => Commands::SegaPCMWrite {
-                offset: bytes.get_u16_le(),
-                value: bytes.get_u8(),
-            },
-            0xC1 => Commands::RF5C68WriteOffset {
-                offset: bytes.get_u16_le(),
-                value: bytes.get_u8(),
-            },
-            0xC2 => Commands::RF5C164WriteOffset {
-                offset: bytes.get_u16_le(),
-                value: bytes.get_u8(),
-            },
-            0xC3 => Commands::MultiPCMSetBank {
-                channel: bytes.get_u8(),
-                offset: bytes.get_u16_le(),
-            },
-            0xC4 => {
-                // TODO: weird stuff with the data
-                let value = bytes.get_u16_le();
-                Commands::QSoundWrite {
-                    register: bytes.get_u8(),
-                    value,
-                }
-            },
-            0xC5 => {
-                // TODO: weird stuff with the data
-                //let value = bytes.get_u16_le();
-                Commands::SCSPWrite {
-                    offset: bytes.get_u16_le(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xC6 => {
-                // TODO: check
-                Commands::WonderSwanWrite16 {
-                    offset: bytes.get_u16_le(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xC7 => {
-                // TODO: check
-                Commands::VSUWrite {
-                    offset: bytes.get_u16_le(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xC8 => {
-                // TODO: check
-                Commands::X1010Write {
-                    offset: bytes.get_u16_le(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xD0 => Commands::YMF278BWrite {
-                port: bytes.get_u8(),
-                register: bytes.get_u8(),
-                value: bytes.get_u8(),
-            },
-            0xD1 => Commands::YMF271Write {
-                port: bytes.get_u8(),
-                register: bytes.get_u8(),
-                value: bytes.get_u8(),
-            },
-            0xD2 => Commands::SCC1Write {
-                port: bytes.get_u8(),
-                register: bytes.get_u8(),
-                value: bytes.get_u8(),
-            },
-            0xD3 => Commands::K054539Write {
-                register: bytes.get_u16_le(),
-                value: bytes.get_u8(),
-            },
-            0xD4 => Commands::C140Write {
-                register: bytes.get_u16_le(),
-                value: bytes.get_u8(),
-            },
-            0xD5 => Commands::ES5503Write {
-                register: bytes.get_u16_le(),
-                value: bytes.get_u8(),
-            },
-            0xD6 => Commands::ES5506Write16 {
-                register: bytes.get_u8(),
-                value: bytes.get_u16_le(),
-            },
-            0xE0 => Commands::SeekPCM {
-                offset: bytes.get_u32_le(),
-            },
-            0xE1 => Commands::C352Write {
-                register: bytes.get_u16_le(),
-                value: bytes.get_u16_le(),
-            },
-            _ => {
-                return Err(VgmError::UnknownCommand { 
-                    opcode: command_val, 
-                    position: 0  // We'd need to track position properly in a real implementation
-                });
-            },
-        };
-        
-        Ok(command)
-    }
-
-    pub fn from_bytes_safe(bytes: &mut Bytes) -> VgmResult<Commands> {
-        let command_val = bytes.get_u8();
-        let command = match command_val {
-            0x30 => {
-                // handle PSG write command - second chip (dual chip support)
-                Commands::PSGWrite {
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0x31 => {
-                // handle AY8910 stereo mask command
-                // `bytes.get(1)` gives you the `dd` value
-                // create and return a `Command` variant
-                Commands::AY8910StereoMask {
-                    value: bytes.get_u8(),
-                }
-            },
-            0x3F => {
-                // handle Game Gear PSG stereo command - second chip (dual chip support)
-                Commands::GameGearPSGStereo {
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0x4F => {
-                // handle Game Gear PSG stereo command - first chip
-                Commands::GameGearPSGStereo {
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x50 => {
-                // handle PSG write command - first chip
-                Commands::PSGWrite {
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x51 => {
-                // handle YM2413 write command - first chip
-                Commands::YM2413Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x52 => {
-                // handle YM2612 port 0 write command - first chip
-                Commands::YM2612Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x53 => {
-                // handle YM2612 port 1 write command - first chip
-                Commands::YM2612Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x54 => {
-                // handle YM2151 write command - first chip
-                Commands::YM2151Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x55 => {
-                // handle YM2203 write command - first chip
-                Commands::YM2203Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x56 => {
-                // handle YM2608 port 0 write command - first chip
-                Commands::YM2608Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x57 => {
-                // handle YM2608 port 1 write command - first chip
-                Commands::YM2608Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x58 => {
-                // handle YM2610 port 0 write command - first chip
-                Commands::YM2610Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x59 => {
-                // handle YM2610 port 1 write command - first chip
-                Commands::YM2610Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x5A => {
-                // handle YM3812 write command - first chip
-                Commands::YM3812Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x5B => {
-                // handle YM3526 write command - first chip
-                Commands::YM3526Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x5C => {
-                // handle Y8950 write command - first chip
-                Commands::Y8950Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x5D => {
-                // handle YMZ280B write command - first chip
-                Commands::YMZ280BWrite {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x5E => {
-                // handle YMF262 port 0 write command - first chip
-                Commands::YMF262Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x5F => {
-                // handle YMF262 port 1 write command - first chip
-                Commands::YMF262Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            // YM family second chip commands (0xA1-0xAF) - Dual Chip Support Method #1
-            0xA1 => {
-                // handle YM2413 write command - second chip
-                Commands::YM2413Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA2 => {
-                // handle YM2612 port 0 write command - second chip
-                Commands::YM2612Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA3 => {
-                // handle YM2612 port 1 write command - second chip
-                Commands::YM2612Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA4 => {
-                // handle YM2151 write command - second chip
-                Commands::YM2151Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA5 => {
-                // handle YM2203 write command - second chip
-                Commands::YM2203Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA6 => {
-                // handle YM2608 port 0 write command - second chip
-                Commands::YM2608Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA7 => {
-                // handle YM2608 port 1 write command - second chip
-                Commands::YM2608Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA8 => {
-                // handle YM2610 port 0 write command - second chip
-                Commands::YM2610Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA9 => {
-                // handle YM2610 port 1 write command - second chip
-                Commands::YM2610Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xAA => {
-                // handle YM3812 write command - second chip
-                Commands::YM3812Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xAB => {
-                // handle YM3526 write command - second chip
-                Commands::YM3526Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xAC => {
-                // handle Y8950 write command - second chip
-                Commands::Y8950Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xAD => {
-                // handle YMZ280B write command - second chip
-                Commands::YMZ280BWrite {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xAE => {
-                // handle YMF262 port 0 write command - second chip
-                Commands::YMF262Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xAF => {
-                // handle YMF262 port 1 write command - second chip
-                Commands::YMF262Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0x61 => {
-                // handle wait command
-                Commands::WaitNSamples {
-                    n: bytes.get_u16_le(),
-                }
-            },
-            0x62 => {
-                // handle wait 735 samples command
-                Commands::Wait735Samples
-            },
-            0x63 => {
-                // handle wait 882 samples command
-                Commands::Wait882Samples
-            },
-            0x66 => {
-                // handle end of sound data command
-                Commands::EndOfSoundData
-            },
-            0x67 => {
-                // handle data block command: 0x67 0x66 tt ss ss ss ss (data)
-                let compatibility_byte = bytes.get_u8();
-                if compatibility_byte != 0x66 {
-                    return Err(VgmError::InvalidCommandParameters {
-                        opcode: 0x67,
-                        position: 0, // TODO: track position
-                        reason: format!("Expected compatibility byte 0x66, found 0x{:02X}", compatibility_byte),
-                    });
-                }
-                
-                let block_type = bytes.get_u8();
-                let data_size = bytes.get_u32_le();
-                
-                // Security: Check data block size limit
-                if data_size > MAX_DATA_BLOCK_SIZE {
-                    return Err(VgmError::DataSizeExceedsLimit {
-                        field: "DataBlock".to_string(),
-                        size: data_size as usize,
-                        limit: MAX_DATA_BLOCK_SIZE as usize,
-                    });
-                }
-                
-                // Security: Ensure sufficient data is available before allocation
-                if bytes.remaining() < data_size as usize {
-                    return Err(VgmError::BufferUnderflow {
-                        offset: 0, // TODO: Track actual position
-                        needed: data_size as usize,
-                        available: bytes.remaining(),
-                    });
-                }
-                
-                // Parse the data block content based on its type
-                let data = DataBlockContent::parse_from_bytes(block_type, data_size, bytes)?;
-                
-                Commands::DataBlock {
-                    block_type,
-                    data,
-                }
-            },
-            0x68 => {
-                // PCM RAM write command: 0x68 0x66 cc oo oo oo dd dd dd ss ss ss
-                let compatibility_byte = bytes.get_u8();
-                if compatibility_byte != 0x66 {
-                    return Err(VgmError::InvalidCommandParameters {
-                        opcode: 0x68,
-                        position: 0, // TODO: track position
-                        reason: format!("Expected compatibility byte 0x66, found 0x{:02X}", compatibility_byte),
-                    });
-                }
-                
-                let chip_type = bytes.get_u8();
-                
-                // Read 24-bit values (little-endian)
-                let read_offset = bytes.get_u8() as u32 | 
-                                ((bytes.get_u8() as u32) << 8) | 
-                                ((bytes.get_u8() as u32) << 16);
-                                
-                let write_offset = bytes.get_u8() as u32 | 
-                                 ((bytes.get_u8() as u32) << 8) | 
-                                 ((bytes.get_u8() as u32) << 16);
-                                 
-                let mut size = bytes.get_u8() as u32 | 
-                             ((bytes.get_u8() as u32) << 8) | 
-                             ((bytes.get_u8() as u32) << 16);
-                
-                // Special case: size of 0 means 0x01000000 bytes
-                if size == 0 {
-                    size = 0x01000000;
-                }
-                
-                // Read the data
-                let data: Vec<u8> = (0..size as usize)
-                    .map(|_| bytes.get_u8())
-                    .collect();
-                
-                Commands::PCMRAMWrite {
-                    chip_type,
-                    read_offset,
-                    write_offset,
-                    size,
-                    data,
-                }
-            },
-            0x70..=0x7F => {
-                // handle wait n+1 samples command
-                Commands::WaitNSamplesPlus1 {
-                    n: command_val - 0x70,
-                }
-            },
-            0x80..=0x8F => {
-                // handle YM2612 port 0 address 2A write command
-                Commands::YM2612Port0Address2AWriteWait {
-                    n: command_val - 0x80,
-                }
-            },
-            0x90..=0x95 => {
-                // DAC Stream Control Write commands - proper parsing implementation
-                match command_val {
-                    0x90 => {
-                        // Setup Stream Control: ss tt pp cc (4 bytes) - DAC Stream dual chip support
-                        let stream_id = bytes.get_u8();
-                        let chip_type_raw = bytes.get_u8();
-                        let chip_type = chip_type_raw & 0x7F; // Mask off bit 7 for actual chip type
-                        let chip_index = if chip_type_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                        let port = bytes.get_u8();
-                        let command = bytes.get_u8();
-                        Commands::DACStreamSetupControl { stream_id, chip_type, port, command, chip_index }
-                    },
-                    0x91 => {
-                        // Set Stream Data: ss dd ll bb (4 bytes)
-                        let stream_id = bytes.get_u8();
-                        let data_bank_id = bytes.get_u8();
-                        let step_size = bytes.get_u8();
-                        let step_base = bytes.get_u8();
-                        Commands::DACStreamSetData { stream_id, data_bank_id, step_size, step_base }
-                    },
-                    0x92 => {
-                        // Set Stream Frequency: ss ff ff ff ff (5 bytes)
-                        let stream_id = bytes.get_u8();
-                        let frequency = bytes.get_u32_le();
-                        Commands::DACStreamSetFrequency { stream_id, frequency }
-                    },
-                    0x93 => {
-                        // Start Stream: ss aa aa aa aa mm ll ll ll ll (10 bytes)
-                        let stream_id = bytes.get_u8();
-                        let data_start_offset = bytes.get_u32_le();
-                        let length_mode = bytes.get_u8();
-                        let data_length = bytes.get_u32_le();
-                        Commands::DACStreamStart { stream_id, data_start_offset, length_mode, data_length }
-                    },
-                    0x94 => {
-                        // Stop Stream: ss (1 byte)
-                        let stream_id = bytes.get_u8();
-                        Commands::DACStreamStop { stream_id }
-                    },
-                    0x95 => {
-                        // Start Stream (fast call): ss bb bb ff (4 bytes)
-                        let stream_id = bytes.get_u8();
-                        let block_id = bytes.get_u16_le();
-                        let flags = bytes.get_u8();
-                        Commands::DACStreamStartFast { stream_id, block_id, flags }
-                    },
-                    _ => unreachable!(), // Range 0x90..=0x95 guarantees this won't happen
-                }
-            },
-            0xA0 => {
-                // handle AY8910 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::AY8910Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB0 => {
-                // handle RF5C68 write command
-                Commands::RF5C68Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xB1 => {
-                // handle RF5C164 write command
-                Commands::RF5C164Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xB2 => {
-                // handle PWM write command
-                // TODO: is not aadd but addd
-                Commands::PWMWrite {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u16_le(),
-                }
-            },
-            0xB3 => {
-                // handle GameBoy DMG write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::GameBoyDMGWrite {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB4 => {
-                // handle NES APU write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::NESAPUWrite {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB5 => {
-                // handle MultiPCM write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::MultiPCMWrite {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB6 => {
-                // handle uPD7759 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::uPD7759Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB7 => {
-                // handle HuC6280 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::HuC6280Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB8 => {
-                // handle OKIM6295 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::OKIM6295Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB9 => {
-                // handle HuC6280 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::HuC6280Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xBA => {
-                // handle K053260 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::K053260Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xBB => {
-                // handle Pokey write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::PokeyWrite {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xBC => {
-                // handle WonderSwan write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::WonderSwanWrite {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xBD => {
-                // handle SAA1099 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::SAA1099Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xBE => {
-                // handle ES5506 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::ES5506Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xBF => {
-                // handle GA20 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::GA20Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xC0 => Commands::SegaPCMWrite {
-                offset: bytes.get_u16_le(),
-                value: bytes.get_u8(),
-            },
-            0xC1 => Commands::RF5C68WriteOffset {
-                offset: bytes.get_u16_le(),
-                value: bytes.get_u8(),
-            },
-            0xC2 => Commands::RF5C164WriteOffset {
-                offset: bytes.get_u16_le(),
-                value: bytes.get_u8(),
-            },
-            0xC3 => Commands::MultiPCMSetBank {
-                channel: bytes.get_u8(),
-                offset: bytes.get_u16_le(),
-            },
-            0xC4 => {
-                // TODO: weird stuff with the data
-                let value = bytes.get_u16_le();
-                Commands::QSoundWrite {
-                    register: bytes.get_u8(),
-                    value,
-                }
-            },
-            0xC5 => {
-                // TODO: weird stuff with the data
-                //let value = bytes.get_u16_le();
-                Commands::SCSPWrite {
-                    offset: bytes.get_u16_le(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xC6 => {
-                // TODO: check
-                Commands::WonderSwanWrite16 {
-                    offset: bytes.get_u16_le(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xC7 => {
-                // TODO: check
-                Commands::VSUWrite {
-                    offset: bytes.get_u16_le(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xC8 => {
-                // TODO: check
-                Commands::X1010Write {
-                    offset: bytes.get_u16_le(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xD0 => Commands::YMF278BWrite {
-                port: bytes.get_u8(),
-                register: bytes.get_u8(),
-                value: bytes.get_u8(),
-            },
-            0xD1 => Commands::YMF271Write {
-                port: bytes.get_u8(),
-                register: bytes.get_u8(),
-                value: bytes.get_u8(),
-            },
-            0xD2 => Commands::SCC1Write {
-                port: bytes.get_u8(),
-                register: bytes.get_u8(),
-                value: bytes.get_u8(),
-            },
-            0xD3 => Commands::K054539Write {
-                register: bytes.get_u16_le(),
-                value: bytes.get_u8(),
-            },
-            0xD4 => Commands::C140Write {
-                register: bytes.get_u16_le(),
-                value: bytes.get_u8(),
-            },
-            0xD5 => Commands::ES5503Write {
-                register: bytes.get_u16_le(),
-                value: bytes.get_u8(),
-            },
-            0xD6 => Commands::ES5506Write16 {
-                register: bytes.get_u8(),
-                value: bytes.get_u16_le(),
-            },
-            0xE0 => Commands::SeekPCM {
-                offset: bytes.get_u32_le(),
-            },
-            0xE1 => Commands::C352Write {
-                register: bytes.get_u16_le(),
-                value: bytes.get_u16_le(),
-            },
-            _ => {
-                return Err(VgmError::UnknownCommand { 
-                    opcode: command_val, 
-                    position: 0  // TODO: Track actual position
-                });
+}
+
+/// The chip a command drives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ChipTarget {
+    pub system: System,
+    /// 0 for the first chip, 1 for the second chip of a dual-chip setup
+    pub chip_index: u8,
+    /// Register port for chips with more than one
+    pub port: Option<u8>,
+}
+
+impl ChipTarget {
+    fn new(system: System, chip_index: u8) -> Self {
+        Self { system, chip_index, port: None }
+    }
+
+    fn with_port(system: System, chip_index: u8, port: u8) -> Self {
+        Self { system, chip_index, port: Some(port) }
+    }
+}
+
+impl Commands {
+    /// Chip the command writes to, `None` for waits, the end marker and DAC
+    /// stream commands that only refer to a stream id
+    pub fn target(&self) -> Option<ChipTarget> {
+        let target = match *self {
+            Commands::PSGWrite { chip_index, .. } | Commands::GameGearPSGStereo { chip_index, .. } => {
+                ChipTarget::new(System::SN76489, chip_index)
             },
+            Commands::AY8910StereoMask { value } => {
+                // Bit 6 picks the YM2203's SSG over an AY8910, bit 7 the chip
+                let system = if value & 0x40 != 0 { System::YM2203 } else { System::AY8910 };
+                ChipTarget::new(system, value >> 7)
+            },
+            Commands::YM2413Write { chip_index, .. } => ChipTarget::new(System::YM2413, chip_index),
+            Commands::YM2612Port0Write { chip_index, .. } => ChipTarget::with_port(System::YM2612, chip_index, 0),
+            Commands::YM2612Port1Write { chip_index, .. } => ChipTarget::with_port(System::YM2612, chip_index, 1),
+            Commands::YM2612Port0Address2AWriteWait { .. } | Commands::SeekPCM { .. } => {
+                ChipTarget::with_port(System::YM2612, 0, 0)
+            },
+            Commands::YM2151Write { chip_index, .. } => ChipTarget::new(System::YM2151, chip_index),
+            Commands::YM2203Write { chip_index, .. } => ChipTarget::new(System::YM2203, chip_index),
+            Commands::YM2608Port0Write { chip_index, .. } => ChipTarget::with_port(System::YM2608, chip_index, 0),
+            Commands::YM2608Port1Write { chip_index, .. } => ChipTarget::with_port(System::YM2608, chip_index, 1),
+            Commands::YM2610Port0Write { chip_index, .. } => ChipTarget::with_port(System::YM2610, chip_index, 0),
+            Commands::YM2610Port1Write { chip_index, .. } => ChipTarget::with_port(System::YM2610, chip_index, 1),
+            Commands::YM3812Write { chip_index, .. } => ChipTarget::new(System::YM3812, chip_index),
+            Commands::YM3526Write { chip_index, .. } => ChipTarget::new(System::YM3526, chip_index),
+            Commands::Y8950Write { chip_index, .. } => ChipTarget::new(System::Y8950, chip_index),
+            Commands::YMZ280BWrite { chip_index, .. } => ChipTarget::new(System::YMZ280B, chip_index),
+            Commands::YMF262Port0Write { chip_index, .. } => ChipTarget::with_port(System::YMF262, chip_index, 0),
+            Commands::YMF262Port1Write { chip_index, .. } => ChipTarget::with_port(System::YMF262, chip_index, 1),
+            Commands::DataBlock { block_type, .. } => ChipTarget::new(System::from_data_block_type(block_type)?, 0),
+            Commands::PCMRAMWrite { chip_type, .. } => ChipTarget::new(System::from_data_block_type(chip_type)?, 0),
+            Commands::DACStreamSetupControl { chip_type, port, chip_index, .. } => {
+                ChipTarget::with_port(System::from_chip_id(chip_type)?, chip_index, port)
+            },
+            Commands::AY8910Write { chip_index, .. } => ChipTarget::new(System::AY8910, chip_index),
+            Commands::RF5C68Write { .. } | Commands::RF5C68WriteOffset { .. } => ChipTarget::new(System::RF5C68, 0),
+            Commands::RF5C164Write { .. } | Commands::RF5C164WriteOffset { .. } => ChipTarget::new(System::RF5C164, 0),
+            Commands::PWMWrite { .. } => ChipTarget::new(System::Pwm, 0),
+            Commands::GameBoyDMGWrite { chip_index, .. } => ChipTarget::new(System::GameboyDmg, chip_index),
+            Commands::NESAPUWrite { chip_index, .. } => ChipTarget::new(System::NesApu, chip_index),
+            Commands::MultiPCMWrite { chip_index, .. } => ChipTarget::new(System::MultiPcm, chip_index),
+            Commands::MultiPCMSetBank { chip_index, .. } => ChipTarget::new(System::MultiPcm, chip_index),
+            Commands::uPD7759Write { chip_index, .. } => ChipTarget::new(System::UPD7759, chip_index),
+            Commands::OKIM6258Write { chip_index, .. } => ChipTarget::new(System::OKIM6258, chip_index),
+            Commands::OKIM6295Write { chip_index, .. } => ChipTarget::new(System::OKIM6295, chip_index),
+            Commands::HuC6280Write { chip_index, .. } => ChipTarget::new(System::HuC6280, chip_index),
+            Commands::K053260Write { chip_index, .. } => ChipTarget::new(System::K053260, chip_index),
+            Commands::PokeyWrite { chip_index, .. } => ChipTarget::new(System::Pokey, chip_index),
+            Commands::WonderSwanWrite { chip_index, .. } => ChipTarget::new(System::WonderSwan, chip_index),
+            Commands::WonderSwanWrite16 { chip_index, .. } => ChipTarget::new(System::WonderSwan, chip_index),
+            Commands::SAA1099Write { chip_index, .. } => ChipTarget::new(System::SAA1099, chip_index),
+            Commands::ES5506Write { chip_index, .. } => ChipTarget::new(System::ES5506, chip_index),
+            Commands::ES5506Write16 { chip_index, .. } => ChipTarget::new(System::ES5506, chip_index),
+            Commands::GA20Write { chip_index, .. } => ChipTarget::new(System::GA20, chip_index),
+            Commands::MikeyWrite { .. } => ChipTarget::new(System::Mikey, 0),
+            Commands::SegaPCMWrite { chip_index, .. } => ChipTarget::new(System::SegaPcm, chip_index),
+            Commands::QSoundWrite { .. } => ChipTarget::new(System::QSound, 0),
+            Commands::SCSPWrite { chip_index, .. } => ChipTarget::new(System::SCSP, chip_index),
+            Commands::VSUWrite { chip_index, .. } => ChipTarget::new(System::VSU, chip_index),
+            Commands::X1010Write { chip_index, .. } => ChipTarget::new(System::X1_010, chip_index),
+            Commands::YMF278BWrite { port, chip_index, .. } => ChipTarget::with_port(System::YMF278B, chip_index, port),
+            Commands::YMF271Write { port, chip_index, .. } => ChipTarget::with_port(System::YMF271, chip_index, port),
+            Commands::SCC1Write { port, chip_index, .. } => ChipTarget::with_port(System::K051649, chip_index, port),
+            Commands::K054539Write { chip_index, .. } => ChipTarget::new(System::K054539, chip_index),
+            Commands::C140Write { chip_index, .. } => ChipTarget::new(System::C140, chip_index),
+            Commands::ES5503Write { chip_index, .. } => ChipTarget::new(System::ES5503, chip_index),
+            Commands::C352Write { chip_index, .. } => ChipTarget::new(System::C352, chip_index),
+            Commands::WaitNSamples { .. }
+            | Commands::Wait735Samples
+            | Commands::Wait882Samples
+            | Commands::WaitNSamplesPlus1 { .. }
+            | Commands::EndOfSoundData
+            | Commands::DACStreamSetData { .. }
+            | Commands::DACStreamSetFrequency { .. }
+            | Commands::DACStreamStart { .. }
+            | Commands::DACStreamStop { .. }
+            | Commands::DACStreamStartFast { .. }
+            | Commands::Reserved { .. } => return None,
         };
+        Some(target)
+    }
+
+    /// Whether the command uses an opcode the spec reserves for future use
+    pub fn is_reserved(&self) -> bool {
+        matches!(self, Commands::Reserved { .. })
+    }
+
+    /// Whether the command does nothing but wait. `0x8n` also waits but
+    /// writes to the YM2612 first, see [`Commands::wait_samples`]
+    pub fn is_wait(&self) -> bool {
+        matches!(
+            self,
+            Commands::WaitNSamples { .. }
+                | Commands::Wait735Samples
+                | Commands::Wait882Samples
+                | Commands::WaitNSamplesPlus1 { .. }
+        )
+    }
 
-        Ok(command)
+    /// Number of samples this command waits for (0 for non-wait commands)
+    pub fn wait_samples(&self) -> u32 {
+        match self {
+            Commands::WaitNSamples { n } => *n as u32,
+            Commands::Wait735Samples => 735,
+            Commands::Wait882Samples => 882,
+            Commands::WaitNSamplesPlus1 { n } => *n as u32 + 1,
+            Commands::YM2612Port0Address2AWriteWait { n } => *n as u32,
+            _ => 0,
+        }
+    }
+
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_bytes(self) -> VgmResult<Vec<u8>> {
+        crate::opcodes::encode_command(self)
+    }
+
+    /// Parse one command with the permissive parser limits
+    pub fn from_bytes(bytes: &mut Bytes) -> VgmResult<Commands> {
+        Self::from_bytes_with_config(
+            bytes,
+            &crate::ParserConfig::permissive(),
+            &mut crate::ResourceTracker::new(),
+        )
+    }
+
+    /// Same as [`Commands::from_bytes`], kept for backward compatibility
+    pub fn from_bytes_safe(bytes: &mut Bytes) -> VgmResult<Commands> {
+        Self::from_bytes(bytes)
     }
     
-    /// Parse command with resource tracking and allocation limits
+    /// Parse command with resource tracking and allocation limits.
+    ///
+    /// Error positions are relative to the start of `bytes`, see
+    /// [`Commands::from_bytes_at`].
     pub fn from_bytes_with_config(
         bytes: &mut Bytes,
         config: &crate::ParserConfig,
         tracker: &mut crate::ResourceTracker
     ) -> VgmResult<Commands> {
-        let command_val = bytes.get_u8();
-        
-        let command = match command_val {
-            0x30 => {
-                Commands::PSGWrite {
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0x31 => {
-                Commands::AY8910StereoMask {
-                    value: bytes.get_u8(),
-                }
-            },
-            0x3F => {
-                Commands::GameGearPSGStereo {
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0x4F => {
-                Commands::GameGearPSGStereo {
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x50 => {
-                Commands::PSGWrite {
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x51 => {
-                Commands::YM2413Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x52 => {
-                Commands::YM2612Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x53 => {
-                Commands::YM2612Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x54 => {
-                Commands::YM2151Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x55 => {
-                Commands::YM2203Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x56 => {
-                Commands::YM2608Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x57 => {
-                Commands::YM2608Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x58 => {
-                Commands::YM2610Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x59 => {
-                Commands::YM2610Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x5A => {
-                Commands::YM3812Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x5B => {
-                Commands::YM3526Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x5C => {
-                Commands::Y8950Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x5D => {
-                Commands::YMZ280BWrite {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x5E => {
-                Commands::YMF262Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            0x5F => {
-                Commands::YMF262Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 0,
-                }
-            },
-            // YM family second chip commands (0xA1-0xAF) - Dual Chip Support Method #1
-            0xA1 => {
-                Commands::YM2413Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA2 => {
-                Commands::YM2612Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA3 => {
-                Commands::YM2612Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA4 => {
-                Commands::YM2151Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA5 => {
-                Commands::YM2203Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA6 => {
-                Commands::YM2608Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA7 => {
-                Commands::YM2608Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA8 => {
-                Commands::YM2610Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xA9 => {
-                Commands::YM2610Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xAA => {
-                Commands::YM3812Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xAB => {
-                Commands::YM3526Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xAC => {
-                Commands::Y8950Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xAD => {
-                Commands::YMZ280BWrite {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xAE => {
-                Commands::YMF262Port0Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0xAF => {
-                Commands::YMF262Port1Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                    chip_index: 1,
-                }
-            },
-            0x61 => {
-                Commands::WaitNSamples {
-                    n: bytes.get_u16_le(),
-                }
-            },
-            0x62 => Commands::Wait735Samples,
-            0x63 => Commands::Wait882Samples,
-            0x66 => Commands::EndOfSoundData,
-            0x67 => {
-                // handle data block command: 0x67 0x66 tt ss ss ss ss (data)
-                let compatibility_byte = bytes.get_u8();
-                if compatibility_byte != 0x66 {
-                    return Err(VgmError::InvalidCommandParameters {
-                        opcode: 0x67,
-                        position: 0, // TODO: track position
-                        reason: format!("Expected compatibility byte 0x66, found 0x{:02X}", compatibility_byte),
-                    });
-                }
-                
-                let block_type = bytes.get_u8();
-                let data_size = bytes.get_u32_le();
-                
-                // Check DataBlock size against config limits
-                config.check_data_block_size(data_size)?;
-                
-                // Track DataBlock allocation
-                tracker.track_data_block(config, data_size)?;
-                
-                // Security: Ensure sufficient data is available before allocation
-                if bytes.remaining() < data_size as usize {
-                    return Err(VgmError::BufferUnderflow {
-                        offset: 0, // TODO: Track actual position
-                        needed: data_size as usize,
-                        available: bytes.remaining(),
-                    });
-                }
-                
-                // Parse the data block content based on its type
-                let data = DataBlockContent::parse_from_bytes(block_type, data_size, bytes)?;
-                
-                Commands::DataBlock {
-                    block_type,
-                    data,
-                }
-            },
-            0x68 => {
-                // PCM RAM write command: 0x68 0x66 cc oo oo oo dd dd dd ss ss ss
-                let compatibility_byte = bytes.get_u8();
-                if compatibility_byte != 0x66 {
-                    return Err(VgmError::InvalidCommandParameters {
-                        opcode: 0x68,
-                        position: 0, // TODO: track position
-                        reason: format!("Expected compatibility byte 0x66, found 0x{:02X}", compatibility_byte),
-                    });
-                }
-                
-                let chip_type = bytes.get_u8();
-                
-                // Read 24-bit values (little-endian)
-                let read_offset = bytes.get_u8() as u32 | 
-                                ((bytes.get_u8() as u32) << 8) | 
-                                ((bytes.get_u8() as u32) << 16);
-                                
-                let write_offset = bytes.get_u8() as u32 | 
-                                 ((bytes.get_u8() as u32) << 8) | 
-                                 ((bytes.get_u8() as u32) << 16);
-                                 
-                let mut size = bytes.get_u8() as u32 | 
-                             ((bytes.get_u8() as u32) << 8) | 
-                             ((bytes.get_u8() as u32) << 16);
-                
-                // Special case: size of 0 means 0x01000000 bytes
-                if size == 0 {
-                    size = 0x01000000;
-                }
-                
-                // Security: Check data size limits before allocation
-                if size as usize > config.max_command_memory {
-                    return Err(VgmError::DataSizeExceedsLimit {
-                        field: "PCM RAM write data".to_string(),
-                        size: size as usize,
-                        limit: config.max_command_memory,
-                    });
-                }
-                
-                // Check buffer availability
-                if bytes.remaining() < size as usize {
-                    return Err(VgmError::BufferUnderflow {
-                        offset: 0, // TODO: track position
-                        needed: size as usize,
-                        available: bytes.remaining(),
-                    });
-                }
-                
-                // Track memory allocation
-                tracker.track_data_block(config, size)?;
-                
-                // Read the data
-                let data: Vec<u8> = (0..size as usize)
-                    .map(|_| bytes.get_u8())
-                    .collect();
-                
-                Commands::PCMRAMWrite {
-                    chip_type,
-                    read_offset,
-                    write_offset,
-                    size,
-                    data,
-                }
-            },
-            0x70..=0x7F => {
-                Commands::WaitNSamplesPlus1 {
-                    n: command_val - 0x70,
-                }
-            },
-            0x80..=0x8F => {
-                Commands::YM2612Port0Address2AWriteWait {
-                    n: command_val - 0x80,
-                }
-            },
-            0x90 => {
-                // Setup Stream Control: ss tt pp cc (4 bytes) - DAC Stream dual chip support
-                let stream_id = bytes.get_u8();
-                let chip_type_raw = bytes.get_u8();
-                let chip_type = chip_type_raw & 0x7F; // Mask off bit 7 for actual chip type
-                let chip_index = if chip_type_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                let port = bytes.get_u8();
-                let command = bytes.get_u8();
-                Commands::DACStreamSetupControl { stream_id, chip_type, port, command, chip_index }
-            },
-            0x91 => {
-                // Set Stream Data: ss dd ll bb (4 bytes)
-                let stream_id = bytes.get_u8();
-                let data_bank_id = bytes.get_u8();
-                let step_size = bytes.get_u8();
-                let step_base = bytes.get_u8();
-                Commands::DACStreamSetData { stream_id, data_bank_id, step_size, step_base }
-            },
-            0x92 => {
-                // Set Stream Frequency: ss ff ff ff ff (5 bytes)
-                let stream_id = bytes.get_u8();
-                let frequency = bytes.get_u32_le();
-                Commands::DACStreamSetFrequency { stream_id, frequency }
-            },
-            0x93 => {
-                // Start Stream: ss aa aa aa aa mm ll ll ll ll (10 bytes)
-                let stream_id = bytes.get_u8();
-                let data_start_offset = bytes.get_u32_le();
-                let length_mode = bytes.get_u8();
-                let data_length = bytes.get_u32_le();
-                Commands::DACStreamStart { stream_id, data_start_offset, length_mode, data_length }
-            },
-            0x94 => {
-                // Stop Stream: ss (1 byte)
-                let stream_id = bytes.get_u8();
-                Commands::DACStreamStop { stream_id }
-            },
-            0x95 => {
-                // Start Stream (fast call): ss bb bb ff (4 bytes)
-                let stream_id = bytes.get_u8();
-                let block_id = bytes.get_u16_le();
-                let flags = bytes.get_u8();
-                Commands::DACStreamStartFast { stream_id, block_id, flags }
-            },
-            0xA0 => {
-                // handle AY8910 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::AY8910Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB0 => {
-                Commands::RF5C68Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xB1 => {
-                Commands::RF5C164Write {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xB2 => {
-                Commands::PWMWrite {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u16_le(),
-                }
-            },
-            0xB3 => {
-                // handle GameBoy DMG write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::GameBoyDMGWrite {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB4 => {
-                // handle NES APU write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::NESAPUWrite {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB5 => {
-                // handle MultiPCM write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::MultiPCMWrite {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB6 => {
-                // handle uPD7759 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::uPD7759Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB7 => {
-                // handle OKIM6258 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::OKIM6258Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB8 => {
-                // handle OKIM6295 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::OKIM6295Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xB9 => {
-                // handle HuC6280 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::HuC6280Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xBA => {
-                // handle K053260 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::K053260Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xBB => {
-                // handle Pokey write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::PokeyWrite {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xBC => {
-                // handle WonderSwan write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::WonderSwanWrite {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xBD => {
-                // handle SAA1099 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::SAA1099Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xBE => {
-                // handle ES5506 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::ES5506Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xBF => {
-                // handle GA20 write command - Method #2: bit 7 determines chip
-                let register_raw = bytes.get_u8();
-                let register = register_raw & 0x7F; // Mask off bit 7 for actual register
-                let chip_index = if register_raw & 0x80 != 0 { 1 } else { 0 }; // Check bit 7 for chip
-                Commands::GA20Write {
-                    register,
-                    value: bytes.get_u8(),
-                    chip_index,
-                }
-            },
-            0xC0 => {
-                Commands::SegaPCMWrite {
-                    offset: bytes.get_u16_le(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xC1 => {
-                Commands::RF5C68WriteOffset {
-                    offset: bytes.get_u16_le(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xC2 => {
-                Commands::RF5C164WriteOffset {
-                    offset: bytes.get_u16_le(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xD0 => {
-                Commands::YMF278BWrite {
-                    port: bytes.get_u8(),
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xD1 => {
-                Commands::YMF271Write {
-                    port: bytes.get_u8(),
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xD2 => {
-                Commands::SCC1Write {
-                    port: bytes.get_u8(),
-                    register: bytes.get_u8(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xD3 => {
-                Commands::K054539Write {
-                    register: bytes.get_u16_le(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xD4 => {
-                Commands::C140Write {
-                    register: bytes.get_u16_le(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xD5 => {
-                Commands::ES5503Write {
-                    register: bytes.get_u16_le(),
-                    value: bytes.get_u8(),
-                }
-            },
-            0xD6 => {
-                Commands::ES5506Write16 {
-                    register: bytes.get_u8(),
-                    value: bytes.get_u16_le(),
-                }
-            },
-            0xE0 => Commands::SeekPCM {
-                offset: bytes.get_u32_le(),
-            },
-            0xE1 => Commands::C352Write {
-                register: bytes.get_u16_le(),
-                value: bytes.get_u16_le(),
-            },
-            _ => {
-                return Err(VgmError::UnknownCommand { 
-                    opcode: command_val, 
-                    position: 0  // TODO: Track actual position
-                });
-            },
-        };
+        Self::from_bytes_at(bytes, 0, config, tracker)
+    }
 
-        Ok(command)
+    /// Parse the command starting at absolute file position `position`.
+    ///
+    /// Truncated input returns [`VgmError::IncompleteCommand`] and leaves
+    /// `bytes` untouched; every error reports `position`.
+    pub fn from_bytes_at(
+        bytes: &mut Bytes,
+        position: usize,
+        config: &crate::ParserConfig,
+        tracker: &mut crate::ResourceTracker
+    ) -> VgmResult<Commands> {
+        crate::opcodes::decode_command(bytes, position, config, tracker)
     }
 }
 
@@ -3838,6 +1756,20 @@ mod tests {
         assert_eq!(result, vec![0x0E, 0x01, 0x30, 0x01]);
     }
 
+    #[test]
+    fn test_bit_packing_rejects_bad_widths() {
+        let compressed_data = vec![0xFF; 4];
+        // Zero bit values would never advance, and over 32 bits can't be stored
+        assert!(decompress_bit_packing(&compressed_data, 0, 8, 0x00, 0, 4, None).is_err());
+        assert!(decompress_bit_packing(&compressed_data, 8, 40, 0x00, 0, 4, None).is_err());
+        // Shifting can't narrow a value
+        assert!(decompress_bit_packing(&compressed_data, 16, 8, 0x01, 0, 4, None).is_err());
+        assert!(decompress_dpcm(&compressed_data, 0, 8, 0, 4, &[0; 16]).is_err());
+
+        // A huge declared size runs out of data instead of reserving it all
+        assert!(decompress_bit_packing(&compressed_data, 8, 8, 0x00, 0, u32::MAX, None).is_err());
+    }
+
     #[test]
     fn test_bit_packing_shift_mode() {
         // Test bit packing with shift left mode (sub_type = 0x01)
@@ -3915,7 +1847,7 @@ mod tests {
         
         // Compressed stream block type 0x40 (YM2612)
         let block_type = 0x40;
-        let data_size = 15; // 9 bytes header + 6 bytes data
+        let data_size = 16; // 10 bytes header + 6 bytes data
         
         // Compression header
         bytes.put_u8(0x00); // Bit packing compression
@@ -3951,6 +1883,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_compressed_stream_block_keeps_alignment() {
+        let block = Commands::DataBlock {
+            block_type: 0x40,
+            data: DataBlockContent::CompressedStream {
+                chip_type: StreamChipType::YM2612,
+                compression: CompressionType::BitPacking {
+                    bits_decompressed: 8,
+                    bits_compressed: 4,
+                    sub_type: 0x00,
+                    add_value: 0,
+                },
+                uncompressed_size: 4,
+                data: vec![0x12, 0x34],
+            },
+        };
+        let mut bytes = block.clone().to_bytes().unwrap();
+        // The size covers the 10 byte compression header and the data
+        assert_eq!(u32::from_le_bytes(bytes[3..7].try_into().unwrap()), 12);
+
+        bytes.push(0x62);
+        let mut bytes = Bytes::from(bytes);
+        assert_eq!(Commands::from_bytes(&mut bytes).unwrap(), block);
+        assert_eq!(Commands::from_bytes(&mut bytes).unwrap(), Commands::Wait735Samples);
+    }
+
     #[test]
     fn test_data_block_decompression() {
         // Create a compressed data block
@@ -4501,4 +2459,203 @@ mod tests {
         assert_eq!(cmd2, expected);
         assert_eq!(cmd3, expected);
     }
+
+    #[test]
+    fn test_wait_samples() {
+        assert_eq!(Commands::WaitNSamples { n: 0x1234 }.wait_samples(), 0x1234);
+        assert_eq!(Commands::Wait735Samples.wait_samples(), 735);
+        assert_eq!(Commands::Wait882Samples.wait_samples(), 882);
+        // 0x7n waits n+1 samples but 0x8n waits only n
+        assert_eq!(Commands::WaitNSamplesPlus1 { n: 0 }.wait_samples(), 1);
+        assert_eq!(Commands::WaitNSamplesPlus1 { n: 15 }.wait_samples(), 16);
+        assert_eq!(Commands::YM2612Port0Address2AWriteWait { n: 0 }.wait_samples(), 0);
+        assert_eq!(Commands::YM2612Port0Address2AWriteWait { n: 15 }.wait_samples(), 15);
+        assert_eq!(Commands::PSGWrite { value: 0x9F, chip_index: 0 }.wait_samples(), 0);
+        assert_eq!(Commands::EndOfSoundData.wait_samples(), 0);
+    }
+
+    #[test]
+    fn test_command_targets() {
+        let target = Commands::YM2612Port1Write { register: 0x30, value: 0x71, chip_index: 1 }.target();
+        assert_eq!(
+            target,
+            Some(ChipTarget { system: System::YM2612, chip_index: 1, port: Some(1) })
+        );
+
+        let target = Commands::GameBoyDMGWrite { register: 0x12, value: 0xF0, chip_index: 0 }.target();
+        assert_eq!(target, Some(ChipTarget { system: System::GameboyDmg, chip_index: 0, port: None }));
+
+        let setup = Commands::DACStreamSetupControl { stream_id: 0, chip_type: 0x02, port: 0, command: 0x2A, chip_index: 0 };
+        assert_eq!(setup.target().map(|t| t.system), Some(System::YM2612));
+
+        let ram_write = Commands::PCMRAMWrite { chip_type: 0x01, read_offset: 0, write_offset: 0, size: 0x10 };
+        assert_eq!(ram_write.target().map(|t| t.system), Some(System::RF5C68));
+
+        assert_eq!(Commands::DACStreamStop { stream_id: 0 }.target(), None);
+        assert_eq!(Commands::Wait735Samples.target(), None);
+    }
+
+    #[test]
+    fn test_is_wait() {
+        assert!(Commands::WaitNSamples { n: 1 }.is_wait());
+        assert!(Commands::WaitNSamplesPlus1 { n: 0 }.is_wait());
+        assert!(!Commands::YM2612Port0Address2AWriteWait { n: 3 }.is_wait());
+        assert!(!Commands::EndOfSoundData.is_wait());
+    }
+
+    #[test]
+    fn test_reserved_opcodes_round_trip() {
+        let config = crate::ParserConfig::default();
+        for bytes in [
+            vec![0x32, 0x01],
+            vec![0x41, 0x01, 0x02],
+            vec![0x4E, 0x01, 0x02],
+            vec![0xC9, 0x01, 0x02, 0x03],
+            vec![0xDF, 0x01, 0x02, 0x03],
+            vec![0xFF, 0x01, 0x02, 0x03, 0x04],
+        ] {
+            assert_eq!(command_length(&bytes, 0).unwrap(), Some(bytes.len()));
+
+            let mut data = Bytes::from(bytes.clone());
+            let command = Commands::from_bytes(&mut data).unwrap();
+            assert_eq!(command, Commands::Reserved { opcode: bytes[0], operands: bytes[1..].to_vec() });
+            assert!(data.is_empty());
+
+            let mut tracker = crate::ResourceTracker::new();
+            let parsed = Commands::from_bytes_with_config(&mut Bytes::from(bytes.clone()), &config, &mut tracker).unwrap();
+            assert_eq!(parsed, command);
+            assert_eq!(Commands::from_bytes_safe(&mut Bytes::from(bytes.clone())).unwrap(), command);
+
+            assert_eq!(command.to_bytes().unwrap(), bytes);
+        }
+
+        // Opcodes outside the reserved ranges have no known length
+        assert!(matches!(command_length(&[0x65], 7), Err(VgmError::UnknownCommand { opcode: 0x65, position: 7 })));
+
+        let wrong_length = Commands::Reserved { opcode: 0xE2, operands: vec![0x00] };
+        assert!(wrong_length.to_bytes().is_err());
+    }
+
+    #[test]
+    fn test_mikey_write() {
+        let config = crate::ParserConfig::default();
+        let mut tracker = crate::ResourceTracker::new();
+
+        // Mikey isn't a dual chip, so bit 7 is part of the register
+        for (bytes, register) in [([0x40, 0x20, 0x7F], 0x20), ([0x40, 0xA0, 0x7F], 0xA0)] {
+            let expected = Commands::MikeyWrite { register, value: 0x7F };
+            assert_eq!(Commands::from_bytes(&mut Bytes::from(bytes.to_vec())).unwrap(), expected);
+            assert_eq!(Commands::from_bytes_safe(&mut Bytes::from(bytes.to_vec())).unwrap(), expected);
+            let parsed = Commands::from_bytes_with_config(&mut Bytes::from(bytes.to_vec()), &config, &mut tracker).unwrap();
+            assert_eq!(parsed, expected);
+            assert_eq!(expected.clone().to_bytes().unwrap(), bytes);
+            assert_eq!(command_length(&bytes, 0).unwrap(), Some(3));
+            assert_eq!(expected.target().map(|t| (t.system, t.chip_index)), Some((System::Mikey, 0)));
+        }
+    }
+
+    #[test]
+    fn test_bit7_second_chip_targets() {
+        let cases: Vec<(Vec<u8>, Commands, ChipTarget)> = vec![
+            (
+                vec![0xC0, 0x34, 0x92, 0x56],
+                Commands::SegaPCMWrite { offset: 0x1234, value: 0x56, chip_index: 1 },
+                ChipTarget::new(System::SegaPcm, 1),
+            ),
+            (
+                vec![0xC3, 0x85, 0x34, 0x12],
+                Commands::MultiPCMSetBank { channel: 0x05, offset: 0x1234, chip_index: 1 },
+                ChipTarget::new(System::MultiPcm, 1),
+            ),
+            (
+                vec![0xC5, 0x81, 0x23, 0x45],
+                Commands::SCSPWrite { offset: 0x0123, value: 0x45, chip_index: 1 },
+                ChipTarget::new(System::SCSP, 1),
+            ),
+            (
+                vec![0xC6, 0x81, 0x23, 0x45],
+                Commands::WonderSwanWrite16 { offset: 0x0123, value: 0x45, chip_index: 1 },
+                ChipTarget::new(System::WonderSwan, 1),
+            ),
+            (
+                vec![0xC7, 0x81, 0x23, 0x45],
+                Commands::VSUWrite { offset: 0x0123, value: 0x45, chip_index: 1 },
+                ChipTarget::new(System::VSU, 1),
+            ),
+            (
+                vec![0xC8, 0x81, 0x23, 0x45],
+                Commands::X1010Write { offset: 0x0123, value: 0x45, chip_index: 1 },
+                ChipTarget::new(System::X1_010, 1),
+            ),
+            (
+                vec![0xD0, 0x81, 0x02, 0x03],
+                Commands::YMF278BWrite { port: 0x01, register: 0x02, value: 0x03, chip_index: 1 },
+                ChipTarget::with_port(System::YMF278B, 1, 0x01),
+            ),
+            (
+                vec![0xD1, 0x83, 0x02, 0x03],
+                Commands::YMF271Write { port: 0x03, register: 0x02, value: 0x03, chip_index: 1 },
+                ChipTarget::with_port(System::YMF271, 1, 0x03),
+            ),
+            (
+                vec![0xD2, 0x80, 0x01, 0x05],
+                Commands::SCC1Write { port: 0x00, register: 0x01, value: 0x05, chip_index: 1 },
+                ChipTarget::with_port(System::K051649, 1, 0x00),
+            ),
+            (
+                vec![0xD3, 0x81, 0x00, 0x05],
+                Commands::K054539Write { register: 0x0100, value: 0x05, chip_index: 1 },
+                ChipTarget::new(System::K054539, 1),
+            ),
+            (
+                vec![0xD4, 0x81, 0x00, 0x05],
+                Commands::C140Write { register: 0x0100, value: 0x05, chip_index: 1 },
+                ChipTarget::new(System::C140, 1),
+            ),
+            (
+                vec![0xD5, 0x81, 0x00, 0x05],
+                Commands::ES5503Write { register: 0x0100, value: 0x05, chip_index: 1 },
+                ChipTarget::new(System::ES5503, 1),
+            ),
+            (
+                vec![0xD6, 0x81, 0x23, 0x45],
+                Commands::ES5506Write16 { register: 0x01, value: 0x2345, chip_index: 1 },
+                ChipTarget::new(System::ES5506, 1),
+            ),
+            (
+                vec![0xE1, 0x81, 0x23, 0x45, 0x67],
+                Commands::C352Write { register: 0x0123, value: 0x4567, chip_index: 1 },
+                ChipTarget::new(System::C352, 1),
+            ),
+        ];
+
+        for (bytes, expected, target) in cases {
+            let command = Commands::from_bytes(&mut Bytes::from(bytes.clone())).unwrap();
+            assert_eq!(command, expected);
+            assert_eq!(command.target(), Some(target));
+            assert_eq!(command.to_bytes().unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_skipped_bytes_are_reported() {
+        // 0x64 isn't a command
+        let data = Bytes::from(vec![0x50, 0x9F, 0x64, 0x62, 0x66]);
+        let config = crate::ParserConfig { parse_mode: crate::ParseMode::Recover, ..crate::ParserConfig::default() };
+        let mut tracker = crate::ResourceTracker::new();
+
+        let (commands, diagnostics) = parse_commands_with_diagnostics(&mut data.clone(), &config, &mut tracker).unwrap();
+        assert_eq!(commands, vec![
+            Commands::PSGWrite { value: 0x9F, chip_index: 0 },
+            Commands::Wait735Samples,
+            Commands::EndOfSoundData,
+        ]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].offset, diagnostics[0].skipped), (2, 1));
+        assert_eq!(diagnostics[0].severity, crate::Severity::Error);
+
+        // The strict default fails instead of truncating
+        let mut tracker = crate::ResourceTracker::new();
+        assert!(parse_commands_with_diagnostics(&mut data.clone(), &crate::ParserConfig::default(), &mut tracker).is_err());
+    }
 }
@@ -0,0 +1,524 @@
+//! Serialization that lays a [`VgmFile`] out from scratch.
+//!
+//! The plain [`VgmWriter`] implementation writes header fields exactly as
+//! stored, which produces a corrupt file as soon as commands or metadata are
+//! edited. The writer here recomputes every offset and counter instead.
+
+use std::path::Path;
+
+use bytes::BytesMut;
+
+use crate::{
+    errors::{VgmError, VgmResult},
+    traits::VgmWriter,
+    HeaderData, VgmFile, VgmMetadata,
+};
+
+/// Where the loop point goes when writing a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoopPoint {
+    /// Keep the loop on the command the header currently points at, resolved
+    /// through `VgmFile::command_offsets`. Those must match `commands`, as
+    /// [`VgmFile::insert_command`] and [`VgmFile::remove_command`] keep them
+    #[default]
+    Keep,
+    /// Loop back to the command at this index
+    Index(usize),
+    /// Write a file that doesn't loop
+    NoLoop,
+}
+
+/// Options for [`VgmFile::to_bytes_with_options`]
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+    /// Loop point to write
+    pub loop_point: LoopPoint,
+    /// Recompute `total_nb_samples` and `loop_nb_samples` from the wait commands
+    pub recompute_sample_counts: bool,
+    /// Write a GD3 tag even when the metadata is empty and the source had none
+    pub always_write_gd3: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            loop_point: LoopPoint::Keep,
+            recompute_sample_counts: true,
+            always_write_gd3: false,
+        }
+    }
+}
+
+impl VgmFile {
+    /// Serialize with every header offset and sample counter recomputed from
+    /// the actual commands and metadata
+    pub fn to_bytes_normalized(&self) -> VgmResult<Vec<u8>> {
+        self.to_bytes_with_options(&WriteOptions::default())
+    }
+
+    /// Serialize the file, laying out header, extra header, commands and GD3
+    /// tag, then patching the EOF, GD3 and loop offsets to match.
+    ///
+    /// The [`crate::RawRegions`] are kept: header padding as far as it fits
+    /// before the VGM data, bytes after the commands ahead of the GD3 tag and
+    /// trailing bytes at the end.
+    pub fn to_bytes_with_options(&self, options: &WriteOptions) -> VgmResult<Vec<u8>> {
+        let mut header = self.header.clone();
+        let vgm_start = layout_extra_header(&mut header)?;
+
+        let loop_index = match options.loop_point {
+            LoopPoint::Keep => {
+                // Offsets out of step with the commands would resolve the loop
+                // to whatever command now sits at the old position
+                if self.header.loop_offset != 0 && self.command_offsets.len() != self.commands.len() {
+                    return Err(VgmError::InconsistentData {
+                        context: "Loop point".to_string(),
+                        reason: format!(
+                            "{} command offsets for {} commands; edit through insert_command/remove_command or pass LoopPoint::Index",
+                            self.command_offsets.len(),
+                            self.commands.len()
+                        ),
+                    });
+                }
+                self.loop_start_index()
+            },
+            LoopPoint::Index(index) => {
+                if index >= self.commands.len() {
+                    return Err(VgmError::InvalidDataFormat {
+                        field: "loop_point".to_string(),
+                        details: format!("loop index {} out of range for {} commands", index, self.commands.len()),
+                    });
+                }
+                Some(index)
+            },
+            LoopPoint::NoLoop => None,
+        };
+
+        // Encode commands first so their positions are known
+        let mut body: Vec<u8> = Vec::new();
+        let mut loop_position = None;
+        for (index, command) in self.commands.iter().enumerate() {
+            if Some(index) == loop_index {
+                loop_position = Some(vgm_start + body.len());
+            }
+            body.extend(command.clone().to_bytes()?);
+        }
+
+        let mut gd3 = BytesMut::new();
+        if options.always_write_gd3 || self.header.gd3_offset != 0 || self.metadata != VgmMetadata::default() {
+            self.metadata.to_bytes(&mut gd3)?;
+        }
+
+        let raw_regions = &self.raw_regions;
+        let gd3_position = vgm_start + body.len() + raw_regions.after_commands.len();
+        let file_size = gd3_position + gd3.len() + raw_regions.trailing.len();
+
+        header.end_of_file_offset = offset_field(file_size, 0x04)?;
+        header.gd3_offset = if gd3.is_empty() { 0 } else { offset_field(gd3_position, 0x14)? };
+        header.loop_offset = match loop_position {
+            Some(position) => offset_field(position, 0x1C)?,
+            None => 0,
+        };
+
+        if options.recompute_sample_counts {
+            let (total, looped) = crate::timeline::sample_counts(&self.commands, loop_index);
+            header.total_nb_samples = total;
+            header.loop_nb_samples = looped;
+        } else if loop_index.is_none() {
+            header.loop_nb_samples = 0;
+        }
+
+        let mut buffer = BytesMut::with_capacity(file_size);
+        header.to_bytes(&mut buffer)?;
+        if buffer.len() > vgm_start {
+            return Err(VgmError::InconsistentData {
+                context: "Header layout".to_string(),
+                reason: format!("header occupies {} bytes but VGM data starts at 0x{:X}", buffer.len(), vgm_start),
+            });
+        }
+        let gap = vgm_start - buffer.len();
+        let padding = &raw_regions.header_padding;
+        buffer.extend_from_slice(&padding[..gap.min(padding.len())]);
+        buffer.resize(vgm_start, 0);
+
+        let mut out = buffer.to_vec();
+        out.extend(body);
+        out.extend_from_slice(&raw_regions.after_commands);
+        out.extend_from_slice(&gd3);
+        out.extend_from_slice(&raw_regions.trailing);
+        Ok(out)
+    }
+}
+
+impl VgmFile {
+    /// Serialize as stored with [`VgmWriter::to_bytes`] and gzip the result,
+    /// as in .vgz files.
+    ///
+    /// Header fields are written untouched, so a parsed file that wasn't
+    /// modified compresses its original bytes; use
+    /// [`VgmFile::to_vgz_bytes_with_options`] after edits. `level` ranges from
+    /// 0 (store) to 9 (best compression).
+    pub fn to_vgz_bytes(&self, level: u32) -> VgmResult<Vec<u8>> {
+        let mut buffer = BytesMut::new();
+        self.to_bytes(&mut buffer)?;
+        crate::utils::compress_gzip(&buffer, level)
+    }
+
+    /// Serialize with [`VgmFile::to_bytes_with_options`] and gzip the result
+    pub fn to_vgz_bytes_with_options(&self, level: u32, options: &WriteOptions) -> VgmResult<Vec<u8>> {
+        crate::utils::compress_gzip(&self.to_bytes_with_options(options)?, level)
+    }
+
+    /// Write the file to `path` as stored, gzipped when the extension is
+    /// `.vgz` and as a plain VGM otherwise.
+    ///
+    /// Like [`VgmWriter::to_bytes`], header fields are written untouched, so
+    /// an unmodified parsed file is written back byte for byte; use
+    /// [`VgmFile::write_to_path_with_options`] after edits.
+    pub fn write_to_path(&self, path: &str) -> VgmResult<()> {
+        let data = if is_vgz_path(path) {
+            self.to_vgz_bytes(crate::utils::DEFAULT_VGZ_COMPRESSION_LEVEL)?
+        } else {
+            let mut buffer = BytesMut::new();
+            self.to_bytes(&mut buffer)?;
+            buffer.to_vec()
+        };
+        write_file(path, data)
+    }
+
+    /// Write the file to `path` laid out by [`VgmFile::to_bytes_with_options`],
+    /// gzipped when the extension is `.vgz`
+    pub fn write_to_path_with_options(&self, path: &str, options: &WriteOptions) -> VgmResult<()> {
+        let data = if is_vgz_path(path) {
+            self.to_vgz_bytes_with_options(crate::utils::DEFAULT_VGZ_COMPRESSION_LEVEL, options)?
+        } else {
+            self.to_bytes_with_options(options)?
+        };
+        write_file(path, data)
+    }
+}
+
+/// Whether `path` has a `.vgz` extension, in any case
+fn is_vgz_path(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("vgz"))
+}
+
+fn write_file(path: &str, data: Vec<u8>) -> VgmResult<()> {
+    std::fs::write(path, data).map_err(|e| match e.kind() {
+        std::io::ErrorKind::PermissionDenied => VgmError::PermissionDenied {
+            path: path.to_string(),
+        },
+        _ => VgmError::FileWriteError {
+            path: path.to_string(),
+            reason: e.to_string(),
+        },
+    })
+}
+
+/// Place the extra header of a 1.70+ file right after the header fields in
+/// use, moving the VGM data back when it no longer fits. Returns where the VGM
+/// data starts.
+///
+/// Files older than 1.70, or without any override, get no extra header.
+fn layout_extra_header(header: &mut HeaderData) -> VgmResult<usize> {
+    let vgm_start = header.vgm_data_start()?;
+    if header.version < 170 || header.extra_header.is_empty() {
+        header.extra_header_offset = 0;
+        return Ok(vgm_start);
+    }
+
+    header.extra_header.normalize();
+    header.extra_header_offset = 0;
+    let position = crate::version::extra_header_position(header);
+    let vgm_start = vgm_start.max((position + header.extra_header.encoded_size()).next_multiple_of(4));
+    header.extra_header_offset = offset_field(position, 0xBC)?;
+    header.vgm_data_offset = offset_field(vgm_start, 0x34)?;
+    Ok(vgm_start)
+}
+
+/// Encode an absolute position as a header offset relative to `field_position`
+fn offset_field(position: usize, field_position: usize) -> VgmResult<u32> {
+    position
+        .checked_sub(field_position)
+        .and_then(|offset| u32::try_from(offset).ok())
+        .ok_or(VgmError::IntegerOverflow {
+            operation: "header offset calculation".to_string(),
+            details: format!("position {} relative to 0x{:X}", position, field_position),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::{Commands, Gd3LocaleData, System, VgmParser, VolumeMode};
+
+    fn sample_file() -> VgmFile {
+        VgmFile {
+            header: HeaderData {
+                version: 151,
+                sn76489_clock: 3_579_545,
+                ym2612_clock: 7_670_453,
+                vgm_data_offset: 0xCC,
+                ..Default::default()
+            },
+            commands: vec![
+                Commands::PSGWrite { value: 0x9F, chip_index: 0 },
+                Commands::Wait735Samples,
+                Commands::PSGWrite { value: 0x90, chip_index: 0 },
+                Commands::WaitNSamplesPlus1 { n: 9 },
+                Commands::YM2612Port0Address2AWriteWait { n: 5 },
+                Commands::EndOfSoundData,
+            ],
+            command_offsets: vec![],
+            metadata: VgmMetadata {
+                english_data: Gd3LocaleData {
+                    track: "Loop".to_string(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            raw_regions: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_normalized_layout() {
+        let vgm = sample_file();
+        let bytes = vgm
+            .to_bytes_with_options(&WriteOptions { loop_point: LoopPoint::Index(2), ..Default::default() })
+            .unwrap();
+
+        let reparsed = VgmFile::from_bytes(&mut Bytes::from(bytes.clone())).unwrap();
+        let header = &reparsed.header;
+
+        assert_eq!(header.end_of_file_offset as usize, bytes.len() - 4);
+        assert_eq!(header.total_nb_samples, 735 + 10 + 5);
+        assert_eq!(header.loop_nb_samples, 10 + 5);
+        assert_eq!(reparsed.loop_start_index(), Some(2));
+        assert_eq!(reparsed.commands, vgm.commands);
+        assert_eq!(reparsed.metadata, vgm.metadata);
+
+        // Data sits right after the 0x100 byte header and GD3 right after the data
+        assert_eq!(reparsed.offset_of(0), Some(0x100));
+        assert_eq!(header.gd3_offset as usize + 0x14, 0x100 + 2 + 1 + 2 + 1 + 1 + 1);
+    }
+
+    #[test]
+    fn test_normalized_after_edit() {
+        let mut vgm = VgmFile::from_bytes(&mut Bytes::from(
+            sample_file()
+                .to_bytes_with_options(&WriteOptions { loop_point: LoopPoint::Index(2), ..Default::default() })
+                .unwrap(),
+        ))
+        .unwrap();
+
+        // Prepend to the intro and move the loop index along with it
+        vgm.commands.insert(0, Commands::WaitNSamples { n: 1000 });
+
+        let bytes = vgm
+            .to_bytes_with_options(&WriteOptions { loop_point: LoopPoint::Index(3), ..Default::default() })
+            .unwrap();
+        let reparsed = VgmFile::from_bytes(&mut Bytes::from(bytes)).unwrap();
+
+        assert_eq!(reparsed.header.total_nb_samples, 1000 + 735 + 10 + 5);
+        assert_eq!(reparsed.header.loop_nb_samples, 15);
+        assert_eq!(reparsed.loop_start_index(), Some(3));
+    }
+
+    #[test]
+    fn test_normalized_keep_and_no_loop() {
+        let looped = VgmFile::from_bytes(&mut Bytes::from(
+            sample_file()
+                .to_bytes_with_options(&WriteOptions { loop_point: LoopPoint::Index(2), ..Default::default() })
+                .unwrap(),
+        ))
+        .unwrap();
+
+        // Keep resolves the existing loop point through the parsed offsets
+        let kept = VgmFile::from_bytes(&mut Bytes::from(looped.to_bytes_normalized().unwrap())).unwrap();
+        assert_eq!(kept.loop_start_index(), Some(2));
+
+        let unlooped = looped
+            .to_bytes_with_options(&WriteOptions { loop_point: LoopPoint::NoLoop, ..Default::default() })
+            .unwrap();
+        let unlooped = VgmFile::from_bytes(&mut Bytes::from(unlooped)).unwrap();
+        assert_eq!(unlooped.header.loop_offset, 0);
+        assert_eq!(unlooped.header.loop_nb_samples, 0);
+    }
+
+    #[test]
+    fn test_keep_loop_after_inserting_command() {
+        let looped = sample_file()
+            .to_bytes_with_options(&WriteOptions { loop_point: LoopPoint::Index(2), ..Default::default() })
+            .unwrap();
+        let mut vgm = VgmFile::from_bytes(&mut Bytes::from(looped)).unwrap();
+        assert_eq!(vgm.commands[2], Commands::PSGWrite { value: 0x90, chip_index: 0 });
+
+        // Editing the list directly leaves the offsets stale
+        let mut stale = vgm.clone();
+        stale.commands.insert(0, Commands::Wait735Samples);
+        assert!(stale.to_bytes_normalized().is_err());
+
+        vgm.insert_command(0, Commands::Wait735Samples).unwrap();
+        let reparsed = VgmFile::from_bytes(&mut Bytes::from(vgm.to_bytes_normalized().unwrap())).unwrap();
+        let loop_index = reparsed.loop_start_index().unwrap();
+        assert_eq!(loop_index, 3);
+        assert_eq!(reparsed.commands[loop_index], Commands::PSGWrite { value: 0x90, chip_index: 0 });
+        assert_eq!(reparsed.header.loop_nb_samples, 10 + 5);
+
+        // Removing the loop command moves the loop to the next one
+        vgm.remove_command(3).unwrap();
+        assert_eq!(vgm.loop_start_index(), Some(3));
+        assert_eq!(vgm.commands[3], Commands::WaitNSamplesPlus1 { n: 9 });
+        assert!(vgm.remove_command(10).is_err());
+    }
+
+    #[test]
+    fn test_normalized_without_gd3() {
+        let mut vgm = sample_file();
+        vgm.metadata = VgmMetadata::default();
+
+        let bytes = vgm.to_bytes_normalized().unwrap();
+        let reparsed = VgmFile::from_bytes(&mut Bytes::from(bytes.clone())).unwrap();
+        assert_eq!(reparsed.header.gd3_offset, 0);
+        assert_eq!(bytes.len(), 0x100 + 8);
+
+        assert!(vgm
+            .to_bytes_with_options(&WriteOptions { loop_point: LoopPoint::Index(6), ..Default::default() })
+            .is_err());
+    }
+
+    #[test]
+    fn test_vgz_bytes_are_gzipped() {
+        let vgm = sample_file();
+        let vgz = vgm.to_vgz_bytes(9).unwrap();
+
+        assert!(crate::utils::is_gzipped(&vgz));
+        let mut buffer = BytesMut::new();
+        vgm.to_bytes(&mut buffer).unwrap();
+        assert_eq!(crate::utils::decompress_gzip(&vgz).unwrap(), buffer);
+        assert!(vgm.to_vgz_bytes(10).is_err());
+
+        let vgz = vgm.to_vgz_bytes_with_options(9, &WriteOptions::default()).unwrap();
+        assert_eq!(crate::utils::decompress_gzip(&vgz).unwrap(), vgm.to_bytes_normalized().unwrap());
+    }
+
+    /// File with padding before the data, notes between the commands and the
+    /// GD3 tag, and a trailer after the tag. The header claims no samples.
+    fn bytes_with_raw_regions() -> Vec<u8> {
+        let mut file = crate::tests::build_test_vgm(&[0x50, 0x9F, 0x62, 0x66]);
+        file[0xF0..0xF4].copy_from_slice(b"trk!");
+        file.extend_from_slice(b"notes");
+        let gd3_pos = file.len() as u32;
+        file[0x14..0x18].copy_from_slice(&(gd3_pos - 0x14).to_le_bytes());
+        let mut gd3 = BytesMut::new();
+        sample_file().metadata.to_bytes(&mut gd3).unwrap();
+        file.extend_from_slice(&gd3);
+        file.extend_from_slice(b"trailer");
+        let eof = file.len() as u32 - 4;
+        file[0x04..0x08].copy_from_slice(&eof.to_le_bytes());
+        file
+    }
+
+    fn file_with_raw_regions() -> VgmFile {
+        VgmFile::from_bytes(&mut Bytes::from(bytes_with_raw_regions())).unwrap()
+    }
+
+    #[test]
+    fn test_vgz_keeps_raw_regions() {
+        let vgm = file_with_raw_regions();
+        assert_eq!(vgm.raw_regions.trailing, b"trailer");
+
+        let vgz = vgm.to_vgz_bytes(9).unwrap();
+        assert_eq!(crate::utils::decompress_gzip(&vgz).unwrap(), bytes_with_raw_regions());
+
+        // Normalizing fixes the sample count and keeps the regions
+        let vgz = vgm.to_vgz_bytes_with_options(9, &WriteOptions::default()).unwrap();
+        let bytes = crate::utils::decompress_gzip(&vgz).unwrap();
+        let reparsed = VgmFile::from_bytes(&mut Bytes::from(bytes.clone())).unwrap();
+        assert_eq!(reparsed.header.total_nb_samples, 735);
+
+        assert_eq!(reparsed.raw_regions, vgm.raw_regions);
+        assert_eq!(reparsed.commands, vgm.commands);
+        assert_eq!(reparsed.metadata, vgm.metadata);
+        assert_eq!(reparsed.header.end_of_file_offset as usize, bytes.len() - 4);
+        assert!(bytes.ends_with(b"trailer"));
+    }
+
+    #[test]
+    fn test_write_to_path_round_trip() {
+        let dir = std::env::temp_dir().join(format!("vgm_parser_write_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let vgm = sample_file();
+
+        for name in ["track.vgm", "track.vgz", "TRACK.VGZ"] {
+            let path = dir.join(name);
+            let path = path.to_str().unwrap();
+            vgm.write_to_path_with_options(path, &WriteOptions::default()).unwrap();
+
+            let written = std::fs::read(path).unwrap();
+            assert_eq!(crate::utils::is_gzipped(&written), !name.ends_with(".vgm"));
+
+            let reparsed = VgmFile::from_path(path).unwrap();
+            assert_eq!(reparsed.commands, vgm.commands);
+            assert_eq!(reparsed.metadata, vgm.metadata);
+            assert_eq!(reparsed.header.version, 151);
+        }
+
+        // A parsed file with unparsed regions and a header sample count that
+        // doesn't match its waits comes back unchanged
+        let original = bytes_with_raw_regions();
+        let parsed = VgmFile::from_bytes(&mut Bytes::from(original.clone())).unwrap();
+        for name in ["kept.vgm", "kept.vgz"] {
+            let path = dir.join(name);
+            let path = path.to_str().unwrap();
+            parsed.write_to_path(path).unwrap();
+
+            let written = crate::utils::detect_and_decompress(&std::fs::read(path).unwrap()).unwrap();
+            assert_eq!(written, original);
+            assert_eq!(VgmFile::from_path(path).unwrap(), parsed);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_normalized_relocates_extra_header() {
+        let mut vgm = sample_file();
+        vgm.header.version = 171;
+        vgm.header.saa1099_clock = 8_000_000;
+        vgm.header.extra_header.set_chip_clock_override(&System::YM2612, 0, 7_600_000).unwrap();
+
+        let bytes = vgm.to_bytes_normalized().unwrap();
+        let reparsed = VgmFile::from_bytes(&mut Bytes::from(bytes)).unwrap();
+        // Right after the last 1.71 field, data still at 0x100
+        assert_eq!(reparsed.header.extra_header_offset as usize + 0xBC, 0xE4);
+        assert_eq!(reparsed.header.vgm_data_start().unwrap(), 0x100);
+        assert_eq!(reparsed.header.saa1099_clock, 8_000_000);
+        assert_eq!(reparsed.header.extra_header.chip_clock_override(&System::YM2612, 0), Some(7_600_000));
+
+        // More overrides than fit before 0x100 move the data back
+        let mut mastered = reparsed.clone();
+        for system in [System::SN76489, System::YM2612, System::SAA1099] {
+            for index in 0..2 {
+                mastered.header.extra_header.set_chip_volume(&system, index, 0xC0, VolumeMode::Relative).unwrap();
+            }
+        }
+        let bytes = mastered.to_bytes_normalized().unwrap();
+        let remastered = VgmFile::from_bytes(&mut Bytes::from(bytes)).unwrap();
+        assert_eq!(remastered.header.vgm_data_start().unwrap(), (0xE4usize + 12 + 6 + 25).next_multiple_of(4));
+        assert_eq!(remastered.header.extra_header.chip_volume_entries, mastered.header.extra_header.chip_volume_entries);
+        assert_eq!(remastered.commands, vgm.commands);
+        assert_eq!(remastered.metadata, vgm.metadata);
+
+        // Files before 1.70 have no extra header
+        vgm.header.version = 161;
+        let older = VgmFile::from_bytes(&mut Bytes::from(vgm.to_bytes_normalized().unwrap())).unwrap();
+        assert_eq!(older.header.extra_header_offset, 0);
+        assert!(older.header.extra_header.is_empty());
+    }
+}
@@ -0,0 +1,448 @@
+//! YM2151 (OPM) FM synthesis.
+//!
+//! Eight 4-operator FM channels with the noise generator on channel 8's last
+//! carrier and the LFO with its four waveforms. The chip runs at its native
+//! rate of clock / 64 and is resampled to 44.1 kHz. Timers and the CT output
+//! pins aren't emulated.
+//!
+//! Pitch is computed from the key code and fraction rather than the chip's
+//! own tables, and the LFO rate follows the datasheet's 0.008-52 Hz curve.
+
+use crate::fm::{algorithm_output, feedback_input, EnvelopeClock, FmTables, Operator, PHASE_MASK, SLOT_OPERATOR};
+use crate::{Commands, SoundChip, VGM_SAMPLE_RATE};
+
+/// Master clock cycles per chip output sample
+const CLOCKS_PER_SAMPLE: f64 = 64.0;
+/// Gain from a full scale channel to the renderer's output range
+const OUTPUT_SCALE: f32 = 1.0 / 32768.0;
+/// Clock the key codes are tuned for, with note A of octave 4 at 440 Hz
+const REFERENCE_CLOCK: f64 = 3_579_545.0;
+/// Phase increment of A4 at the reference clock
+const A4_INCREMENT: f64 = 440.0 * CLOCKS_PER_SAMPLE * (1 << 20) as f64 / REFERENCE_CLOCK;
+
+/// Pitch offset of each DT2 setting in cents
+const DT2_CENTS: [f64; 4] = [0.0, 600.0, 781.0, 950.0];
+/// Peak vibrato in cents for each PMS setting at full PMD
+const PM_DEPTHS: [f32; 8] = [0.0, 5.0, 10.0, 20.0, 50.0, 100.0, 400.0, 700.0];
+/// LFO rate in Hz at LFRQ 0 and 255 for the reference clock
+const LFO_RATE_RANGE: (f64, f64) = (0.0081, 52.6);
+
+#[derive(Debug, Clone, Default)]
+struct Channel {
+    /// Octave in bits 4-6 and note code in bits 0-3
+    key_code: u8,
+    /// Fraction of a semitone in 64ths
+    key_fraction: u8,
+    algorithm: u8,
+    feedback: u8,
+    left: bool,
+    right: bool,
+    pms: u8,
+    ams: u8,
+    /// Operators in M1, C1, M2, C2 order
+    operators: [Operator; 4],
+    detune2: [u8; 4],
+    /// Phase increment of each operator before detune, multiple and vibrato
+    base_increments: [u32; 4],
+    /// Last two outputs of M1 for self-feedback
+    feedback_history: [i32; 2],
+}
+
+impl Channel {
+    /// Key code as used for rate scaling and detune: octave and the top bits of the note
+    fn scaling_key_code(&self) -> u8 {
+        (self.key_code >> 2) & 0x1F
+    }
+
+    fn update_increments(&mut self) {
+        let octave = ((self.key_code >> 4) & 0x07) as f64;
+        // Note codes skip every fourth value: 0-2 are C#-D#, 4-6 E-F#, 8-10 G-A, 12-14 A#-C
+        let note = (self.key_code & 0x0F) as f64 - ((self.key_code & 0x0F) / 4) as f64;
+        let cents = ((octave - 4.0) * 12.0 + note - 8.0) * 100.0 + self.key_fraction as f64 * 100.0 / 64.0;
+
+        for (increment, detune2) in self.base_increments.iter_mut().zip(self.detune2) {
+            *increment = (A4_INCREMENT * ((cents + DT2_CENTS[detune2 as usize]) / 1200.0).exp2()) as u32;
+        }
+    }
+}
+
+/// One YM2151 chip
+#[derive(Debug, Clone)]
+pub struct Ym2151 {
+    channels: [Channel; 8],
+    noise_enabled: bool,
+    noise_frequency: u8,
+    noise_counter: u32,
+    noise_shift_register: u32,
+    /// LFO position within its period, 0 to 1
+    lfo_phase: f64,
+    /// LFO phase advance per chip sample
+    lfo_rate: f64,
+    /// LFO held at its start while set through register `0x01`
+    lfo_reset: bool,
+    lfo_waveform: u8,
+    amd: u8,
+    pmd: u8,
+    /// Random level of the noise waveform and the LFO step it was drawn at
+    lfo_noise: (f32, u32),
+    envelope_clock: EnvelopeClock,
+    tables: FmTables,
+    clock: u32,
+    /// Chip samples per output sample
+    step: f64,
+    fraction: f64,
+    previous: (i32, i32),
+    current: (i32, i32),
+}
+
+impl Ym2151 {
+    /// Chip running at `clock` Hz, in its reset state
+    pub fn new(clock: u32) -> Self {
+        let mut chip = Self {
+            channels: Default::default(),
+            noise_enabled: false,
+            noise_frequency: 0,
+            noise_counter: 0,
+            noise_shift_register: 1,
+            lfo_phase: 0.0,
+            lfo_rate: 0.0,
+            lfo_reset: false,
+            lfo_waveform: 0,
+            amd: 0,
+            pmd: 0,
+            lfo_noise: (0.0, 0),
+            envelope_clock: EnvelopeClock::default(),
+            tables: FmTables::new(),
+            clock,
+            step: clock as f64 / CLOCKS_PER_SAMPLE / VGM_SAMPLE_RATE as f64,
+            fraction: 0.0,
+            previous: (0, 0),
+            current: (0, 0),
+        };
+        chip.set_lfo_frequency(0);
+        for channel in &mut chip.channels {
+            channel.update_increments();
+        }
+        chip
+    }
+
+    /// Write `value` to `register`
+    pub fn write(&mut self, register: u8, value: u8) {
+        let index = (register & 0x07) as usize;
+        match register {
+            0x01 => self.lfo_reset = value & 0x02 != 0,
+            0x08 => {
+                let channel = &mut self.channels[(value & 0x07) as usize];
+                let key_code = channel.scaling_key_code();
+                for (operator, slot) in channel.operators.iter_mut().enumerate() {
+                    if value & (0x08 << operator) != 0 {
+                        slot.key_on(key_code);
+                    } else {
+                        slot.key_off();
+                    }
+                }
+            },
+            0x0F => {
+                self.noise_enabled = value & 0x80 != 0;
+                self.noise_frequency = value & 0x1F;
+            },
+            0x18 => self.set_lfo_frequency(value),
+            0x19 if value & 0x80 != 0 => self.pmd = value & 0x7F,
+            0x19 => self.amd = value & 0x7F,
+            // Bits 6-7 drive the CT output pins, which aren't connected to sound
+            0x1B => self.lfo_waveform = value & 0x03,
+            0x20..=0x27 => {
+                let channel = &mut self.channels[index];
+                channel.left = value & 0x40 != 0;
+                channel.right = value & 0x80 != 0;
+                channel.feedback = (value >> 3) & 0x07;
+                channel.algorithm = value & 0x07;
+            },
+            0x28..=0x2F => {
+                self.channels[index].key_code = value & 0x7F;
+                self.channels[index].update_increments();
+            },
+            0x30..=0x37 => {
+                self.channels[index].key_fraction = value >> 2;
+                self.channels[index].update_increments();
+            },
+            0x38..=0x3F => {
+                self.channels[index].pms = (value >> 4) & 0x07;
+                self.channels[index].ams = value & 0x03;
+            },
+            0x40..=0xFF => {
+                let slot = SLOT_OPERATOR[((register >> 3) & 0x03) as usize];
+                let channel = &mut self.channels[index];
+                let operator = &mut channel.operators[slot];
+                match register & 0xE0 {
+                    0x40 => {
+                        operator.detune = (value >> 4) & 0x07;
+                        operator.multiple = value & 0x0F;
+                    },
+                    0x60 => operator.total_level = value & 0x7F,
+                    0x80 => {
+                        operator.key_scale = value >> 6;
+                        operator.attack_rate = value & 0x1F;
+                    },
+                    0xA0 => {
+                        operator.am_enabled = value & 0x80 != 0;
+                        operator.decay_rate = value & 0x1F;
+                    },
+                    0xC0 => {
+                        operator.sustain_rate = value & 0x1F;
+                        channel.detune2[slot] = value >> 6;
+                        channel.update_increments();
+                    },
+                    _ => {
+                        operator.sustain_level = value >> 4;
+                        operator.release_rate = value & 0x0F;
+                    },
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn set_lfo_frequency(&mut self, value: u8) {
+        let (low, high) = LFO_RATE_RANGE;
+        let hz = low * (high / low).powf(value as f64 / 255.0) * self.clock as f64 / REFERENCE_CLOCK;
+        self.lfo_rate = hz * CLOCKS_PER_SAMPLE / self.clock.max(1) as f64;
+    }
+
+    fn update_lfo(&mut self) {
+        if self.lfo_reset {
+            self.lfo_phase = 0.0;
+            return;
+        }
+        self.lfo_phase = (self.lfo_phase + self.lfo_rate).fract();
+
+        // The noise waveform draws a new level on each of the LFO's 256 steps
+        let step = (self.lfo_phase * 256.0) as u32;
+        if step != self.lfo_noise.1 {
+            self.lfo_noise = ((self.noise_shift_register & 0xFF) as f32 / 255.0, step);
+        }
+    }
+
+    /// LFO amplitude modulation between 0 and 1 and vibrato between -1 and 1
+    fn lfo_waves(&self) -> (f32, f32) {
+        let phase = self.lfo_phase as f32;
+        match self.lfo_waveform {
+            0 => (1.0 - phase, phase * 2.0 - 1.0),
+            1 if phase < 0.5 => (1.0, 1.0),
+            1 => (0.0, -1.0),
+            2 => {
+                let pm = if phase < 0.25 {
+                    phase * 4.0
+                } else if phase < 0.75 {
+                    2.0 - phase * 4.0
+                } else {
+                    phase * 4.0 - 4.0
+                };
+                ((1.0 - phase * 2.0).abs(), pm)
+            },
+            _ => (self.lfo_noise.0, self.lfo_noise.0 * 2.0 - 1.0),
+        }
+    }
+
+    /// Step the noise shift register at the rate set by NFRQ
+    fn update_noise(&mut self) {
+        let period = 32 - self.noise_frequency as u32;
+        self.noise_counter += 2;
+        while self.noise_counter >= period {
+            self.noise_counter -= period;
+            let bit = ((self.noise_shift_register ^ (self.noise_shift_register >> 3)) & 1) ^ 1;
+            self.noise_shift_register = (bit << 16) | (self.noise_shift_register >> 1);
+        }
+    }
+
+    /// Output of channel `index` before panning, and M1's output for feedback
+    fn channel_output(&self, index: usize, am: i32) -> (i32, i32) {
+        let channel = &self.channels[index];
+        let operators = &channel.operators;
+        let am = match channel.ams {
+            0 => 0,
+            ams => am << (ams - 1),
+        };
+        let noise = index == 7 && self.noise_enabled;
+
+        let feedback = feedback_input(channel.feedback_history, channel.feedback);
+        let out1 = self.tables.output(&operators[0], feedback, am);
+        let output = algorithm_output(channel.algorithm, out1, |n, modulation| {
+            if noise && n == 3 {
+                let level = (self.tables.level(operators[3].attenuation(am)) * 8192.0) as i32;
+                if self.noise_shift_register & 1 != 0 {
+                    level
+                } else {
+                    -level
+                }
+            } else {
+                self.tables.output(&operators[n], modulation >> 1, am)
+            }
+        });
+        (output, out1)
+    }
+
+    /// Run the chip for one native sample
+    fn clock_sample(&mut self) -> (i32, i32) {
+        self.update_lfo();
+        self.update_noise();
+
+        if let Some(counter) = self.envelope_clock.tick() {
+            for channel in &mut self.channels {
+                let key_code = channel.scaling_key_code();
+                for operator in &mut channel.operators {
+                    operator.update_envelope(counter, key_code);
+                }
+            }
+        }
+
+        let (am_wave, pm_wave) = self.lfo_waves();
+        let am = (am_wave * self.amd as f32) as i32;
+        let (mut left, mut right) = (0, 0);
+        for index in 0..8 {
+            let (output, out1) = self.channel_output(index, am);
+            let channel = &mut self.channels[index];
+            channel.feedback_history = [channel.feedback_history[1], out1];
+            if channel.left {
+                left += output;
+            }
+            if channel.right {
+                right += output;
+            }
+
+            let pm_factor = match channel.pms {
+                0 => 1.0,
+                pms => (PM_DEPTHS[pms as usize] * self.pmd as f32 / 127.0 * pm_wave / 1200.0).exp2(),
+            };
+            let key_code = channel.scaling_key_code();
+            for (operator, base) in channel.operators.iter_mut().zip(channel.base_increments) {
+                operator.phase = (operator.phase + operator.phase_increment(base, key_code, pm_factor)) & PHASE_MASK;
+            }
+        }
+        (left, right)
+    }
+}
+
+impl SoundChip for Ym2151 {
+    fn write(&mut self, command: &Commands) {
+        if let Commands::YM2151Write { register, value, .. } = *command {
+            Ym2151::write(self, register, value);
+        }
+    }
+
+    fn next_sample(&mut self) -> (f32, f32) {
+        self.fraction += self.step;
+        while self.fraction >= 1.0 {
+            self.fraction -= 1.0;
+            self.previous = self.current;
+            self.current = self.clock_sample();
+        }
+
+        let t = self.fraction as f32;
+        let lerp = |a: i32, b: i32| (a as f32 + (b - a) as f32 * t) * OUTPUT_SCALE;
+        (lerp(self.previous.0, self.current.0), lerp(self.previous.1, self.current.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLOCK: u32 = 3_579_545;
+
+    /// Single sine carrier on `channel`: algorithm 7 with only C2 audible
+    fn sine_patch(chip: &mut Ym2151, channel: u8, pan: u8) {
+        chip.write(0x20 + channel, pan | 0x07);
+        for register in [0x60, 0x68, 0x70] {
+            chip.write(register + channel, 0x7F);
+        }
+        chip.write(0x58 + channel, 0x01);
+        chip.write(0x78 + channel, 0x00);
+        chip.write(0x98 + channel, 0x1F);
+        chip.write(0xF8 + channel, 0x0F);
+        // A4
+        chip.write(0x28 + channel, 0x4A);
+    }
+
+    fn zero_crossings(chip: &mut Ym2151, samples: usize) -> usize {
+        let mut previous = chip.next_sample().0;
+        let mut crossings = 0;
+        for _ in 0..samples {
+            let sample = chip.next_sample().0;
+            if (sample >= 0.0) != (previous >= 0.0) {
+                crossings += 1;
+            }
+            previous = sample;
+        }
+        crossings
+    }
+
+    #[test]
+    fn test_key_code_frequency() {
+        let mut chip = Ym2151::new(CLOCK);
+        sine_patch(&mut chip, 0, 0xC0);
+        assert!((0..1000).all(|_| chip.next_sample() == (0.0, 0.0)));
+
+        chip.write(0x08, 0x78);
+        // 440 Hz crosses zero 880 times a second
+        let crossings = zero_crossings(&mut chip, VGM_SAMPLE_RATE as usize);
+        assert!((870..=890).contains(&crossings), "{crossings} crossings");
+
+        // An octave up
+        chip.write(0x28, 0x5A);
+        assert_eq!(chip.channels[0].base_increments[3], (A4_INCREMENT * 2.0) as u32);
+    }
+
+    #[test]
+    fn test_panning() {
+        let mut chip = Ym2151::new(CLOCK);
+        sine_patch(&mut chip, 3, 0x80);
+        chip.write(0x08, 0x78 | 3);
+
+        let samples: Vec<_> = (0..2000).map(|_| chip.next_sample()).collect();
+        assert!(samples.iter().all(|&(left, _)| left == 0.0));
+        assert!(samples.iter().any(|&(_, right)| right != 0.0));
+    }
+
+    #[test]
+    fn test_noise_channel() {
+        let mut chip = Ym2151::new(CLOCK);
+        sine_patch(&mut chip, 7, 0xC0);
+        // Put C2 below the audible range so only the noise can be heard
+        chip.write(0x2F, 0x00);
+        chip.write(0x08, 0x78 | 7);
+        let tone = zero_crossings(&mut chip, 4410);
+
+        chip.write(0x0F, 0x80 | 0x1F);
+        let noise = zero_crossings(&mut chip, 4410);
+        assert!(noise > tone * 10, "{noise} crossings with noise, {tone} without");
+    }
+
+    #[test]
+    fn test_lfo_waveforms() {
+        let mut chip = Ym2151::new(CLOCK);
+        chip.write(0x1B, 0x01);
+        chip.lfo_phase = 0.25;
+        assert_eq!(chip.lfo_waves(), (1.0, 1.0));
+        chip.lfo_phase = 0.75;
+        assert_eq!(chip.lfo_waves(), (0.0, -1.0));
+
+        chip.write(0x1B, 0x02);
+        assert_eq!(chip.lfo_waves().1, -1.0);
+
+        // CT bits don't change the waveform
+        chip.write(0x1B, 0xC0);
+        assert_eq!(chip.lfo_waveform, 0);
+
+        // Reset holds the LFO at its start
+        chip.write(0x18, 0xFF);
+        chip.write(0x01, 0x02);
+        for _ in 0..100 {
+            chip.clock_sample();
+        }
+        assert_eq!(chip.lfo_phase, 0.0);
+        chip.write(0x01, 0x00);
+        chip.clock_sample();
+        assert!(chip.lfo_phase > 0.0);
+    }
+}
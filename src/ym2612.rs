@@ -0,0 +1,419 @@
+//! YM2612 (OPN2) FM synthesis.
+//!
+//! Six 4-operator FM channels with SSG-EG, the LFO and the channel 3
+//! per-operator frequency mode, plus the 8-bit DAC that replaces channel 6.
+//! The chip runs at its native rate of clock / 144 and is resampled to
+//! 44.1 kHz. Timers and CSM key-on aren't emulated.
+
+use crate::fm::{algorithm_output, feedback_input, EnvelopeClock, FmTables, Operator, PHASE_MASK, SLOT_OPERATOR};
+use crate::{Commands, SoundChip, VGM_SAMPLE_RATE};
+
+/// Master clock cycles per chip output sample
+const CLOCKS_PER_SAMPLE: f64 = 144.0;
+/// Gain from a full scale channel to the renderer's output range
+const OUTPUT_SCALE: f32 = 1.0 / 32768.0;
+
+/// Low two bits of the key code, from the top four bits of the F-number
+const KEY_CODE_NOTE: [u8; 16] = [0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 3, 3, 3, 3, 3, 3];
+
+/// Chip samples per LFO step for each LFO frequency setting
+const LFO_PERIODS: [u32; 8] = [108, 77, 71, 67, 62, 44, 8, 5];
+/// Right shift of the LFO amplitude for each AMS setting
+const AM_SHIFTS: [u8; 4] = [8, 3, 1, 0];
+/// Peak vibrato in cents for each FMS setting
+const PM_DEPTHS: [f32; 8] = [0.0, 3.4, 6.7, 10.0, 14.0, 20.0, 40.0, 80.0];
+
+/// Channel 3 frequency register (`0xA8` + n) of operators S1-S3 in special mode
+const CHANNEL3_FREQUENCY: [usize; 3] = [1, 2, 0];
+
+#[derive(Debug, Clone, Default)]
+struct Channel {
+    fnum: u16,
+    block: u8,
+    /// Block and F-number high bits written to `0xA4`, applied by `0xA0`
+    fnum_latch: u8,
+    algorithm: u8,
+    feedback: u8,
+    left: bool,
+    right: bool,
+    ams: u8,
+    fms: u8,
+    /// Operators in S1, S2, S3, S4 order
+    operators: [Operator; 4],
+    /// Last two outputs of S1 for self-feedback
+    feedback_history: [i32; 2],
+}
+
+/// Key code from the block and the top bits of the F-number
+fn key_code(fnum: u16, block: u8) -> u8 {
+    (block << 2) | KEY_CODE_NOTE[(fnum >> 7) as usize & 0x0F]
+}
+
+/// One YM2612 chip
+#[derive(Debug, Clone)]
+pub struct Ym2612 {
+    channels: [Channel; 6],
+    /// Channel 3 mode from register `0x27`, non-zero for per-operator frequencies
+    channel3_mode: u8,
+    channel3_frequencies: [(u16, u8); 3],
+    channel3_latches: [u8; 3],
+    lfo_enabled: bool,
+    lfo_rate: u8,
+    lfo_counter: u32,
+    /// LFO position, 0-127
+    lfo_step: u8,
+    dac_enabled: bool,
+    dac_data: u8,
+    envelope_clock: EnvelopeClock,
+    tables: FmTables,
+    /// Chip samples per output sample
+    step: f64,
+    fraction: f64,
+    previous: (i32, i32),
+    current: (i32, i32),
+}
+
+impl Ym2612 {
+    /// Chip running at `clock` Hz, in its reset state
+    pub fn new(clock: u32) -> Self {
+        let mut channels: [Channel; 6] = Default::default();
+        for channel in &mut channels {
+            channel.left = true;
+            channel.right = true;
+        }
+
+        Self {
+            channels,
+            channel3_mode: 0,
+            channel3_frequencies: [(0, 0); 3],
+            channel3_latches: [0; 3],
+            lfo_enabled: false,
+            lfo_rate: 0,
+            lfo_counter: 0,
+            lfo_step: 0,
+            dac_enabled: false,
+            dac_data: 0x80,
+            envelope_clock: EnvelopeClock::default(),
+            tables: FmTables::new(),
+            step: clock as f64 / CLOCKS_PER_SAMPLE / VGM_SAMPLE_RATE as f64,
+            fraction: 0.0,
+            previous: (0, 0),
+            current: (0, 0),
+        }
+    }
+
+    /// Write `value` to `register` of `port` 0 or 1
+    pub fn write(&mut self, port: u8, register: u8, value: u8) {
+        if register < 0x30 {
+            if port == 0 {
+                self.write_global(register, value);
+            }
+            return;
+        }
+
+        let offset = (register & 0x03) as usize;
+        if offset == 3 || register > 0xB6 {
+            return;
+        }
+        let index = offset + 3 * (port as usize & 1);
+
+        if register < 0xA0 {
+            let operator = &mut self.channels[index].operators[SLOT_OPERATOR[((register >> 2) & 0x03) as usize]];
+            match register & 0xF0 {
+                0x30 => {
+                    operator.detune = (value >> 4) & 0x07;
+                    operator.multiple = value & 0x0F;
+                },
+                0x40 => operator.total_level = value & 0x7F,
+                0x50 => {
+                    operator.key_scale = value >> 6;
+                    operator.attack_rate = value & 0x1F;
+                },
+                0x60 => {
+                    operator.am_enabled = value & 0x80 != 0;
+                    operator.decay_rate = value & 0x1F;
+                },
+                0x70 => operator.sustain_rate = value & 0x1F,
+                0x80 => {
+                    operator.sustain_level = value >> 4;
+                    operator.release_rate = value & 0x0F;
+                },
+                _ => operator.ssg_eg = value & 0x0F,
+            }
+            return;
+        }
+
+        let channel = &mut self.channels[index];
+        match register & 0xFC {
+            0xA0 => {
+                channel.fnum = ((channel.fnum_latch as u16 & 0x07) << 8) | value as u16;
+                channel.block = (channel.fnum_latch >> 3) & 0x07;
+            },
+            0xA4 => channel.fnum_latch = value & 0x3F,
+            0xA8 if port == 0 => {
+                let latch = self.channel3_latches[offset];
+                self.channel3_frequencies[offset] = (((latch as u16 & 0x07) << 8) | value as u16, (latch >> 3) & 0x07);
+            },
+            0xAC if port == 0 => self.channel3_latches[offset] = value & 0x3F,
+            0xB0 => {
+                channel.feedback = (value >> 3) & 0x07;
+                channel.algorithm = value & 0x07;
+            },
+            0xB4 => {
+                channel.left = value & 0x80 != 0;
+                channel.right = value & 0x40 != 0;
+                channel.ams = (value >> 4) & 0x03;
+                channel.fms = value & 0x07;
+            },
+            _ => {},
+        }
+    }
+
+    fn write_global(&mut self, register: u8, value: u8) {
+        match register {
+            0x22 => {
+                self.lfo_enabled = value & 0x08 != 0;
+                self.lfo_rate = value & 0x07;
+            },
+            0x27 => self.channel3_mode = value >> 6,
+            0x28 => {
+                let index = match value & 0x07 {
+                    channel @ 0..=2 => channel as usize,
+                    channel @ 4..=6 => channel as usize - 1,
+                    _ => return,
+                };
+                for operator in 0..4 {
+                    let (fnum, block) = self.operator_frequency(index, operator);
+                    let slot = &mut self.channels[index].operators[operator];
+                    if value & (0x10 << operator) != 0 {
+                        slot.key_on(key_code(fnum, block));
+                    } else {
+                        slot.key_off();
+                    }
+                }
+            },
+            0x2A => self.dac_data = value,
+            0x2B => self.dac_enabled = value & 0x80 != 0,
+            _ => {},
+        }
+    }
+
+    /// F-number and block of `operator` of channel `index`, following channel 3 mode
+    fn operator_frequency(&self, index: usize, operator: usize) -> (u16, u8) {
+        if index == 2 && self.channel3_mode != 0 && operator < 3 {
+            self.channel3_frequencies[CHANNEL3_FREQUENCY[operator]]
+        } else {
+            (self.channels[index].fnum, self.channels[index].block)
+        }
+    }
+
+    fn update_lfo(&mut self) {
+        if !self.lfo_enabled {
+            self.lfo_counter = 0;
+            self.lfo_step = 0;
+            return;
+        }
+        self.lfo_counter += 1;
+        if self.lfo_counter >= LFO_PERIODS[self.lfo_rate as usize] {
+            self.lfo_counter = 0;
+            self.lfo_step = (self.lfo_step + 1) & 0x7F;
+        }
+    }
+
+    /// LFO amplitude modulation before the AMS shift, 0-126
+    fn lfo_am(&self) -> i32 {
+        let step = self.lfo_step as i32;
+        if step < 64 {
+            step * 2
+        } else {
+            126 - (step & 0x3F) * 2
+        }
+    }
+
+    /// LFO vibrato as a triangle between -1 and 1
+    fn lfo_pm(&self) -> f32 {
+        let position = self.lfo_step as f32 / 128.0;
+        if position < 0.25 {
+            position * 4.0
+        } else if position < 0.75 {
+            2.0 - position * 4.0
+        } else {
+            position * 4.0 - 4.0
+        }
+    }
+
+    /// Output of channel `index` before panning, and S1's output for feedback
+    fn channel_output(&self, index: usize, am: i32) -> (i32, i32) {
+        let channel = &self.channels[index];
+        let operators = &channel.operators;
+        let am = am >> AM_SHIFTS[channel.ams as usize];
+
+        let feedback = feedback_input(channel.feedback_history, channel.feedback);
+        let out1 = self.tables.output(&operators[0], feedback, am);
+        let output = algorithm_output(channel.algorithm, out1, |n, modulation| {
+            self.tables.output(&operators[n], modulation >> 1, am)
+        });
+        (output, out1)
+    }
+
+    /// Run the chip for one native sample
+    fn clock_sample(&mut self) -> (i32, i32) {
+        self.update_lfo();
+
+        if let Some(counter) = self.envelope_clock.tick() {
+            for index in 0..6 {
+                for operator in 0..4 {
+                    let (fnum, block) = self.operator_frequency(index, operator);
+                    self.channels[index].operators[operator].update_envelope(counter, key_code(fnum, block));
+                }
+            }
+        }
+
+        let am = self.lfo_am();
+        let pm = self.lfo_pm();
+        let (mut left, mut right) = (0, 0);
+        for index in 0..6 {
+            let output = if index == 5 && self.dac_enabled {
+                (self.dac_data as i32 - 0x80) << 6
+            } else {
+                let (output, out1) = self.channel_output(index, am);
+                let history = &mut self.channels[index].feedback_history;
+                *history = [history[1], out1];
+                output
+            };
+
+            let channel = &self.channels[index];
+            if channel.left {
+                left += output;
+            }
+            if channel.right {
+                right += output;
+            }
+
+            let pm_factor = match channel.fms {
+                0 => 1.0,
+                fms => (PM_DEPTHS[fms as usize] * pm / 1200.0).exp2(),
+            };
+            for operator in 0..4 {
+                let (fnum, block) = self.operator_frequency(index, operator);
+                let slot = &mut self.channels[index].operators[operator];
+                let increment = slot.phase_increment(((fnum as u32) << block) >> 1, key_code(fnum, block), pm_factor);
+                slot.phase = (slot.phase + increment) & PHASE_MASK;
+            }
+        }
+        (left, right)
+    }
+}
+
+impl SoundChip for Ym2612 {
+    fn write(&mut self, command: &Commands) {
+        match *command {
+            Commands::YM2612Port0Write { register, value, .. } => Ym2612::write(self, 0, register, value),
+            Commands::YM2612Port1Write { register, value, .. } => Ym2612::write(self, 1, register, value),
+            _ => {},
+        }
+    }
+
+    fn next_sample(&mut self) -> (f32, f32) {
+        self.fraction += self.step;
+        while self.fraction >= 1.0 {
+            self.fraction -= 1.0;
+            self.previous = self.current;
+            self.current = self.clock_sample();
+        }
+
+        let t = self.fraction as f32;
+        let lerp = |a: i32, b: i32| (a as f32 + (b - a) as f32 * t) * OUTPUT_SCALE;
+        (lerp(self.previous.0, self.current.0), lerp(self.previous.1, self.current.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fm::EnvelopeState;
+
+    const NTSC_CLOCK: u32 = 7_670_453;
+
+    /// Single sine carrier on channel 1: algorithm 7 with only S4 audible
+    fn sine_patch(chip: &mut Ym2612) {
+        chip.write(0, 0xB0, 0x07);
+        for register in [0x40, 0x44, 0x48] {
+            chip.write(0, register, 0x7F);
+        }
+        chip.write(0, 0x3C, 0x01);
+        chip.write(0, 0x4C, 0x00);
+        chip.write(0, 0x5C, 0x1F);
+        chip.write(0, 0x8C, 0x0F);
+        // Middle C: F-number 644, block 4
+        chip.write(0, 0xA4, 0x22);
+        chip.write(0, 0xA0, 0x84);
+    }
+
+    fn zero_crossings(chip: &mut Ym2612, samples: usize) -> usize {
+        let mut previous = chip.next_sample().0;
+        let mut crossings = 0;
+        for _ in 0..samples {
+            let sample = chip.next_sample().0;
+            if (sample >= 0.0) != (previous >= 0.0) {
+                crossings += 1;
+            }
+            previous = sample;
+        }
+        crossings
+    }
+
+    #[test]
+    fn test_key_on_frequency() {
+        let mut chip = Ym2612::new(NTSC_CLOCK);
+        sine_patch(&mut chip);
+        assert!((0..1000).all(|_| chip.next_sample() == (0.0, 0.0)));
+
+        chip.write(0, 0x28, 0xF0);
+        // Middle C at ~261.7 Hz crosses zero about 523 times a second
+        let crossings = zero_crossings(&mut chip, VGM_SAMPLE_RATE as usize);
+        assert!((515..=530).contains(&crossings), "{crossings} crossings");
+
+        // Release at the fastest rate silences the channel
+        chip.write(0, 0x28, 0x00);
+        for _ in 0..2000 {
+            chip.next_sample();
+        }
+        assert_eq!(chip.channels[0].operators[3].state, EnvelopeState::Off);
+    }
+
+    #[test]
+    fn test_dac_and_panning() {
+        let mut chip = Ym2612::new(NTSC_CLOCK);
+        chip.write(0, 0x2B, 0x80);
+        chip.write(0, 0x2A, 0xFF);
+        // Channel 6 on the right only
+        chip.write(1, 0xB6, 0x40);
+        for _ in 0..4 {
+            chip.next_sample();
+        }
+        let (left, right) = chip.next_sample();
+        assert_eq!(left, 0.0);
+        assert!((right - (0x7F << 6) as f32 * OUTPUT_SCALE).abs() < 1e-6);
+
+        chip.write(0, 0x2B, 0x00);
+        for _ in 0..4 {
+            chip.next_sample();
+        }
+        assert_eq!(chip.next_sample(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_channel3_special_mode() {
+        let mut chip = Ym2612::new(NTSC_CLOCK);
+        chip.write(0, 0xA6, 0x22);
+        chip.write(0, 0xA2, 0x84);
+        chip.write(0, 0xAD, 0x1A);
+        chip.write(0, 0xA9, 0x00);
+        assert_eq!(chip.operator_frequency(2, 0), (644, 4));
+
+        chip.write(0, 0x27, 0x40);
+        assert_eq!(chip.operator_frequency(2, 0), (0x200, 3));
+        assert_eq!(chip.operator_frequency(2, 3), (644, 4));
+    }
+}